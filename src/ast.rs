@@ -4,14 +4,37 @@ pub type Args = Block;
 pub type Params = Vec<Param>;
 pub type Functions = Vec<Function>;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+// Position in the source program. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone)]
 pub struct Block {
     pub content: Vec<Expr>,
+    // Position of the opening brace and of each statement. Not set for
+    // blocks that are not parsed from a program.
+    pub span: Option<Span>,
+    pub stmt_spans: Vec<Span>,
 }
 
 impl Block {
     pub fn new(exprs: Vec<Expr>) -> Block {
-        Block { content: exprs }
+        Block {
+            content: exprs,
+            span: None,
+            stmt_spans: Vec::new(),
+        }
+    }
+}
+
+// Positions are not compared so that parsed blocks are equal to blocks
+// constructed without them.
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        self.content == other.content
     }
 }
 
@@ -111,20 +134,115 @@ impl fmt::Display for Op {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
+    Int8,
+    Int16,
     Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    USize,
+    Float64,
     Bool,
-    Void, // for functions
+    Char,
+    Str, // &str
+    Tuple(Vec<Type>),
+    Vec(Box<Type>), // only scalar element types
+    Void,           // for functions
+}
+
+impl Type {
+    pub fn is_int(&self) -> bool {
+        self.bit_width().is_some()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        match self {
+            Type::Int8 | Type::Int16 | Type::Int32 | Type::Int64 => true,
+            _ => false,
+        }
+    }
+
+    // Returns the width in bits of integer types. usize is always 64 bits wide.
+    pub fn bit_width(&self) -> Option<u32> {
+        match self {
+            Type::Int8 | Type::UInt8 => Some(8),
+            Type::Int16 | Type::UInt16 => Some(16),
+            Type::Int32 | Type::UInt32 => Some(32),
+            Type::Int64 | Type::UInt64 | Type::USize => Some(64),
+            _ => None,
+        }
+    }
+
+    // Returns the smallest and largest value of integer types.
+    pub fn int_range(&self) -> Option<(i128, i128)> {
+        let width = self.bit_width()?;
+        if self.is_signed() {
+            Some((-(1 << (width - 1)), (1 << (width - 1)) - 1))
+        } else {
+            Some((0, (1 << width) - 1))
+        }
+    }
+
+    // The name of the type in the source language.
+    pub fn source_name(&self) -> String {
+        match self {
+            Type::Int8 => "i8".to_string(),
+            Type::Int16 => "i16".to_string(),
+            Type::Int32 => "i32".to_string(),
+            Type::Int64 => "i64".to_string(),
+            Type::UInt8 => "u8".to_string(),
+            Type::UInt16 => "u16".to_string(),
+            Type::UInt32 => "u32".to_string(),
+            Type::UInt64 => "u64".to_string(),
+            Type::USize => "usize".to_string(),
+            Type::Float64 => "f64".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Char => "char".to_string(),
+            Type::Str => "&str".to_string(),
+            Type::Tuple(types) => format!(
+                "({})",
+                types
+                    .iter()
+                    .map(Type::source_name)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Type::Vec(elem_type) => format!("Vec<{}>", elem_type.source_name()),
+            Type::Void => "()".to_string(),
+        }
+    }
 }
 
 impl From<Type> for String {
     fn from(t: Type) -> String {
         match t {
+            Type::Int8 => "Int8".to_string(),
+            Type::Int16 => "Int16".to_string(),
             Type::Int32 => "Int32".to_string(),
+            Type::Int64 => "Int64".to_string(),
+            Type::UInt8 => "UInt8".to_string(),
+            Type::UInt16 => "UInt16".to_string(),
+            Type::UInt32 => "UInt32".to_string(),
+            Type::UInt64 => "UInt64".to_string(),
+            Type::USize => "USize".to_string(),
+            Type::Float64 => "Float64".to_string(),
             Type::Bool => "Bool".to_string(),
+            Type::Char => "Char".to_string(),
+            Type::Str => "Str".to_string(),
+            Type::Tuple(types) => format!(
+                "({})",
+                types
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Type::Vec(elem_type) => format!("Vec<{}>", String::from(*elem_type)),
             Type::Void => "Void".to_string(),
-            _ => panic!("Could not convert to String. No such type found."),
         }
     }
 }
@@ -141,12 +259,15 @@ impl Param {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub name: String,
     pub params: Params,
     pub block: Block,
     pub return_type: Type,
+    // Declared in an ´extern "C"´ block. The function has no body and is
+    // resolved against C libraries when the program is linked.
+    pub is_extern: bool,
 }
 
 impl Function {
@@ -156,11 +277,22 @@ impl Function {
             params,
             block,
             return_type,
+            is_extern: false,
+        }
+    }
+
+    pub fn new_extern(name: String, params: Params, return_type: Type) -> Self {
+        Function {
+            name,
+            params,
+            block: Block::new(Vec::new()),
+            return_type,
+            is_extern: true,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FunctionCall {
     pub name: String,
     pub args: Args,
@@ -170,16 +302,32 @@ impl FunctionCall {
     pub fn new(name: String, args: Args) -> Self {
         FunctionCall { name, args }
     }
+
+    // Built-in functions are provided by the interpreter and compiler and
+    // are not declared in the program.
+    pub fn is_builtin(&self) -> bool {
+        self.name == "print" || self.name == "println"
+    }
 }
 
 // Value, Keyword, _Expr (rename to Expr), Node (same functionality
 // as the old Expr) should be used instead of only Expr
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Num(i32),
+    Int(i128, Type), // integers of any other width than i32
+    Float(f64),
     Var(String),
     Bool(bool),
+    Char(char),
+    Str(String),
+    Tuple(Vec<Self>),
+    Vec(Vec<Self>, Type), // elements and their type
     Return(Box<Self>),
+    // ´return f(...)´ with the evaluated arguments, which the interpreter
+    // calls after the function that returns it. Not returned by its public
+    // functions.
+    TailCall(String, Vec<Self>),
 }
 
 // Implement TryForm trait instead to get a Result back
@@ -214,7 +362,7 @@ impl From<bool> for Value {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum Keyword {
     Let(Box<Node>, Type, Box<Node>),
     If(Box<Node>, Block),
@@ -224,29 +372,38 @@ pub enum Keyword {
     Return(Box<Node>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum _Expr {
     BinOp(Box<Node>, Op, Box<Node>),
     VarOp(Box<Node>, Op, Box<Node>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum Node {
     Value(Value),
     Keyword(Keyword),
     Expr(_Expr),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     // Right-hand expressions
     BinOp(Box<Expr>, Op, Box<Expr>),
-    Num(i32),
+    Num(i128),
+    Float(f64),
     Var(String),
     Bool(bool),
+    Char(char),
+    Str(String),
+    Tuple(Vec<Expr>),
+    TupleIndex(Box<Expr>, usize),
+    Cast(Box<Expr>, Type),
+    VecNew,
+    Index(Box<Expr>, Box<Expr>),
+    MethodCall(Box<Expr>, String, Vec<Expr>),
 
     // Keywords (coud be moved to another enum?)
-    Let(Box<Expr>, Type, Box<Expr>),
+    Let(Box<Expr>, Type, Box<Expr>), // the pattern is either a Var or a Tuple of patterns
     VarOp(Box<Expr>, Op, Box<Expr>),
     If(Box<Expr>, Block),
     IfElse(Box<Expr>, Block), // not implemented yet
@@ -255,11 +412,11 @@ pub enum Expr {
     Return(Box<Expr>),
 }
 
-impl From<Expr> for i32 {
-    fn from(e: Expr) -> i32 {
+impl From<Expr> for i128 {
+    fn from(e: Expr) -> i128 {
         match e {
             Expr::Num(i) => i,
-            _ => panic!("Could not convert to i32. Wrong type."),
+            _ => panic!("Could not convert to i128. Wrong type."),
         }
     }
 }
@@ -271,6 +428,44 @@ impl From<Expr> for String {
             Expr::Var(s) => s,
             Expr::Bool(b) => format!("{}", b),
             Expr::Num(i) => format!("{}", i),
+            Expr::Float(f) => format!("{:?}", f),
+            Expr::Char(c) => format!("{:?}", c),
+            Expr::Str(s) => format!("{:?}", s),
+            Expr::Tuple(exprs) => format!(
+                "({})",
+                exprs
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Expr::TupleIndex(expr, i) => format!("{}.{}", String::from(*expr), i),
+            Expr::Cast(expr, t) => format!("{} as {}", String::from(*expr), String::from(t)),
+            Expr::BinOp(l, op, r) => format!("{} {} {}", String::from(*l), op, String::from(*r)),
+            Expr::VecNew => "Vec::new()".to_string(),
+            Expr::Index(expr, index) => {
+                format!("{}[{}]", String::from(*expr), String::from(*index))
+            }
+            Expr::MethodCall(expr, name, args) => format!(
+                "{}.{}({})",
+                String::from(*expr),
+                name,
+                args.into_iter()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Expr::FuncCall(fn_call) => format!(
+                "{}({})",
+                fn_call.name,
+                fn_call
+                    .args
+                    .content
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
             _ => panic!("Could not convert to String. Wrong type."),
         }
     }
@@ -285,3 +480,69 @@ impl From<Expr> for bool {
         }
     }
 }
+
+pub type TypedBlock = Vec<TypedExpr>;
+pub type TypedFunctions = Vec<TypedFunction>;
+
+// The typed AST produced by the type checker. Every expression is annotated
+// with its type, integer literals included, so later passes do not need to
+// infer types themselves.
+// Statements keep the position they were parsed at.
+#[derive(Debug, Clone)]
+pub struct TypedExpr {
+    pub kind: TypedExprKind,
+    pub expr_type: Type,
+    pub span: Option<Span>,
+}
+
+impl TypedExpr {
+    pub fn new(kind: TypedExprKind, expr_type: Type) -> Self {
+        TypedExpr {
+            kind,
+            expr_type,
+            span: None,
+        }
+    }
+}
+
+impl PartialEq for TypedExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.expr_type == other.expr_type
+    }
+}
+
+// Same as Expr except that types written in the program, such as the target
+// of a cast, are found in the annotation instead. Statements have the type
+// Void.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedExprKind {
+    BinOp(Box<TypedExpr>, Op, Box<TypedExpr>),
+    Num(i128),
+    Float(f64),
+    Var(String),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Tuple(Vec<TypedExpr>),
+    TupleIndex(Box<TypedExpr>, usize),
+    Cast(Box<TypedExpr>),
+    VecNew,
+    Index(Box<TypedExpr>, Box<TypedExpr>),
+    MethodCall(Box<TypedExpr>, String, Vec<TypedExpr>),
+    Let(Box<TypedExpr>, Box<TypedExpr>), // the pattern is annotated with the declared type
+    VarOp(Box<TypedExpr>, Op, Box<TypedExpr>),
+    If(Box<TypedExpr>, TypedBlock),
+    While(Box<TypedExpr>, TypedBlock),
+    FuncCall(String, Vec<TypedExpr>),
+    Return(Box<TypedExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedFunction {
+    pub name: String,
+    pub params: Params,
+    pub block: TypedBlock,
+    pub return_type: Type,
+    pub span: Option<Span>,
+    pub is_extern: bool,
+}
@@ -0,0 +1,166 @@
+use crate::ast::*;
+use crate::type_checker::*;
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::process;
+
+// Errors and helpers shared by the backends that generate source code for
+// other tools instead of using LLVM. The backends keep the semantics of the
+// interpreter: both operands of ´&&´ and ´||´ are always evaluated, and a
+// function with a return value that ends without a return stops the program.
+
+pub type BackendRes<T> = Result<T, BackendError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    // The program was rejected by the type checker
+    TypeError(String),
+    UnknownVariable(String),
+    UnknownFunction(String),
+    MissingMain,
+    // The program uses a feature that the backend cannot translate
+    Unsupported(String),
+    // The generated code was rejected by the validator of the backend
+    Invalid(String),
+    // An output file could not be written
+    Io(String),
+    // The system compiler, assembler or linker failed
+    Tool(String),
+}
+
+impl From<TypeErr> for BackendError {
+    fn from(e: TypeErr) -> Self {
+        BackendError::TypeError(e.0)
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::TypeError(msg) => write!(f, "type error: {}", msg),
+            BackendError::UnknownVariable(var) => write!(f, "cannot find variable ´{}´", var),
+            BackendError::UnknownFunction(name) => write!(f, "cannot find function ´{}´", name),
+            BackendError::MissingMain => write!(f, "no ´main´ function found"),
+            BackendError::Unsupported(what) => write!(f, "cannot translate {}", what),
+            BackendError::Invalid(msg) => write!(f, "invalid output: {}", msg),
+            BackendError::Io(msg) => write!(f, "could not write output: {}", msg),
+            BackendError::Tool(msg) => write!(f, "external tool failed: {}", msg),
+        }
+    }
+}
+
+pub(crate) fn err_unsupported<T>(what: &str) -> BackendRes<T> {
+    Err(BackendError::Unsupported(what.to_string()))
+}
+
+// Type checks the program. Every backend needs a main function.
+pub(crate) fn check_program(mut fn_list: Functions) -> BackendRes<TypedFunctions> {
+    let typed_fns = tc_program(&mut fn_list)?;
+    if !typed_fns.iter().any(|func| func.name == "main") {
+        return Err(BackendError::MissingMain);
+    }
+    Ok(typed_fns)
+}
+
+// Rejects the expressions that none of the backends can translate as a value.
+pub(crate) fn check_value(expr: &TypedExpr) -> BackendRes<()> {
+    match &expr.kind {
+        TypedExprKind::FuncCall(name, _)
+            if expr.expr_type == Type::Void && name != "print" && name != "println" =>
+        {
+            err_unsupported(&format!("the result of ´{}´, which returns ´()´", name))
+        }
+        TypedExprKind::Tuple(_) | TypedExprKind::TupleIndex(..) => err_unsupported("tuples"),
+        TypedExprKind::VecNew | TypedExprKind::Index(..) | TypedExprKind::MethodCall(..) => {
+            err_unsupported("vectors")
+        }
+        _ => Ok(()),
+    }
+}
+
+// For the statements that a backend finds where it expects a value
+pub(crate) fn err_statement_value<T>() -> BackendRes<T> {
+    err_unsupported("a statement as a value")
+}
+
+// Runs a tool such as ´cc´ and returns its error output if it fails.
+pub(crate) fn run_tool<S: AsRef<OsStr>>(program: &str, args: &[S]) -> BackendRes<()> {
+    let output = process::Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| BackendError::Tool(format!("could not run ´{}´: {}", program, e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(BackendError::Tool(format!(
+            "{}: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr)
+        )))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod backend_tests {
+    use super::*;
+    use crate::c99::*;
+    use crate::parser::*;
+    use crate::wasm::*;
+    use crate::x86_64::*;
+    use std::env;
+    use std::path::Path;
+
+    pub(crate) const FIBO: &str = "
+        fn fibo(i: i32) -> i32 {
+            if i == 1 {
+                return 0;
+            };
+            if i == 2 {
+                return 1;
+            };
+
+            return fibo(i-1) + fibo(i-2);
+        }
+
+        fn main() -> i32 {
+            let a: i32 = 7;
+            return fibo(a);
+        }
+    ";
+
+    // Builds the program into an executable in the temporary directory with
+    // the build function of a backend, runs it and returns the exit code and
+    // output. The executable and the generated file with the extension are
+    // removed.
+    pub(crate) fn run_built(
+        build: fn(Functions, &Path) -> BackendRes<()>,
+        program: &str,
+        name: &str,
+        extension: &str,
+    ) -> (Option<i32>, String) {
+        let exe_path = env::temp_dir().join(format!("{}-{}", process::id(), name));
+        build(parse_program(program).unwrap().1, &exe_path).unwrap();
+        let output = process::Command::new(&exe_path).output().unwrap();
+        let _ = std::fs::remove_file(&exe_path);
+        let _ = std::fs::remove_file(exe_path.with_extension(extension));
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+        )
+    }
+
+    #[test]
+    fn backends_reject_tuples() {
+        let program = "
+            fn main() -> i32 {
+                let t: (i32, i32) = (1, 2);
+                return t.0;
+            }
+        ";
+        let tuples = Err(BackendError::Unsupported("tuples".to_string()));
+        assert_eq!(c_program(parse_program(program).unwrap().1), tuples);
+        assert_eq!(wat_program(parse_program(program).unwrap().1), tuples);
+        assert_eq!(asm_program(parse_program(program).unwrap().1), tuples);
+    }
+}
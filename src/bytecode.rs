@@ -0,0 +1,597 @@
+use crate::ast::*;
+use crate::interpreter::{eval_int_literal, EvalErr, EvalRes};
+use crate::type_checker::is_int_literal;
+
+use std::collections::HashMap;
+
+// Compiles programs to bytecode for the stack machine in vm.rs. Every
+// expression leaves exactly one value on the stack, like every expression of
+// the interpreter evaluates to a value. Variables are resolved to numbered
+// slots of the function's frame when compiling, so the VM does not look up
+// names. Errors that the interpreter reports when a statement is evaluated,
+// such as unknown variables, are compiled to ´Fail´ instructions so that they
+// happen at the same point of the program.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    // Pushes the constant with the index in the constant pool
+    Const(usize),
+    // Pushes a copy of the local variable in the slot
+    Load(usize),
+    // Pops the value into the slot
+    Store(usize),
+    Pop,
+    Dup,
+    BinOp(Op),
+    // Pops the right operand and computes the operation with the integer
+    // literal on the left in the type that literal_type gives the operand
+    LiteralLeft(Expr, Op),
+    // Pops the left operand and computes the operation with the integer
+    // literal on the right in the type that literal_type gives the operand
+    LiteralRight(Op, Expr),
+    Cast(Type),
+    // Gives integer literals the type of a declaration
+    Coerce(Type),
+    // Pushes an integer literal computed in the type that literal_type gives
+    // the value in the slot, like the interpreter does for assigned literals
+    IntLiteral(usize, Expr),
+    // Pops the elements and pushes them as a tuple
+    MakeTuple(usize),
+    TupleIndex(usize),
+    // Pops a tuple with the number of elements and pushes the elements with
+    // the first one on top
+    Destructure(usize),
+    // Converts the integer on top of the stack to an index
+    ToIndex,
+    // Pops an index and a vector and pushes the element
+    Index,
+    // Pops an index and pushes the element of the vector in the slot
+    IndexVar(usize),
+    Len,
+    LenVar(usize),
+    // Assigns the value on top of the stack to the slot, leaving the new value
+    Assign(usize),
+    AssignOp(usize, Op),
+    // Pops a value and an index and assigns the element of the vector in the
+    // slot. Leaves the updated vector on the stack if the flag is set.
+    ElemOp(usize, Op, bool),
+    VecPush(usize),
+    VecPop(usize),
+    // A method that does not exist on the value in the slot
+    UnknownMethod(usize, String),
+    // Replaces the value on top of the stack with its text
+    Format,
+    // Pops the formatted values and prints them between the pieces of the
+    // format string. Prints a newline if the flag is set.
+    Print(Vec<String>, bool),
+    Jump(usize),
+    // Pops a boolean and jumps if it is false
+    JumpIfFalse(usize),
+    // Calls the function with the index with the number of arguments on the
+    // stack
+    Call(usize, usize),
+    // Returns from a return statement
+    Return,
+    // Returns the value of the last statement of a function
+    End,
+    Fail(EvalErr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnInfo {
+    pub name: String,
+    // Index of the first instruction
+    pub entry: usize,
+    pub param_types: Vec<Type>,
+    pub return_type: Type,
+    // Number of slots for parameters and variables
+    pub num_locals: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub code: Vec<Instr>,
+    pub constants: Vec<Value>,
+    pub functions: Vec<FnInfo>,
+    pub main: usize,
+}
+
+fn err_no_var() -> EvalErr {
+    EvalErr::NotFound("Key not found in context scopes".to_string())
+}
+
+fn is_var(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_) => true,
+        _ => false,
+    }
+}
+
+fn err_not_in_var() -> EvalErr {
+    EvalErr::WrongType("Cannot modify a vector that is not stored in a variable.".to_string())
+}
+
+struct BytecodeCompiler<'a> {
+    fn_list: &'a Functions,
+    code: Vec<Instr>,
+    constants: Vec<Value>,
+    // Variables of the blocks around the statement being compiled
+    scopes: Vec<HashMap<String, usize>>,
+    num_locals: usize,
+    // Return type of the function being compiled
+    return_type: Type,
+}
+
+impl<'a> BytecodeCompiler<'a> {
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    fn emit_const(&mut self, val: Value) {
+        self.constants.push(val);
+        let index = self.constants.len() - 1;
+        self.emit(Instr::Const(index));
+    }
+
+    // Sets the target of a jump emitted before the target was known.
+    fn patch_jump(&mut self, at: usize, target: usize) {
+        match &mut self.code[at] {
+            Instr::Jump(t) | Instr::JumpIfFalse(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.num_locals;
+        self.num_locals += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), slot);
+        slot
+    }
+
+    // The slot of a variable holding a vector that is modified in place.
+    // The error is that of the interpreter for the missing variable.
+    fn vec_slot(&self, expr: &Expr) -> Result<usize, EvalErr> {
+        match expr {
+            Expr::Var(name) => self.lookup(name).ok_or_else(err_no_var),
+            _ => Err(err_not_in_var()),
+        }
+    }
+
+    // Integer literals and arithmetic on them are computed when compiling.
+    // Overflows fail when the literal is reached, like in the interpreter.
+    fn compile_int_literal(&mut self, expr: &Expr, t: &Type) {
+        match eval_int_literal(expr, t) {
+            Ok(val) => self.emit_const(val),
+            Err(e) => {
+                self.emit(Instr::Fail(e));
+            }
+        }
+    }
+
+    // Compiles the value of a declaration in its type, like eval_expect.
+    fn compile_expect(&mut self, expr: &Expr, t: &Type) {
+        match (expr, t) {
+            (expr, _) if t.is_int() && is_int_literal(expr) => self.compile_int_literal(expr, t),
+            (Expr::Tuple(exprs), Type::Tuple(types)) if exprs.len() == types.len() => {
+                for (expr, t) in exprs.iter().zip(types.iter()) {
+                    self.compile_expect(expr, t);
+                }
+                self.emit(Instr::MakeTuple(exprs.len()));
+            }
+            (expr, _) => {
+                self.compile_expr(expr);
+                self.emit(Instr::Coerce(t.clone()));
+            }
+        }
+    }
+
+    // Compiles a value assigned to the variable in the slot, like eval_assigned.
+    // The type of its literals depends on the value of the variable.
+    fn compile_assigned(&mut self, expr: &Expr, slot: Option<usize>) {
+        match slot {
+            Some(slot) if is_int_literal(expr) => {
+                self.emit(Instr::IntLiteral(slot, expr.clone()));
+            }
+            _ => self.compile_expr(expr),
+        }
+    }
+
+    fn compile_block(&mut self, block: &Block) {
+        if block.content.is_empty() {
+            self.emit(Instr::Fail(EvalErr::NotFound(
+                "No expressions found.".to_string(),
+            )));
+            return;
+        }
+
+        self.scopes.push(HashMap::new());
+        let last = block.content.len() - 1;
+        for (i, expr) in block.content.iter().enumerate() {
+            self.compile_stmt(expr, i == last);
+        }
+        self.scopes.pop();
+    }
+
+    // Compiles a statement of a block. Only the value of the last statement
+    // is used, so assignments to vector elements do not need to copy the
+    // vector for the other statements.
+    fn compile_stmt(&mut self, expr: &Expr, used: bool) {
+        match expr {
+            Expr::VarOp(var, op, new_val) if !used => {
+                if let Expr::Index(vec_expr, index) = &**var {
+                    self.compile_elem_op(vec_expr, index, *op, new_val, false);
+                    return;
+                }
+                self.compile_expr(expr);
+                self.emit(Instr::Pop);
+            }
+            _ if !used => {
+                self.compile_expr(expr);
+                self.emit(Instr::Pop);
+            }
+            _ => self.compile_expr(expr),
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Num(_) => self.compile_int_literal(expr, &Type::Int32),
+            Expr::Float(f) => self.emit_const(Value::Float(*f)),
+            Expr::Bool(b) => self.emit_const(Value::Bool(*b)),
+            Expr::Char(c) => self.emit_const(Value::Char(*c)),
+            Expr::Str(s) => self.emit_const(Value::Str(s.clone())),
+            Expr::VecNew => self.emit_const(Value::Vec(vec![], Type::Int32)),
+            Expr::Var(name) => match self.lookup(name) {
+                Some(slot) => {
+                    self.emit(Instr::Load(slot));
+                }
+                None => {
+                    self.emit(Instr::Fail(err_no_var()));
+                }
+            },
+            Expr::Tuple(exprs) => {
+                for expr in exprs {
+                    self.compile_expr(expr);
+                }
+                self.emit(Instr::MakeTuple(exprs.len()));
+            }
+            Expr::TupleIndex(expr, index) => {
+                self.compile_expr(expr);
+                self.emit(Instr::TupleIndex(*index));
+            }
+            Expr::Cast(expr, target) => {
+                self.compile_expr(expr);
+                self.emit(Instr::Cast(target.clone()));
+            }
+            // A literal on one side takes the type of the other side, like
+            // in eval_bin_expr.
+            Expr::BinOp(l, op, r) => match (&**l, &**r) {
+                (Expr::Num(_), Expr::Num(_)) => {
+                    self.compile_expr(l);
+                    self.compile_expr(r);
+                    self.emit(Instr::BinOp(*op));
+                }
+                (Expr::Num(_), r) => {
+                    self.compile_expr(r);
+                    self.emit(Instr::LiteralLeft(*l.clone(), *op));
+                }
+                (l, r) if is_int_literal(r) => {
+                    self.compile_expr(l);
+                    self.emit(Instr::LiteralRight(*op, r.clone()));
+                }
+                (l, r) => {
+                    self.compile_expr(l);
+                    self.compile_expr(r);
+                    self.emit(Instr::BinOp(*op));
+                }
+            },
+            Expr::Index(vec_expr, index) => match &**vec_expr {
+                Expr::Var(name) if self.lookup(name).is_some() => {
+                    let slot = self.lookup(name).unwrap();
+                    self.compile_expr(index);
+                    self.emit(Instr::ToIndex);
+                    self.emit(Instr::IndexVar(slot));
+                }
+                _ => {
+                    self.compile_expr(vec_expr);
+                    self.compile_expr(index);
+                    self.emit(Instr::ToIndex);
+                    self.emit(Instr::Index);
+                }
+            },
+            Expr::MethodCall(vec_expr, name, args) => {
+                self.compile_method_call(vec_expr, name, args)
+            }
+            Expr::Let(pattern, var_type, expr) => {
+                self.compile_expect(expr, var_type);
+                self.emit(Instr::Dup);
+                self.compile_pattern(pattern);
+            }
+            Expr::VarOp(var, op, new_val) => match &**var {
+                Expr::Index(vec_expr, index) => {
+                    self.compile_elem_op(vec_expr, index, *op, new_val, true)
+                }
+                var => {
+                    let key = String::from(var.clone());
+                    self.compile_assigned(new_val, self.lookup(&key));
+                    match (self.lookup(&key), op) {
+                        (Some(slot), Op::VarOp(VarToken::Assign)) => self.emit(Instr::Assign(slot)),
+                        (Some(slot), op) => self.emit(Instr::AssignOp(slot, *op)),
+                        (None, _) => self.emit(Instr::Fail(err_no_var())),
+                    };
+                }
+            },
+            Expr::If(cond, block) => {
+                self.compile_expr(cond);
+                let jump_else = self.emit(Instr::JumpIfFalse(0));
+                self.compile_block(block);
+                let jump_end = self.emit(Instr::Jump(0));
+                self.patch_jump(jump_else, self.code.len());
+                self.emit_const(Value::Bool(false));
+                self.patch_jump(jump_end, self.code.len());
+            }
+            // The value of the body is dropped after every iteration. A loop
+            // that ends is ´false´ like its condition.
+            Expr::While(cond, block) => {
+                let top = self.code.len();
+                self.compile_expr(cond);
+                let jump_exit = self.emit(Instr::JumpIfFalse(0));
+                self.compile_block(block);
+                self.emit(Instr::Pop);
+                self.emit(Instr::Jump(top));
+                self.patch_jump(jump_exit, self.code.len());
+                self.emit_const(Value::Bool(false));
+            }
+            Expr::FuncCall(fn_call) if fn_call.is_builtin() => self.compile_print(fn_call),
+            Expr::FuncCall(fn_call) => self.compile_fn_call(fn_call),
+            // Literals take the return type, like in the interpreter.
+            Expr::Return(expr) if self.return_type.is_int() && is_int_literal(expr) => {
+                let return_type = self.return_type.clone();
+                self.compile_int_literal(expr, &return_type);
+                self.emit(Instr::Return);
+            }
+            Expr::Return(expr) => {
+                self.compile_expr(expr);
+                self.emit(Instr::Return);
+            }
+            _ => {
+                self.emit(Instr::Fail(EvalErr::NotImplemented));
+            }
+        }
+    }
+
+    // Binds the value on top of the stack to the variables of the pattern.
+    // The variables are declared after the value is compiled, so that
+    // ´let a: i32 = a + 1;´ uses the earlier ´a´.
+    fn compile_pattern(&mut self, pattern: &Expr) {
+        match pattern {
+            Expr::Var(name) => {
+                let slot = self.declare(name);
+                self.emit(Instr::Store(slot));
+            }
+            Expr::Tuple(patterns) => {
+                self.emit(Instr::Destructure(patterns.len()));
+                for pattern in patterns {
+                    self.compile_pattern(pattern);
+                }
+            }
+            _ => {
+                self.emit(Instr::Fail(EvalErr::TypeMismatch(
+                    "Cannot destructure value. Pattern does not match.".to_string(),
+                )));
+            }
+        }
+    }
+
+    // Compiles assignments to vector elements such as ´v[i] += 1´.
+    fn compile_elem_op(
+        &mut self,
+        vec_expr: &Expr,
+        index: &Expr,
+        op: Op,
+        new_val: &Expr,
+        used: bool,
+    ) {
+        if !is_var(vec_expr) {
+            self.emit(Instr::Fail(err_not_in_var()));
+            return;
+        }
+
+        self.compile_expr(index);
+        self.emit(Instr::ToIndex);
+        self.compile_assigned(new_val, self.vec_slot(vec_expr).ok());
+        match self.vec_slot(vec_expr) {
+            Ok(slot) => self.emit(Instr::ElemOp(slot, op, used)),
+            Err(e) => self.emit(Instr::Fail(e)),
+        };
+    }
+
+    // Compiles the vector methods ´push´, ´pop´ and ´len´. Only the last
+    // argument of ´push´ is pushed, like in the interpreter.
+    fn compile_method_call(&mut self, vec_expr: &Expr, name: &str, args: &[Expr]) {
+        if name == "len" {
+            match vec_expr {
+                Expr::Var(var) if self.lookup(var).is_some() => {
+                    let slot = self.lookup(var).unwrap();
+                    self.emit(Instr::LenVar(slot));
+                }
+                _ => {
+                    self.compile_expr(vec_expr);
+                    self.emit(Instr::Len);
+                }
+            }
+            return;
+        }
+
+        if !is_var(vec_expr) {
+            self.emit(Instr::Fail(err_not_in_var()));
+            return;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            self.compile_assigned(arg, self.vec_slot(vec_expr).ok());
+            if i + 1 < args.len() || name != "push" {
+                self.emit(Instr::Pop);
+            }
+        }
+
+        let slot = match self.vec_slot(vec_expr) {
+            Ok(slot) => slot,
+            Err(e) => {
+                self.emit(Instr::Fail(e));
+                return;
+            }
+        };
+        match (name, args.len()) {
+            ("push", n) if n > 0 => self.emit(Instr::VecPush(slot)),
+            ("pop", 0) => self.emit(Instr::VecPop(slot)),
+            _ => self.emit(Instr::UnknownMethod(slot, name.to_string())),
+        };
+    }
+
+    // Compiles ´print´ and ´println´. The format string is split when
+    // compiling and each argument is formatted right after it is evaluated.
+    fn compile_print(&mut self, fn_call: &FunctionCall) {
+        let mut args = fn_call.args.content.iter();
+        let fmt = match args.next() {
+            Some(Expr::Str(fmt)) => fmt.clone(),
+            None => String::new(),
+            _ => {
+                self.emit(Instr::Fail(EvalErr::WrongType(
+                    "Format argument must be a string literal.".to_string(),
+                )));
+                return;
+            }
+        };
+
+        let pieces: Vec<String> = fmt.split("{}").map(String::from).collect();
+        for _ in 1..pieces.len() {
+            match args.next() {
+                Some(arg) => {
+                    self.compile_expr(arg);
+                    self.emit(Instr::Format);
+                }
+                None => {
+                    self.emit(Instr::Fail(EvalErr::NotFound(
+                        "No argument found for placeholder.".to_string(),
+                    )));
+                    return;
+                }
+            }
+        }
+        self.emit(Instr::Print(pieces, fn_call.name == "println"));
+    }
+
+    // Integer literal arguments take the types of the parameters, like in the
+    // interpreter.
+    fn compile_fn_call(&mut self, fn_call: &FunctionCall) {
+        let index = self
+            .fn_list
+            .iter()
+            .position(|func| func.name == fn_call.name);
+        let param_types: Vec<Type> = match index {
+            Some(index) => self.fn_list[index]
+                .params
+                .iter()
+                .map(|p| p.param_type.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        for (i, arg) in fn_call.args.content.iter().enumerate() {
+            match param_types.get(i) {
+                Some(t) if t.is_int() && is_int_literal(arg) => self.compile_int_literal(arg, t),
+                _ => self.compile_expr(arg),
+            }
+        }
+
+        match index {
+            Some(index) if self.fn_list[index].is_extern => {
+                self.emit(Instr::Fail(EvalErr::NotImplemented));
+            }
+            Some(index) if fn_call.args.content.len() < self.fn_list[index].params.len() => {
+                self.emit(Instr::Fail(EvalErr::TypeMismatch(format!(
+                    "function ´{}´ takes {} argument(s) but {} were supplied",
+                    fn_call.name,
+                    self.fn_list[index].params.len(),
+                    fn_call.args.content.len()
+                ))));
+            }
+            Some(index) => {
+                self.emit(Instr::Call(index, fn_call.args.content.len()));
+            }
+            None => {
+                self.emit(Instr::Fail(EvalErr::NotFound(
+                    "Function not found in tree.".to_string(),
+                )));
+            }
+        }
+    }
+
+    // The parameters take the first slots. The interpreter does not bind
+    // parameters of main, so they are not declared for it.
+    fn compile_function(&mut self, func: &Function) -> FnInfo {
+        let entry = self.code.len();
+        self.num_locals = 0;
+        self.scopes = vec![HashMap::new()];
+        self.return_type = func.return_type.clone();
+        if func.name != "main" {
+            for param in func.params.iter() {
+                self.declare(&param.name);
+            }
+        }
+
+        if func.is_extern {
+            self.emit(Instr::Fail(EvalErr::NotImplemented));
+        } else {
+            self.compile_block(&func.block);
+            self.emit(Instr::End);
+        }
+
+        FnInfo {
+            name: func.name.clone(),
+            entry,
+            param_types: func.params.iter().map(|p| p.param_type.clone()).collect(),
+            return_type: func.return_type.clone(),
+            num_locals: self.num_locals,
+        }
+    }
+}
+
+// Compiles every function of the program. Fails like the interpreter if
+// there is no main function.
+pub fn compile_bytecode(fn_list: &Functions) -> EvalRes<Program> {
+    let main = match fn_list.iter().position(|func| func.name == "main") {
+        Some(main) => main,
+        None => return Err(EvalErr::NotFound("Function not found in tree.".to_string())),
+    };
+
+    let mut compiler = BytecodeCompiler {
+        fn_list,
+        code: Vec::new(),
+        constants: Vec::new(),
+        scopes: Vec::new(),
+        num_locals: 0,
+        return_type: Type::Void,
+    };
+    let functions = fn_list
+        .iter()
+        .map(|func| compiler.compile_function(func))
+        .collect();
+
+    Ok(Program {
+        code: compiler.code,
+        constants: compiler.constants,
+        functions,
+        main,
+    })
+}
@@ -0,0 +1,617 @@
+use crate::ast::*;
+use crate::backend::*;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+// Translates programs to C99 source that can be compiled by any C compiler.
+// Arithmetic is checked by the functions of the prelude, which stop the
+// program with exit code 101 like a panicking Rust program. Expressions are
+// translated to C expressions without side effects. Calls and checked
+// operations are stored in temporaries first, so that they happen in the same
+// order as in the interpreter. Variables are prefixed with ´v_´ and functions
+// with ´f_´, so that they do not clash with C keywords and the C library.
+
+const PRELUDE: &str = r#"#include <inttypes.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static void rt_panic(const char *msg) {
+    fflush(stdout);
+    fprintf(stderr, "panicked at '%s'\n", msg);
+    exit(101);
+}
+
+#define RT_INT_OPS(T, N, MIN, MAX, SIGNED, FMT) \
+static inline T rt_add_##N(T a, T b) { \
+    if ((b > 0 && a > MAX - b) || (SIGNED && b < 0 && a < MIN - b)) \
+        rt_panic("attempt to add with overflow"); \
+    return (T)(a + b); \
+} \
+static inline T rt_sub_##N(T a, T b) { \
+    if ((SIGNED && b < 0 && a > MAX + b) || (b > 0 && a < MIN + b)) \
+        rt_panic("attempt to subtract with overflow"); \
+    return (T)(a - b); \
+} \
+static inline T rt_mul_##N(T a, T b) { \
+    if (a > 0 ? (b > 0 ? a > MAX / b : (SIGNED && b < MIN / a)) \
+              : (b > 0 ? (SIGNED && a < MIN / b) : (SIGNED && a != 0 && b < MAX / a))) \
+        rt_panic("attempt to multiply with overflow"); \
+    return (T)(a * b); \
+} \
+static inline T rt_div_##N(T a, T b) { \
+    if (b == 0) \
+        rt_panic("attempt to divide by zero"); \
+    if (SIGNED && a == MIN && b == (T)-1) \
+        rt_panic("attempt to divide with overflow"); \
+    return (T)(a / b); \
+} \
+static inline T rt_f64_to_##N(double f) { \
+    if (f != f) \
+        return 0; \
+    if (f <= (double)MIN) \
+        return MIN; \
+    if (f >= (double)MAX) \
+        return MAX; \
+    return (T)f; \
+} \
+static inline void rt_print_##N(T a) { \
+    printf("%" FMT, a); \
+}
+
+RT_INT_OPS(int8_t, i8, INT8_MIN, INT8_MAX, 1, PRId8)
+RT_INT_OPS(int16_t, i16, INT16_MIN, INT16_MAX, 1, PRId16)
+RT_INT_OPS(int32_t, i32, INT32_MIN, INT32_MAX, 1, PRId32)
+RT_INT_OPS(int64_t, i64, INT64_MIN, INT64_MAX, 1, PRId64)
+RT_INT_OPS(uint8_t, u8, 0, UINT8_MAX, 0, PRIu8)
+RT_INT_OPS(uint16_t, u16, 0, UINT16_MAX, 0, PRIu16)
+RT_INT_OPS(uint32_t, u32, 0, UINT32_MAX, 0, PRIu32)
+RT_INT_OPS(uint64_t, u64, 0, UINT64_MAX, 0, PRIu64)
+
+static inline void rt_print_bool(bool b) {
+    fputs(b ? "true" : "false", stdout);
+}
+
+static inline void rt_print_char(uint32_t c) {
+    char buf[4];
+    int len;
+    if (c < 0x80) {
+        buf[0] = (char)c;
+        len = 1;
+    } else if (c < 0x800) {
+        buf[0] = (char)(0xc0 | (c >> 6));
+        buf[1] = (char)(0x80 | (c & 0x3f));
+        len = 2;
+    } else if (c < 0x10000) {
+        buf[0] = (char)(0xe0 | (c >> 12));
+        buf[1] = (char)(0x80 | ((c >> 6) & 0x3f));
+        buf[2] = (char)(0x80 | (c & 0x3f));
+        len = 3;
+    } else {
+        buf[0] = (char)(0xf0 | (c >> 18));
+        buf[1] = (char)(0x80 | ((c >> 12) & 0x3f));
+        buf[2] = (char)(0x80 | ((c >> 6) & 0x3f));
+        buf[3] = (char)(0x80 | (c & 0x3f));
+        len = 4;
+    }
+    fwrite(buf, 1, (size_t)len, stdout);
+}
+"#;
+
+fn c_type(t: &Type) -> BackendRes<&'static str> {
+    Ok(match t {
+        Type::Int8 => "int8_t",
+        Type::Int16 => "int16_t",
+        Type::Int32 => "int32_t",
+        Type::Int64 => "int64_t",
+        Type::UInt8 => "uint8_t",
+        Type::UInt16 => "uint16_t",
+        Type::UInt32 | Type::Char => "uint32_t",
+        Type::UInt64 | Type::USize => "uint64_t",
+        Type::Float64 => "double",
+        Type::Bool => "bool",
+        Type::Str => "const char *",
+        Type::Void => "void",
+        t => return err_unsupported(&format!("values of type ´{}´", String::from(t.clone()))),
+    })
+}
+
+// The suffix of the prelude functions for the integer type, e.g. ´rt_add_u8´.
+fn int_suffix(t: &Type) -> BackendRes<&'static str> {
+    Ok(match t {
+        Type::Int8 => "i8",
+        Type::Int16 => "i16",
+        Type::Int32 => "i32",
+        Type::Int64 => "i64",
+        Type::UInt8 => "u8",
+        Type::UInt16 => "u16",
+        Type::UInt32 | Type::Char => "u32",
+        Type::UInt64 | Type::USize => "u64",
+        t => {
+            return err_unsupported(&format!(
+                "arithmetic on values of type ´{}´",
+                String::from(t.clone())
+            ))
+        }
+    })
+}
+
+// Escapes the string for a C string literal. Bytes outside of printable
+// ASCII are written as octal escapes.
+fn c_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn int_literal(num: i128, t: &Type) -> BackendRes<String> {
+    if *t == Type::Float64 {
+        return Ok(format!("{}.0", num));
+    }
+    // The smallest value of a type can not be written as a negated literal, and
+    // literals above the range of long long need an unsigned suffix.
+    let literal = if t.int_range().map(|(min, _)| min) == Some(num) && num < 0 {
+        format!("({} - 1)", num + 1)
+    } else if num > i64::MAX as i128 {
+        format!("{}ULL", num)
+    } else {
+        num.to_string()
+    };
+    Ok(match t {
+        Type::Int32 => literal,
+        t => format!("(({}){})", c_type(t)?, literal),
+    })
+}
+
+fn rel_op(token: RelToken) -> &'static str {
+    match token {
+        RelToken::Equal => "==",
+        RelToken::Neq => "!=",
+        RelToken::Ge => ">",
+        RelToken::Le => "<",
+    }
+}
+
+struct CGen {
+    // Lines of the function being translated with their indentation
+    lines: Vec<(usize, String)>,
+    indent: usize,
+    temps: usize,
+    // C names of the variables in the blocks around the statement
+    scopes: Vec<HashMap<String, String>>,
+    // Number of variables declared with each name in the function
+    declared: HashMap<String, usize>,
+    externs: HashSet<String>,
+    return_type: Type,
+}
+
+impl CGen {
+    fn emit(&mut self, line: String) {
+        self.lines.push((self.indent, line));
+    }
+
+    fn fn_name(&self, name: &str) -> String {
+        if self.externs.contains(name) {
+            name.to_string()
+        } else {
+            format!("f_{}", name)
+        }
+    }
+
+    // Variables that shadow another variable of the function get a number.
+    fn declare(&mut self, name: &str) -> String {
+        let count = self.declared.entry(name.to_string()).or_insert(0);
+        let c_name = match *count {
+            0 => format!("v_{}", name),
+            n => format!("v_{}_{}", name, n),
+        };
+        *count += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), c_name.clone());
+        c_name
+    }
+
+    fn lookup(&self, name: &str) -> BackendRes<String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| BackendError::UnknownVariable(name.to_string()))
+    }
+
+    // Stores the value in a new temporary and returns its name.
+    fn temp(&mut self, t: &Type, val: String) -> BackendRes<String> {
+        let name = format!("t_{}", self.temps);
+        self.temps += 1;
+        let line = format!("{} {} = {};", c_type(t)?, name, val);
+        self.emit(line);
+        Ok(name)
+    }
+
+    // Translates the expression and returns a C expression for its value.
+    // Statements that need to run first are emitted before.
+    fn gen_expr(&mut self, expr: &TypedExpr) -> BackendRes<String> {
+        check_value(expr)?;
+        match &expr.kind {
+            TypedExprKind::Num(num) => int_literal(*num, &expr.expr_type),
+            TypedExprKind::Float(f) => Ok(format!("{:?}", f)),
+            TypedExprKind::Bool(b) => Ok(b.to_string()),
+            TypedExprKind::Char(c) => Ok(format!("UINT32_C({})", *c as u32)),
+            TypedExprKind::Str(s) => Ok(c_string(s)),
+            TypedExprKind::Var(name) => self.lookup(name),
+            TypedExprKind::BinOp(l, op, r) => {
+                let operand_type = l.expr_type.clone();
+                let l = self.gen_expr(l)?;
+                let r = self.gen_expr(r)?;
+                match op {
+                    Op::MathOp(token) if operand_type == Type::Float64 => {
+                        Ok(format!("({} {} {})", l, token, r))
+                    }
+                    Op::MathOp(token) => {
+                        let func = match token {
+                            MathToken::Plus => "add",
+                            MathToken::Minus => "sub",
+                            MathToken::Multiply => "mul",
+                            MathToken::Division => "div",
+                        };
+                        let call =
+                            format!("rt_{}_{}({}, {})", func, int_suffix(&operand_type)?, l, r);
+                        self.temp(&operand_type, call)
+                    }
+                    Op::RelOp(token) => Ok(format!("({} {} {})", l, rel_op(*token), r)),
+                    // ´&´ and ´|´ do not short-circuit.
+                    Op::BoolOp(BoolToken::And) => Ok(format!("({} & {})", l, r)),
+                    Op::BoolOp(BoolToken::Or) => Ok(format!("({} | {})", l, r)),
+                    op => err_unsupported(&format!("´{}´ as a binary operator", op)),
+                }
+            }
+            TypedExprKind::Cast(inner) => {
+                let src_type = inner.expr_type.clone();
+                let val = self.gen_expr(inner)?;
+                self.gen_cast(val, &src_type, &expr.expr_type)
+            }
+            TypedExprKind::FuncCall(name, _) if name == "print" || name == "println" => {
+                err_unsupported("´print´ as a value")
+            }
+            TypedExprKind::FuncCall(name, args) => {
+                let call = self.gen_call(name, args)?;
+                self.temp(&expr.expr_type, call)
+            }
+            _ => err_statement_value(),
+        }
+    }
+
+    // Casts follow ´as´ in Rust. Floats are saturated to the range of the
+    // integer type by the prelude.
+    fn gen_cast(&mut self, val: String, src_type: &Type, target: &Type) -> BackendRes<String> {
+        Ok(match (src_type, target) {
+            (Type::Float64, Type::Float64) => val,
+            (Type::Float64, t) => format!("rt_f64_to_{}({})", int_suffix(t)?, val),
+            (_, t) => format!("(({}){})", c_type(t)?, val),
+        })
+    }
+
+    fn gen_call(&mut self, name: &str, args: &[TypedExpr]) -> BackendRes<String> {
+        let mut arg_vals: Vec<String> = Vec::new();
+        for arg in args {
+            arg_vals.push(self.gen_expr(arg)?);
+        }
+        Ok(format!("{}({})", self.fn_name(name), arg_vals.join(", ")))
+    }
+
+    // Prints the pieces of the format string and the arguments. The
+    // arguments are evaluated before anything is printed.
+    fn gen_print(&mut self, name: &str, args: &[TypedExpr]) -> BackendRes<()> {
+        let mut args = args.iter();
+        let fmt = match args.next().map(|arg| &arg.kind) {
+            Some(TypedExprKind::Str(fmt)) => fmt.clone(),
+            None => String::new(),
+            _ => return err_unsupported("a format string that is not a literal"),
+        };
+
+        let mut pieces = fmt.split("{}");
+        let mut prints: Vec<String> = Vec::new();
+        let first = pieces.next().unwrap_or("");
+        if !first.is_empty() {
+            prints.push(format!("fputs({}, stdout);", c_string(first)));
+        }
+        for (piece, arg) in pieces.zip(args) {
+            let val = self.gen_expr(arg)?;
+            prints.push(match &arg.expr_type {
+                Type::Str => format!("fputs({}, stdout);", val),
+                Type::Bool => format!("rt_print_bool({});", val),
+                Type::Char => format!("rt_print_char({});", val),
+                t if t.is_int() => format!("rt_print_{}({});", int_suffix(t)?, val),
+                t => {
+                    return err_unsupported(&format!(
+                        "printing values of type ´{}´",
+                        String::from(t.clone())
+                    ))
+                }
+            });
+            if !piece.is_empty() {
+                prints.push(format!("fputs({}, stdout);", c_string(piece)));
+            }
+        }
+        if name == "println" {
+            prints.push("putchar('\\n');".to_string());
+        }
+
+        for print in prints {
+            self.emit(print);
+        }
+        Ok(())
+    }
+
+    fn gen_block(&mut self, block: &[TypedExpr]) -> BackendRes<()> {
+        self.scopes.push(HashMap::new());
+        self.indent += 1;
+        for stmt in block {
+            self.gen_stmt(stmt)?;
+        }
+        self.indent -= 1;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn gen_stmt(&mut self, stmt: &TypedExpr) -> BackendRes<()> {
+        match &stmt.kind {
+            TypedExprKind::Let(pattern, val) => {
+                let name = match &pattern.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("tuple patterns"),
+                };
+                let val = self.gen_expr(val)?;
+                let c_name = self.declare(name);
+                let line = format!("{} {} = {};", c_type(&pattern.expr_type)?, c_name, val);
+                self.emit(line);
+            }
+            TypedExprKind::VarOp(var, op, val) => {
+                let name = match &var.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("assignments to vector elements"),
+                };
+                let val = self.gen_expr(val)?;
+                let c_name = self.lookup(name)?;
+                let new_val = match op {
+                    Op::VarOp(VarToken::Assign) => val,
+                    Op::VarOp(token) if var.expr_type == Type::Float64 => {
+                        format!("{} {} {}", c_name, &token.to_string()[..1], val)
+                    }
+                    Op::VarOp(token) => {
+                        let func = match token {
+                            VarToken::PlusEq => "add",
+                            VarToken::MinEq => "sub",
+                            _ => "mul",
+                        };
+                        format!(
+                            "rt_{}_{}({}, {})",
+                            func,
+                            int_suffix(&var.expr_type)?,
+                            c_name,
+                            val
+                        )
+                    }
+                    op => return err_unsupported(&format!("´{}´ as an assignment", op)),
+                };
+                self.emit(format!("{} = {};", c_name, new_val));
+            }
+            TypedExprKind::If(cond, block) => {
+                let cond = self.gen_expr(cond)?;
+                self.emit(format!("if ({}) {{", cond));
+                self.gen_block(block)?;
+                self.emit("}".to_string());
+            }
+            // Conditions that need statements of their own are checked at
+            // the start of the body.
+            TypedExprKind::While(cond, block) => {
+                let start = self.lines.len();
+                self.indent += 1;
+                let cond_val = self.gen_expr(cond)?;
+                self.indent -= 1;
+                if self.lines.len() == start {
+                    self.emit(format!("while ({}) {{", cond_val));
+                } else {
+                    self.lines
+                        .insert(start, (self.indent, "while (1) {".to_string()));
+                    self.indent += 1;
+                    self.emit(format!("if (!{}) break;", cond_val));
+                    self.indent -= 1;
+                }
+                self.gen_block(block)?;
+                self.emit("}".to_string());
+            }
+            TypedExprKind::FuncCall(name, args) if name == "print" || name == "println" => {
+                self.gen_print(name, args)?
+            }
+            TypedExprKind::FuncCall(name, args) => {
+                let call = self.gen_call(name, args)?;
+                self.emit(format!("{};", call));
+            }
+            TypedExprKind::Return(val) if self.return_type == Type::Void => {
+                self.gen_expr(val)?;
+                self.emit("return;".to_string());
+            }
+            TypedExprKind::Return(val) => {
+                let val = self.gen_expr(val)?;
+                self.emit(format!("return {};", val));
+            }
+            _ => {
+                let val = self.gen_expr(stmt)?;
+                self.emit(format!("(void){};", val));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn signature(func: &TypedFunction, name: &str) -> BackendRes<String> {
+    let mut params: Vec<String> = Vec::new();
+    for param in func.params.iter() {
+        params.push(format!("{} v_{}", c_type(&param.param_type)?, param.name));
+    }
+    if params.is_empty() {
+        params.push("void".to_string());
+    }
+    Ok(format!(
+        "{} {}({})",
+        c_type(&func.return_type)?,
+        name,
+        params.join(", ")
+    ))
+}
+
+// Translates the program to C99 source. ´main´ is translated to ´f_main´,
+// which is called by a C ´main´ returning its value as the exit code.
+pub fn c_program(fn_list: Functions) -> BackendRes<String> {
+    let typed_fns = check_program(fn_list)?;
+    let externs: HashSet<String> = typed_fns
+        .iter()
+        .filter(|func| func.is_extern)
+        .map(|func| func.name.clone())
+        .collect();
+
+    let mut source = String::from(PRELUDE);
+    source.push('\n');
+    for func in typed_fns.iter() {
+        if func.is_extern {
+            source.push_str(&format!("extern {};\n", signature(func, &func.name)?));
+        } else {
+            let name = format!("f_{}", func.name);
+            source.push_str(&format!("static {};\n", signature(func, &name)?));
+        }
+    }
+
+    let mut main_type = Type::Void;
+    for func in typed_fns.iter().filter(|func| !func.is_extern) {
+        if func.name == "main" {
+            main_type = func.return_type.clone();
+        }
+
+        let mut gen = CGen {
+            lines: Vec::new(),
+            indent: 0,
+            temps: 0,
+            scopes: vec![HashMap::new()],
+            declared: HashMap::new(),
+            externs: externs.clone(),
+            return_type: func.return_type.clone(),
+        };
+        for param in func.params.iter() {
+            gen.declare(&param.name);
+        }
+        gen.gen_block(&func.block)?;
+        // Reached when a function with a return value does not return
+        if func.return_type != Type::Void {
+            gen.indent = 1;
+            let msg = format!("function {} did not return a value", func.name);
+            gen.emit(format!("rt_panic({});", c_string(&msg)));
+            gen.emit("return 0;".to_string());
+        }
+
+        let name = format!("f_{}", func.name);
+        source.push_str(&format!("\nstatic {} {{\n", signature(func, &name)?));
+        for (indent, line) in gen.lines {
+            source.push_str(&"    ".repeat(indent));
+            source.push_str(&line);
+            source.push('\n');
+        }
+        source.push_str("}\n");
+    }
+
+    source.push_str("\nint main(void) {\n");
+    match main_type {
+        Type::Int32 => source.push_str("    return f_main();\n"),
+        Type::Void => source.push_str("    f_main();\n    return 0;\n"),
+        _ => return err_unsupported("a ´main´ function that does not return ´i32´ or ´()´"),
+    }
+    source.push_str("}\n");
+    Ok(source)
+}
+
+// Translates the program and compiles it to an executable with the system
+// ´cc´. The C source is written next to the executable with the extension
+// .c.
+pub fn build_c_program(fn_list: Functions, path: &Path) -> BackendRes<()> {
+    let source = c_program(fn_list)?;
+    let source_path = path.with_extension("c");
+    std::fs::write(&source_path, source)
+        .map_err(|e| BackendError::Io(format!("{}: {}", source_path.display(), e)))?;
+    run_tool(
+        "cc",
+        &[
+            "-std=c99".as_ref(),
+            source_path.as_os_str(),
+            "-o".as_ref(),
+            path.as_os_str(),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod c99_tests {
+    use super::*;
+    use crate::backend::backend_tests::*;
+    use crate::parser::*;
+
+    fn run_c(program: &str, name: &str) -> (Option<i32>, String) {
+        run_built(build_c_program, program, &format!("c99-{}", name), "c")
+    }
+
+    #[test]
+    fn c99_fibo_program() {
+        let source = c_program(parse_program(FIBO).unwrap().1).unwrap();
+        assert!(source.contains("static int32_t f_fibo(int32_t v_i) {\n"));
+        assert!(source.contains("int main(void) {\n    return f_main();\n}\n"));
+        assert_eq!(run_c(FIBO, "fibo"), (Some(8), String::new()));
+    }
+
+    #[test]
+    fn c99_scopes_program() {
+        let program = "
+            fn main() -> () {
+                let x: i32 = 1;
+                let i: u8 = 0;
+                while i < (2 + 1) {
+                    let x: i32 = x + 10;
+                    i += 1;
+                    println(\"{} {} {}\", i, x, 'é');
+                };
+                let f: f64 = 300.5;
+                println(\"{} {}\", x, f as u8);
+            }
+        ";
+        assert_eq!(
+            run_c(program, "scopes"),
+            (Some(0), "1 11 é\n2 11 é\n3 11 é\n1 255\n".to_string())
+        );
+    }
+
+    #[test]
+    fn c99_overflow_program() {
+        let program = "
+            fn main() -> i32 {
+                let a: i8 = 100;
+                println(\"{}\", a);
+                a += 100;
+                println(\"{}\", a);
+                return 0;
+            }
+        ";
+        assert_eq!(run_c(program, "overflow"), (Some(101), "100\n".to_string()));
+    }
+}
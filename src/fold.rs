@@ -0,0 +1,501 @@
+use crate::ast::*;
+use crate::interpreter::{eval_cast, eval_int_literal, eval_values, int_value, EvalErr};
+use crate::type_checker::is_int_literal;
+
+use std::collections::HashMap;
+use std::fmt;
+
+// Constant folding and propagation on the AST. Operations on literals are
+// evaluated with the same functions as in the interpreter, so folding does
+// not change the result of a program. Variables declared with a literal
+// value, that are not assigned anywhere in the rest of their block, are
+// replaced by the literal. Integers of other types than i32 are replaced by
+// a cast of the literal to their type, which evaluates to the same value.
+//
+// ´if´ and ´while´ statements with a constant condition are simplified. The
+// body of ´if true´ replaces the statement unless it declares variables,
+// which would then be visible after it. Statements that never run are
+// removed, or replaced by ´false´ at the end of a block, which is the value
+// of an ´if´ whose condition is false.
+//
+// Operations that always fail, such as division by zero, are left in place
+// and reported as diagnostics. Folding can hide type errors, e.g. an i32
+// variable replaced by a literal takes on the type of the other operand, so
+// programs should be type checked before they are folded.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub function: String,
+    // Position of the statement, if the program was parsed
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(
+                f,
+                "{}:{}: in function ´{}´: {}",
+                span.line, span.column, self.function, self.message
+            ),
+            None => write!(f, "in function ´{}´: {}", self.function, self.message),
+        }
+    }
+}
+
+fn literal(expr: &Expr) -> Option<Value> {
+    match expr {
+        // Literals outside of the range of i32 only have a value in their
+        // declared type, so they are left as they are.
+        Expr::Num(num) => int_value(*num, &Type::Int32).ok(),
+        Expr::Float(f) => Some(Value::Float(*f)),
+        Expr::Bool(b) => Some(Value::Bool(*b)),
+        Expr::Char(c) => Some(Value::Char(*c)),
+        _ => None,
+    }
+}
+
+fn from_value(val: Value) -> Option<Expr> {
+    match val {
+        Value::Num(num) => Some(Expr::Num(num as i128)),
+        Value::Float(f) => Some(Expr::Float(f)),
+        Value::Bool(b) => Some(Expr::Bool(b)),
+        Value::Char(c) => Some(Expr::Char(c)),
+        _ => None,
+    }
+}
+
+// Returns true if the variable is the target of an assignment in the
+// statements, in any nested block. Shadowing is not taken into account.
+fn is_assigned(name: &str, stmts: &[Expr]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Expr::VarOp(var, _, _) => **var == Expr::Var(name.to_string()),
+        Expr::If(_, block) | Expr::IfElse(_, block) | Expr::While(_, block) => {
+            is_assigned(name, &block.content)
+        }
+        _ => false,
+    })
+}
+
+// The names of the variables declared by a pattern.
+pub(crate) fn pattern_names(pattern: &Expr, names: &mut Vec<String>) {
+    match pattern {
+        Expr::Var(name) => names.push(name.clone()),
+        Expr::Tuple(patterns) => {
+            for pattern in patterns {
+                pattern_names(pattern, names);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn declares_variables(block: &Block) -> bool {
+    block
+        .content
+        .iter()
+        .any(|stmt| matches!(stmt, Expr::Let(..)))
+}
+
+struct Folder {
+    function: String,
+    span: Option<Span>,
+    // The literal of each constant variable, or None for variables that are
+    // not constant, in the blocks around the expression
+    scopes: Vec<HashMap<String, Option<Expr>>>,
+    // Return type of the function, which returned literals take
+    return_type: Type,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Folder {
+    fn report(&mut self, err: EvalErr) {
+        let message = match err {
+            EvalErr::DivisionByZero => "attempt to divide by zero".to_string(),
+            EvalErr::Overflow(msg) => msg,
+            // Other errors are type errors, which the type checker reports.
+            _ => return,
+        };
+        self.diagnostics.push(Diagnostic {
+            function: self.function.clone(),
+            span: self.span,
+            message,
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Expr> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(constant) = scope.get(name) {
+                return constant.clone();
+            }
+        }
+        None
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Var(name) => self.lookup(&name).unwrap_or(Expr::Var(name)),
+            Expr::BinOp(l, op, r) => {
+                // Arithmetic on only literals takes the type of where it is
+                // used, which can be wider than i32. It is folded as i32 here,
+                // so an overflow is not known to fail, see fold_expect.
+                let is_literal = is_int_literal(&l) && is_int_literal(&r);
+                let l = self.fold_expr(*l);
+                let r = self.fold_expr(*r);
+                if let (Some(l_val), Some(r_val)) = (literal(&l), literal(&r)) {
+                    match eval_values(l_val, op, r_val) {
+                        Ok(val) => {
+                            if let Some(folded) = from_value(val) {
+                                return folded;
+                            }
+                        }
+                        Err(EvalErr::Overflow(_)) if is_literal => (),
+                        Err(e) => self.report(e),
+                    }
+                }
+                Expr::BinOp(Box::new(l), op, Box::new(r))
+            }
+            Expr::Cast(expr, target) => {
+                let expr = self.fold_expr(*expr);
+                // Only casts to i32 and f64 give values that are literals.
+                if target == Type::Int32 || target == Type::Float64 {
+                    if let Some(val) = literal(&expr) {
+                        if let Some(folded) = eval_cast(val, &target).ok().and_then(from_value) {
+                            return folded;
+                        }
+                    }
+                }
+                Expr::Cast(Box::new(expr), target)
+            }
+            Expr::Tuple(exprs) => {
+                Expr::Tuple(exprs.into_iter().map(|e| self.fold_expr(e)).collect())
+            }
+            Expr::TupleIndex(expr, index) => {
+                Expr::TupleIndex(Box::new(self.fold_expr(*expr)), index)
+            }
+            Expr::Index(expr, index) => Expr::Index(
+                Box::new(self.fold_expr(*expr)),
+                Box::new(self.fold_expr(*index)),
+            ),
+            Expr::MethodCall(expr, name, args) => Expr::MethodCall(
+                expr,
+                name,
+                args.into_iter().map(|e| self.fold_expr(e)).collect(),
+            ),
+            Expr::FuncCall(mut fn_call) => {
+                let args = std::mem::take(&mut fn_call.args.content);
+                fn_call.args.content = args.into_iter().map(|e| self.fold_expr(e)).collect();
+                Expr::FuncCall(fn_call)
+            }
+            expr => expr,
+        }
+    }
+
+    // Folds an expression of a known type. Integer literals and arithmetic on
+    // them are computed in the type, like the interpreter does.
+    fn fold_expect(&mut self, expr: Expr, t: &Type) -> Expr {
+        match (expr, t) {
+            (expr, _) if t.is_int() && is_int_literal(&expr) => match eval_int_literal(&expr, t) {
+                Ok(Value::Num(num)) => Expr::Num(num as i128),
+                Ok(Value::Int(num, _)) => Expr::Num(num),
+                Ok(_) => expr,
+                Err(e) => {
+                    self.report(e);
+                    expr
+                }
+            },
+            (Expr::Tuple(exprs), Type::Tuple(types)) if exprs.len() == types.len() => Expr::Tuple(
+                exprs
+                    .into_iter()
+                    .zip(types.iter())
+                    .map(|(expr, t)| self.fold_expect(expr, t))
+                    .collect(),
+            ),
+            (expr, _) => self.fold_expr(expr),
+        }
+    }
+
+    // Folds the block in a new scope. Statements that never run are removed.
+    fn fold_block(&mut self, block: Block) -> Block {
+        let has_spans = block.stmt_spans.len() == block.content.len();
+        let mut spans = block.stmt_spans.into_iter();
+        let mut stmts = block.content.into_iter().peekable();
+        let mut content: Vec<Expr> = Vec::new();
+        let mut stmt_spans: Vec<Span> = Vec::new();
+
+        self.scopes.push(HashMap::new());
+        while let Some(stmt) = stmts.next() {
+            let span = spans.next();
+            if has_spans {
+                self.span = span;
+            }
+            let rest: Vec<Expr> = stmts.clone().collect();
+            let is_last = rest.is_empty();
+
+            match self.fold_stmt(stmt, &rest) {
+                Folded::Stmt(stmt) => {
+                    content.push(stmt);
+                    stmt_spans.extend(span);
+                }
+                Folded::Inline(block) => {
+                    content.extend(block.content);
+                    stmt_spans.extend(block.stmt_spans);
+                }
+                Folded::Removed if is_last => {
+                    content.push(Expr::Bool(false));
+                    stmt_spans.extend(span);
+                }
+                Folded::Removed => (),
+            }
+        }
+        self.scopes.pop();
+
+        if stmt_spans.len() != content.len() {
+            stmt_spans.clear();
+        }
+        Block {
+            content,
+            span: block.span,
+            stmt_spans,
+        }
+    }
+
+    // Folds a statement. ´rest´ are the statements after it in the block.
+    fn fold_stmt(&mut self, stmt: Expr, rest: &[Expr]) -> Folded {
+        match stmt {
+            Expr::Let(pattern, t, val) => {
+                let val = self.fold_expect(*val, &t);
+                let constant = match (&*pattern, literal(&val)) {
+                    (Expr::Var(name), Some(_)) if !is_assigned(name, rest) => {
+                        if t.is_int() && t != Type::Int32 {
+                            Some(Expr::Cast(Box::new(val.clone()), t.clone()))
+                        } else {
+                            Some(val.clone())
+                        }
+                    }
+                    _ => None,
+                };
+                let mut names: Vec<String> = Vec::new();
+                pattern_names(&pattern, &mut names);
+                for name in names {
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .insert(name, constant.clone());
+                }
+                Folded::Stmt(Expr::Let(pattern, t, Box::new(val)))
+            }
+            Expr::VarOp(var, op, val) => {
+                let var = match *var {
+                    Expr::Index(vec, index) => Expr::Index(vec, Box::new(self.fold_expr(*index))),
+                    var => var,
+                };
+                Folded::Stmt(Expr::VarOp(
+                    Box::new(var),
+                    op,
+                    Box::new(self.fold_expr(*val)),
+                ))
+            }
+            Expr::If(cond, block) => match self.fold_expr(*cond) {
+                Expr::Bool(false) => Folded::Removed,
+                Expr::Bool(true) => {
+                    let block = self.fold_block(block);
+                    if block.content.is_empty() || declares_variables(&block) {
+                        Folded::Stmt(Expr::If(Box::new(Expr::Bool(true)), block))
+                    } else {
+                        Folded::Inline(block)
+                    }
+                }
+                cond => Folded::Stmt(Expr::If(Box::new(cond), self.fold_block(block))),
+            },
+            Expr::While(cond, block) => match self.fold_expr(*cond) {
+                Expr::Bool(false) => Folded::Removed,
+                cond => Folded::Stmt(Expr::While(Box::new(cond), self.fold_block(block))),
+            },
+            Expr::Return(val) => {
+                let return_type = self.return_type.clone();
+                Folded::Stmt(Expr::Return(Box::new(self.fold_expect(*val, &return_type))))
+            }
+            stmt => Folded::Stmt(self.fold_expr(stmt)),
+        }
+    }
+}
+
+enum Folded {
+    Stmt(Expr),
+    // The statements of the body of ´if true´
+    Inline(Block),
+    Removed,
+}
+
+// Folds every function of the program in place and returns the operations
+// that always fail.
+pub fn fold_program(fn_list: &mut Functions) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for func in fn_list.iter_mut() {
+        if func.is_extern {
+            continue;
+        }
+        let mut folder = Folder {
+            function: func.name.clone(),
+            span: None,
+            scopes: Vec::new(),
+            return_type: func.return_type.clone(),
+            diagnostics: Vec::new(),
+        };
+        let block = std::mem::replace(&mut func.block, Block::new(Vec::new()));
+        func.block = folder.fold_block(block);
+        diagnostics.append(&mut folder.diagnostics);
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod fold_tests {
+    use super::*;
+    use crate::parser::*;
+
+    fn fold(program: &str) -> (Functions, Vec<Diagnostic>) {
+        let mut fn_list = parse_program(program).unwrap().1;
+        let diagnostics = fold_program(&mut fn_list);
+        (fn_list, diagnostics)
+    }
+
+    #[test]
+    fn fold_constants() {
+        let program = "
+        fn main() -> i32 {
+            let a: i32 = (1 + 3);
+            let b: u8 = 2;
+            let c: i32 = 0;
+            c += 1;
+            if (a == 4) {
+                println(\"{}\", (a * 2));
+            };
+            return ((b + b) as i32 + c);
+        }
+        ";
+        let (fn_list, diagnostics) = fold(program);
+        assert_eq!(diagnostics, vec![]);
+        let content = &fn_list[0].block.content;
+        assert_eq!(
+            content[0],
+            Expr::Let(
+                Box::new(Expr::Var("a".to_string())),
+                Type::Int32,
+                Box::new(Expr::Num(4))
+            )
+        );
+        // The body of ´if true´ replaces the statement.
+        match &content[4] {
+            Expr::FuncCall(fn_call) => assert_eq!(fn_call.args.content[1], Expr::Num(8)),
+            stmt => panic!("{:?}", stmt),
+        }
+        let b = Expr::Cast(Box::new(Expr::Num(2)), Type::UInt8);
+        assert_eq!(
+            content[5],
+            Expr::Return(Box::new(Expr::BinOp(
+                Box::new(Expr::Cast(
+                    Box::new(Expr::BinOp(
+                        Box::new(b.clone()),
+                        Op::MathOp(MathToken::Plus),
+                        Box::new(b)
+                    )),
+                    Type::Int32
+                )),
+                Op::MathOp(MathToken::Plus),
+                Box::new(Expr::Var("c".to_string()))
+            )))
+        );
+        assert_eq!(fn_list[0].block.stmt_spans.len(), content.len());
+    }
+
+    #[test]
+    fn fold_dead_statements() {
+        let program = "
+        fn main() -> () {
+            let a: bool = false;
+            while a {
+                println(\"never\");
+            };
+            if (1 > 2) {
+                println(\"never\");
+            };
+            if true {
+                let b: i32 = 1;
+            };
+            if a {
+                println(\"never\");
+            };
+        }
+        ";
+        let (fn_list, _) = fold(program);
+        let content = &fn_list[0].block.content;
+        assert_eq!(content.len(), 3);
+        // ´if true´ stays when its body declares variables.
+        match &content[1] {
+            Expr::If(cond, _) => assert_eq!(**cond, Expr::Bool(true)),
+            stmt => panic!("{:?}", stmt),
+        }
+        // The value of the last ´if´ is still ´false´.
+        assert_eq!(content[2], Expr::Bool(false));
+    }
+
+    #[test]
+    fn fold_diagnostics() {
+        let program = "
+        fn main() -> i32 {
+            let a: i32 = 0;
+            let b: i32 = (2147483647 + 1);
+            return (10 / a);
+        }
+        ";
+        let (fn_list, diagnostics) = fold(program);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].function, "main");
+        assert_eq!(diagnostics[1].message, "attempt to divide by zero");
+        assert_eq!(
+            diagnostics[1].span.map(|span| span.line),
+            Some(5),
+            "{}",
+            diagnostics[1]
+        );
+        // Operations that fail are left for the program to fail at run time.
+        assert_eq!(
+            fn_list[0].block.content[2],
+            Expr::Return(Box::new(Expr::BinOp(
+                Box::new(Expr::Num(10)),
+                Op::MathOp(MathToken::Division),
+                Box::new(Expr::Num(0))
+            )))
+        );
+    }
+
+    #[test]
+    fn fold_typed_literals() {
+        let program = "
+        fn main() -> i64 {
+            let a: i64 = (100000 * 100000);
+            let b: u8 = (200 + 100);
+            a = (100000 * 100000);
+            return (3000000000 * 2);
+        }
+        ";
+        let (fn_list, diagnostics) = fold(program);
+        // Only the literals of ´b´ are known to overflow. The type of the
+        // assigned literals is not known when folding.
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.map(|span| span.line), Some(4));
+        let content = &fn_list[0].block.content;
+        assert_eq!(
+            content[0],
+            Expr::Let(
+                Box::new(Expr::Var("a".to_string())),
+                Type::Int64,
+                Box::new(Expr::Num(10000000000))
+            )
+        );
+        assert_eq!(content[3], Expr::Return(Box::new(Expr::Num(6000000000))));
+    }
+}
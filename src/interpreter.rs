@@ -4,32 +4,98 @@ use crate::ast::{
 };
 use crate::context;
 use crate::context::*;
+use crate::type_checker::is_int_literal;
 use std::collections::HashMap;
+use std::io::{self, Write};
 
 pub type EvalRes<T> = Result<T, EvalErr>;
 pub type FnContext<'a> = context::ContextStack<'a, Value>;
 pub type Context = context::Context<Value>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EvalErr {
+    DivisionByZero,
+    IndexOutOfBounds(String),
+    IoError(String),
     NotFound(String),
     NotImplemented,
+    Overflow(String),
     TypeMismatch(String),
     WrongOp(String),
     WrongType(String),
 }
 
+fn err_overflow<T: std::fmt::Display>(l: T, op: Op, r: T) -> EvalErr {
+    EvalErr::Overflow(format!(
+        "attempt to compute ´{} {} {}´ with overflow",
+        l, op, r
+    ))
+}
+
+// Arithmetic is checked, overflow and division by zero are reported as errors
+// like in a debug build of rustc.
 fn eval_i32_expr(l: i32, op: Op, r: i32) -> EvalRes<Value> {
+    let res = match op {
+        Op::MathOp(MathToken::Division) if r == 0 => return Err(EvalErr::DivisionByZero),
+        Op::MathOp(MathToken::Division) => l.checked_div(r),
+        Op::MathOp(MathToken::Multiply) => l.checked_mul(r),
+        Op::MathOp(MathToken::Plus) => l.checked_add(r),
+        Op::MathOp(MathToken::Minus) => l.checked_sub(r),
+        Op::RelOp(RelToken::Equal) => return Ok(Bool(l == r)),
+        Op::RelOp(RelToken::Ge) => return Ok(Bool(l > r)),
+        Op::RelOp(RelToken::Le) => return Ok(Bool(l < r)),
+        Op::RelOp(RelToken::Neq) => return Ok(Bool(l != r)),
+        _ => return Err(EvalErr::WrongOp(String::from("Not an i32 operator."))),
+    };
+
+    res.map(Num).ok_or_else(|| err_overflow(l, op, r))
+}
+
+// Evaluates operations between integers of the other widths. The values are
+// computed as i128 and the result is checked against the range of the type.
+fn eval_int_expr(l: i128, op: Op, r: i128, t: Type) -> EvalRes<Value> {
+    let res = match op {
+        Op::MathOp(MathToken::Division) if r == 0 => return Err(EvalErr::DivisionByZero),
+        Op::MathOp(MathToken::Division) => l.checked_div(r),
+        Op::MathOp(MathToken::Multiply) => l.checked_mul(r),
+        Op::MathOp(MathToken::Plus) => l.checked_add(r),
+        Op::MathOp(MathToken::Minus) => l.checked_sub(r),
+        Op::RelOp(RelToken::Equal) => return Ok(Bool(l == r)),
+        Op::RelOp(RelToken::Ge) => return Ok(Bool(l > r)),
+        Op::RelOp(RelToken::Le) => return Ok(Bool(l < r)),
+        Op::RelOp(RelToken::Neq) => return Ok(Bool(l != r)),
+        _ => return Err(EvalErr::WrongOp(String::from("Not an integer operator."))),
+    };
+
+    let (min, max) = t.int_range().unwrap();
+    match res {
+        Some(val) if val >= min && val <= max => Ok(Value::Int(val, t)),
+        _ => Err(err_overflow(l, op, r)),
+    }
+}
+
+// Floats follow IEEE 754 semantics. Division by zero results in an infinity or NaN.
+fn eval_f64_expr(l: f64, op: Op, r: f64) -> EvalRes<Value> {
+    match op {
+        Op::MathOp(MathToken::Division) => Ok(Value::Float(l / r)),
+        Op::MathOp(MathToken::Multiply) => Ok(Value::Float(l * r)),
+        Op::MathOp(MathToken::Plus) => Ok(Value::Float(l + r)),
+        Op::MathOp(MathToken::Minus) => Ok(Value::Float(l - r)),
+        Op::RelOp(RelToken::Equal) => Ok(Bool(l == r)),
+        Op::RelOp(RelToken::Ge) => Ok(Bool(l > r)),
+        Op::RelOp(RelToken::Le) => Ok(Bool(l < r)),
+        Op::RelOp(RelToken::Neq) => Ok(Bool(l != r)),
+        _ => Err(EvalErr::WrongOp(String::from("Not an f64 operator."))),
+    }
+}
+
+fn eval_char_expr(l: char, op: Op, r: char) -> EvalRes<Value> {
     match op {
-        Op::MathOp(MathToken::Division) => Ok(Num(l / r)),
-        Op::MathOp(MathToken::Multiply) => Ok(Num(l * r)),
-        Op::MathOp(MathToken::Plus) => Ok(Num(l + r)),
-        Op::MathOp(MathToken::Minus) => Ok(Num(l - r)),
         Op::RelOp(RelToken::Equal) => Ok(Bool(l == r)),
         Op::RelOp(RelToken::Ge) => Ok(Bool(l > r)),
         Op::RelOp(RelToken::Le) => Ok(Bool(l < r)),
         Op::RelOp(RelToken::Neq) => Ok(Bool(l != r)),
-        _ => Err(EvalErr::WrongOp(String::from("Not an i32 operator."))),
+        _ => Err(EvalErr::WrongOp(String::from("Not a char operator."))),
     }
 }
 
@@ -45,54 +111,419 @@ fn eval_bool_expr(l: bool, op: Op, r: bool) -> EvalRes<Value> {
     }
 }
 
-// Evaluates whether an expression is an i32 or bool operation.
+// Evaluates whether an operation is an integer or bool operation. Integer
+// literals are always evaluated to i32 and take on the type of the other
+// operand when combined with integers of another width.
+pub(crate) fn eval_values(l_val: Value, op: Op, r_val: Value) -> EvalRes<Value> {
+    match (l_val, r_val) {
+        (Num(l_val), Num(r_val)) => eval_i32_expr(l_val, op, r_val),
+        (Value::Int(l_val, l_type), Value::Int(r_val, r_type)) if l_type == r_type => {
+            eval_int_expr(l_val, op, r_val, l_type)
+        }
+        (Value::Int(l_val, t), Num(r_val)) => eval_int_expr(l_val, op, r_val as i128, t),
+        (Num(l_val), Value::Int(r_val, t)) => eval_int_expr(l_val as i128, op, r_val, t),
+        (Value::Float(l_val), Value::Float(r_val)) => eval_f64_expr(l_val, op, r_val),
+        (Value::Char(l_val), Value::Char(r_val)) => eval_char_expr(l_val, op, r_val),
+        (Bool(l_val), Bool(r_val)) => eval_bool_expr(l_val, op, r_val),
+        _ => Err(EvalErr::TypeMismatch(String::from(
+            "Can not evaluate an operation between values of different types.",
+        ))),
+    }
+}
+
 fn eval_bin_expr(
     l: Expr,
     op: Op,
     r: Expr,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
-    let l_val = eval_expr(l, fn_tree, fn_context)?;
-    let r_val = eval_expr(r, fn_tree, fn_context)?;
+    // A literal on one side takes the type of the other side, like in the type
+    // checker. Literals have no side effects, so a literal on the left can be
+    // evaluated after the right side.
+    let (l_val, r_val) = match (&l, &r) {
+        (Expr::Num(_), Expr::Num(_)) => (
+            eval_expr(l, fn_tree, fn_context, out)?,
+            eval_expr(r, fn_tree, fn_context, out)?,
+        ),
+        (Expr::Num(_), _) => {
+            let r_val = eval_expr(r, fn_tree, fn_context, out)?;
+            (eval_int_literal(&l, &literal_type(&r_val))?, r_val)
+        }
+        (_, r) if is_int_literal(r) => {
+            let l_val = eval_expr(l, fn_tree, fn_context, out)?;
+            let r_val = eval_int_literal(r, &literal_type(&l_val))?;
+            (l_val, r_val)
+        }
+        _ => (
+            eval_expr(l, fn_tree, fn_context, out)?,
+            eval_expr(r, fn_tree, fn_context, out)?,
+        ),
+    };
 
-    match (l_val, r_val) {
-        (Num(l_val), Num(r_val)) => eval_i32_expr(l_val, op, r_val),
-        (Bool(l_val), Bool(r_val)) => eval_bool_expr(l_val, op, r_val),
-        _ => Err(EvalErr::TypeMismatch(String::from(
-            "Can not evaluate an operation between a bool and an i32.",
+    eval_values(l_val, op, r_val)
+}
+
+// Converts a value with the same semantics as ´as´ in Rust. Integers are
+// truncated to the width of the type and then sign or zero-extended. Floats
+// are rounded towards zero and saturated to the range of the integer type.
+// Only u8 can be cast to char.
+pub(crate) fn eval_cast(val: Value, target: &Type) -> EvalRes<Value> {
+    if *target == Type::Char {
+        return match val {
+            Value::Int(num, Type::UInt8) => Ok(Value::Char(num as u8 as char)),
+            Value::Char(c) => Ok(Value::Char(c)),
+            _ => Err(EvalErr::WrongType(
+                "Only u8 can be cast to char.".to_string(),
+            )),
+        };
+    }
+
+    if *target == Type::Float64 {
+        return match val {
+            Num(num) => Ok(Value::Float(num as f64)),
+            Value::Int(num, _) => Ok(Value::Float(num as f64)),
+            Value::Float(f) => Ok(Value::Float(f)),
+            _ => Err(EvalErr::WrongType(
+                "Cannot cast a non-primitive value.".to_string(),
+            )),
+        };
+    }
+
+    let num: i128 = match val {
+        Num(num) => num as i128,
+        Value::Int(num, _) => num,
+        Value::Float(f) => match target.int_range() {
+            Some(_) if f.is_nan() => 0,
+            // The maximum of 64-bit types is rounded up as a float.
+            Some((min, max)) => ((f.trunc().max(min as f64).min(max as f64)) as i128).min(max),
+            None => 0,
+        },
+        Bool(b) => b as i128,
+        Value::Char(c) => c as i128,
+        _ => {
+            return Err(EvalErr::WrongType(
+                "Cannot cast a non-primitive value.".to_string(),
+            ))
+        }
+    };
+    let width = match target.bit_width() {
+        Some(width) => width,
+        None => {
+            return Err(EvalErr::WrongType(
+                "Cannot cast to a non-integer type.".to_string(),
+            ))
+        }
+    };
+
+    let bits = (num as u128) & ((1 << width) - 1);
+    let res = if target.is_signed() && bits >> (width - 1) == 1 {
+        bits as i128 - (1 << width)
+    } else {
+        bits as i128
+    };
+
+    match target {
+        Type::Int32 => Ok(Num(res as i32)),
+        _ => Ok(Value::Int(res, target.clone())),
+    }
+}
+
+// The value of an integer literal of the integer type. Literals outside of the
+// range of the type overflow.
+pub(crate) fn int_value(num: i128, t: &Type) -> EvalRes<Value> {
+    match t.int_range() {
+        Some((min, max)) if num < min || num > max => Err(EvalErr::Overflow(format!(
+            "literal ´{}´ out of range for ´{}´",
+            num,
+            t.source_name()
+        ))),
+        _ if *t == Type::Int32 => Ok(Num(num as i32)),
+        _ => Ok(Value::Int(num, t.clone())),
+    }
+}
+
+// Evaluates an integer literal, or arithmetic on only integer literals, in the
+// integer type that the type checker gives it where it is used, so that
+// ´let a: i64 = 100000 * 100000;´ does not overflow an i32.
+pub(crate) fn eval_int_literal(expr: &Expr, t: &Type) -> EvalRes<Value> {
+    match expr {
+        Expr::Num(num) => int_value(*num, t),
+        Expr::BinOp(l, op, r) => eval_values(eval_int_literal(l, t)?, *op, eval_int_literal(r, t)?),
+        _ => Err(EvalErr::WrongType("Not an integer literal.".to_string())),
+    }
+}
+
+// The integer type of literals assigned to the value of a variable: the type of
+// an integer or the element type of a vector of integers. Literals are i32
+// otherwise.
+pub(crate) fn literal_type(val: &Value) -> Type {
+    match val {
+        Value::Int(_, t) => t.clone(),
+        Value::Vec(_, t) if t.is_int() => t.clone(),
+        _ => Type::Int32,
+    }
+}
+
+// Gives integer literals, which are evaluated to i32 where their type is not
+// known, their declared type. Literals outside of the range of the type
+// overflow.
+pub(crate) fn coerce_to(val: Value, t: &Type) -> EvalRes<Value> {
+    match (val, t) {
+        (Num(num), _) if t.is_int() => int_value(num as i128, t),
+        (Value::Tuple(values), Type::Tuple(types)) if values.len() == types.len() => {
+            let mut coerced = Vec::new();
+            for (val, t) in values.into_iter().zip(types.iter()) {
+                coerced.push(coerce_to(val, t)?);
+            }
+            Ok(Value::Tuple(coerced))
+        }
+        (Value::Vec(values, _), Type::Vec(elem_type)) => {
+            let mut coerced = Vec::new();
+            for val in values {
+                coerced.push(coerce_to(val, elem_type)?);
+            }
+            Ok(Value::Vec(coerced, *elem_type.clone()))
+        }
+        (val, _) => Ok(val),
+    }
+}
+
+// Converts an index value, which is an integer literal or a usize, to a usize.
+pub(crate) fn to_index(val: Value) -> EvalRes<usize> {
+    match val {
+        Num(num) if num >= 0 => Ok(num as usize),
+        Value::Int(num, Type::USize) => Ok(num as usize),
+        _ => Err(EvalErr::WrongType(format!(
+            "Cannot index with {:?}. Not a usize.",
+            val
         ))),
     }
 }
 
+pub(crate) fn err_out_of_bounds(index: usize, len: usize) -> EvalErr {
+    EvalErr::IndexOutOfBounds(format!(
+        "index out of bounds: the len is {} but the index is {}",
+        len, index
+    ))
+}
+
+// Returns the name of the variable holding the vector of a method call or an
+// indexing. Only vectors stored in variables can be modified.
+fn vec_key(expr: Expr) -> EvalRes<String> {
+    match expr {
+        Expr::Var(key) => Ok(key),
+        _ => Err(EvalErr::WrongType(
+            "Cannot modify a vector that is not stored in a variable.".to_string(),
+        )),
+    }
+}
+
+// Evaluates indexing such as ´v[i]´.
+fn eval_index(
+    expr: Expr,
+    index: Expr,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let vec_val = eval_expr(expr, fn_tree, fn_context, out)?;
+    let index = to_index(eval_expr(index, fn_tree, fn_context, out)?)?;
+
+    match vec_val {
+        Value::Vec(mut values, _) => {
+            if index < values.len() {
+                Ok(values.swap_remove(index))
+            } else {
+                Err(err_out_of_bounds(index, values.len()))
+            }
+        }
+        _ => Err(EvalErr::WrongType(
+            "Cannot index a non-vector value.".to_string(),
+        )),
+    }
+}
+
+// Evaluates assignments to vector elements such as ´v[i] += 1´.
+fn eval_elem_op(
+    expr: Expr,
+    index: Expr,
+    op: Op,
+    new_val: Expr,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let key = vec_key(expr)?;
+    let index = to_index(eval_expr(index, fn_tree, fn_context, out)?)?;
+    let new_val = eval_assigned(new_val, &key, fn_tree, fn_context, out)?;
+    let context = fn_context.get_last_context()?;
+
+    let (mut values, elem_type) = match context.get_val(&key)? {
+        Value::Vec(values, elem_type) => (values, elem_type),
+        _ => {
+            return Err(EvalErr::WrongType(
+                "Cannot index a non-vector value.".to_string(),
+            ))
+        }
+    };
+    if index >= values.len() {
+        return Err(err_out_of_bounds(index, values.len()));
+    }
+
+    values[index] = match op {
+        Op::VarOp(VarToken::Assign) => coerce_to(new_val, &elem_type)?,
+        _ => eval_values(values[index].clone(), var_op_to_math_op(op)?, new_val)?,
+    };
+    context.update_var(&key, &Value::Vec(values, elem_type))
+}
+
+// Evaluates the vector methods ´push´, ´pop´ and ´len´. Popping an empty
+// vector is an error.
+fn eval_method_call(
+    expr: Expr,
+    name: String,
+    args: Vec<Expr>,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    if name == "len" {
+        return match eval_expr(expr, fn_tree, fn_context, out)? {
+            Value::Vec(values, _) => Ok(Value::Int(values.len() as i128, Type::USize)),
+            _ => Err(EvalErr::WrongType("Not a vector.".to_string())),
+        };
+    }
+
+    let key = vec_key(expr)?;
+    let mut arg_vals: Vec<Value> = Vec::new();
+    for arg in args {
+        arg_vals.push(eval_assigned(arg, &key, fn_tree, fn_context, out)?);
+    }
+    let context = fn_context.get_last_context()?;
+
+    let (mut values, elem_type) = match context.get_val(&key)? {
+        Value::Vec(values, elem_type) => (values, elem_type),
+        _ => return Err(EvalErr::WrongType("Not a vector.".to_string())),
+    };
+    let res = match (name.as_str(), arg_vals.pop()) {
+        ("push", Some(val)) => {
+            values.push(coerce_to(val, &elem_type)?);
+            Value::Tuple(vec![])
+        }
+        ("pop", None) => match values.pop() {
+            Some(val) => val,
+            None => {
+                return Err(EvalErr::IndexOutOfBounds(
+                    "called ´pop´ on an empty vector".to_string(),
+                ))
+            }
+        },
+        _ => return Err(EvalErr::NotFound(format!("No method named ´{}´.", name))),
+    };
+
+    context.update_var(&key, &Value::Vec(values, elem_type))?;
+    Ok(res)
+}
+
 // Evaluates a complete binomial tree to a single integer or bool.
 // Should clone the expression before first calling the function. Except
-// in functions that eval_expr itself calls.
-pub fn eval_expr(e: Expr, fn_tree: &mut Functions, fn_context: &mut FnContext) -> EvalRes<Value> {
+// in functions that eval_expr itself calls. A tail call ´return f(...)´ is
+// made before returning, so its value is a ´Return´ like the value of any
+// other return statement.
+pub fn eval_expr(
+    e: Expr,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let res = eval_expr_tail(e, fn_tree, fn_context, out);
+    resolve_tail_call(res, fn_tree, fn_context, out)
+}
+
+// Same as eval_expr, but a tail call is returned as ´TailCall´ for the
+// function containing it to make.
+fn eval_expr_tail(
+    e: Expr,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
     //let context = fn_context.get_last_context()?;
     match e {
-        Expr::Num(num) => Ok(Num(num)),
+        Expr::Num(num) => int_value(num, &Type::Int32),
+        Expr::Float(f) => Ok(Value::Float(f)),
         Expr::Bool(b) => Ok(Bool(b)),
+        Expr::Char(c) => Ok(Value::Char(c)),
+        Expr::Str(s) => Ok(Value::Str(s)),
         Expr::Var(s) => fn_context.get_last_context()?.get_val(&s),
-        Expr::BinOp(left, op, right) => eval_bin_expr(*left, op, *right, fn_tree, fn_context),
+        Expr::Tuple(exprs) => {
+            let mut values: Vec<Value> = Vec::new();
+            for expr in exprs {
+                values.push(eval_expr(expr, fn_tree, fn_context, out)?);
+            }
+            Ok(Value::Tuple(values))
+        }
+        Expr::TupleIndex(expr, index) => match eval_expr(*expr, fn_tree, fn_context, out)? {
+            Value::Tuple(mut values) if index < values.len() => Ok(values.swap_remove(index)),
+            _ => Err(EvalErr::WrongType(format!(
+                "Cannot access field {}. Not a tuple with enough elements.",
+                index
+            ))),
+        },
+        Expr::Cast(expr, target) => eval_cast(eval_expr(*expr, fn_tree, fn_context, out)?, &target),
+        Expr::VecNew => Ok(Value::Vec(vec![], Type::Int32)),
+        Expr::Index(expr, index) => eval_index(*expr, *index, fn_tree, fn_context, out),
+        Expr::MethodCall(expr, name, args) => {
+            eval_method_call(*expr, name, args, fn_tree, fn_context, out)
+        }
+        Expr::BinOp(left, op, right) => eval_bin_expr(*left, op, *right, fn_tree, fn_context, out),
         Expr::VarOp(var, op, expr) => {
+            if let Expr::Index(vec_expr, index) = *var {
+                return eval_elem_op(*vec_expr, *index, op, *expr, fn_tree, fn_context, out);
+            }
             let key = String::from(*var);
-            let expr_val = eval_expr(*expr, fn_tree, fn_context)?;
+            let expr_val = eval_assigned(*expr, &key, fn_tree, fn_context, out)?;
+            let context = fn_context.get_last_context()?;
 
             match op {
                 Op::VarOp(VarToken::Assign) => {
-                    fn_context.get_last_context()?.update_var(&key, &expr_val)
+                    // New values keep the integer or element type of the variable.
+                    let expr_val = match context.get_val(&key)? {
+                        Value::Int(_, t) => coerce_to(expr_val, &t)?,
+                        Value::Vec(_, t) => coerce_to(expr_val, &Type::Vec(Box::new(t)))?,
+                        _ => expr_val,
+                    };
+                    context.update_var(&key, &expr_val)
                 }
-                _ => eval_var_op(&key, op, &expr_val, fn_context.get_last_context()?),
+                _ => eval_var_op(&key, op, &expr_val, context),
             }
         }
-        Expr::Let(var, _, expr) => assign_var(*var, *expr, fn_tree, fn_context), // ignore type for now
-        Expr::If(expr, block) => eval_if(*expr, block, fn_tree, fn_context),
-        Expr::FuncCall(fn_call) => eval_fn_call(fn_call, fn_tree, fn_context),
-        Expr::Return(val) => Ok(Value::Return(Box::new(eval_expr(
-            *val, fn_tree, fn_context,
-        )?))),
-        Expr::While(expr, block) => eval_while(*expr, block, fn_tree, fn_context),
+        Expr::Let(var, var_type, expr) => {
+            assign_var(*var, var_type, *expr, fn_tree, fn_context, out)
+        }
+        Expr::If(expr, block) => eval_if(*expr, block, fn_tree, fn_context, out),
+        Expr::FuncCall(fn_call) if fn_call.is_builtin() => {
+            eval_print(fn_call, fn_tree, fn_context, out)
+        }
+        Expr::FuncCall(fn_call) => eval_fn_call(fn_call, fn_tree, fn_context, out),
+        Expr::Return(val) => match *val {
+            Expr::FuncCall(fn_call) if !fn_call.is_builtin() => {
+                let arg_values = eval_args(&fn_call.name, fn_call.args, fn_tree, fn_context, out)?;
+                Ok(Value::TailCall(fn_call.name, arg_values))
+            }
+            // Literals take the return type, which call_fn keeps in the
+            // context of the function.
+            val => Ok(Value::Return(Box::new(eval_assigned(
+                val,
+                "return_type",
+                fn_tree,
+                fn_context,
+                out,
+            )?))),
+        },
+        Expr::While(expr, block) => eval_while_tail(*expr, block, fn_tree, fn_context, out),
         _ => Err(EvalErr::NotImplemented),
     }
 }
@@ -100,36 +531,92 @@ pub fn eval_expr(e: Expr, fn_tree: &mut Functions, fn_context: &mut FnContext) -
 // Assigns value to variable. Store it in current scope.
 fn assign_var(
     var: Expr,
+    var_type: Type,
     expr: Expr,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
-    let id = String::from(var);
-    let expr_val = eval_expr(expr, fn_tree, fn_context)?;
-    fn_context
-        .get_last_context()?
-        .insert_to_current_scope(&id, &expr_val);
+    let expr_val = eval_expect(expr, &var_type, fn_tree, fn_context, out)?;
+    bind_pattern(var, &expr_val, fn_context.get_last_context()?)?;
     Ok(expr_val)
 }
 
+// Evaluates an expression in the type it is declared with. Integer literals and
+// arithmetic on them are computed in the type, like the type checker types
+// them, and other values are coerced to it.
+fn eval_expect(
+    expr: Expr,
+    t: &Type,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    match (expr, t) {
+        (expr, _) if t.is_int() && is_int_literal(&expr) => eval_int_literal(&expr, t),
+        (Expr::Tuple(exprs), Type::Tuple(types)) if exprs.len() == types.len() => {
+            let mut values: Vec<Value> = Vec::new();
+            for (expr, t) in exprs.into_iter().zip(types.iter()) {
+                values.push(eval_expect(expr, t, fn_tree, fn_context, out)?);
+            }
+            Ok(Value::Tuple(values))
+        }
+        (expr, _) => coerce_to(eval_expr(expr, fn_tree, fn_context, out)?, t),
+    }
+}
+
+// Evaluates a value assigned to the variable. Integer literals are computed in
+// the type of the variable, see literal_type.
+fn eval_assigned(
+    expr: Expr,
+    key: &str,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    if !is_int_literal(&expr) {
+        return eval_expr(expr, fn_tree, fn_context, out);
+    }
+    let t = match fn_context.get_last_context()?.get_val(key) {
+        Ok(val) => literal_type(&val),
+        Err(_) => Type::Int32,
+    };
+    eval_int_literal(&expr, &t)
+}
+
+// Binds the variables of a pattern to the value. Tuple patterns are
+// destructured element-wise.
+fn bind_pattern(pattern: Expr, val: &Value, context: &mut Context) -> EvalRes<Value> {
+    match (pattern, val) {
+        (Expr::Var(id), _) => {
+            context.insert_to_current_scope(&id, val);
+            Ok(val.clone())
+        }
+        (Expr::Tuple(patterns), Value::Tuple(values)) if patterns.len() == values.len() => {
+            for (pattern, val) in patterns.into_iter().zip(values.iter()) {
+                bind_pattern(pattern, val, context)?;
+            }
+            Ok(val.clone())
+        }
+        _ => Err(EvalErr::TypeMismatch(
+            "Cannot destructure value. Pattern does not match.".to_string(),
+        )),
+    }
+}
+
 // Evaluates variable operations such as ´a += b´ etc.
 fn eval_var_op(key: &str, op: Op, new_val: &Value, context: &mut Context) -> EvalRes<Value> {
-    let old_val: i32 = i32::from(context.get_val(&key)?);
-    let expr_val: i32 = i32::from(new_val.clone());
+    let old_val = context.get_val(&key)?;
+    let new_val = eval_values(old_val, var_op_to_math_op(op)?, new_val.clone())?;
+    context.update_var(&key, &new_val)
+}
 
+// Returns the math operator of a compound assignment, e.g. ´+´ for ´+=´.
+pub(crate) fn var_op_to_math_op(op: Op) -> EvalRes<Op> {
     match op {
-        Op::VarOp(VarToken::PlusEq) => {
-            let new_val = Num(old_val + expr_val);
-            context.update_var(&key, &new_val)
-        }
-        Op::VarOp(VarToken::MinEq) => {
-            let new_val = Num(old_val - expr_val);
-            context.update_var(&key, &new_val)
-        }
-        Op::VarOp(VarToken::MulEq) => {
-            let new_val = Num(old_val * expr_val);
-            context.update_var(&key, &new_val)
-        }
+        Op::VarOp(VarToken::PlusEq) => Ok(Op::MathOp(MathToken::Plus)),
+        Op::VarOp(VarToken::MinEq) => Ok(Op::MathOp(MathToken::Minus)),
+        Op::VarOp(VarToken::MulEq) => Ok(Op::MathOp(MathToken::Multiply)),
         _ => Err(EvalErr::WrongOp("Not a variable operator.".to_string())),
     }
 }
@@ -139,13 +626,14 @@ fn eval_if(
     block: Block,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
-    let condition = eval_expr(e, fn_tree, fn_context)?;
+    let condition = eval_expr(e, fn_tree, fn_context, out)?;
     let res: EvalRes<Value>;
 
     match condition {
         Bool(true) => {
-            res = eval_block(block, fn_tree, fn_context);
+            res = eval_block_tail(block, fn_tree, fn_context, out);
         }
         Bool(false) => res = Ok(Bool(false)),
         _ => {
@@ -163,21 +651,38 @@ pub fn eval_while(
     block: Block,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
-    let if_val: EvalRes<Value> = eval_if(e.clone(), block.clone(), fn_tree, fn_context);
-    let ret_val: EvalRes<Value>;
-    println!("while");
-    match if_val {
-        Ok(Bool(false)) => ret_val = if_val,
-        Ok(_) => ret_val = eval_while(e.clone(), block.clone(), fn_tree, fn_context),
-        Err(_) => {
-            ret_val = Err(EvalErr::WrongType(
-                "Cannot evaluate condition. Not a boolean expression.".to_string(),
-            ))
+    let res = eval_while_tail(e, block, fn_tree, fn_context, out);
+    resolve_tail_call(res, fn_tree, fn_context, out)
+}
+
+fn eval_while_tail(
+    e: Expr,
+    block: Block,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    // The condition is evaluated separately, since the value of the body
+    // can be a boolean as well.
+    loop {
+        match eval_expr(e.clone(), fn_tree, fn_context, out)? {
+            Bool(true) => (),
+            Bool(false) => return Ok(Bool(false)),
+            _ => {
+                return Err(EvalErr::WrongType(
+                    "Cannot evaluate condition. Not a boolean expression.".to_string(),
+                ))
+            }
         }
-    }
 
-    ret_val
+        // Returns and errors in the body end the loop.
+        let res = eval_block_tail(block.clone(), fn_tree, fn_context, out)?;
+        if let Value::Return(_) | Value::TailCall(..) = res {
+            return Ok(res);
+        }
+    }
 }
 
 // Evaluates a complete block. Returns the value from the last instruction evaluated.
@@ -185,14 +690,25 @@ pub fn eval_block(
     block: Block,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let res = eval_block_tail(block, fn_tree, fn_context, out);
+    resolve_tail_call(res, fn_tree, fn_context, out)
+}
+
+fn eval_block_tail(
+    block: Block,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
     fn_context.get_last_context()?.new_scope();
 
     let mut res: EvalRes<Value> = Err(EvalErr::NotFound("No expressions found.".to_string()));
     for e in block.content.iter() {
-        res = eval_expr(e.clone(), fn_tree, fn_context);
+        res = eval_expr_tail(e.clone(), fn_tree, fn_context, out);
         match res {
-            Ok(Value::Return(_)) => break,
+            Ok(Value::Return(_)) | Ok(Value::TailCall(..)) | Err(_) => break,
             _ => continue,
         }
     }
@@ -203,52 +719,202 @@ pub fn eval_block(
     res
 }
 
-pub fn eval_fn_call(
+// Formats a value the same way as ´{}´ in Rust.
+pub(crate) fn format_value(val: &Value) -> EvalRes<String> {
+    match val {
+        Num(num) => Ok(num.to_string()),
+        Value::Int(num, _) => Ok(num.to_string()),
+        Bool(b) => Ok(b.to_string()),
+        Value::Char(c) => Ok(c.to_string()),
+        Value::Str(s) => Ok(s.clone()),
+        _ => Err(EvalErr::WrongType(format!("Cannot print {:?}.", val))),
+    }
+}
+
+// Evaluates the built-in ´print´ and ´println´. Every ´{}´ in the format
+// string is replaced by the next argument. Returns the unit value ´()´.
+fn eval_print(
     fn_call: FunctionCall,
     fn_tree: &mut Functions,
     fn_context: &mut FnContext,
+    out: &mut dyn Write,
 ) -> EvalRes<Value> {
-    println!("{:#?}", fn_context);
-    // Get the argument values.
-    let mut arg_values: Vec<Value> = Vec::new();
-    for arg in fn_call.args.content {
-        arg_values.push(eval_expr(arg, fn_tree, fn_context)?);
+    let mut args = fn_call.args.content.into_iter();
+    let fmt = match args.next() {
+        Some(Expr::Str(fmt)) => fmt,
+        None => String::new(),
+        _ => {
+            return Err(EvalErr::WrongType(
+                "Format argument must be a string literal.".to_string(),
+            ))
+        }
+    };
+
+    let mut pieces = fmt.split("{}");
+    let mut text = pieces.next().unwrap_or("").to_string();
+    for piece in pieces {
+        match args.next() {
+            Some(arg) => text.push_str(&format_value(&eval_expr(arg, fn_tree, fn_context, out)?)?),
+            None => {
+                return Err(EvalErr::NotFound(
+                    "No argument found for placeholder.".to_string(),
+                ))
+            }
+        }
+        text.push_str(piece);
+    }
+    if fn_call.name == "println" {
+        text.push('\n');
     }
 
-    // Match the argument values with the parameter names. Place into the top scope of a new context.
-    let func_temp: EvalRes<Function> = fn_tree.get_fn(fn_call.name);
-    let func = func_temp?;
-    fn_context.new_context()?.new_scope();
-    let context = fn_context.get_last_context()?;
-    let mut step = 0;
-    for param in func.params {
-        context.insert_to_current_scope(&param.name, &arg_values[step]);
-        step += 1;
+    match out.write_all(text.as_bytes()) {
+        Ok(_) => Ok(Value::Tuple(vec![])),
+        Err(e) => Err(EvalErr::IoError(e.to_string())),
     }
+}
 
-    let return_val = eval_block(func.block, fn_tree, fn_context);
+// Evaluates the arguments of a call. Integer literals take the types of the
+// parameters of the function, if it exists.
+fn eval_args(
+    name: &str,
+    args: Block,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Vec<Value>> {
+    let param_types: Vec<Type> = match fn_tree.iter().find(|func| func.name == name) {
+        Some(func) => func.params.iter().map(|p| p.param_type.clone()).collect(),
+        None => Vec::new(),
+    };
+    let mut arg_values: Vec<Value> = Vec::new();
+    for (i, arg) in args.content.into_iter().enumerate() {
+        let arg_val = match param_types.get(i) {
+            Some(t) if t.is_int() && is_int_literal(&arg) => eval_int_literal(&arg, t)?,
+            _ => eval_expr(arg, fn_tree, fn_context, out)?,
+        };
+        arg_values.push(arg_val);
+    }
+    Ok(arg_values)
+}
 
-    // Drop the function's context (comment out for debug)
-    fn_context.drop_current_context();
+pub fn eval_fn_call(
+    fn_call: FunctionCall,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let arg_values = eval_args(&fn_call.name, fn_call.args, fn_tree, fn_context, out)?;
+    call_fn(fn_call.name, arg_values, fn_tree, fn_context, out)
+}
+
+// Calls the function with the argument values. A tail call ´return f(...)´
+// ends the function with ´TailCall´, and f is called in the same loop after
+// the context of the function is dropped, so tail recursion runs in constant
+// Rust stack space.
+fn call_fn(
+    name: String,
+    args: Vec<Value>,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let mut name = name;
+    let mut arg_values = args;
+    // The value of a tail call is returned by the first function as well.
+    let mut return_type: Option<Type> = None;
+    loop {
+        // Match the argument values with the parameter names. Place into the top scope of a new context.
+        let func_temp: EvalRes<Function> = fn_tree.get_fn(name);
+        let func = func_temp?;
+        // C functions are only available to compiled programs
+        if func.is_extern {
+            return Err(EvalErr::NotImplemented);
+        }
+        fn_context.new_context()?.new_scope();
+        let context = fn_context.get_last_context()?;
+        for (param, arg_val) in func.params.iter().zip(arg_values) {
+            let arg_val = coerce_to(arg_val, &param.param_type)?;
+            context.insert_to_current_scope(&param.name, &arg_val);
+        }
+        insert_return_type(&func, context)?;
+
+        let return_val = eval_block_tail(func.block, fn_tree, fn_context, out);
 
-    // Unwraps return statements
-    match return_val {
-        Err(e) => Err(e),
-        Ok(Value::Return(val)) => Ok(*val),
-        _ => return_val,
+        // Drop the function's context (comment out for debug)
+        fn_context.drop_current_context();
+
+        // Unwraps return statements
+        let val = match return_val {
+            Err(e) => return Err(e),
+            Ok(Value::TailCall(callee, callee_args)) => {
+                return_type.get_or_insert(func.return_type);
+                name = callee;
+                arg_values = callee_args;
+                continue;
+            }
+            Ok(Value::Return(val)) => coerce_to(*val, &func.return_type)?,
+            Ok(val) => val,
+        };
+        return match return_type {
+            Some(t) => coerce_to(val, &t),
+            None => Ok(val),
+        };
+    }
+}
+
+// Makes the tail call that ended the evaluation, if any, and returns its
+// value as a ´Return´.
+fn resolve_tail_call(
+    res: EvalRes<Value>,
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    match res? {
+        Value::TailCall(name, arg_values) => Ok(Value::Return(Box::new(call_fn(
+            name, arg_values, fn_tree, fn_context, out,
+        )?))),
+        val => Ok(val),
     }
 }
 
+// Keeps a value of the integer return type of the function in its context, so
+// that returned literals take the type. The name is not a valid identifier.
+fn insert_return_type(func: &Function, context: &mut Context) -> EvalRes<()> {
+    if func.return_type.is_int() {
+        context.insert_to_current_scope("return_type", &int_value(0, &func.return_type)?);
+    }
+    Ok(())
+}
+
+// Evaluates the body of main. Its value is not unwrapped, so that a value
+// returned by main is a ´Return´.
+fn eval_main(
+    fn_tree: &mut Functions,
+    fn_context: &mut FnContext,
+    out: &mut dyn Write,
+) -> EvalRes<Value> {
+    let main_res: EvalRes<Function> = fn_tree.get_fn("main".to_string());
+    let main = main_res?;
+
+    let context = fn_context.get_last_context()?;
+    context.new_scope();
+    insert_return_type(&main, context)?;
+    eval_block(main.block, fn_tree, fn_context, out)
+}
+
 // Main entry
 pub fn eval_program(fn_tree: &mut Functions) -> EvalRes<Value> {
+    eval_program_with_output(fn_tree, &mut io::stdout())
+}
+
+// Same as eval_program but everything the program prints is written to ´out´.
+pub fn eval_program_with_output(fn_tree: &mut Functions, out: &mut dyn Write) -> EvalRes<Value> {
     // Setup new contexts
     let mut fn_context = FnContext::new();
     fn_context.new_context()?;
 
-    let main_res: EvalRes<Function> = fn_tree.get_fn("main".to_string());
-    let main = main_res?;
-
-    eval_block(main.block, fn_tree, &mut fn_context)
+    eval_main(fn_tree, &mut fn_context, out)
 }
 
 // Returns the FnContext instead
@@ -257,10 +923,7 @@ pub fn eval_program_debug(fn_tree: &mut Functions) -> EvalRes<FnContext> {
     let mut fn_context = FnContext::new();
     fn_context.new_context()?;
 
-    let main_res: EvalRes<Function> = fn_tree.get_fn("main".to_string());
-    let main: Function = main_res?;
-
-    eval_block(main.block, fn_tree, &mut fn_context)?;
+    eval_main(fn_tree, &mut fn_context, &mut io::stdout())?;
 
     Ok(fn_context)
 }
@@ -321,4 +984,359 @@ mod interpreter_tests {
         let mut tree = parse_program(main).unwrap().1;
         assert_eq!(eval_program(&mut tree).is_ok(), true);
     }
+
+    #[test]
+    fn eval_tuple_program() {
+        let program = "
+            fn divmod(a: i32, b: i32) -> (i32, i32) {
+                return (a / b, a - (a / b) * b);
+            }
+
+            fn main() -> () {
+                let (q, r): (i32, i32) = divmod(17, 5);
+                let t: ((i32, i32), bool) = (divmod(9, 2), true);
+                return (q * 10) + (r + t.0.1);
+            }
+        ";
+
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree).unwrap(),
+            Value::Return(Box::new(Num(33)))
+        );
+    }
+
+    #[test]
+    fn eval_int_widths() {
+        let program = "
+            fn wrap(a: i32) -> u8 {
+                return a as u8;
+            }
+
+            fn main() -> () {
+                let a: u8 = 200;
+                let b: i64 = (a as i64) * 30000000;
+                let c: i8 = -1;
+                let d: u16 = c as u16;
+                return (wrap(300) as u64, b, d as i32);
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree).unwrap(),
+            Value::Return(Box::new(Value::Tuple(vec![
+                Value::Int(44, Type::UInt64),
+                Value::Int(6000000000, Type::Int64),
+                Num(65535)
+            ])))
+        );
+
+        let overflow = "
+            fn main() -> () {
+                let a: u8 = 200;
+                a += 100;
+            }
+        ";
+        let mut tree = parse_program(overflow).unwrap().1;
+        match eval_program(&mut tree) {
+            Err(EvalErr::Overflow(_)) => (),
+            res => panic!("expected overflow, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn eval_typed_literals() {
+        // Literal arithmetic is computed in the declared type.
+        let wide = "
+            fn main() -> i64 {
+                let a: i64 = 100000 * 100000;
+                return a;
+            }
+        ";
+        let mut tree = parse_program(wide).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree).unwrap(),
+            Value::Return(Box::new(Value::Int(10000000000, Type::Int64)))
+        );
+
+        let narrow = "
+            fn main() -> u8 {
+                let a: u8 = 200 + 100;
+                return a;
+            }
+        ";
+        let mut tree = parse_program(narrow).unwrap().1;
+        match eval_program(&mut tree) {
+            Err(EvalErr::Overflow(_)) => (),
+            res => panic!("expected overflow, got {:?}", res),
+        }
+
+        // Literals take the type of parameters, variables, vector elements,
+        // return types and the other operand as well.
+        let program = "
+            fn add(a: i64, b: u64) -> u64 {
+                return ((a + 2147483648) as u64) + (b + 3000000000);
+            }
+
+            fn big() -> i64 {
+                return 3000000000 * 2;
+            }
+
+            fn main() -> () {
+                let a: i64 = 0;
+                a = 100000 * 100000;
+                let v: Vec<u64> = Vec::new();
+                v.push(5000000000);
+                v[0] += 5000000000;
+                let b: i64 = 3000000000 + a;
+                let c: i64 = a - 3000000000;
+                let t: (u8, i64) = (255, -3000000000);
+                return (add(-2147483648, 4000000000), big(), b, c, v[0], t);
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree).unwrap(),
+            Value::Return(Box::new(Value::Tuple(vec![
+                Value::Int(7000000000, Type::UInt64),
+                Value::Int(6000000000, Type::Int64),
+                Value::Int(13000000000, Type::Int64),
+                Value::Int(7000000000, Type::Int64),
+                Value::Int(10000000000, Type::UInt64),
+                Value::Tuple(vec![
+                    Value::Int(255, Type::UInt8),
+                    Value::Int(-3000000000, Type::Int64)
+                ])
+            ])))
+        );
+
+        // A literal out of range of the type of the variable overflows.
+        let out_of_range = "
+            fn main() -> () {
+                let a: u8 = 0;
+                a = 256;
+            }
+        ";
+        let mut tree = parse_program(out_of_range).unwrap().1;
+        match eval_program(&mut tree) {
+            Err(EvalErr::Overflow(_)) => (),
+            res => panic!("expected overflow, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn eval_f64_program() {
+        let program = "
+            fn half(a: f64) -> f64 {
+                return a / 2.0;
+            }
+
+            fn main() -> () {
+                let a: f64 = half(3 as f64);
+                let b: f64 = 1.0 / 0.0;
+                return (a, a as i32, b > 1e300, -2.5e2 as u8);
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree).unwrap(),
+            Value::Return(Box::new(Value::Tuple(vec![
+                Value::Float(1.5),
+                Num(1),
+                Bool(true),
+                Value::Int(0, Type::UInt8)
+            ])))
+        );
+    }
+
+    #[test]
+    fn eval_print_program() {
+        let program = r#"
+            fn main() -> () {
+                let name: &str = "world";
+                let c: char = 'a';
+                print("hello {}", name);
+                println!("!");
+                println("{} {} {} {}", c, c < 'b', 200 as u8 as char, -3);
+            }
+        "#;
+        let mut tree = parse_program(program).unwrap().1;
+        let mut out: Vec<u8> = Vec::new();
+        assert_eq!(eval_program_with_output(&mut tree, &mut out).is_ok(), true);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "hello world!\na true \u{c8} -3\n"
+        );
+    }
+
+    #[test]
+    fn eval_vec_program() {
+        let program = "
+            fn sum(v: Vec<i64>) -> i64 {
+                let s: i64 = 0;
+                let i: usize = 0;
+                while i < v.len() {
+                    s += v[i];
+                    i += 1;
+                };
+                return s;
+            }
+
+            fn main() -> i64 {
+                let v: Vec<i64> = Vec::new();
+                v.push(10);
+                v.push(20);
+                v.push(30);
+                v[0] *= 2;
+                let last: i64 = v.pop();
+                return sum(v) + last;
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree),
+            Ok(Value::Return(Box::new(Value::Int(70, Type::Int64))))
+        );
+
+        let out_of_bounds = "
+            fn main() -> i32 {
+                let v: Vec<i32> = Vec::new();
+                v.push(1);
+                return v[1];
+            }
+        ";
+        let mut tree = parse_program(out_of_bounds).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree),
+            Err(EvalErr::IndexOutOfBounds(
+                "index out of bounds: the len is 1 but the index is 1".to_string()
+            ))
+        );
+
+        let reassigned = "
+            fn main() -> u8 {
+                let v: Vec<u8> = Vec::new();
+                v.push(1);
+                v = Vec::new();
+                v.push(2);
+                return v.pop();
+            }
+        ";
+        let mut tree = parse_program(reassigned).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree),
+            Ok(Value::Return(Box::new(Value::Int(2, Type::UInt8))))
+        );
+    }
+
+    #[test]
+    fn eval_while_program() {
+        let program = "
+            fn main() -> i32 {
+                let i: i32 = 0;
+                while i < 10 {
+                    if i == 3 {
+                        return i;
+                    };
+                    i += 1;
+                };
+                return 10;
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(eval_program(&mut tree), Ok(Value::Return(Box::new(Num(3)))));
+
+        // Errors stop the program, also when they are not in the last statement.
+        let overflow = "
+            fn main() -> i32 {
+                let a: i32 = 1;
+                while a > 0 {
+                    a *= 1000;
+                };
+                return 0;
+            }
+        ";
+        let mut tree = parse_program(overflow).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree),
+            Err(EvalErr::Overflow(
+                "attempt to compute ´1000000000 * 1000´ with overflow".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn eval_tail_calls() {
+        // Deep enough to overflow the Rust stack if every call was nested.
+        let program = "
+            fn count(n: i32, acc: u16) -> u16 {
+                if n == 0 {
+                    return acc;
+                };
+                return count(n - 1, (acc + 1));
+            }
+
+            fn even(n: i32) -> bool {
+                if n == 0 {
+                    return true;
+                };
+                return odd(n - 1);
+            }
+
+            fn odd(n: i32) -> bool {
+                if n == 0 {
+                    return false;
+                };
+                return even(n - 1);
+            }
+
+            fn main() -> u16 {
+                if even(100001) {
+                    return 0;
+                };
+                return count(60000, 0);
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            eval_program(&mut tree),
+            Ok(Value::Return(Box::new(Value::Int(60000, Type::UInt16))))
+        );
+    }
+
+    #[test]
+    fn eval_block_makes_tail_calls() {
+        let program = "
+            fn double(a: i32) -> i32 {
+                return a * 2;
+            }
+
+            fn main() -> i32 {
+                while true {
+                    return double(21);
+                };
+                return 0;
+            }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        let main = tree
+            .iter()
+            .find(|func| func.name == "main")
+            .unwrap()
+            .clone();
+        let mut fn_context = FnContext::new();
+        fn_context.new_context().unwrap().new_scope();
+        let mut out: Vec<u8> = Vec::new();
+        let returned = Ok(Value::Return(Box::new(Num(42))));
+
+        assert_eq!(
+            eval_block(main.block.clone(), &mut tree, &mut fn_context, &mut out),
+            returned
+        );
+        let while_expr = main.block.content[0].clone();
+        assert_eq!(
+            eval_expr(while_expr, &mut tree, &mut fn_context, &mut out),
+            returned
+        );
+    }
 }
@@ -0,0 +1,458 @@
+use crate::ast::*;
+
+mod eval;
+mod lower;
+mod verify;
+
+pub use eval::{run_ir, run_ir_with_output};
+pub use lower::lower_program;
+pub use verify::verify_module;
+
+use std::fmt;
+
+// A mid-level intermediate representation in SSA form. Every function is a
+// control flow graph of basic blocks, each a list of instructions followed by
+// a terminator that jumps to other blocks or returns. Every instruction
+// defines a new value, which is never assigned again. Where control flow
+// joins, phi instructions at the start of the block select the value that
+// comes from the predecessor the block was entered from.
+//
+// The parameters of a function are the values 0 to n - 1 and block 0 is the
+// entry block. Instructions that produce nothing, like calls of functions
+// returning ´()´, define a value of type ´Void´ that is never used.
+//
+// Constants are ´Value´s of the interpreter and operations on them have the
+// same semantics, so that the IR interpreter in ir/eval.rs gives the same
+// results as the interpreter.
+
+pub type ValueId = usize;
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    Const(Value),
+    BinOp(Op, ValueId, ValueId),
+    // Converts the value to the type of the result, like ´as´
+    Cast(ValueId),
+    Call(String, Vec<ValueId>),
+    // Format string, arguments and whether a newline is printed
+    Print(String, Vec<ValueId>, bool),
+    // The value coming from each predecessor of the block
+    Phi(Vec<(BlockId, ValueId)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inst {
+    pub result: ValueId,
+    pub kind: InstKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    // Jumps to the first block if the condition is true, else to the second
+    Branch(ValueId, BlockId, BlockId),
+    Return(Option<ValueId>),
+    // The end of a function that has to return a value but does not
+    Unreachable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrFunction {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    // Empty for C functions
+    pub blocks: Vec<BasicBlock>,
+    // The type of every value of the function
+    pub types: Vec<Type>,
+    pub is_extern: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub functions: Vec<IrFunction>,
+}
+
+impl InstKind {
+    // The values used by the instruction.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            InstKind::Const(_) => vec![],
+            InstKind::BinOp(_, l, r) => vec![*l, *r],
+            InstKind::Cast(val) => vec![*val],
+            InstKind::Call(_, args) | InstKind::Print(_, args, _) => args.clone(),
+            InstKind::Phi(incoming) => incoming.iter().map(|(_, val)| *val).collect(),
+        }
+    }
+
+    fn map_operands(&mut self, f: &dyn Fn(ValueId) -> ValueId) {
+        match self {
+            InstKind::Const(_) => (),
+            InstKind::BinOp(_, l, r) => {
+                *l = f(*l);
+                *r = f(*r);
+            }
+            InstKind::Cast(val) => *val = f(*val),
+            InstKind::Call(_, args) | InstKind::Print(_, args, _) => {
+                for arg in args.iter_mut() {
+                    *arg = f(*arg);
+                }
+            }
+            InstKind::Phi(incoming) => {
+                for (_, val) in incoming.iter_mut() {
+                    *val = f(*val);
+                }
+            }
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then_block, else_block) => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Branch(cond, _, _) => vec![*cond],
+            Terminator::Return(Some(val)) => vec![*val],
+            _ => vec![],
+        }
+    }
+
+    fn map_operands(&mut self, f: &dyn Fn(ValueId) -> ValueId) {
+        match self {
+            Terminator::Branch(cond, _, _) => *cond = f(*cond),
+            Terminator::Return(Some(val)) => *val = f(*val),
+            _ => (),
+        }
+    }
+}
+
+impl IrFunction {
+    // The predecessors of every block, in the order of the blocks.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds: Vec<Vec<BlockId>> = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                if !preds[succ].contains(&id) {
+                    preds[succ].push(id);
+                }
+            }
+        }
+        preds
+    }
+
+    // Replaces every use of a value, but not the definitions.
+    pub fn map_values(&mut self, f: &dyn Fn(ValueId) -> ValueId) {
+        for block in self.blocks.iter_mut() {
+            for inst in block.insts.iter_mut() {
+                inst.kind.map_operands(f);
+            }
+            block.terminator.map_operands(f);
+        }
+    }
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&IrFunction> {
+        self.functions.iter().find(|func| func.name == name)
+    }
+}
+
+fn join(values: &[ValueId]) -> String {
+    values
+        .iter()
+        .map(|val| format!("v{}", val))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for InstKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstKind::Const(Value::Num(num)) => write!(f, "const {}", num),
+            InstKind::Const(Value::Int(num, _)) => write!(f, "const {}", num),
+            InstKind::Const(Value::Float(num)) => write!(f, "const {:?}", num),
+            InstKind::Const(Value::Bool(b)) => write!(f, "const {}", b),
+            InstKind::Const(Value::Char(c)) => write!(f, "const {:?}", c),
+            InstKind::Const(Value::Str(s)) => write!(f, "const {:?}", s),
+            InstKind::Const(val) => write!(f, "const {:?}", val),
+            InstKind::BinOp(op, l, r) => write!(f, "v{} {} v{}", l, op, r),
+            InstKind::Cast(val) => write!(f, "cast v{}", val),
+            InstKind::Call(name, args) => write!(f, "call {}({})", name, join(args)),
+            InstKind::Print(fmt, args, newline) => {
+                let name = if *newline { "println" } else { "print" };
+                write!(f, "{} {:?}", name, fmt)?;
+                for arg in args {
+                    write!(f, ", v{}", arg)?;
+                }
+                Ok(())
+            }
+            InstKind::Phi(incoming) => {
+                let incoming: Vec<String> = incoming
+                    .iter()
+                    .map(|(block, val)| format!("[bb{}: v{}]", block, val))
+                    .collect();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jmp bb{}", target),
+            Terminator::Branch(cond, then_block, else_block) => {
+                write!(f, "br v{}, bb{}, bb{}", cond, then_block, else_block)
+            }
+            Terminator::Return(Some(val)) => write!(f, "ret v{}", val),
+            Terminator::Return(None) => write!(f, "ret"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(val, t)| format!("v{}: {}", val, t.source_name()))
+            .collect();
+        let signature = format!(
+            "fn {}({}) -> {}",
+            self.name,
+            params.join(", "),
+            self.return_type.source_name()
+        );
+        if self.is_extern {
+            return writeln!(f, "extern {}", signature);
+        }
+
+        writeln!(f, "{} {{", signature)?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "bb{}:", id)?;
+            for inst in block.insts.iter() {
+                match self.types.get(inst.result) {
+                    Some(Type::Void) => writeln!(f, "    {}", inst.kind)?,
+                    Some(t) => writeln!(
+                        f,
+                        "    v{}: {} = {}",
+                        inst.result,
+                        t.source_name(),
+                        inst.kind
+                    )?,
+                    None => writeln!(f, "    v{} = {}", inst.result, inst.kind)?,
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            func.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod ir_tests {
+    use super::*;
+    use crate::backend::BackendError;
+    use crate::interpreter::*;
+    use crate::parser::*;
+
+    fn lower(program: &str) -> Module {
+        lower_program(parse_program(program).unwrap().1).unwrap()
+    }
+
+    // Runs the program with the interpreter and from the IR, and checks that
+    // the output and errors are the same.
+    fn assert_same(program: &str) -> EvalRes<Value> {
+        let mut tree = parse_program(program).unwrap().1;
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut tree, &mut expected_out);
+
+        let mut out: Vec<u8> = Vec::new();
+        let res = run_ir_with_output(&lower(program), &mut out);
+        match (&expected, &res) {
+            (Ok(Value::Return(expected)), Ok(res)) => assert_eq!(**expected, *res),
+            // The interpreter gives the value of the last statement of a main
+            // without a return.
+            (Ok(_), Ok(res)) => assert_eq!(*res, Value::Tuple(vec![])),
+            (Ok(_), Err(_)) | (Err(_), _) => assert_eq!(res, expected),
+        }
+        assert_eq!(out, expected_out);
+        res
+    }
+
+    #[test]
+    fn ir_while_program() {
+        let program = "
+            fn main() -> i32 {
+                let x: i32 = 0;
+                let i: u8 = 0;
+                while i < 3 {
+                    x += 10;
+                    i += 1;
+                };
+                return x;
+            }
+        ";
+        assert_eq!(
+            lower(program).to_string(),
+            "fn main() -> i32 {
+bb0:
+    v0: i32 = const 0
+    v1: u8 = const 0
+    jmp bb1
+bb1:
+    v2: i32 = phi [bb0: v0], [bb2: v7]
+    v3: u8 = phi [bb0: v1], [bb2: v9]
+    v4: u8 = const 3
+    v5: bool = v3 < v4
+    br v5, bb2, bb3
+bb2:
+    v6: i32 = const 10
+    v7: i32 = v2 + v6
+    v8: u8 = const 1
+    v9: u8 = v3 + v8
+    jmp bb1
+bb3:
+    ret v2
+}
+"
+        );
+        assert_eq!(assert_same(program), Ok(Value::Num(30)));
+    }
+
+    #[test]
+    fn ir_if_program() {
+        let program = "
+            fn clamp(a: i64, max: i64) -> i64 {
+                if a > max {
+                    return max;
+                };
+                let b: i64 = a;
+                if b < 0 {
+                    b = 0;
+                    println(\"{} is negative\", a);
+                };
+                return b;
+            }
+
+            fn main() -> i32 {
+                println(\"{} {} {}\", clamp(5, 3), clamp(2, 3), clamp(0 - 4, 3));
+                return 0;
+            }
+        ";
+        let module = lower(program);
+        let clamp = module.function("clamp").unwrap();
+        assert_eq!(clamp.blocks.len(), 5);
+        assert_eq!(
+            clamp.blocks[4].insts[0].kind,
+            InstKind::Phi(vec![(2, 0), (3, 5)])
+        );
+        assert_eq!(assert_same(program), Ok(Value::Num(0)));
+
+        let no_return = "
+            fn main() -> () {
+                println(\"{}\", 1);
+            }
+        ";
+        assert_eq!(assert_same(no_return), Ok(Value::Tuple(vec![])));
+    }
+
+    #[test]
+    fn ir_error_program() {
+        let program = "
+            fn main() -> i32 {
+                let a: i8 = 100;
+                println(\"{}\", a);
+                a += 100;
+                return 0;
+            }
+        ";
+        assert!(assert_same(program).is_err());
+    }
+
+    #[test]
+    fn ir_verify_errors() {
+        let program = "
+            fn main() -> i32 {
+                let x: i32 = 1;
+                if x == 1 {
+                    x = 2;
+                };
+                return x;
+            }
+        ";
+        let module = lower(program);
+        let verify = |f: &dyn Fn(&mut IrFunction)| {
+            let mut module = module.clone();
+            f(&mut module.functions[0]);
+            match verify_module(&module) {
+                Err(BackendError::Invalid(msg)) => msg,
+                res => panic!("{:?}", res),
+            }
+        };
+
+        assert_eq!(
+            verify(&|func| func.blocks[0].insts.swap(0, 2)),
+            "in function ´main´: ´v0´ is used in bb0 before it is defined"
+        );
+        assert_eq!(
+            verify(&|func| func.blocks[0].terminator = Terminator::Branch(0, 1, 2)),
+            "in function ´main´: the condition ´v0´ is not a bool"
+        );
+        assert_eq!(
+            verify(&|func| func.blocks[1].terminator = Terminator::Return(Some(3))),
+            "in function ´main´: the phi ´v4´ does not have one value for each predecessor"
+        );
+        assert_eq!(
+            verify(&|func| func.blocks[2].insts[0].kind = InstKind::Phi(vec![(0, 0), (1, 5)])),
+            "in function ´main´: ´v5´ is not defined"
+        );
+        assert_eq!(
+            verify(&|func| func.types[4] = Type::Int64),
+            "in function ´main´: ´v0´ has the wrong type"
+        );
+    }
+
+    #[test]
+    fn ir_unsupported_program() {
+        let program = "
+            fn main() -> i32 {
+                let t: (i32, i32) = (1, 2);
+                return t.0;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            lower_program(tree),
+            Err(BackendError::Unsupported("tuples".to_string()))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use super::*;
+use crate::interpreter::*;
+
+use std::io::{self, Write};
+
+// Runs the IR directly, for testing the lowering and the passes on it. The
+// operations are those of the interpreter, so a program gives the same
+// output and errors as with ´eval_program´.
+
+struct Machine<'a> {
+    module: &'a Module,
+    out: &'a mut dyn Write,
+}
+
+impl<'a> Machine<'a> {
+    fn call(&mut self, name: &str, args: Vec<Value>) -> EvalRes<Value> {
+        let module = self.module;
+        let func = match module.function(name) {
+            Some(func) => func,
+            None => return Err(EvalErr::NotFound(format!("Function ´{}´ not found.", name))),
+        };
+        // C functions are only available to compiled programs
+        if func.is_extern {
+            return Err(EvalErr::NotImplemented);
+        }
+
+        let mut values: Vec<Option<Value>> = vec![None; func.types.len()];
+        for (val, arg) in args.into_iter().enumerate() {
+            values[val] = Some(arg);
+        }
+        let get = |values: &[Option<Value>], val: ValueId| -> EvalRes<Value> {
+            match &values[val] {
+                Some(val) => Ok(val.clone()),
+                None => Err(EvalErr::NotFound(format!("´v{}´ has no value.", val))),
+            }
+        };
+
+        let mut block: BlockId = 0;
+        let mut prev: Option<BlockId> = None;
+        loop {
+            let insts = &func.blocks[block].insts;
+            // The phis of a block are evaluated at the same time.
+            let mut phi_values: Vec<(ValueId, Value)> = Vec::new();
+            for inst in insts.iter() {
+                if let InstKind::Phi(incoming) = &inst.kind {
+                    let from = incoming.iter().find(|(pred, _)| Some(*pred) == prev);
+                    match from {
+                        Some((_, val)) => phi_values.push((inst.result, get(&values, *val)?)),
+                        None => {
+                            return Err(EvalErr::NotFound(format!(
+                                "´v{}´ has no value for the predecessor.",
+                                inst.result
+                            )))
+                        }
+                    }
+                }
+            }
+            for (val, phi_value) in phi_values {
+                values[val] = Some(phi_value);
+            }
+
+            for inst in insts.iter() {
+                let res = match &inst.kind {
+                    InstKind::Phi(_) => continue,
+                    InstKind::Const(val) => val.clone(),
+                    InstKind::BinOp(op, l, r) => {
+                        eval_values(get(&values, *l)?, *op, get(&values, *r)?)?
+                    }
+                    InstKind::Cast(val) => {
+                        eval_cast(get(&values, *val)?, &func.types[inst.result])?
+                    }
+                    InstKind::Call(name, args) => {
+                        let mut arg_values: Vec<Value> = Vec::new();
+                        for arg in args {
+                            arg_values.push(get(&values, *arg)?);
+                        }
+                        self.call(name, arg_values)?
+                    }
+                    InstKind::Print(fmt, args, newline) => {
+                        let mut pieces = fmt.split("{}");
+                        let mut text = pieces.next().unwrap_or("").to_string();
+                        for (piece, arg) in pieces.zip(args.iter()) {
+                            text.push_str(&format_value(&get(&values, *arg)?)?);
+                            text.push_str(piece);
+                        }
+                        if *newline {
+                            text.push('\n');
+                        }
+                        if let Err(e) = self.out.write_all(text.as_bytes()) {
+                            return Err(EvalErr::IoError(e.to_string()));
+                        }
+                        Value::Tuple(vec![])
+                    }
+                };
+                values[inst.result] = Some(res);
+            }
+
+            prev = Some(block);
+            match &func.blocks[block].terminator {
+                Terminator::Jump(target) => block = *target,
+                Terminator::Branch(cond, then_block, else_block) => {
+                    block = match get(&values, *cond)? {
+                        Value::Bool(true) => *then_block,
+                        Value::Bool(false) => *else_block,
+                        _ => {
+                            return Err(EvalErr::WrongType(
+                                "Cannot evaluate condition. Not a boolean expression.".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Terminator::Return(Some(val)) => return get(&values, *val),
+                Terminator::Return(None) => return Ok(Value::Tuple(vec![])),
+                Terminator::Unreachable => {
+                    return Err(EvalErr::NotFound(format!(
+                        "Function ´{}´ did not return a value.",
+                        name
+                    )))
+                }
+            }
+        }
+    }
+}
+
+// Runs the main function of the module and returns the value it returns, or
+// ´()´ as an empty tuple.
+pub fn run_ir(module: &Module) -> EvalRes<Value> {
+    run_ir_with_output(module, &mut io::stdout())
+}
+
+// Same as run_ir but everything the program prints is written to ´out´.
+pub fn run_ir_with_output(module: &Module, out: &mut dyn Write) -> EvalRes<Value> {
+    let mut machine = Machine { module, out };
+    machine.call("main", Vec::new())
+}
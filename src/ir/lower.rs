@@ -0,0 +1,447 @@
+use super::*;
+use crate::backend::*;
+
+use std::collections::HashMap;
+
+// Lowers the typed AST to SSA form. Since the only control flow is ´if´
+// without ´else´ and ´while´, the phis are placed while lowering: the value
+// of every variable on the current path is known, the block after an ´if´
+// gets a phi for each variable assigned in its body, and the header of a
+// loop gets a phi for each variable in scope. Phis that turn out to select a
+// single value are removed afterwards.
+
+struct Builder<'a> {
+    func: IrFunction,
+    current: BlockId,
+    // Blocks that have a terminator
+    terminated: Vec<bool>,
+    // The current value of each variable, and the variables of the blocks
+    // around the statement by name
+    defs: Vec<ValueId>,
+    scopes: Vec<HashMap<String, usize>>,
+    // The return type of every function
+    return_types: &'a HashMap<String, Type>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(BasicBlock {
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        self.terminated.push(false);
+        self.func.blocks.len() - 1
+    }
+
+    fn new_value(&mut self, t: Type) -> ValueId {
+        self.func.types.push(t);
+        self.func.types.len() - 1
+    }
+
+    fn push(&mut self, kind: InstKind, t: Type) -> ValueId {
+        let result = self.new_value(t);
+        self.func.blocks[self.current]
+            .insts
+            .push(Inst { result, kind });
+        result
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.func.blocks[self.current].terminator = terminator;
+        self.terminated[self.current] = true;
+    }
+
+    // Code after a return is lowered to a block without predecessors, which
+    // is removed at the end.
+    fn is_open(&self) -> bool {
+        !self.terminated[self.current]
+    }
+
+    fn lookup(&self, name: &str) -> BackendRes<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| BackendError::UnknownVariable(name.to_string()))
+    }
+
+    fn declare(&mut self, name: &str, val: ValueId) {
+        self.defs.push(val);
+        let var = self.defs.len() - 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), var);
+    }
+
+    fn gen_expr(&mut self, expr: &TypedExpr) -> BackendRes<ValueId> {
+        let t = expr.expr_type.clone();
+        match &expr.kind {
+            TypedExprKind::Num(num) if t == Type::Float64 => {
+                Ok(self.push(InstKind::Const(Value::Float(*num as f64)), t))
+            }
+            TypedExprKind::Num(num) => {
+                let val = match t {
+                    Type::Int32 => Value::Num(*num as i32),
+                    _ => Value::Int(*num, t.clone()),
+                };
+                Ok(self.push(InstKind::Const(val), t))
+            }
+            TypedExprKind::Float(f) => Ok(self.push(InstKind::Const(Value::Float(*f)), t)),
+            TypedExprKind::Bool(b) => Ok(self.push(InstKind::Const(Value::Bool(*b)), t)),
+            TypedExprKind::Char(c) => Ok(self.push(InstKind::Const(Value::Char(*c)), t)),
+            TypedExprKind::Str(s) => Ok(self.push(InstKind::Const(Value::Str(s.clone())), t)),
+            TypedExprKind::Var(name) => Ok(self.defs[self.lookup(name)?]),
+            TypedExprKind::BinOp(l, op, r) => {
+                let l = self.gen_expr(l)?;
+                let r = self.gen_expr(r)?;
+                Ok(self.push(InstKind::BinOp(*op, l, r), t))
+            }
+            TypedExprKind::Cast(inner) => {
+                let val = self.gen_expr(inner)?;
+                Ok(self.push(InstKind::Cast(val), t))
+            }
+            TypedExprKind::FuncCall(name, args) if name == "print" || name == "println" => {
+                let mut args = args.iter();
+                let fmt = match args.next().map(|arg| &arg.kind) {
+                    Some(TypedExprKind::Str(fmt)) => fmt.clone(),
+                    None => String::new(),
+                    _ => return err_unsupported("a format string that is not a literal"),
+                };
+                let mut values: Vec<ValueId> = Vec::new();
+                for arg in args {
+                    values.push(self.gen_expr(arg)?);
+                }
+                let kind = InstKind::Print(fmt, values, name == "println");
+                Ok(self.push(kind, Type::Void))
+            }
+            TypedExprKind::FuncCall(name, args) => {
+                let return_type = match self.return_types.get(name) {
+                    Some(t) => t.clone(),
+                    None => return Err(BackendError::UnknownFunction(name.to_string())),
+                };
+                let mut values: Vec<ValueId> = Vec::new();
+                for arg in args {
+                    values.push(self.gen_expr(arg)?);
+                }
+                Ok(self.push(InstKind::Call(name.to_string(), values), return_type))
+            }
+            TypedExprKind::Tuple(_) | TypedExprKind::TupleIndex(..) => err_unsupported("tuples"),
+            TypedExprKind::VecNew | TypedExprKind::Index(..) | TypedExprKind::MethodCall(..) => {
+                err_unsupported("vectors")
+            }
+            _ => err_unsupported("a statement as a value"),
+        }
+    }
+
+    fn gen_block(&mut self, block: &[TypedExpr]) -> BackendRes<()> {
+        self.scopes.push(HashMap::new());
+        for stmt in block {
+            self.gen_stmt(stmt)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn gen_if(&mut self, cond: &TypedExpr, block: &[TypedExpr]) -> BackendRes<()> {
+        let cond = self.gen_expr(cond)?;
+        let before = self.current;
+        let then_block = self.new_block();
+        let merge = self.new_block();
+        self.terminate(Terminator::Branch(cond, then_block, merge));
+
+        let outer_defs = self.defs.clone();
+        self.current = then_block;
+        self.gen_block(block)?;
+        let then_open = self.is_open();
+        let then_end = self.current;
+        let then_defs: Vec<ValueId> = self.defs.drain(..).take(outer_defs.len()).collect();
+        if then_open {
+            self.terminate(Terminator::Jump(merge));
+        }
+
+        self.current = merge;
+        for (var, outer) in outer_defs.into_iter().enumerate() {
+            let val = if then_open && then_defs[var] != outer {
+                let t = self.func.types[outer].clone();
+                let incoming = vec![(before, outer), (then_end, then_defs[var])];
+                self.push(InstKind::Phi(incoming), t)
+            } else {
+                outer
+            };
+            self.defs.push(val);
+        }
+        Ok(())
+    }
+
+    fn gen_while(&mut self, cond: &TypedExpr, block: &[TypedExpr]) -> BackendRes<()> {
+        let before = self.current;
+        let header = self.new_block();
+        self.terminate(Terminator::Jump(header));
+
+        // Any variable in scope can be assigned in the body.
+        self.current = header;
+        let mut phis: Vec<ValueId> = Vec::new();
+        for var in 0..self.defs.len() {
+            let outer = self.defs[var];
+            let t = self.func.types[outer].clone();
+            let phi = self.push(InstKind::Phi(vec![(before, outer)]), t);
+            self.defs[var] = phi;
+            phis.push(phi);
+        }
+        let cond = self.gen_expr(cond)?;
+        let body = self.new_block();
+        let exit = self.new_block();
+        self.terminate(Terminator::Branch(cond, body, exit));
+
+        self.current = body;
+        self.gen_block(block)?;
+        if self.is_open() {
+            let body_end = self.current;
+            self.terminate(Terminator::Jump(header));
+            for (var, phi) in phis.iter().enumerate() {
+                let inst = self.func.blocks[header]
+                    .insts
+                    .iter_mut()
+                    .find(|inst| inst.result == *phi)
+                    .unwrap();
+                if let InstKind::Phi(incoming) = &mut inst.kind {
+                    incoming.push((body_end, self.defs[var]));
+                }
+            }
+        }
+
+        self.current = exit;
+        self.defs = phis;
+        Ok(())
+    }
+
+    fn gen_stmt(&mut self, stmt: &TypedExpr) -> BackendRes<()> {
+        match &stmt.kind {
+            TypedExprKind::Let(pattern, val) => {
+                let name = match &pattern.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("tuple patterns"),
+                };
+                let val = self.gen_expr(val)?;
+                self.declare(name, val);
+            }
+            TypedExprKind::VarOp(var, op, val) => {
+                let name = match &var.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("assignments to vector elements"),
+                };
+                let val = self.gen_expr(val)?;
+                let var_index = self.lookup(name)?;
+                let new_val = match op {
+                    Op::VarOp(VarToken::Assign) => val,
+                    Op::VarOp(token) => {
+                        let token = match token {
+                            VarToken::PlusEq => MathToken::Plus,
+                            VarToken::MinEq => MathToken::Minus,
+                            _ => MathToken::Multiply,
+                        };
+                        let old = self.defs[var_index];
+                        let kind = InstKind::BinOp(Op::MathOp(token), old, val);
+                        self.push(kind, var.expr_type.clone())
+                    }
+                    op => return err_unsupported(&format!("´{}´ as an assignment", op)),
+                };
+                self.defs[var_index] = new_val;
+            }
+            TypedExprKind::If(cond, block) => self.gen_if(cond, block)?,
+            TypedExprKind::While(cond, block) => self.gen_while(cond, block)?,
+            TypedExprKind::Return(val) => {
+                let val = self.gen_expr(val)?;
+                if self.func.return_type == Type::Void {
+                    self.terminate(Terminator::Return(None));
+                } else {
+                    self.terminate(Terminator::Return(Some(val)));
+                }
+                self.current = self.new_block();
+            }
+            _ => {
+                self.gen_expr(stmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Empties the blocks that cannot be reached from the entry block, and
+// removes their values from the phis of the other blocks. Returns which
+// blocks are reachable.
+fn remove_unreachable(func: &mut IrFunction) -> Vec<bool> {
+    let mut reachable = vec![false; func.blocks.len()];
+    let mut stack: Vec<BlockId> = vec![0];
+    while let Some(block) = stack.pop() {
+        if reachable[block] {
+            continue;
+        }
+        reachable[block] = true;
+        stack.extend(func.blocks[block].terminator.successors());
+    }
+
+    for block in func.blocks.iter_mut() {
+        for inst in block.insts.iter_mut() {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                incoming.retain(|(pred, _)| reachable[*pred]);
+            }
+        }
+    }
+    for (block, is_reachable) in reachable.iter().enumerate() {
+        if !is_reachable {
+            func.blocks[block].insts.clear();
+            func.blocks[block].terminator = Terminator::Unreachable;
+        }
+    }
+    reachable
+}
+
+// Removes phis whose operands are all the same value, or the phi itself, and
+// replaces their uses by that value. Removing a phi can make others trivial.
+fn remove_trivial_phis(func: &mut IrFunction) {
+    loop {
+        let mut trivial: Option<(BlockId, ValueId, ValueId)> = None;
+        'search: for (id, block) in func.blocks.iter().enumerate() {
+            for inst in block.insts.iter() {
+                if let InstKind::Phi(incoming) = &inst.kind {
+                    let mut values = incoming
+                        .iter()
+                        .map(|(_, val)| *val)
+                        .filter(|val| *val != inst.result);
+                    if let Some(first) = values.next() {
+                        if values.all(|val| val == first) {
+                            trivial = Some((id, inst.result, first));
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+
+        match trivial {
+            Some((block, phi, val)) => {
+                func.blocks[block].insts.retain(|inst| inst.result != phi);
+                func.map_values(&|used| if used == phi { val } else { used });
+            }
+            None => return,
+        }
+    }
+}
+
+// Renumbers the blocks without the removed ones, and the values in the order
+// they are defined.
+fn renumber(func: &mut IrFunction, reachable: &[bool]) {
+    let mut block_ids: HashMap<BlockId, BlockId> = HashMap::new();
+    for (block, _) in reachable.iter().enumerate().filter(|(_, r)| **r) {
+        block_ids.insert(block, block_ids.len());
+    }
+    let mut value_ids: HashMap<ValueId, ValueId> = HashMap::new();
+    let mut types: Vec<Type> = func.params.clone();
+    for val in 0..func.params.len() {
+        value_ids.insert(val, val);
+    }
+
+    let old_blocks = std::mem::take(&mut func.blocks);
+    for (id, mut block) in old_blocks.into_iter().enumerate() {
+        if !reachable[id] {
+            continue;
+        }
+        for inst in block.insts.iter_mut() {
+            value_ids.insert(inst.result, types.len());
+            types.push(func.types[inst.result].clone());
+            inst.result = types.len() - 1;
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming.iter_mut() {
+                    *pred = block_ids[pred];
+                }
+            }
+        }
+        block.terminator = match block.terminator {
+            Terminator::Jump(target) => Terminator::Jump(block_ids[&target]),
+            Terminator::Branch(cond, then_block, else_block) => {
+                Terminator::Branch(cond, block_ids[&then_block], block_ids[&else_block])
+            }
+            terminator => terminator,
+        };
+        func.blocks.push(block);
+    }
+    func.types = types;
+    func.map_values(&|val| value_ids[&val]);
+}
+
+fn lower_function(
+    typed_fn: &TypedFunction,
+    return_types: &HashMap<String, Type>,
+) -> BackendRes<IrFunction> {
+    let params: Vec<Type> = typed_fn
+        .params
+        .iter()
+        .map(|param| param.param_type.clone())
+        .collect();
+    let mut builder = Builder {
+        func: IrFunction {
+            name: typed_fn.name.clone(),
+            params: params.clone(),
+            return_type: typed_fn.return_type.clone(),
+            blocks: Vec::new(),
+            types: params,
+            is_extern: typed_fn.is_extern,
+        },
+        current: 0,
+        terminated: Vec::new(),
+        defs: Vec::new(),
+        scopes: vec![HashMap::new()],
+        return_types,
+    };
+    if typed_fn.is_extern {
+        return Ok(builder.func);
+    }
+
+    builder.new_block();
+    for (val, param) in typed_fn.params.iter().enumerate() {
+        builder.declare(&param.name, val);
+    }
+    builder.gen_block(&typed_fn.block)?;
+    if typed_fn.return_type == Type::Void {
+        builder.terminate(Terminator::Return(None));
+    }
+
+    let mut func = builder.func;
+    let reachable = remove_unreachable(&mut func);
+    remove_trivial_phis(&mut func);
+    renumber(&mut func, &reachable);
+    Ok(func)
+}
+
+// Lowers the program to the IR and verifies it. Only programs that pass the
+// type checker are lowered. Tuples and vectors are not supported.
+pub fn lower_program(fn_list: Functions) -> BackendRes<Module> {
+    let typed_fns = check_program(fn_list)?;
+    let mut return_types: HashMap<String, Type> = HashMap::new();
+    for func in typed_fns.iter() {
+        for t in func
+            .params
+            .iter()
+            .map(|param| &param.param_type)
+            .chain(std::iter::once(&func.return_type))
+        {
+            match t {
+                Type::Tuple(_) => return err_unsupported("tuples"),
+                Type::Vec(_) => return err_unsupported("vectors"),
+                _ => (),
+            }
+        }
+        return_types.insert(func.name.clone(), func.return_type.clone());
+    }
+
+    let mut module = Module {
+        functions: Vec::new(),
+    };
+    for func in typed_fns.iter() {
+        module.functions.push(lower_function(func, &return_types)?);
+    }
+    verify_module(&module)?;
+    Ok(module)
+}
@@ -0,0 +1,291 @@
+use super::*;
+use crate::backend::*;
+
+use std::collections::HashMap;
+
+// Checks that a module is well formed: every block is reachable from the
+// entry block, phis are at the start of their block and have one value for
+// each predecessor, every value is defined once and before it is used on
+// every path, and the operands of each instruction have the right types.
+
+fn err_invalid<T>(func: &IrFunction, msg: String) -> BackendRes<T> {
+    Err(BackendError::Invalid(format!(
+        "in function ´{}´: {}",
+        func.name, msg
+    )))
+}
+
+// The type of a constant, or None for values that cannot be constants.
+fn const_type(val: &Value) -> Option<Type> {
+    match val {
+        Value::Num(_) => Some(Type::Int32),
+        Value::Int(_, t) if t.is_int() => Some(t.clone()),
+        Value::Float(_) => Some(Type::Float64),
+        Value::Bool(_) => Some(Type::Bool),
+        Value::Char(_) => Some(Type::Char),
+        Value::Str(_) => Some(Type::Str),
+        _ => None,
+    }
+}
+
+fn is_scalar(t: &Type) -> bool {
+    !matches!(t, Type::Tuple(_) | Type::Vec(_) | Type::Void)
+}
+
+// The blocks that dominate each block, found by iterating until nothing
+// changes. Every block has to be reachable.
+fn dominators(preds: &[Vec<BlockId>]) -> Vec<Vec<bool>> {
+    let n = preds.len();
+    let mut doms: Vec<Vec<bool>> = vec![vec![true; n]; n];
+    doms[0] = vec![false; n];
+    doms[0][0] = true;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in 1..n {
+            let mut new_doms = vec![true; n];
+            for pred in preds[block].iter() {
+                for (dom, pred_dom) in new_doms.iter_mut().zip(doms[*pred].iter()) {
+                    *dom = *dom && *pred_dom;
+                }
+            }
+            new_doms[block] = true;
+            if new_doms != doms[block] {
+                doms[block] = new_doms;
+                changed = true;
+            }
+        }
+    }
+    doms
+}
+
+struct Checker<'a> {
+    func: &'a IrFunction,
+    module: &'a Module,
+    // The block and position of the definition of every value. Parameters
+    // are defined before the first instruction of the entry block.
+    def_sites: HashMap<ValueId, (BlockId, usize)>,
+    doms: Vec<Vec<bool>>,
+}
+
+impl<'a> Checker<'a> {
+    fn value_type(&self, val: ValueId) -> BackendRes<&'a Type> {
+        match self.func.types.get(val) {
+            Some(t) => Ok(t),
+            None => err_invalid(self.func, format!("´v{}´ has no type", val)),
+        }
+    }
+
+    // Checks that the value is defined before the position in the block, on
+    // every path from the entry block.
+    fn check_use(&self, val: ValueId, block: BlockId, pos: usize) -> BackendRes<()> {
+        let defined = match self.def_sites.get(&val) {
+            Some((def_block, def_pos)) if *def_block == block => *def_pos < pos,
+            Some((def_block, _)) => self.doms[block][*def_block],
+            None => return err_invalid(self.func, format!("´v{}´ is not defined", val)),
+        };
+        if defined {
+            Ok(())
+        } else {
+            err_invalid(
+                self.func,
+                format!("´v{}´ is used in bb{} before it is defined", val, block),
+            )
+        }
+    }
+
+    fn check_inst(&self, inst: &Inst, block: BlockId, pos: usize) -> BackendRes<()> {
+        let func = self.func;
+        let result_type = self.value_type(inst.result)?;
+        if let InstKind::Phi(incoming) = &inst.kind {
+            // The value from a predecessor has to be defined at its end.
+            for (pred, val) in incoming.iter() {
+                self.check_use(*val, *pred, usize::MAX)?;
+                if self.value_type(*val)? != result_type {
+                    return err_invalid(func, format!("´v{}´ has the wrong type", val));
+                }
+            }
+            return Ok(());
+        }
+        for val in inst.kind.operands() {
+            self.check_use(val, block, pos)?;
+        }
+
+        let ok = match &inst.kind {
+            InstKind::Const(val) => const_type(val).as_ref() == Some(result_type),
+            InstKind::BinOp(op, l, r) => {
+                let l_type = self.value_type(*l)?;
+                let r_type = self.value_type(*r)?;
+                l_type == r_type
+                    && match op {
+                        Op::MathOp(_) => {
+                            result_type == l_type && (l_type.is_int() || *l_type == Type::Float64)
+                        }
+                        Op::RelOp(_) => *result_type == Type::Bool && is_scalar(l_type),
+                        Op::BoolOp(_) => *result_type == Type::Bool && *l_type == Type::Bool,
+                        Op::VarOp(_) => false,
+                    }
+            }
+            InstKind::Cast(val) => is_scalar(self.value_type(*val)?) && is_scalar(result_type),
+            InstKind::Call(name, args) => {
+                let callee = match self.module.function(name) {
+                    Some(callee) => callee,
+                    None => return err_invalid(func, format!("´{}´ is not defined", name)),
+                };
+                let mut arg_types: Vec<&Type> = Vec::new();
+                for arg in args {
+                    arg_types.push(self.value_type(*arg)?);
+                }
+                *result_type == callee.return_type
+                    && arg_types == callee.params.iter().collect::<Vec<&Type>>()
+            }
+            InstKind::Print(fmt, args, _) => {
+                let mut printable = *result_type == Type::Void;
+                for arg in args {
+                    printable = printable && is_scalar(self.value_type(*arg)?);
+                }
+                printable && fmt.matches("{}").count() == args.len()
+            }
+            InstKind::Phi(_) => unreachable!(),
+        };
+        if ok {
+            Ok(())
+        } else {
+            err_invalid(
+                func,
+                format!("´{}´ has operands of the wrong type", inst.kind),
+            )
+        }
+    }
+
+    fn check_terminator(&self, block: BlockId) -> BackendRes<()> {
+        let func = self.func;
+        let terminator = &func.blocks[block].terminator;
+        for val in terminator.operands() {
+            self.check_use(val, block, usize::MAX)?;
+        }
+        match terminator {
+            Terminator::Branch(cond, _, _) if *self.value_type(*cond)? != Type::Bool => {
+                err_invalid(func, format!("the condition ´v{}´ is not a bool", cond))
+            }
+            Terminator::Return(Some(val)) if *self.value_type(*val)? != func.return_type => {
+                err_invalid(func, format!("´v{}´ is returned with the wrong type", val))
+            }
+            Terminator::Return(None) if func.return_type != Type::Void => {
+                err_invalid(func, "´ret´ without a value".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn verify_function(func: &IrFunction, module: &Module) -> BackendRes<()> {
+    if func.is_extern {
+        if func.blocks.is_empty() {
+            return Ok(());
+        }
+        return err_invalid(func, "C functions have no blocks".to_string());
+    }
+    if func.blocks.is_empty() {
+        return err_invalid(func, "the function has no blocks".to_string());
+    }
+    for (block_id, block) in func.blocks.iter().enumerate() {
+        for succ in block.terminator.successors() {
+            if succ >= func.blocks.len() {
+                return err_invalid(func, format!("bb{} jumps to a missing block", block_id));
+            }
+        }
+    }
+
+    let preds = func.predecessors();
+    if !preds[0].is_empty() {
+        return err_invalid(func, "the entry block has predecessors".to_string());
+    }
+    let mut reachable = vec![false; func.blocks.len()];
+    let mut stack: Vec<BlockId> = vec![0];
+    while let Some(block) = stack.pop() {
+        if !reachable[block] {
+            reachable[block] = true;
+            stack.extend(func.blocks[block].terminator.successors());
+        }
+    }
+    if let Some(block) = reachable.iter().position(|r| !r) {
+        return err_invalid(func, format!("bb{} is not reachable", block));
+    }
+
+    let mut def_sites: HashMap<ValueId, (BlockId, usize)> = HashMap::new();
+    for val in 0..func.params.len() {
+        def_sites.insert(val, (0, 0));
+        if func.types.get(val) != Some(&func.params[val]) {
+            return err_invalid(func, format!("the parameter ´v{}´ has the wrong type", val));
+        }
+    }
+    for (block_id, block) in func.blocks.iter().enumerate() {
+        let mut phis_done = false;
+        for (pos, inst) in block.insts.iter().enumerate() {
+            // Parameters are defined at position 0, so instructions start at 1.
+            if def_sites.insert(inst.result, (block_id, pos + 1)).is_some() {
+                return err_invalid(func, format!("´v{}´ is defined twice", inst.result));
+            }
+            match &inst.kind {
+                InstKind::Phi(_) if phis_done => {
+                    return err_invalid(
+                        func,
+                        format!(
+                            "the phi ´v{}´ is not at the start of bb{}",
+                            inst.result, block_id
+                        ),
+                    )
+                }
+                InstKind::Phi(incoming) => {
+                    let mut from: Vec<BlockId> = incoming.iter().map(|(pred, _)| *pred).collect();
+                    let mut expected = preds[block_id].clone();
+                    from.sort();
+                    expected.sort();
+                    if from != expected {
+                        return err_invalid(
+                            func,
+                            format!(
+                                "the phi ´v{}´ does not have one value for each predecessor",
+                                inst.result
+                            ),
+                        );
+                    }
+                }
+                _ => phis_done = true,
+            }
+        }
+    }
+
+    let checker = Checker {
+        func,
+        module,
+        def_sites,
+        doms: dominators(&preds),
+    };
+    for (block_id, block) in func.blocks.iter().enumerate() {
+        for (pos, inst) in block.insts.iter().enumerate() {
+            checker.check_inst(inst, block_id, pos + 1)?;
+        }
+        checker.check_terminator(block_id)?;
+    }
+    Ok(())
+}
+
+// Verifies every function of the module. Errors are
+// ´BackendError::Invalid´ with the name of the function.
+pub fn verify_module(module: &Module) -> BackendRes<()> {
+    for (i, func) in module.functions.iter().enumerate() {
+        if module.functions[..i]
+            .iter()
+            .any(|other| other.name == func.name)
+        {
+            return Err(BackendError::Invalid(format!(
+                "´{}´ is defined twice",
+                func.name
+            )));
+        }
+        verify_function(func, module)?;
+    }
+    Ok(())
+}
@@ -1,8 +1,17 @@
 pub mod ast;
+pub mod backend;
+pub mod bytecode;
+pub mod c99;
 pub mod context;
+pub mod fold;
 pub mod interpreter;
+pub mod ir;
 pub mod llvm;
+pub mod optimize;
 pub mod parser;
 pub mod type_checker;
+pub mod vm;
+pub mod wasm;
+pub mod x86_64;
 
 //fn compile_file() {}
@@ -1,44 +1,136 @@
 use crate::ast::*;
 use crate::parser::*;
+use crate::type_checker::*;
+
+mod debug;
+mod header;
+mod jit;
+mod passes;
+mod runtime;
+
+pub use jit::JitSession;
+pub use passes::OptLevel;
 
 use inkwell::{
+    attributes::{Attribute, AttributeLoc},
     builder::Builder,
     context::Context,
-    execution_engine::{ExecutionEngine, JitFunction},
-    module::Module,
-    passes::PassManager,
-    types::BasicTypeEnum,
-    values::{BasicValueEnum, FloatValue, FunctionValue, InstructionValue, IntValue, PointerValue},
-    FloatPredicate, IntPredicate, OptimizationLevel,
+    execution_engine::JitFunction,
+    module::{Linkage, Module},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
+    types::{BasicType, BasicTypeEnum, IntType},
+    values::{
+        BasicValueEnum, CallSiteValue, FloatValue, FunctionValue, InstructionValue, IntValue,
+        PointerValue, StructValue,
+    },
+    AddressSpace, FloatPredicate, IntPredicate,
 };
 use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::{env, process};
 
 type ExprFunc = unsafe extern "C" fn() -> i32;
 
+// Declared by the runtime and therefore not usable as function names
+const RUNTIME_C_FUNCTIONS: [&str; 5] = ["printf", "dprintf", "exit", "realloc", "free"];
+
+// LLVM's number for the C calling convention
+const CALL_CONV_C: u32 = 0;
+
+pub type CompileRes<T> = Result<T, CompileError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    // The program was rejected by the type checker
+    TypeError(String),
+    UnknownVariable(String),
+    UnknownFunction(String),
+    MissingMain,
+    // The program uses a feature that cannot be compiled
+    Unsupported(String),
+    // The generated module was rejected by the LLVM verifier
+    Verify(String),
+    // The execution engine could not be created or main could not be found
+    Jit(String),
+    // The arguments do not match the signature of the called function
+    InvalidArgs(String),
+    // A function called in a JIT session panicked
+    Panic(String),
+    // No target machine could be created for the target
+    Target(String),
+    // An output file could not be written
+    Io(String),
+    // The system linker failed to produce an executable
+    Link(String),
+}
+
+impl From<TypeErr> for CompileError {
+    fn from(e: TypeErr) -> Self {
+        CompileError::TypeError(e.0)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::TypeError(msg) => write!(f, "type error: {}", msg),
+            CompileError::UnknownVariable(var) => write!(f, "cannot find variable ´{}´", var),
+            CompileError::UnknownFunction(name) => write!(f, "cannot find function ´{}´", name),
+            CompileError::MissingMain => write!(f, "no ´main´ function found"),
+            CompileError::Unsupported(what) => write!(f, "cannot compile {}", what),
+            CompileError::Verify(msg) => write!(f, "invalid module: {}", msg),
+            CompileError::Jit(msg) => write!(f, "jit error: {}", msg),
+            CompileError::InvalidArgs(msg) => write!(f, "invalid arguments: {}", msg),
+            CompileError::Panic(msg) => write!(f, "{}", msg),
+            CompileError::Target(msg) => write!(f, "target error: {}", msg),
+            CompileError::Io(msg) => write!(f, "could not write output: {}", msg),
+            CompileError::Link(msg) => write!(f, "linking failed: {}", msg),
+        }
+    }
+}
+
+fn err_unsupported<T>(what: &str) -> CompileRes<T> {
+    Err(CompileError::Unsupported(what.to_string()))
+}
+
 pub struct Compiler<'a> {
     pub context: &'a Context,
     pub builder: &'a Builder,
     pub module: &'a Module,
-    execution_engine: &'a ExecutionEngine,
-    variables: HashMap<String, PointerValue>,
+    // Variables of the blocks around the statement being compiled, so that a
+    // variable declared in a block is dropped when the block ends
+    variables: Vec<HashMap<String, PointerValue>>,
     fn_value_opt: Option<FunctionValue>,
+    // Variables holding vectors, freed when their block ends
+    vec_scopes: Vec<Vec<PointerValue>>,
+    debug: Option<debug::DebugInfo>,
+    overflow_checks: bool,
+    // Panics return to the entry function instead of exiting the process
+    recover_panics: bool,
+    // Source file and position of the statement being compiled, reported
+    // by panics
+    source_name: String,
+    span: Option<Span>,
 }
 
 impl<'a> Compiler<'a> {
     #[inline]
-    fn get_function(&self, name: &str) -> Option<FunctionValue> {
-        self.module.get_function(name)
+    fn get_function(&self, name: &str) -> CompileRes<FunctionValue> {
+        self.module
+            .get_function(name)
+            .ok_or_else(|| CompileError::UnknownFunction(name.to_string()))
     }
 
     #[inline]
-    fn get_variable(&self, name: &str) -> &PointerValue {
-        match self.variables.get(name) {
-            Some(var) => var,
-            None => panic!(
-                "Could not find a matching variable, {} in {:?}",
-                name, self.variables
-            ),
-        }
+    fn get_variable(&self, name: &str) -> CompileRes<PointerValue> {
+        self.variables
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| CompileError::UnknownVariable(name.to_string()))
     }
 
     #[inline]
@@ -46,39 +138,523 @@ impl<'a> Compiler<'a> {
         self.fn_value_opt.unwrap()
     }
 
-    fn compile_expr(&self, expr: Expr) -> IntValue {
-        match expr.clone() {
-            Expr::Var(var) => {
-                let val = self.get_variable(&var);
-                self.builder.build_load(*val, &var).into_int_value()
+    // Returns the LLVM integer type of the same width. Signedness is not
+    // part of LLVM types but of the instructions operating on them.
+    fn int_type(&self, t: &Type) -> CompileRes<IntType> {
+        match t.bit_width() {
+            Some(8) => Ok(self.context.i8_type()),
+            Some(16) => Ok(self.context.i16_type()),
+            Some(32) => Ok(self.context.i32_type()),
+            Some(64) => Ok(self.context.i64_type()),
+            _ => err_unsupported(&format!(
+                "values of type ´{}´ as integers",
+                String::from(t.clone())
+            )),
+        }
+    }
+
+    // Returns the LLVM type used to represent values of the given type.
+    fn basic_type(&self, t: &Type) -> CompileRes<BasicTypeEnum> {
+        Ok(match t {
+            t if t.is_int() => self.int_type(t)?.into(),
+            Type::Float64 => self.context.f64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            // Chars are stored as their code point and strings as a pointer to
+            // a NUL-terminated global constant.
+            Type::Char => self.context.i32_type().into(),
+            Type::Str => self
+                .context
+                .i8_type()
+                .ptr_type(AddressSpace::Generic)
+                .into(),
+            Type::Vec(_) => self.rt_vec_type().into(),
+            Type::Tuple(types) => {
+                let field_types = types
+                    .iter()
+                    .map(|t| self.basic_type(t))
+                    .collect::<CompileRes<Vec<BasicTypeEnum>>>()?;
+                self.context.struct_type(&field_types, false).into()
             }
-            Expr::Num(i) => self.compile_num(i),
-            Expr::Bool(b) => {
+            t => return err_unsupported(&format!("values of type ´{}´", String::from(t.clone()))),
+        })
+    }
+
+    // Compiles an expression. The type annotation decides the LLVM type of
+    // the value, e.g. integer literals are compiled to the width of their type.
+    fn compile_expr(&self, expr: TypedExpr) -> CompileRes<BasicValueEnum> {
+        let expr_type = expr.expr_type;
+        Ok(match expr.kind {
+            // Vectors are copied out of variables, like in the interpreter.
+            // The variable keeps its own vector, which is freed when its
+            // block ends.
+            TypedExprKind::Var(var) => {
+                let val = self.get_variable(&var)?;
+                match &expr_type {
+                    Type::Vec(elem_type) => self
+                        .builder
+                        .build_call(
+                            self.rt_vec_clone(),
+                            &[val.into(), self.size_of(elem_type)?.into()],
+                            "copy",
+                        )
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap(),
+                    _ => self.builder.build_load(val, &var),
+                }
+            }
+            TypedExprKind::Num(num) => self
+                .int_type(&expr_type)?
+                .const_int(num as u64, expr_type.is_signed())
+                .into(),
+            TypedExprKind::Float(f) => self.context.f64_type().const_float(f).into(),
+            TypedExprKind::Bool(b) => {
                 if b {
-                    self.context.bool_type().const_int(1, false)
+                    self.context.bool_type().const_int(1, false).into()
                 } else {
-                    self.context.bool_type().const_int(0, false)
+                    self.context.bool_type().const_int(0, false).into()
                 }
             }
-            Expr::BinOp(l, op, r) => self.compile_bin_op(*l, op, *r),
-            Expr::FuncCall(fn_call) => self.compile_function_call(fn_call),
-            _ => unimplemented!(),
+            TypedExprKind::Char(c) => self.context.i32_type().const_int(c as u64, false).into(),
+            TypedExprKind::Str(s) => self
+                .builder
+                .build_global_string_ptr(&s, "str")
+                .as_pointer_value()
+                .into(),
+            TypedExprKind::BinOp(l, op, r) => self.compile_bin_op(*l, op, *r)?,
+            TypedExprKind::Cast(expr) => self.compile_cast(*expr, &expr_type)?,
+            TypedExprKind::Tuple(exprs) => {
+                let values = exprs
+                    .into_iter()
+                    .map(|e| self.compile_expr(e))
+                    .collect::<CompileRes<Vec<BasicValueEnum>>>()?;
+                self.build_tuple(values).into()
+            }
+            TypedExprKind::TupleIndex(expr, index) => {
+                let tuple = self.compile_expr(*expr)?.into_struct_value();
+                self.builder
+                    .build_extract_value(tuple, index as u32, "tupleidx")
+                    .unwrap()
+            }
+            // Functions without a return value have no value to use.
+            TypedExprKind::FuncCall(name, args) => {
+                match self
+                    .compile_function_call(&name, args)?
+                    .try_as_basic_value()
+                    .left()
+                {
+                    Some(val) => val,
+                    None => {
+                        return err_unsupported(&format!(
+                            "the value of ´{}´ which returns nothing",
+                            name
+                        ))
+                    }
+                }
+            }
+            TypedExprKind::VecNew => self.compile_vec_new().into(),
+            TypedExprKind::Index(expr, index) => {
+                let elem_ptr = self.compile_elem_ptr(*expr, *index)?;
+                self.builder.build_load(elem_ptr, "elem")
+            }
+            TypedExprKind::MethodCall(expr, name, _) if name == "len" => self
+                .builder
+                .build_extract_value(self.compile_vec_operand(*expr)?, 1, "len")
+                .unwrap(),
+            TypedExprKind::MethodCall(expr, name, _) if name == "pop" => {
+                self.compile_vec_pop(*expr)?
+            }
+            TypedExprKind::MethodCall(_, name, _) => {
+                return err_unsupported(&format!("the method ´{}´", name))
+            }
+            _ => return err_unsupported("a statement as an expression"),
+        })
+    }
+
+    fn compile_vec_new(&self) -> StructValue {
+        let null = self
+            .context
+            .i8_type()
+            .ptr_type(AddressSpace::Generic)
+            .const_null();
+        let zero = self.context.i64_type().const_int(0, false);
+        self.context
+            .const_struct(&[null.into(), zero.into(), zero.into()], false)
+    }
+
+    // Compiles the vector of an indexing or a call to ´len´. Vectors in
+    // variables are only borrowed and not copied.
+    fn compile_vec_operand(&self, expr: TypedExpr) -> CompileRes<StructValue> {
+        match expr.kind {
+            TypedExprKind::Var(var) => Ok(self
+                .builder
+                .build_load(self.get_variable(&var)?, &var)
+                .into_struct_value()),
+            _ => Ok(self.compile_expr(expr)?.into_struct_value()),
+        }
+    }
+
+    // Returns the variable of the vector a method mutates.
+    fn compile_vec_place(&self, expr: TypedExpr, method: &str) -> CompileRes<PointerValue> {
+        match expr.kind {
+            TypedExprKind::Var(var) => self.get_variable(&var),
+            _ => err_unsupported(&format!(
+                "´{}´ on a vector not stored in a variable",
+                method
+            )),
         }
     }
 
-    fn compile_num(&self, num: i32) -> IntValue {
-        self.context.i32_type().const_int(num as u64, false)
+    fn elem_type(&self, vec_expr: &TypedExpr) -> CompileRes<Type> {
+        match &vec_expr.expr_type {
+            Type::Vec(elem_type) => Ok(*elem_type.clone()),
+            t => err_unsupported(&format!("indexing into ´{}´", String::from(t.clone()))),
+        }
+    }
+
+    // Returns the number of bytes needed to store the type.
+    fn size_of(&self, t: &Type) -> CompileRes<IntValue> {
+        match self.basic_type(t)? {
+            BasicTypeEnum::IntType(t) => Ok(t.size_of()),
+            BasicTypeEnum::FloatType(t) => Ok(t.size_of()),
+            _ => err_unsupported(&format!("vectors of ´{}´", String::from(t.clone()))),
+        }
+    }
+
+    // Calls the panic routine if the condition is true. Code following the check
+    // is placed in a new block. The panic reports the position of the current
+    // statement.
+    fn compile_panic_if(&self, cond: IntValue, msg: &str) {
+        let panic_block = self.context.append_basic_block(&self.fn_value(), "panic");
+        let ok_block = self.context.append_basic_block(&self.fn_value(), "ok");
+        self.builder
+            .build_conditional_branch(cond, &panic_block, &ok_block);
+
+        self.builder.position_at_end(&panic_block);
+        self.compile_panic(msg);
+
+        self.builder.position_at_end(&ok_block);
     }
 
-    fn compile_bin_op(&self, l: Expr, op: Op, r: Expr) -> IntValue {
-        let l_val = self.compile_expr(l);
-        let r_val = self.compile_expr(r);
+    // Calls the panic routine, which does not return, at the end of the current
+    // block.
+    fn compile_panic(&self, msg: &str) {
+        let msg = self.builder.build_global_string_ptr(msg, "panicmsg");
+        let file = self
+            .builder
+            .build_global_string_ptr(&self.source_name, "panicfile");
+        let (line, column) = self.span.map_or((0, 0), |span| (span.line, span.column));
+        let i32_type = self.context.i32_type();
+        self.builder.build_call(
+            self.rt_panic(),
+            &[
+                msg.as_pointer_value().into(),
+                file.as_pointer_value().into(),
+                i32_type.const_int(line as u64, false).into(),
+                i32_type.const_int(column as u64, false).into(),
+            ],
+            "panic",
+        );
+        self.builder.build_unreachable();
+    }
 
+    // Returns a pointer to the element of a vector. The index is checked
+    // against the length of the vector.
+    fn compile_elem_ptr(&self, vec_expr: TypedExpr, index: TypedExpr) -> CompileRes<PointerValue> {
+        let elem_type = self.basic_type(&self.elem_type(&vec_expr)?)?;
+        let vec = self.compile_vec_operand(vec_expr)?;
+        let index = self.compile_expr(index)?.into_int_value();
+
+        let len = self
+            .builder
+            .build_extract_value(vec, 1, "len")
+            .unwrap()
+            .into_int_value();
+        let out_of_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::UGE, index, len, "oob");
+        self.compile_panic_if(out_of_bounds, "index out of bounds");
+
+        let data = self.builder.build_extract_value(vec, 0, "data").unwrap();
+        let elems = self.builder.build_pointer_cast(
+            data.into_pointer_value(),
+            elem_type.ptr_type(AddressSpace::Generic),
+            "elems",
+        );
+        Ok(unsafe { self.builder.build_gep(elems, &[index], "elemptr") })
+    }
+
+    fn compile_vec_push(
+        &self,
+        vec_expr: TypedExpr,
+        val: TypedExpr,
+    ) -> CompileRes<InstructionValue> {
+        let elem_type = self.elem_type(&vec_expr)?;
+        let vec_ptr = self.compile_vec_place(vec_expr, "push")?;
+        let val = self.compile_expr(val)?;
+
+        self.builder.build_call(
+            self.rt_vec_reserve(),
+            &[vec_ptr.into(), self.size_of(&elem_type)?.into()],
+            "reserve",
+        );
+        let vec = self.builder.build_load(vec_ptr, "vec").into_struct_value();
+        let data = self.builder.build_extract_value(vec, 0, "data").unwrap();
+        let len = self
+            .builder
+            .build_extract_value(vec, 1, "len")
+            .unwrap()
+            .into_int_value();
+        let elems = self.builder.build_pointer_cast(
+            data.into_pointer_value(),
+            self.basic_type(&elem_type)?.ptr_type(AddressSpace::Generic),
+            "elems",
+        );
+        let elem_ptr = unsafe { self.builder.build_gep(elems, &[len], "elemptr") };
+        self.builder.build_store(elem_ptr, val);
+
+        let one = self.context.i64_type().const_int(1, false);
+        let new_len = self.builder.build_int_add(len, one, "newlen");
+        let vec = self
+            .builder
+            .build_insert_value(vec, new_len, 1, "vec")
+            .unwrap();
+        Ok(self.builder.build_store(vec_ptr, vec))
+    }
+
+    // Popping an empty vector panics.
+    fn compile_vec_pop(&self, vec_expr: TypedExpr) -> CompileRes<BasicValueEnum> {
+        let elem_type = self.basic_type(&self.elem_type(&vec_expr)?)?;
+        let vec_ptr = self.compile_vec_place(vec_expr, "pop")?;
+
+        let vec = self.builder.build_load(vec_ptr, "vec").into_struct_value();
+        let len = self
+            .builder
+            .build_extract_value(vec, 1, "len")
+            .unwrap()
+            .into_int_value();
+        let zero = self.context.i64_type().const_int(0, false);
+        let is_empty = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, len, zero, "isempty");
+        self.compile_panic_if(is_empty, "called `pop` on an empty vector");
+
+        let one = self.context.i64_type().const_int(1, false);
+        let new_len = self.builder.build_int_sub(len, one, "newlen");
+        let data = self.builder.build_extract_value(vec, 0, "data").unwrap();
+        let elems = self.builder.build_pointer_cast(
+            data.into_pointer_value(),
+            elem_type.ptr_type(AddressSpace::Generic),
+            "elems",
+        );
+        let elem_ptr = unsafe { self.builder.build_gep(elems, &[new_len], "elemptr") };
+        let elem = self.builder.build_load(elem_ptr, "elem");
+
+        let vec = self
+            .builder
+            .build_insert_value(vec, new_len, 1, "vec")
+            .unwrap();
+        self.builder.build_store(vec_ptr, vec);
+        Ok(elem)
+    }
+
+    // Frees the data of the vectors stored in the variables.
+    fn compile_drop_vecs(&self, vec_ptrs: &[PointerValue]) {
+        for vec_ptr in vec_ptrs {
+            let vec = self.builder.build_load(*vec_ptr, "vec").into_struct_value();
+            let data = self.builder.build_extract_value(vec, 0, "data").unwrap();
+            self.builder.build_call(self.rt_free(), &[data], "free");
+        }
+    }
+
+    // Tuples are represented as anonymous structs and passed around by value.
+    fn build_tuple(&self, values: Vec<BasicValueEnum>) -> StructValue {
+        let field_types: Vec<BasicTypeEnum> = values.iter().map(|v| v.get_type()).collect();
+
+        let mut tuple = self.context.struct_type(&field_types, false).get_undef();
+        for (i, val) in values.into_iter().enumerate() {
+            tuple = self
+                .builder
+                .build_insert_value(tuple, val, i as u32, "tuple")
+                .unwrap()
+                .into_struct_value();
+        }
+        tuple
+    }
+
+    // Integer casts truncate or extend the value depending on the widths. The value
+    // is sign-extended if the source type is signed, otherwise zero-extended.
+    // Floats are saturated to the range of the integer type.
+    // Chars are cast as the u32 of their code point.
+    fn compile_cast(&self, expr: TypedExpr, target: &Type) -> CompileRes<BasicValueEnum> {
+        let char_as_int = |t: Type| match t {
+            Type::Char => Type::UInt32,
+            t => t,
+        };
+        let src_type = char_as_int(expr.expr_type.clone());
+        let target = &char_as_int(target.clone());
+        let val = self.compile_expr(expr)?;
+
+        Ok(match (&src_type, target) {
+            (Type::Float64, Type::Float64) => val,
+            (Type::Float64, t) => self.compile_float_to_int(val.into_float_value(), t)?.into(),
+            (src, Type::Float64) if src.is_signed() => self
+                .builder
+                .build_signed_int_to_float(val.into_int_value(), self.context.f64_type(), "sitofp")
+                .into(),
+            (_, Type::Float64) => self
+                .builder
+                .build_unsigned_int_to_float(
+                    val.into_int_value(),
+                    self.context.f64_type(),
+                    "uitofp",
+                )
+                .into(),
+            _ => self
+                .compile_int_cast(val.into_int_value(), &src_type, target)?
+                .into(),
+        })
+    }
+
+    // Converts like ´as´ in Rust: NaN becomes 0 and values out of range the
+    // minimum or maximum of the type. LLVM 8 has no saturating conversion,
+    // and fptosi and fptoui give undefined values out of range, so those
+    // values are replaced by 0 before the conversion and the result is
+    // selected afterwards.
+    fn compile_float_to_int(&self, val: FloatValue, t: &Type) -> CompileRes<IntValue> {
+        let int_type = self.int_type(t)?;
+        let (min, max) = match t.int_range() {
+            Some(range) => range,
+            None => return err_unsupported(&format!("casts to ´{}´", String::from(t.clone()))),
+        };
+        let f64_type = self.context.f64_type();
+        let compare = |predicate: FloatPredicate, limit: f64, name: &str| {
+            self.builder
+                .build_float_compare(predicate, val, f64_type.const_float(limit), name)
+        };
+        // Both limits are powers of two, which are exact as floats.
+        let is_nan = compare(FloatPredicate::UNO, 0.0, "isnan");
+        let below = compare(FloatPredicate::OLT, min as f64, "below");
+        let above = compare(FloatPredicate::OGE, (max + 1) as f64, "above");
+
+        let out_of_range = self.builder.build_or(
+            is_nan,
+            self.builder.build_or(below, above, "outofrange"),
+            "outofrange",
+        );
+        let in_range = self
+            .builder
+            .build_select(out_of_range, f64_type.const_float(0.0), val, "inrange")
+            .into_float_value();
+        let converted = if t.is_signed() {
+            self.builder
+                .build_float_to_signed_int(in_range, int_type, "fptosi")
+        } else {
+            self.builder
+                .build_float_to_unsigned_int(in_range, int_type, "fptoui")
+        };
+
+        let select = |cond: IntValue, then: IntValue, otherwise: IntValue| {
+            self.builder
+                .build_select(cond, then, otherwise, "sat")
+                .into_int_value()
+        };
+        let res = select(above, int_type.const_int(max as u64, false), converted);
+        let res = select(below, int_type.const_int(min as u64, true), res);
+        Ok(select(is_nan, int_type.const_int(0, false), res))
+    }
+
+    fn compile_int_cast(
+        &self,
+        val: IntValue,
+        src_type: &Type,
+        target: &Type,
+    ) -> CompileRes<IntValue> {
+        let target_type = self.int_type(target)?;
+
+        let src_width = val.get_type().get_bit_width();
+        let target_width = target_type.get_bit_width();
+        Ok(if src_width > target_width {
+            self.builder.build_int_truncate(val, target_type, "trunc")
+        } else if src_width < target_width && src_type.is_signed() {
+            self.builder.build_int_s_extend(val, target_type, "sext")
+        } else if src_width < target_width {
+            self.builder.build_int_z_extend(val, target_type, "zext")
+        } else {
+            val
+        })
+    }
+
+    fn compile_bin_op(&self, l: TypedExpr, op: Op, r: TypedExpr) -> CompileRes<BasicValueEnum> {
+        // Both operands have the same type after type checking.
+        let operand_type = l.expr_type.clone();
+        let l_val = self.compile_expr(l)?;
+        let r_val = self.compile_expr(r)?;
+
+        if operand_type == Type::Float64 {
+            let (l_val, r_val) = (l_val.into_float_value(), r_val.into_float_value());
+            return match op {
+                Op::MathOp(token) => Ok(self.compile_float_math_op(l_val, token, r_val).into()),
+                Op::RelOp(token) => Ok(self.compile_float_rel_op(l_val, token, r_val).into()),
+                op => err_unsupported(&format!("´{}´ on floats", op)),
+            };
+        }
+
+        let (l_val, r_val) = (l_val.into_int_value(), r_val.into_int_value());
         match op {
-            Op::BoolOp(token) => self.compile_bool_op(l_val, token, r_val),
-            Op::MathOp(token) => self.compile_math_op(l_val, token, r_val),
-            Op::RelOp(token) => self.compile_rel_op(l_val, token, r_val),
-            _ => panic!("Not a valid expression"),
+            Op::BoolOp(token) => Ok(self.compile_bool_op(l_val, token, r_val).into()),
+            Op::MathOp(token) => Ok(self
+                .compile_math_op(l_val, token, r_val, &operand_type)
+                .into()),
+            Op::RelOp(token) => Ok(self
+                .compile_rel_op(l_val, token, r_val, &operand_type)
+                .into()),
+            op => err_unsupported(&format!("´{}´ as a binary operator", op)),
+        }
+    }
+
+    fn compile_float_math_op(&self, l: FloatValue, token: MathToken, r: FloatValue) -> FloatValue {
+        match token {
+            MathToken::Plus => self.builder.build_float_add(l, r, "fadd"),
+            MathToken::Minus => self.builder.build_float_sub(l, r, "fsub"),
+            MathToken::Multiply => self.builder.build_float_mul(l, r, "fmul"),
+            MathToken::Division => self.builder.build_float_div(l, r, "fdiv"),
+        }
+    }
+
+    // Comparisons are ordered except for ´!=´ which is true if either value is NaN.
+    fn compile_float_rel_op(&self, l: FloatValue, token: RelToken, r: FloatValue) -> IntValue {
+        match token {
+            RelToken::Equal => self
+                .builder
+                .build_float_compare(FloatPredicate::OEQ, l, r, "feq"),
+            RelToken::Ge => self
+                .builder
+                .build_float_compare(FloatPredicate::OGT, l, r, "fge"),
+            RelToken::Le => self
+                .builder
+                .build_float_compare(FloatPredicate::OLT, l, r, "fle"),
+            RelToken::Neq => self
+                .builder
+                .build_float_compare(FloatPredicate::UNE, l, r, "fneq"),
+        }
+    }
+
+    // Arithmetic on values of either integer or float type.
+    fn compile_arith_op(
+        &self,
+        l: BasicValueEnum,
+        token: MathToken,
+        r: BasicValueEnum,
+        t: &Type,
+    ) -> BasicValueEnum {
+        match t {
+            Type::Float64 => self
+                .compile_float_math_op(l.into_float_value(), token, r.into_float_value())
+                .into(),
+            _ => self
+                .compile_math_op(l.into_int_value(), token, r.into_int_value(), t)
+                .into(),
         }
     }
 
@@ -89,74 +665,146 @@ impl<'a> Compiler<'a> {
         }
     }
 
-    fn compile_math_op(&self, l: IntValue, token: MathToken, r: IntValue) -> IntValue {
+    // Overflow wraps around unless overflow checks are enabled.
+    fn compile_math_op(&self, l: IntValue, token: MathToken, r: IntValue, t: &Type) -> IntValue {
         match token {
+            MathToken::Division => self.compile_div(l, r, t),
+            _ if self.overflow_checks => self.compile_checked_op(l, token, r, t),
             MathToken::Plus => self.builder.build_int_add(l, r, "add"),
             MathToken::Minus => self.builder.build_int_sub(l, r, "sub"),
             MathToken::Multiply => self.builder.build_int_mul(l, r, "mul"),
-            MathToken::Division => self.builder.build_int_signed_div(l, r, "div"),
         }
     }
 
-    fn compile_rel_op(&self, l: IntValue, token: RelToken, r: IntValue) -> IntValue {
+    // Arithmetic using the overflow intrinsics. Panics like a debug build of
+    // rustc if the result does not fit the type.
+    fn compile_checked_op(&self, l: IntValue, token: MathToken, r: IntValue, t: &Type) -> IntValue {
+        let (op, verb) = match token {
+            MathToken::Plus => ("add", "add"),
+            MathToken::Minus => ("sub", "subtract"),
+            MathToken::Multiply => ("mul", "multiply"),
+            MathToken::Division => return self.compile_div(l, r, t),
+        };
+        let sign = if t.is_signed() { "s" } else { "u" };
+        let intrinsic = self.rt_overflow_intrinsic(&format!("{}{}", sign, op), l.get_type());
+
+        let res = self
+            .builder
+            .build_call(intrinsic, &[l.into(), r.into()], op)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let overflow = self
+            .builder
+            .build_extract_value(res, 1, "overflow")
+            .unwrap()
+            .into_int_value();
+        self.compile_panic_if(overflow, &format!("attempt to {} with overflow", verb));
+        self.builder
+            .build_extract_value(res, 0, op)
+            .unwrap()
+            .into_int_value()
+    }
+
+    // Division by zero and the signed division ´MIN / -1´ panic, also when
+    // overflow checks are disabled, as in Rust.
+    fn compile_div(&self, l: IntValue, r: IntValue, t: &Type) -> IntValue {
+        let int_type = l.get_type();
+        let is_zero = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            r,
+            int_type.const_int(0, false),
+            "iszero",
+        );
+        self.compile_panic_if(is_zero, "attempt to divide by zero");
+
+        if !t.is_signed() {
+            return self.builder.build_int_unsigned_div(l, r, "div");
+        }
+        let bit_width = int_type.get_bit_width();
+        let min = int_type.const_int(1 << (bit_width - 1), false);
+        let is_min = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, l, min, "ismin");
+        let is_minus_one = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            r,
+            int_type.const_all_ones(),
+            "isminusone",
+        );
+        let overflow = self.builder.build_and(is_min, is_minus_one, "overflow");
+        self.compile_panic_if(overflow, "attempt to divide with overflow");
+        self.builder.build_int_signed_div(l, r, "div")
+    }
+
+    // Booleans are compared as unsigned values so that ´true > false´.
+    fn compile_rel_op(&self, l: IntValue, token: RelToken, r: IntValue, t: &Type) -> IntValue {
+        let (gt, lt) = if t.is_signed() {
+            (IntPredicate::SGT, IntPredicate::SLT)
+        } else {
+            (IntPredicate::UGT, IntPredicate::ULT)
+        };
+
         match token {
             RelToken::Equal => self.builder.build_int_compare(IntPredicate::EQ, l, r, "eq"),
-            RelToken::Ge => self
-                .builder
-                .build_int_compare(IntPredicate::SGT, l, r, "ge"),
-            RelToken::Le => self
-                .builder
-                .build_int_compare(IntPredicate::SLT, l, r, "le"),
+            RelToken::Ge => self.builder.build_int_compare(gt, l, r, "ge"),
+            RelToken::Le => self.builder.build_int_compare(lt, l, r, "le"),
             RelToken::Neq => self
                 .builder
                 .build_int_compare(IntPredicate::NE, l, r, "neq"),
         }
     }
 
-    // Kind of hacked together but I did not want to spend to much time on this part
-    fn compile_var_op(&self, var: Expr, op: Op, expr: Expr) -> InstructionValue {
-        let val = self.compile_expr(expr);
+    // Assigns to a variable or a vector element. A vector that is overwritten
+    // is freed first.
+    fn compile_var_op(
+        &self,
+        var: TypedExpr,
+        op: Op,
+        expr: TypedExpr,
+    ) -> CompileRes<InstructionValue> {
+        let var_type = var.expr_type.clone();
+        let val = self.compile_expr(expr)?;
+        let var_ptr = match var.kind {
+            TypedExprKind::Var(var) => self.get_variable(&var)?,
+            TypedExprKind::Index(vec_expr, index) => self.compile_elem_ptr(*vec_expr, *index)?,
+            _ => return err_unsupported("assignment to an expression"),
+        };
+        let old_val = || self.builder.build_load(var_ptr, "old");
 
-        match op {
+        let new_val = match op {
             Op::VarOp(VarToken::Assign) => {
-                let var_ptr = match var {
-                    Expr::Var(var) => self.get_variable(&var),
-                    _ => panic!(),
-                };
-                self.builder.build_store(*var_ptr, val)
+                if let Type::Vec(_) = var_type {
+                    self.compile_drop_vecs(&[var_ptr]);
+                }
+                val
             }
             Op::VarOp(VarToken::PlusEq) => {
-                let var_ptr = match &var {
-                    Expr::Var(var) => self.get_variable(&var),
-                    _ => panic!(),
-                };
-                let var_val = self.compile_expr(var);
-                let new_val = self.compile_math_op(var_val, MathToken::Plus, val);
-                self.builder.build_store(*var_ptr, new_val)
+                self.compile_arith_op(old_val(), MathToken::Plus, val, &var_type)
             }
             Op::VarOp(VarToken::MinEq) => {
-                let var_ptr = match &var {
-                    Expr::Var(var) => self.get_variable(&var),
-                    _ => panic!(),
-                };
-                let var_val = self.compile_expr(var);
-                let new_val = self.compile_math_op(var_val, MathToken::Minus, val);
-                self.builder.build_store(*var_ptr, new_val)
+                self.compile_arith_op(old_val(), MathToken::Minus, val, &var_type)
             }
             Op::VarOp(VarToken::MulEq) => {
-                let var_ptr = match &var {
-                    Expr::Var(var) => self.get_variable(&var),
-                    _ => panic!(),
-                };
-                let var_val = self.compile_expr(var);
-                let new_val = self.compile_math_op(var_val, MathToken::Multiply, val);
-                self.builder.build_store(*var_ptr, new_val)
+                self.compile_arith_op(old_val(), MathToken::Multiply, val, &var_type)
             }
-            _ => unimplemented!(),
-        }
+            op => return err_unsupported(&format!("´{}´ as an assignment", op)),
+        };
+        Ok(self.builder.build_store(var_ptr, new_val))
     }
 
-    fn create_entry_block_alloca(&mut self, name: &str) -> PointerValue {
+    fn create_entry_block_alloca(&mut self, name: &str, ty: BasicTypeEnum) -> PointerValue {
+        let alloca = self.build_entry_block_alloca(name, ty);
+        self.variables
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), alloca);
+        alloca
+    }
+
+    // Allocates stack space in the entry block without binding it to a variable.
+    fn build_entry_block_alloca(&self, name: &str, ty: BasicTypeEnum) -> PointerValue {
         let builder = self.context.create_builder();
 
         let entry = self.fn_value().get_first_basic_block().unwrap();
@@ -165,168 +813,758 @@ impl<'a> Compiler<'a> {
             Some(first_instr) => builder.position_before(&first_instr),
             None => builder.position_at_end(&entry),
         }
-        
-        let alloca = builder.build_alloca(self.context.i32_type(), name);
-        self.variables.insert(name.to_string(), alloca);
-        alloca
+
+        builder.build_alloca(ty, name)
     }
 
-    fn compile_function_call(&self, fn_call: FunctionCall) -> IntValue {
-        let function = self.module.get_function(&fn_call.name).unwrap();
-        let args: Vec<BasicValueEnum> = fn_call
-            .args
-            .content
-            .iter()
-            .map(|a| self.compile_expr(a.clone()).into())
-            .collect();
-        let call = self.builder.build_call(function, &args, &fn_call.name);
-        *call.try_as_basic_value().left().unwrap().as_int_value()
+    fn compile_function_call(&self, name: &str, args: Vec<TypedExpr>) -> CompileRes<CallSiteValue> {
+        let function = self.get_function(name)?;
+        let args = args
+            .into_iter()
+            .map(|a| self.compile_expr(a))
+            .collect::<CompileRes<Vec<BasicValueEnum>>>()?;
+        Ok(self.builder.build_call(function, &args, name))
     }
 
-    fn compile_keyword(&mut self, keyword: Expr) -> (InstructionValue, bool) {
-        match keyword.clone() {
-            Expr::Let(var, _, expr) => match *var {
-                Expr::Var(var) => {
-                    let val = self.compile_expr(*expr);
-                    let alloca = self.create_entry_block_alloca(&var);
-                    let store = self.builder.build_store(alloca, val);
+    // Compiles ´print´ and ´println´ into a call to printf. Each ´{}´ in the
+    // format string is replaced by the conversion for the type of its argument.
+    // Booleans are printed by selecting between two constant strings and chars
+    // are encoded as UTF-8 into a buffer on the stack.
+    fn compile_print(&self, name: &str, args: Vec<TypedExpr>) -> CompileRes<InstructionValue> {
+        let mut args = args.into_iter();
+        let fmt = match args.next().map(|arg| arg.kind) {
+            Some(TypedExprKind::Str(fmt)) => fmt,
+            None => String::new(),
+            _ => return err_unsupported("a format string that is not a literal"),
+        };
 
-                    (store, false)
+        let mut pieces = fmt.split("{}");
+        let mut printf_fmt = pieces.next().unwrap_or("").replace('%', "%%");
+        let mut printf_args: Vec<BasicValueEnum> = Vec::new();
+        for (piece, arg) in pieces.zip(args) {
+            let arg_type = arg.expr_type.clone();
+            let val = self.compile_expr(arg)?;
+            let (spec, printf_arg) = match &arg_type {
+                Type::Str => ("%s", val),
+                Type::Bool => {
+                    let true_str = self.builder.build_global_string_ptr("true", "true");
+                    let false_str = self.builder.build_global_string_ptr("false", "false");
+                    let selected = self.builder.build_select(
+                        val.into_int_value(),
+                        true_str.as_pointer_value(),
+                        false_str.as_pointer_value(),
+                        "boolstr",
+                    );
+                    ("%s", selected)
                 }
-                _ => panic!(),
-            },
-            Expr::VarOp(var, op, expr) => (self.compile_var_op(*var, op, *expr), false),
-            Expr::If(cond, block) => (self.compile_if(*cond, block), false),
-            Expr::While(cond, block) => (self.compile_while(*cond, block), false),
-            Expr::Return(expr) => {
-                let val = self.compile_expr(*expr);
-                (self.builder.build_return(Some(&val)), true)
+                Type::Char => {
+                    let buf_type = self.context.i8_type().array_type(5);
+                    let buf = self.build_entry_block_alloca("charbuf", buf_type.into());
+                    let zero = self.context.i32_type().const_int(0, false);
+                    let buf_ptr = unsafe { self.builder.build_gep(buf, &[zero, zero], "charptr") };
+                    self.builder.build_call(
+                        self.rt_utf8_encode(),
+                        &[val, buf_ptr.into()],
+                        "encode",
+                    );
+                    ("%s", buf_ptr.into())
+                }
+                t if t.bit_width() == Some(64) => {
+                    (if t.is_signed() { "%lld" } else { "%llu" }, val)
+                }
+                t if t.is_int() => {
+                    let val = self.compile_int_cast(val.into_int_value(), t, &Type::Int32)?;
+                    (if t.is_signed() { "%d" } else { "%u" }, val.into())
+                }
+                t => {
+                    return err_unsupported(&format!(
+                        "printing values of type ´{}´",
+                        String::from(t.clone())
+                    ))
+                }
+            };
+            printf_fmt.push_str(spec);
+            printf_fmt.push_str(&piece.replace('%', "%%"));
+            printf_args.push(printf_arg);
+        }
+        if name == "println" {
+            printf_fmt.push('\n');
+        }
+
+        let fmt_ptr = self.builder.build_global_string_ptr(&printf_fmt, "fmt");
+        printf_args.insert(0, fmt_ptr.as_pointer_value().into());
+        let call = self
+            .builder
+            .build_call(self.rt_printf(), &printf_args, "printf");
+        Ok(call
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value()
+            .as_instruction()
+            .unwrap())
+    }
+
+    // Stores the value in a new variable. Tuple patterns are destructured
+    // into one variable per element.
+    fn compile_let_pattern(
+        &mut self,
+        pattern: TypedExpr,
+        val: BasicValueEnum,
+    ) -> CompileRes<InstructionValue> {
+        match pattern.kind {
+            TypedExprKind::Var(var) => {
+                let alloca =
+                    self.create_entry_block_alloca(&var, self.basic_type(&pattern.expr_type)?);
+                self.debug_variable(&var, &pattern.expr_type, alloca, None);
+                if let Type::Vec(_) = pattern.expr_type {
+                    self.vec_scopes.last_mut().unwrap().push(alloca);
+                }
+                Ok(self.builder.build_store(alloca, val))
             }
-            Expr::FuncCall(_) => (self.compile_expr(keyword).as_instruction().unwrap(), false),
-            _ => unimplemented!(),
+            TypedExprKind::Tuple(patterns) => {
+                let tuple = val.into_struct_value();
+                let mut last_store: Option<InstructionValue> = None;
+                for (i, pattern) in patterns.into_iter().enumerate() {
+                    let elem = self
+                        .builder
+                        .build_extract_value(tuple, i as u32, "destructure")
+                        .unwrap();
+                    last_store = Some(self.compile_let_pattern(pattern, elem)?);
+                }
+                match last_store {
+                    Some(store) => Ok(store),
+                    None => err_unsupported("an empty tuple pattern"),
+                }
+            }
+            _ => err_unsupported("a let binding that is not a variable or a tuple"),
         }
     }
 
-    fn compile_if(&mut self, condition: Expr, block: Block) -> InstructionValue {
-        let cond = self.compile_expr(condition);
+    // Returns the last instruction of the current block.
+    fn last_instruction(&self) -> InstructionValue {
+        let block = self.builder.get_insert_block().unwrap();
+        block.get_last_instruction().unwrap()
+    }
+
+    // Compiles a statement. Returns true if the statement was a return.
+    fn compile_keyword(&mut self, keyword: TypedExpr) -> CompileRes<(InstructionValue, bool)> {
+        Ok(match keyword.kind.clone() {
+            TypedExprKind::Let(var, expr) => {
+                let val = self.compile_expr(*expr)?;
+                (self.compile_let_pattern(*var, val)?, false)
+            }
+            TypedExprKind::VarOp(var, op, expr) => (self.compile_var_op(*var, op, *expr)?, false),
+            TypedExprKind::If(cond, block) => (self.compile_if(*cond, block)?, false),
+            TypedExprKind::While(cond, block) => (self.compile_while(*cond, block)?, false),
+            TypedExprKind::Return(expr) => {
+                let val = self.compile_expr(*expr)?;
+                let vec_ptrs: Vec<PointerValue> =
+                    self.vec_scopes.iter().flatten().cloned().collect();
+                self.compile_drop_vecs(&vec_ptrs);
+                (self.builder.build_return(Some(&val)), true)
+            }
+            TypedExprKind::FuncCall(name, args) if name == "print" || name == "println" => {
+                (self.compile_print(&name, args)?, false)
+            }
+            TypedExprKind::MethodCall(vec_expr, name, mut args) if name == "push" => {
+                match args.pop() {
+                    Some(val) => (self.compile_vec_push(*vec_expr, val)?, false),
+                    None => return err_unsupported("´push´ without a value"),
+                }
+            }
+            // Calls whose value is not used. Functions without a return value
+            // can only be called as statements.
+            TypedExprKind::FuncCall(name, args) => {
+                self.compile_function_call(&name, args)?;
+                (self.last_instruction(), false)
+            }
+            TypedExprKind::MethodCall(_, _, _) => {
+                self.compile_expr(keyword)?;
+                (self.last_instruction(), false)
+            }
+            _ => return err_unsupported("an expression as a statement"),
+        })
+    }
+
+    fn compile_if(
+        &mut self,
+        condition: TypedExpr,
+        block: TypedBlock,
+    ) -> CompileRes<InstructionValue> {
+        let cond = self.compile_expr(condition)?.into_int_value();
         let then_block = self.context.append_basic_block(&self.fn_value(), "then");
         let cont_block = self.context.append_basic_block(&self.fn_value(), "cont");
 
-        self.builder
+        let branch = self
+            .builder
             .build_conditional_branch(cond, &then_block, &cont_block);
         self.builder.position_at_end(&then_block);
-        self.compile_block(block);
+        self.compile_block(block)?;
 
-        self.builder.build_unconditional_branch(&cont_block);
+        // The block may already have been terminated by a return.
+        if !self.has_terminator() {
+            self.builder.build_unconditional_branch(&cont_block);
+        }
         self.builder.position_at_end(&cont_block);
-
-        let phi = self.builder.build_phi(self.context.i32_type(), "iftmp");
-        phi.add_incoming(&[
-            (&self.compile_num(0), &then_block),
-            (&self.compile_num(0), &cont_block),
-        ]);
-        phi.as_instruction()
+        Ok(branch)
     }
 
-    fn compile_while(&mut self, condition: Expr, block: Block) -> InstructionValue {
+    fn compile_while(
+        &mut self,
+        condition: TypedExpr,
+        block: TypedBlock,
+    ) -> CompileRes<InstructionValue> {
         let do_block = self.context.append_basic_block(&self.fn_value(), "do");
         let cont_block = self.context.append_basic_block(&self.fn_value(), "cont");
 
-        self.builder.build_conditional_branch(
-            self.compile_expr(condition.clone()),
+        let branch = self.builder.build_conditional_branch(
+            self.compile_expr(condition.clone())?.into_int_value(),
             &do_block,
             &cont_block,
         );
         self.builder.position_at_end(&do_block);
-        self.compile_block(block);
+        self.compile_block(block)?;
 
-        self.builder.build_conditional_branch(
-            self.compile_expr(condition.clone()),
-            &do_block,
-            &cont_block,
-        );
+        if !self.has_terminator() {
+            self.builder.build_conditional_branch(
+                self.compile_expr(condition)?.into_int_value(),
+                &do_block,
+                &cont_block,
+            );
+        }
         self.builder.position_at_end(&cont_block);
+        Ok(branch)
+    }
 
-        // This phi node does nothing, used to return an InstructionValue
-        let phi = self.builder.build_phi(self.context.i32_type(), "whiletmp");
-        phi.add_incoming(&[
-            (&self.compile_num(0), &do_block),
-            (&self.compile_num(0), &do_block),
-        ]);
-        phi.as_instruction()
+    #[inline]
+    fn has_terminator(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
     }
 
-    fn compile_block(&mut self, block: Block) -> InstructionValue {
-        let mut last_cmd: Option<InstructionValue> = None;
-        for expr in block.content {
-            let (cmd, ret) = self.compile_keyword(expr);
+    // Statements after a return are never reached and are not compiled.
+    // The position of the statement containing the block is restored at the
+    // end, since the condition of a while loop is compiled again after it.
+    fn compile_block(&mut self, block: TypedBlock) -> CompileRes<()> {
+        let outer_span = self.span;
+        self.variables.push(HashMap::new());
+        self.vec_scopes.push(Vec::new());
+        for expr in block {
+            self.span = expr.span;
+            self.debug_location(expr.span);
+            let (_, ret) = self.compile_keyword(expr)?;
             if ret {
-                return cmd;
+                self.variables.pop();
+                self.vec_scopes.pop();
+                self.span = outer_span;
+                return Ok(());
             }
-            last_cmd = Some(cmd);
         }
+        self.span = outer_span;
+        self.variables.pop();
 
-        match last_cmd {
-            Some(instruction) => instruction,
-            None => panic!(),
-        }
+        // Vectors declared in the block are freed when it ends.
+        let vec_ptrs = self.vec_scopes.pop().unwrap();
+        self.compile_drop_vecs(&vec_ptrs);
+        Ok(())
     }
 
-    // Still working on compiling parameters
-    fn compile_function(&self, func: Function) -> FunctionValue {
-        let param_types: Vec<BasicTypeEnum> = func
+    // Declares the function. The body is compiled separately so that functions
+    // can call functions declared later in the program.
+    fn compile_function(&self, func: &TypedFunction) -> CompileRes<FunctionValue> {
+        let param_types = func
             .params
             .iter()
-            .map(|param| match param.param_type {
-                Type::Int32 => self.context.i32_type().into(),
-                Type::Bool => self.context.bool_type().into(),
-                _ => unreachable!(),
-            })
-            .collect();
-
-        let fn_ret_type = match func.return_type {
-            Type::Bool => self.context.bool_type().fn_type(&param_types, false),
-            Type::Int32 => self.context.i32_type().fn_type(&param_types, false),
+            .map(|param| self.basic_type(&param.param_type))
+            .collect::<CompileRes<Vec<BasicTypeEnum>>>()?;
+
+        // Tuples are returned as aggregate (struct) values. A main function
+        // without a return value returns 0 so that it can be the entry point
+        // of an executable.
+        let fn_ret_type = match &func.return_type {
+            Type::Void if func.name == "main" => {
+                self.context.i32_type().fn_type(&param_types, false)
+            }
             Type::Void => self.context.void_type().fn_type(&param_types, false),
+            ret_type => self.basic_type(ret_type)?.fn_type(&param_types, false),
+        };
+
+        // C functions used by the runtime are declared with other types, and
+        // the runtime would call a function of the program with the same name.
+        if RUNTIME_C_FUNCTIONS.contains(&func.name.as_str()) {
+            let action = if func.is_extern {
+                "declaring"
+            } else {
+                "defining"
+            };
+            return err_unsupported(&format!(
+                "{} ´{}´, which is used by the runtime",
+                action, func.name
+            ));
+        }
+
+        // Every function uses the C calling convention and external linkage,
+        // so that compiled functions can be called from C and extern
+        // functions are resolved by the linker.
+        let function = self
+            .module
+            .add_function(&func.name, fn_ret_type, Some(Linkage::External));
+        function.set_call_conventions(CALL_CONV_C);
+        for (i, param) in func.params.iter().enumerate() {
+            if let Some(attribute) = self.c_ext_attribute(&param.param_type) {
+                function.add_attribute(AttributeLoc::Param(i as u32), attribute);
+            }
+        }
+        if let Some(attribute) = self.c_ext_attribute(&func.return_type) {
+            function.add_attribute(AttributeLoc::Return, attribute);
+        }
+        Ok(function)
+    }
+
+    // C expects bools and integers narrower than int to be extended to int by
+    // the caller, and by the callee for return values.
+    fn c_ext_attribute(&self, t: &Type) -> Option<Attribute> {
+        let kind = match t {
+            Type::Bool => "zeroext",
+            t if t.bit_width().map_or(false, |width| width < 32) => {
+                if t.is_signed() {
+                    "signext"
+                } else {
+                    "zeroext"
+                }
+            }
+            _ => return None,
         };
+        Some(
+            self.context
+                .create_enum_attribute(Attribute::get_named_enum_kind_id(kind), 0),
+        )
+    }
+
+    // Compiles the body of a declared function. The parameters are spilled to
+    // allocas in the entry block so they can be used like any other variable.
+    fn compile_function_body(&mut self, func: TypedFunction) -> CompileRes<()> {
+        let llvm_func = self.get_function(&func.name)?;
+        self.fn_value_opt = Some(llvm_func);
+        self.variables = vec![HashMap::new()];
+        self.vec_scopes = vec![Vec::new()];
+        self.span = func.span;
 
-        self.module.add_function(&func.name, fn_ret_type, None)
+        let entry = self.context.append_basic_block(&llvm_func, "entry");
+        self.builder.position_at_end(&entry);
+        self.debug_function(&func, llvm_func);
+
+        for (i, (param, arg)) in func.params.iter().zip(llvm_func.get_params()).enumerate() {
+            let alloca =
+                self.create_entry_block_alloca(&param.name, self.basic_type(&param.param_type)?);
+            self.debug_variable(&param.name, &param.param_type, alloca, Some(i as u32 + 1));
+            self.builder.build_store(alloca, arg);
+            if let Type::Vec(_) = param.param_type {
+                self.vec_scopes[0].push(alloca);
+            }
+        }
+
+        self.compile_block(func.block)?;
+
+        // Functions without a return at the end of the body. Functions with a
+        // return value panic, like the other backends.
+        let param_vecs = self.vec_scopes.pop().unwrap();
+        if !self.has_terminator() {
+            self.compile_drop_vecs(&param_vecs);
+            match func.return_type {
+                Type::Void if func.name == "main" => {
+                    let zero = self.context.i32_type().const_int(0, false);
+                    self.builder.build_return(Some(&zero));
+                }
+                Type::Void => {
+                    self.builder.build_return(None);
+                }
+                _ => self.compile_panic(&format!("function {} did not return a value", func.name)),
+            };
+        }
+        Ok(())
     }
 }
 
-pub fn compile_program(fn_list: Functions) {
-    let context = Context::create();
-    let mut module = context.create_module("llvm-program");
+// Compiles the functions into the module. All functions are declared before
+// any body is compiled. For a JIT session, functions that can be called from
+// Rust get an entry function and panics return to it.
+fn compile_functions(
+    context: &Context,
+    module: &Module,
+    fn_list: TypedFunctions,
+    options: &CompileOptions,
+    session: bool,
+) -> CompileRes<()> {
     let builder = context.create_builder();
-    let execution_engine = module
-        .create_jit_execution_engine(OptimizationLevel::None)
-        .unwrap();
-
+    let debug = options
+        .debug_source
+        .as_ref()
+        .map(|source| debug::DebugInfo::new(module, source, options.opt_level != OptLevel::O0));
     let mut compiler = Compiler {
-        context: &context,
+        context,
         builder: &builder,
-        module: &module,
-        execution_engine: &execution_engine,
+        module,
         fn_value_opt: None,
-        variables: HashMap::new(),
+        variables: Vec::new(),
+        vec_scopes: Vec::new(),
+        debug,
+        overflow_checks: options.overflow_checks,
+        recover_panics: session,
+        source_name: match &options.debug_source {
+            Some(source) => source.display().to_string(),
+            None => "<anon>".to_string(),
+        },
+        span: None,
     };
 
-    for function in fn_list {
-        let llvm_func = compiler.compile_function(function.clone());
-        compiler.fn_value_opt = Some(llvm_func);
-        let basic_block = compiler.context.append_basic_block(&llvm_func, "entry");
+    for function in fn_list.iter() {
+        compiler.compile_function(function)?;
+    }
+    let signatures: Vec<jit::Signature> = fn_list.iter().map(jit::Signature::of).collect();
+    for function in fn_list.into_iter().filter(|function| !function.is_extern) {
+        compiler.compile_function_body(function)?;
+    }
+    if session {
+        for sig in signatures.iter().filter(|sig| sig.is_callable()) {
+            compiler.compile_entry(sig);
+        }
+    }
+    if let Some(debug) = &compiler.debug {
+        debug.finalize();
+    }
+    Ok(())
+}
+
+// A verified module together with the context it was created in.
+pub struct CompiledModule {
+    module: Module,
+    context: Context,
+    // None for libraries without a main function
+    main_type: Option<Type>,
+    signatures: Vec<jit::Signature>,
+    opt_level: OptLevel,
+    // Target triple the module was compiled for, None for the host
+    target: Option<String>,
+}
 
-        compiler.builder.position_at_end(&basic_block);
-        compiler.compile_block(function.block);
+impl CompiledModule {
+    // Returns the textual LLVM IR of the module.
+    pub fn ir(&self) -> String {
+        self.module.print_to_string().to_string()
     }
 
-    module.print_to_stderr();
-    let fun_expr: JitFunction<ExprFunc> =
-        unsafe { execution_engine.get_function("main").ok().unwrap() };
+    pub fn print_to_stderr(&self) {
+        self.module.print_to_stderr();
+    }
 
-    unsafe {
-        println!("{}", fun_expr.call());
+    // Runs the main function with the JIT and returns its value. A main
+    // function without a return value returns 0. The execution engine takes
+    // ownership of the module, so it can only be run once.
+    pub fn run_main(self) -> CompileRes<i32> {
+        self.check_host_target("running")?;
+        let execution_engine = self
+            .module
+            .create_jit_execution_engine(self.opt_level.codegen_level())
+            .map_err(|e| CompileError::Jit(e.to_string()))?;
+        let err_lookup = |e| CompileError::Jit(format!("{:?}", e));
+
+        match self.main_type {
+            None => Err(CompileError::MissingMain),
+            Some(Type::Void) | Some(Type::Int32) => {
+                let fun_expr: JitFunction<ExprFunc> =
+                    unsafe { execution_engine.get_function("main") }.map_err(err_lookup)?;
+                Ok(unsafe { fun_expr.call() })
+            }
+            _ => err_unsupported("a ´main´ function that does not return ´i32´ or ´()´"),
+        }
+    }
+
+    // Code compiled for another target can not be run or linked on the host.
+    // Targets are compared by their data layout, as a triple can be written
+    // in several ways, e.g. with another vendor than the default triple.
+    fn check_host_target(&self, action: &str) -> CompileRes<()> {
+        let triple = match &self.target {
+            Some(triple) => triple,
+            None => return Ok(()),
+        };
+        let host = target_machine(None, self.opt_level)?;
+        if self.module.get_data_layout().as_str()
+            == host.get_target_data().get_data_layout().as_str()
+        {
+            Ok(())
+        } else {
+            err_unsupported(&format!("{} code for ´{}´ on the host", action, triple))
+        }
+    }
+
+    // C calls main as a function that returns an int, so the main function of
+    // an executable can only return values that are passed like one. Other
+    // values would give a meaningless exit code.
+    fn check_executable_main(&self) -> CompileRes<()> {
+        match &self.main_type {
+            Some(t)
+                if !(t.is_int() || *t == Type::Bool || *t == Type::Char || *t == Type::Void) =>
+            {
+                err_unsupported(&format!(
+                    "a ´main´ function that returns ´{}´ in an executable",
+                    String::from(t.clone())
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Writes the module to the path. Executables are linked by the system
+    // ´cc´ from an object file written to the temporary directory, and static
+    // libraries are archived by ´ar´ from one.
+    pub fn write_to_file(&self, kind: OutputKind, path: &Path) -> CompileRes<()> {
+        let err_io = |e: String| CompileError::Io(format!("{}: {}", path.display(), e));
+        match kind {
+            OutputKind::LlvmIr => self
+                .module
+                .print_to_file(path)
+                .map_err(|e| err_io(e.to_string())),
+            OutputKind::Bitcode => {
+                if self.module.write_bitcode_to_path(path) {
+                    Ok(())
+                } else {
+                    Err(err_io("could not write bitcode".to_string()))
+                }
+            }
+            OutputKind::Object => {
+                target_machine(self.target.as_ref().map(String::as_str), self.opt_level)?
+                    .write_to_file(&self.module, FileType::Object, path)
+                    .map_err(|e| err_io(e.to_string()))
+            }
+            OutputKind::Executable => {
+                self.check_host_target("linking")?;
+                self.check_executable_main()?;
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let obj_path = env::temp_dir().join(format!("{}-{}.o", file_name, process::id()));
+                self.write_to_file(OutputKind::Object, &obj_path)?;
+                let res = link_executable(&obj_path, path);
+                let _ = std::fs::remove_file(&obj_path);
+                res
+            }
+            OutputKind::StaticLib => {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let obj_path = env::temp_dir().join(format!("{}-{}.o", file_name, process::id()));
+                self.write_to_file(OutputKind::Object, &obj_path)?;
+                let res = archive_library(&obj_path, path);
+                let _ = std::fs::remove_file(&obj_path);
+                res?;
+
+                let header_path = path.with_extension("h");
+                std::fs::write(
+                    &header_path,
+                    header::c_header(&header_path, &self.signatures),
+                )
+                .map_err(|e| CompileError::Io(format!("{}: {}", header_path.display(), e)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    // Textual LLVM IR (.ll)
+    LlvmIr,
+    // LLVM bitcode (.bc)
+    Bitcode,
+    // Object file for the host (.o)
+    Object,
+    // Executable linked with the system ´cc´. The exit code of the process
+    // is the value returned by main.
+    Executable,
+    // Static library (.a) archived with the system ´ar´, together with a C
+    // header declaring its functions. The header has the path of the library
+    // with the extension .h. The program does not need a main function.
+    StaticLib,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileOptions {
+    pub output_kind: OutputKind,
+    pub output_path: PathBuf,
+    pub opt_level: OptLevel,
+    // Source file of the program. Debug info is emitted when it is set.
+    pub debug_source: Option<PathBuf>,
+    // Arithmetic overflow panics instead of wrapping around
+    pub overflow_checks: bool,
+    // Target triple, e.g. ´thumbv7em-none-eabihf´. The program is compiled
+    // for the host when it is not set.
+    pub target: Option<String>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            output_kind: OutputKind::Executable,
+            output_path: PathBuf::from("a.out"),
+            opt_level: OptLevel::O0,
+            debug_source: None,
+            overflow_checks: true,
+            target: None,
+        }
+    }
+}
+
+// Initialises the LLVM target of the architecture in the triple. Other
+// architectures, e.g. RISC-V which is experimental in LLVM 8, are looked up
+// among all targets LLVM was built with.
+fn initialize_target(triple: &str) {
+    let config = InitializationConfig::default();
+    let arch = triple.split('-').next().unwrap_or_default();
+    if arch.starts_with("x86") || arch == "i686" {
+        Target::initialize_x86(&config);
+    } else if arch.starts_with("arm") || arch.starts_with("thumb") {
+        Target::initialize_arm(&config);
+    } else if arch.starts_with("aarch64") {
+        Target::initialize_aarch64(&config);
+    } else {
+        Target::initialize_all(&config);
+    }
+}
+
+// Creates a target machine for the triple, or for the host the compiler is
+// running on. Code for bare-metal targets (without an OS) is not position
+// independent.
+fn target_machine(triple: Option<&str>, opt_level: OptLevel) -> CompileRes<TargetMachine> {
+    let triple = match triple {
+        Some(triple) => {
+            initialize_target(triple);
+            triple.to_string()
+        }
+        None => {
+            Target::initialize_native(&InitializationConfig::default())
+                .map_err(CompileError::Target)?;
+            TargetMachine::get_default_triple().to_string()
+        }
+    };
+    let target_triple = TargetTriple::create(&triple);
+    let target = Target::from_triple(&target_triple)
+        .map_err(|e| CompileError::Target(format!("´{}´: {}", triple, e)))?;
+    let reloc_mode = if triple.contains("-none-") {
+        RelocMode::Static
+    } else {
+        RelocMode::PIC
+    };
+    target
+        .create_target_machine(
+            &target_triple,
+            "generic",
+            "",
+            opt_level.codegen_level(),
+            reloc_mode,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| CompileError::Target(format!("no target machine for ´{}´", triple)))
+}
+
+// Links the object file with the C runtime, which calls main and exits with
+// its return value.
+fn link_executable(obj_path: &Path, path: &Path) -> CompileRes<()> {
+    let output = process::Command::new("cc")
+        .arg(obj_path)
+        .arg("-o")
+        .arg(path)
+        .output()
+        .map_err(|e| CompileError::Link(format!("could not run ´cc´: {}", e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(CompileError::Link(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+// Creates a static library holding the object file.
+fn archive_library(obj_path: &Path, path: &Path) -> CompileRes<()> {
+    let _ = std::fs::remove_file(path);
+    let output = process::Command::new("ar")
+        .arg("rcs")
+        .arg(path)
+        .arg(obj_path)
+        .output()
+        .map_err(|e| CompileError::Link(format!("could not run ´ar´: {}", e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(CompileError::Link(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+// Programs are type checked before they are compiled. Ill-typed programs
+// are not compiled. The module is checked by the LLVM verifier.
+pub fn compile_program(fn_list: Functions) -> CompileRes<CompiledModule> {
+    compile_program_with_options(fn_list, &CompileOptions::default())
+}
+
+// The module is verified before it is optimised, so that errors in the
+// generated code are not hidden by the passes.
+pub fn compile_program_with_options(
+    fn_list: Functions,
+    options: &CompileOptions,
+) -> CompileRes<CompiledModule> {
+    compile_module(fn_list, options, false)
+}
+
+fn compile_module(
+    mut fn_list: Functions,
+    options: &CompileOptions,
+    session: bool,
+) -> CompileRes<CompiledModule> {
+    let typed_fns = tc_program(&mut fn_list)?;
+    let signatures = typed_fns.iter().map(jit::Signature::of).collect();
+    let main_type = typed_fns
+        .iter()
+        .find(|func| func.name == "main")
+        .map(|main| main.return_type.clone());
+    if main_type.is_none() && options.output_kind != OutputKind::StaticLib {
+        return Err(CompileError::MissingMain);
+    }
+
+    let context = Context::create();
+    let module = context.create_module("llvm-program");
+    if let Some(triple) = &options.target {
+        let machine = target_machine(Some(triple), options.opt_level)?;
+        module.set_triple(&TargetTriple::create(triple));
+        module.set_data_layout(&machine.get_target_data().get_data_layout());
     }
+    compile_functions(&context, &module, typed_fns, options, session)?;
+    module
+        .verify()
+        .map_err(|e| CompileError::Verify(e.to_string()))?;
+    passes::run_passes(&module, options.opt_level);
+
+    Ok(CompiledModule {
+        module,
+        context,
+        main_type,
+        signatures,
+        opt_level: options.opt_level,
+        target: options.target.clone(),
+    })
+}
+
+// Compiles the program and writes it to the output path in the format given
+// by the options.
+pub fn build_program(fn_list: Functions, options: &CompileOptions) -> CompileRes<()> {
+    compile_program_with_options(fn_list, options)?
+        .write_to_file(options.output_kind, &options.output_path)
+}
+
+// Compiles the program and runs the main function with the JIT. Returns the
+// value returned by main.
+pub fn jit_program(fn_list: Functions) -> CompileRes<i32> {
+    compile_program(fn_list)?.run_main()
 }
 
 /* pub fn test() {
@@ -373,3 +1611,649 @@ pub fn compile_program(fn_list: Functions) {
         println!("{}", fun_expr.call());
     }
 } */
+
+#[cfg(test)]
+mod llvm_tests {
+    use super::*;
+
+    #[test]
+    fn jit_fibo_program() {
+        let fibo = "
+            fn fibo(i: i32) -> i32 {
+                if i == 1 {
+                    return 0;
+                };
+                if i == 2 {
+                    return 1;
+                };
+
+                return fibo(i-1) + fibo(i-2);
+            }
+
+            fn main() -> i32 {
+                let a: i32 = 7;
+                return fibo(a);
+            }
+        ";
+        let tree = parse_program(fibo).unwrap().1;
+        assert_eq!(jit_program(tree), Ok(8));
+    }
+
+    #[test]
+    fn jit_params_program() {
+        let program = "
+            fn main() -> i32 {
+                return pick(false, 3, 4);
+            }
+
+            fn pick(first: bool, a: i32, b: i32) -> i32 {
+                if first {
+                    return a;
+                };
+                b += 10;
+                return b;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        assert_eq!(jit_program(tree), Ok(14));
+    }
+
+    #[test]
+    fn jit_scopes_program() {
+        // Variables declared in a block, also with another type, shadow the
+        // variables of the function until the block ends.
+        let program = "
+            fn main() -> i32 {
+                let a: i32 = 1;
+                let c: bool = true;
+                if c {
+                    let a: i32 = 2;
+                    let a: bool = false;
+                };
+                while c {
+                    let a: u8 = 5;
+                    a += 1;
+                    c = false;
+                };
+                return a;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        assert_eq!(jit_program(tree), Ok(1));
+    }
+
+    #[test]
+    fn jit_bool_program() {
+        let program = "
+            fn negate(b: bool) -> bool {
+                let res: bool = true;
+                if b {
+                    res = false;
+                };
+                return res;
+            }
+
+            fn main() -> i32 {
+                let small: u8 = 250;
+                let flag: bool = negate(small > 3);
+                if flag {
+                    return 1;
+                };
+                return 2;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        assert_eq!(jit_program(tree), Ok(2));
+
+        let ill_typed = "
+            fn main() -> i32 {
+                let b: bool = 1;
+                return b;
+            }
+        ";
+        let tree = parse_program(ill_typed).unwrap().1;
+        assert_eq!(jit_program(tree).is_err(), true);
+    }
+
+    #[test]
+    fn emit_program() {
+        let program = "
+            fn main() -> i32 {
+                let a: i32 = 40;
+                return a + 2;
+            }
+        ";
+        let dir = env::temp_dir();
+        let emit = |kind: OutputKind, name: &str| {
+            let path = dir.join(format!("{}-{}", process::id(), name));
+            let tree = parse_program(program).unwrap().1;
+            let options = CompileOptions {
+                output_kind: kind,
+                output_path: path.clone(),
+                ..CompileOptions::default()
+            };
+            assert_eq!(build_program(tree, &options), Ok(()));
+            path
+        };
+
+        let read = |kind: OutputKind, name: &str| {
+            let path = emit(kind, name);
+            let contents = std::fs::read(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            contents
+        };
+
+        let ir = String::from_utf8(read(OutputKind::LlvmIr, "emit.ll")).unwrap();
+        assert!(ir.contains("define i32 @main()"));
+        let bitcode = read(OutputKind::Bitcode, "emit.bc");
+        assert_eq!(&bitcode[..4], b"BC\xc0\xde");
+        let object = read(OutputKind::Object, "emit.o");
+        assert!(!object.is_empty());
+
+        let path = emit(OutputKind::Executable, "emit");
+        let status = process::Command::new(&path).status().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(status.code(), Some(42));
+
+        // The exit code of a main that does not return an integer would be
+        // meaningless.
+        for main in &[
+            "fn main() -> f64 { return 1.5; }",
+            "fn main() -> (i32, i32) { return (1, 2); }",
+        ] {
+            let module = compile_program(parse_program(main).unwrap().1).unwrap();
+            let path = dir.join(format!("{}-emit-main", process::id()));
+            match module.write_to_file(OutputKind::Executable, &path) {
+                Err(CompileError::Unsupported(_)) => (),
+                res => panic!("expected an unsupported main, got {:?}", res),
+            }
+            assert!(module.write_to_file(OutputKind::LlvmIr, &path).is_ok());
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn optimise_program() {
+        let program = "
+            fn add(a: i32, b: i32) -> i32 {
+                return a + b;
+            }
+
+            fn main() -> i32 {
+                let i: i32 = 0;
+                let sum: i32 = 0;
+                while i < 10 {
+                    sum = add(sum, i);
+                    i += 1;
+                };
+                return sum;
+            }
+        ";
+        let compile = |opt_level: OptLevel| {
+            let tree = parse_program(program).unwrap().1;
+            let options = CompileOptions {
+                opt_level,
+                ..CompileOptions::default()
+            };
+            compile_program_with_options(tree, &options).unwrap()
+        };
+
+        let unoptimised = compile(OptLevel::O0).ir();
+        assert!(unoptimised.contains("alloca"));
+        assert!(unoptimised.contains("call i32 @add"));
+
+        // Variables are promoted to registers from -O1 and calls are inlined
+        // from -O2.
+        let o1 = compile(OptLevel::O1).ir();
+        assert!(!o1.contains("alloca"));
+        assert!(o1.contains("call i32 @add"));
+        let o2 = compile(OptLevel::O2).ir();
+        assert!(!o2.contains("call i32 @add"));
+
+        for level in vec![OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3] {
+            assert_eq!(compile(level).run_main(), Ok(45));
+        }
+    }
+
+    #[test]
+    fn debug_info_program() {
+        let program = "fn main() -> i32 {
+    let a: i32 = 1;
+    a += 41;
+    return a;
+}";
+        let tree = parse_program(program).unwrap().1;
+        let options = CompileOptions {
+            debug_source: Some(PathBuf::from("/tmp/main.rs")),
+            ..CompileOptions::default()
+        };
+        let ir = compile_program_with_options(tree, &options).unwrap().ir();
+
+        assert!(ir.contains("!DICompileUnit(language: DW_LANG_Rust"));
+        assert!(ir.contains("!DIFile(filename: \"main.rs\", directory: \"/tmp\")"));
+        assert!(ir.contains("!DISubprogram(name: \"main\""));
+        assert!(ir.contains("!DILocalVariable(name: \"a\""));
+        assert!(ir.contains("!DIBasicType(name: \"i32\", size: 32, encoding: DW_ATE_signed"));
+        assert!(ir.contains("call void @llvm.dbg.declare"));
+        assert!(ir.contains("!DILocation(line: 3, column: 5"));
+        assert!(ir.contains("!DILocation(line: 4, column: 5"));
+    }
+
+    #[test]
+    fn jit_session_calls() {
+        let program = "
+            fn add(a: i32, b: i32) -> i32 {
+                return a + b;
+            }
+
+            fn choose(first: bool, a: i32, b: i32) -> i32 {
+                if first {
+                    return a;
+                };
+                return b;
+            }
+
+            fn is_small(a: i32) -> bool {
+                return a < 10;
+            }
+
+            fn main() -> () {
+                let a: i32 = add(1, 2);
+            }
+
+            fn div(a: i32, b: i32) -> i32 {
+                return a / b;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        let session = JitSession::new(tree).unwrap();
+
+        assert_eq!(
+            session.call("add", vec![Value::Num(2), Value::Num(-5)]),
+            Ok(Value::Num(-3))
+        );
+        assert_eq!(
+            session.call("add", vec![Value::Num(40), Value::Num(2)]),
+            Ok(Value::Num(42))
+        );
+        assert_eq!(
+            session.call(
+                "choose",
+                vec![Value::Bool(false), Value::Num(1), Value::Num(2)]
+            ),
+            Ok(Value::Num(2))
+        );
+        assert_eq!(
+            session.call("is_small", vec![Value::Num(3)]),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(session.call("main", vec![]), Ok(Value::Tuple(vec![])));
+
+        let is_invalid = |res: CompileRes<Value>| match res {
+            Err(CompileError::InvalidArgs(_)) => true,
+            _ => false,
+        };
+        assert!(is_invalid(session.call("add", vec![Value::Num(1)])));
+        assert!(is_invalid(
+            session.call("is_small", vec![Value::Bool(true)])
+        ));
+        assert_eq!(
+            session.call("sub", vec![]),
+            Err(CompileError::UnknownFunction("sub".to_string()))
+        );
+
+        // Panics are returned as errors and the session can still be used.
+        assert_eq!(
+            session.call("add", vec![Value::Num(i32::MAX), Value::Num(1)]),
+            Err(CompileError::Panic(
+                "panicked at 'attempt to add with overflow', <anon>:3:17".to_string()
+            ))
+        );
+        assert_eq!(
+            session.call("div", vec![Value::Num(1), Value::Num(0)]),
+            Err(CompileError::Panic(
+                "panicked at 'attempt to divide by zero', <anon>:22:17".to_string()
+            ))
+        );
+        assert_eq!(
+            session.call("div", vec![Value::Num(7), Value::Num(2)]),
+            Ok(Value::Num(3))
+        );
+
+        let tree = parse_program(program).unwrap().1;
+        let options = CompileOptions {
+            overflow_checks: false,
+            ..CompileOptions::default()
+        };
+        let session = JitSession::with_options(tree, &options).unwrap();
+        assert_eq!(
+            session.call("add", vec![Value::Num(i32::MAX), Value::Num(1)]),
+            Ok(Value::Num(i32::MIN))
+        );
+    }
+
+    #[test]
+    fn jit_session_debug_info() {
+        let program = "fn double(a: i32) -> i32 {
+    return a * 2;
+}
+
+fn main() -> () {
+    let a: i32 = double(1);
+}";
+        let tree = parse_program(program).unwrap().1;
+        let options = CompileOptions {
+            debug_source: Some(PathBuf::from("/tmp/main.rs")),
+            ..CompileOptions::default()
+        };
+        let session = JitSession::with_options(tree, &options).unwrap();
+
+        assert_eq!(
+            session.call("double", vec![Value::Num(21)]),
+            Ok(Value::Num(42))
+        );
+        assert_eq!(session.call("main", vec![]), Ok(Value::Tuple(vec![])));
+    }
+
+    #[test]
+    fn runtime_checks_program() {
+        let run = |program: &str, overflow_checks: bool| {
+            let path = env::temp_dir().join(format!("{}-checks", process::id()));
+            let tree = parse_program(program).unwrap().1;
+            let options = CompileOptions {
+                output_path: path.clone(),
+                overflow_checks,
+                ..CompileOptions::default()
+            };
+            build_program(tree, &options).unwrap();
+            let output = process::Command::new(&path).output().unwrap();
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            (output.status.code(), stderr)
+        };
+
+        let overflow = "fn main() -> i32 {
+    let a: u8 = 200;
+    a += 100;
+    return a as i32;
+}";
+        assert_eq!(
+            run(overflow, true),
+            (
+                Some(101),
+                "panicked at 'attempt to add with overflow', <anon>:3:5\n".to_string()
+            )
+        );
+        assert_eq!(run(overflow, false), (Some(44), String::new()));
+
+        let div_by_zero = "fn main() -> i32 {
+    let a: i32 = 0;
+    while a < 1 {
+        return 10 / a;
+    };
+    return 0;
+}";
+        assert_eq!(
+            run(div_by_zero, false),
+            (
+                Some(101),
+                "panicked at 'attempt to divide by zero', <anon>:4:9\n".to_string()
+            )
+        );
+
+        // Reported at the body of the function
+        let missing_return = "fn f(a: i32) -> i32 {
+    if a > 0 {
+        return a;
+    };
+}
+
+fn main() -> i32 {
+    return f(0);
+}";
+        assert_eq!(
+            run(missing_return, true),
+            (
+                Some(101),
+                "panicked at 'function f did not return a value', <anon>:1:21\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn extern_c_program() {
+        let program = "extern \"C\" {
+    fn putchar(c: i32) -> i32;
+    fn abs(x: i32) -> i32;
+}
+
+fn main() -> i32 {
+    let s: Vec<u8> = Vec::new();
+    s.push(72);
+    s.push(105);
+    s.push(10);
+    let i: usize = 0;
+    while i < s.len() {
+        putchar(s[i] as i32);
+        i += 1;
+    };
+    return abs(-7);
+}";
+        let path = env::temp_dir().join(format!("{}-extern", process::id()));
+        let options = CompileOptions {
+            output_path: path.clone(),
+            ..CompileOptions::default()
+        };
+        build_program(parse_program(program).unwrap().1, &options).unwrap();
+        let output = process::Command::new(&path).output().unwrap();
+        assert_eq!(output.status.code(), Some(7));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hi\n");
+
+        let program = "extern \"C\" {
+    fn abs(x: i32) -> i32;
+}
+
+fn main() -> i32 {
+    return abs(-7) + abs(3);
+}";
+        assert_eq!(jit_program(parse_program(program).unwrap().1), Ok(10));
+
+        let program = "extern \"C\" {
+    fn free(x: i32);
+}
+
+fn main() -> () {
+    free(0);
+}";
+        assert_eq!(
+            jit_program(parse_program(program).unwrap().1),
+            Err(CompileError::Unsupported(
+                "declaring ´free´, which is used by the runtime".to_string()
+            ))
+        );
+
+        let program = "extern \"C\" {
+    fn exit(code: u8);
+}
+
+fn main() -> () {
+    exit(1);
+}";
+        assert_eq!(
+            jit_program(parse_program(program).unwrap().1),
+            Err(CompileError::Unsupported(
+                "declaring ´exit´, which is used by the runtime".to_string()
+            ))
+        );
+
+        let program = "fn free(x: i32) -> i32 {
+    return x;
+}
+
+fn main() -> i32 {
+    return free(1);
+}";
+        assert_eq!(
+            jit_program(parse_program(program).unwrap().1),
+            Err(CompileError::Unsupported(
+                "defining ´free´, which is used by the runtime".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn static_library_program() {
+        let program = "fn square(x: i32) -> i32 {
+    return x * x;
+}
+
+fn isEven(x: i32) -> bool {
+    return ((x / 2) * 2) == x;
+}
+
+fn pair(x: i32) -> (i32, i32) {
+    return (x, x);
+}";
+        let dir = env::temp_dir().join(format!("{}-staticlib", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib_path = dir.join("libkernels.a");
+        let options = CompileOptions {
+            output_kind: OutputKind::StaticLib,
+            output_path: lib_path.clone(),
+            ..CompileOptions::default()
+        };
+        build_program(parse_program(program).unwrap().1, &options).unwrap();
+
+        let header = std::fs::read_to_string(dir.join("libkernels.h")).unwrap();
+        assert!(header.contains("int32_t square(int32_t x);\n"));
+        assert!(header.contains("bool isEven(int32_t x);\n"));
+        assert!(header.contains("/* pair takes or returns values that cannot be passed to C */\n"));
+
+        let c_path = dir.join("main.c");
+        std::fs::write(
+            &c_path,
+            "#include \"libkernels.h\"\nint main(void) { return square(5) + isEven(4); }\n",
+        )
+        .unwrap();
+        let exe_path = dir.join("main");
+        let status = process::Command::new("cc")
+            .arg(&c_path)
+            .arg(&lib_path)
+            .arg("-o")
+            .arg(&exe_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = process::Command::new(&exe_path).output().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(output.status.code(), Some(26));
+    }
+
+    #[test]
+    fn cross_target_program() {
+        let program = "
+            fn add(a: i32, b: i32) -> i32 {
+                return a + b;
+            }
+
+            fn main() -> i32 {
+                return add(40, 2);
+            }
+        ";
+        // ELF class (32 or 64 bit) and machine of the object file
+        let emit = |triple: &str| -> CompileRes<(u8, u16)> {
+            let path = env::temp_dir().join(format!("{}-{}.o", process::id(), triple));
+            let options = CompileOptions {
+                output_kind: OutputKind::Object,
+                output_path: path.clone(),
+                target: Some(triple.to_string()),
+                ..CompileOptions::default()
+            };
+            build_program(parse_program(program).unwrap().1, &options)?;
+            let object = std::fs::read(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            assert_eq!(&object[..4], b"\x7fELF");
+            Ok((object[4], u16::from_le_bytes([object[18], object[19]])))
+        };
+
+        // LLVM may be built without the targets, e.g. RISC-V which is
+        // experimental in LLVM 8. They are then skipped.
+        let check_emit = |triple: &str, expected: (u8, u16)| match emit(triple) {
+            Err(CompileError::Target(_)) => (),
+            res => assert_eq!(res, Ok(expected)),
+        };
+        const EM_ARM: u16 = 40;
+        const EM_X86_64: u16 = 62;
+        const EM_RISCV: u16 = 243;
+        check_emit("thumbv7em-none-eabihf", (1, EM_ARM));
+        check_emit("x86_64-unknown-linux-gnu", (2, EM_X86_64));
+        check_emit("riscv32imac-unknown-none-elf", (1, EM_RISCV));
+
+        let compile = |triple: &str| {
+            compile_program_with_options(
+                parse_program(program).unwrap().1,
+                &CompileOptions {
+                    target: Some(triple.to_string()),
+                    ..CompileOptions::default()
+                },
+            )
+        };
+        match compile("thumbv7em-none-eabihf") {
+            Err(CompileError::Target(_)) => (),
+            res => {
+                let module = res.unwrap();
+                assert!(module
+                    .ir()
+                    .contains("target triple = \"thumbv7em-none-eabihf\""));
+                assert_eq!(
+                    module.run_main(),
+                    Err(CompileError::Unsupported(
+                        "running code for ´thumbv7em-none-eabihf´ on the host".to_string()
+                    ))
+                );
+            }
+        }
+        // The host triple written with another vendor
+        if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            let module = compile("x86_64-unknown-linux-gnu").unwrap();
+            assert_eq!(module.run_main(), Ok(42));
+        }
+    }
+
+    #[test]
+    fn compile_errors() {
+        let program = "
+            fn main() -> f64 {
+                return 1.5;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        let module = compile_program(tree).unwrap();
+        assert!(module.ir().contains("define double @main()"));
+        assert!(match module.run_main() {
+            Err(CompileError::Unsupported(_)) => true,
+            _ => false,
+        });
+
+        let no_main = "
+            fn one() -> i32 {
+                return 1;
+            }
+        ";
+        let tree = parse_program(no_main).unwrap().1;
+        assert!(match compile_program(tree) {
+            Err(CompileError::MissingMain) => true,
+            _ => false,
+        });
+
+        let ill_typed = "
+            fn main() -> i32 {
+                return true;
+            }
+        ";
+        let tree = parse_program(ill_typed).unwrap().1;
+        assert!(match jit_program(tree) {
+            Err(CompileError::TypeError(_)) => true,
+            _ => false,
+        });
+    }
+}
@@ -0,0 +1,245 @@
+// DWARF debug info for compiled programs. Functions get a subprogram,
+// statements a line location and variables of scalar types a descriptor, so
+// that executables can be stepped and inspected in gdb or lldb.
+use super::Compiler;
+use crate::ast::*;
+
+use inkwell::{
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DIScope, DIType, DWARFEmissionKind,
+        DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
+    values::{FunctionValue, PointerValue},
+};
+use std::path::Path;
+
+// DWARF base type encodings
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+const DW_ATE_UTF: u32 = 0x10;
+
+pub(super) struct DebugInfo {
+    builder: DebugInfoBuilder,
+    compile_unit: DICompileUnit,
+    // Scope and line of the function being compiled
+    scope: Option<DIScope>,
+    line: u32,
+}
+
+impl DebugInfo {
+    pub(super) fn new(module: &Module, source: &Path, is_optimized: bool) -> Self {
+        let context = module.get_context();
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(3, false),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(4, false),
+        );
+
+        let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+        let directory = source.parent().unwrap_or_else(|| Path::new("."));
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::Rust,
+            &file_name,
+            &directory.to_string_lossy(),
+            "simple-rustc",
+            is_optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+        );
+
+        DebugInfo {
+            builder,
+            compile_unit,
+            scope: None,
+            line: 0,
+        }
+    }
+
+    // Resolves the forward references. Must be called before the module is
+    // verified.
+    pub(super) fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    // Makes a new subprogram attached to the function the scope of the
+    // statements compiled next.
+    fn subprogram(
+        &mut self,
+        name: &str,
+        return_type: Option<DIType>,
+        param_types: &[DIType],
+        line: u32,
+        flags: DIFlags,
+        llvm_func: FunctionValue,
+    ) {
+        let file = self.compile_unit.get_file();
+        let subroutine_type =
+            self.builder
+                .create_subroutine_type(file, return_type, param_types, flags);
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name,
+            None,
+            file,
+            line,
+            subroutine_type,
+            false,
+            true,
+            line,
+            flags,
+            false,
+        );
+        llvm_func.set_subprogram(subprogram);
+        self.scope = Some(subprogram.as_debug_info_scope());
+        self.line = line;
+    }
+
+    // Tuples, strings and vectors are not described.
+    fn di_type(&self, t: &Type) -> Option<DIType> {
+        let (size, encoding) = match t {
+            Type::Bool => (8, DW_ATE_BOOLEAN),
+            Type::Char => (32, DW_ATE_UTF),
+            Type::Float64 => (64, DW_ATE_FLOAT),
+            t if t.is_signed() => (t.bit_width()? as u64, DW_ATE_SIGNED),
+            t if t.is_int() => (t.bit_width()? as u64, DW_ATE_UNSIGNED),
+            _ => return None,
+        };
+        self.builder
+            .create_basic_type(&t.source_name(), size, encoding, DIFlags::PUBLIC)
+            .ok()
+            .map(|t| t.as_type())
+    }
+}
+
+impl<'a> Compiler<'a> {
+    // Attaches a subprogram to the function and makes it the scope of the
+    // statements compiled next.
+    pub(super) fn debug_function(&mut self, func: &TypedFunction, llvm_func: FunctionValue) {
+        let debug = match self.debug.as_mut() {
+            Some(debug) => debug,
+            None => return,
+        };
+
+        let return_type = debug.di_type(&func.return_type);
+        let param_types: Vec<DIType> = func
+            .params
+            .iter()
+            .filter_map(|param| debug.di_type(&param.param_type))
+            .collect();
+        let line = func.span.map_or(0, |span| span.line);
+        debug.subprogram(
+            &func.name,
+            return_type,
+            &param_types,
+            line,
+            DIFlags::PUBLIC,
+            llvm_func,
+        );
+        self.debug_location(func.span);
+    }
+
+    // Attaches an artificial subprogram to a function generated by the
+    // compiler, so that the calls it makes have a location.
+    pub(super) fn debug_generated_function(&mut self, name: &str, llvm_func: FunctionValue) {
+        let debug = match self.debug.as_mut() {
+            Some(debug) => debug,
+            None => return,
+        };
+
+        debug.subprogram(name, None, &[], 0, DIFlags::ARTIFICIAL, llvm_func);
+        self.debug_location(None);
+    }
+
+    // Sets the location of the instructions built next. Statements without a
+    // position keep the location of the previous statement.
+    pub(super) fn debug_location(&mut self, span: Option<Span>) {
+        let debug = match self.debug.as_mut() {
+            Some(debug) => debug,
+            None => return,
+        };
+        let scope = match debug.scope {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let column = match span {
+            Some(span) => {
+                debug.line = span.line;
+                span.column
+            }
+            None => 0,
+        };
+        let location =
+            debug
+                .builder
+                .create_debug_location(self.context, debug.line, column, scope, None);
+        self.builder
+            .set_current_debug_location(self.context, location);
+    }
+
+    // Describes a variable stored in the alloca. Parameters are numbered
+    // from 1.
+    pub(super) fn debug_variable(
+        &self,
+        name: &str,
+        var_type: &Type,
+        alloca: PointerValue,
+        arg_no: Option<u32>,
+    ) {
+        let debug = match self.debug.as_ref() {
+            Some(debug) => debug,
+            None => return,
+        };
+        let (scope, di_type) = match (debug.scope, debug.di_type(var_type)) {
+            (Some(scope), Some(di_type)) => (scope, di_type),
+            _ => return,
+        };
+
+        let file = debug.compile_unit.get_file();
+        let var_info = match arg_no {
+            Some(arg_no) => debug.builder.create_parameter_variable(
+                scope,
+                name,
+                arg_no,
+                file,
+                debug.line,
+                di_type,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => debug.builder.create_auto_variable(
+                scope,
+                name,
+                file,
+                debug.line,
+                di_type,
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        };
+
+        let location =
+            debug
+                .builder
+                .create_debug_location(self.context, debug.line, 0, scope, None);
+        let block = self.builder.get_insert_block().unwrap();
+        debug
+            .builder
+            .insert_declare_at_end(alloca, Some(var_info), None, location, block);
+    }
+}
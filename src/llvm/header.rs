@@ -0,0 +1,92 @@
+// C header for programs compiled to a static library. Every function except
+// main and the extern ones is declared, so that C programs can link against
+// the library.
+use super::jit::Signature;
+use crate::ast::*;
+
+use std::path::Path;
+
+// The C type of values of the type, or None if they cannot be passed to C.
+fn c_type(t: &Type) -> Option<&'static str> {
+    Some(match t {
+        Type::Int8 => "int8_t",
+        Type::Int16 => "int16_t",
+        Type::Int32 => "int32_t",
+        Type::Int64 => "int64_t",
+        Type::UInt8 => "uint8_t",
+        Type::UInt16 => "uint16_t",
+        Type::UInt32 | Type::Char => "uint32_t",
+        Type::UInt64 | Type::USize => "uint64_t",
+        Type::Float64 => "double",
+        Type::Bool => "bool",
+        Type::Str => "const char *",
+        Type::Void => "void",
+        _ => return None,
+    })
+}
+
+// Declares the function, e.g. ´int32_t add(int32_t a, int32_t b);´.
+// Functions taking or returning tuples or vectors have no C declaration.
+fn c_declaration(sig: &Signature) -> Option<String> {
+    let mut params: Vec<String> = Vec::new();
+    for (name, t) in sig.param_names.iter().zip(sig.param_types.iter()) {
+        match c_type(t)? {
+            "void" => return None,
+            c_type if c_type.ends_with('*') => params.push(format!("{}{}", c_type, name)),
+            c_type => params.push(format!("{} {}", c_type, name)),
+        }
+    }
+    if params.is_empty() {
+        params.push("void".to_string());
+    }
+
+    let return_type = c_type(&sig.return_type)?;
+    Some(format!(
+        "{} {}({});",
+        return_type,
+        sig.name,
+        params.join(", ")
+    ))
+}
+
+// Returns the header for the library written to the path. The include
+// guard is derived from the file name of the header.
+pub(super) fn c_header(path: &Path, signatures: &[Signature]) -> String {
+    let guard: String = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut header = format!(
+        "#ifndef {0}\n#define {0}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {{\n#endif\n\n",
+        guard
+    );
+    for sig in signatures
+        .iter()
+        .filter(|sig| !sig.is_extern && sig.name != "main")
+    {
+        match c_declaration(sig) {
+            Some(declaration) => header.push_str(&declaration),
+            None => header.push_str(&format!(
+                "/* {} takes or returns values that cannot be passed to C */",
+                sig.name
+            )),
+        }
+        header.push('\n');
+    }
+    header.push_str(&format!(
+        "\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {} */\n",
+        guard
+    ));
+    header
+}
@@ -0,0 +1,231 @@
+// Calls compiled functions from Rust. Every function with supported types gets
+// an entry function taking its arguments and result as arrays of i64, so that
+// functions of any signature can be called through a single function type.
+use super::*;
+
+use inkwell::execution_engine::ExecutionEngine;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+type EntryFunc = unsafe extern "C" fn(*const i64, *mut i64) -> i32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Signature {
+    pub(super) name: String,
+    pub(super) param_names: Vec<String>,
+    pub(super) param_types: Vec<Type>,
+    pub(super) return_type: Type,
+    pub(super) is_extern: bool,
+}
+
+impl Signature {
+    pub(super) fn of(func: &TypedFunction) -> Self {
+        Signature {
+            name: func.name.clone(),
+            param_names: func.params.iter().map(|p| p.name.clone()).collect(),
+            param_types: func.params.iter().map(|p| p.param_type.clone()).collect(),
+            return_type: func.return_type.clone(),
+            is_extern: func.is_extern,
+        }
+    }
+
+    // Only i32 and bool values can be passed between Rust and compiled code.
+    pub(super) fn is_callable(&self) -> bool {
+        let is_value = |t: &Type| *t == Type::Int32 || *t == Type::Bool;
+        self.param_types.iter().all(is_value)
+            && (is_value(&self.return_type) || self.return_type == Type::Void)
+    }
+}
+
+fn entry_name(name: &str) -> String {
+    format!("__jit_entry_{}", name)
+}
+
+impl<'a> Compiler<'a> {
+    // i32 __jit_entry_<name>(const i64 *args, i64 *ret)
+    // Returns 0 and stores the result in ret[0], or returns 1 if the function
+    // panicked and stores the message, file, line and column in ret[0..4].
+    pub(super) fn compile_entry(&mut self, sig: &Signature) {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let i64_ptr = i64_type.ptr_type(AddressSpace::Generic);
+        let fn_type = i32_type.fn_type(&[i64_ptr.into(), i64_ptr.into()], false);
+        let name = entry_name(&sig.name);
+        let entry = self.module.add_function(&name, fn_type, None);
+        let args_ptr = entry.get_nth_param(0).unwrap().into_pointer_value();
+        let ret_ptr = entry.get_nth_param(1).unwrap().into_pointer_value();
+        let entry_block = self.context.append_basic_block(&entry, "entry");
+        let call_block = self.context.append_basic_block(&entry, "call");
+        let panic_block = self.context.append_basic_block(&entry, "panicked");
+        self.builder.position_at_end(&entry_block);
+        self.debug_generated_function(&name, entry);
+
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let jmp_buf = self
+            .builder
+            .build_pointer_cast(self.rt_panic_jmp_buf(), str_type, "jmpbuf");
+        let jumped = self
+            .builder
+            .build_call(self.rt_setjmp(), &[jmp_buf.into()], "setjmp")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let panicked = self.builder.build_int_compare(
+            IntPredicate::NE,
+            jumped,
+            i32_type.const_int(0, false),
+            "panicked",
+        );
+        self.builder
+            .build_conditional_branch(panicked, &panic_block, &call_block);
+
+        self.builder.position_at_end(&panic_block);
+        let info = self.rt_panic_info();
+        let zero = i32_type.const_int(0, false);
+        for i in 0..4 {
+            let index = i32_type.const_int(i, false);
+            let field = unsafe { self.builder.build_gep(info, &[zero, index], "field") };
+            let field = self.builder.build_load(field, "field");
+            let field = match field {
+                BasicValueEnum::PointerValue(ptr) => {
+                    self.builder.build_ptr_to_int(ptr, i64_type, "field")
+                }
+                _ => self
+                    .builder
+                    .build_int_z_extend(field.into_int_value(), i64_type, "field"),
+            };
+            let ret_field = unsafe {
+                self.builder
+                    .build_gep(ret_ptr, &[i64_type.const_int(i, false)], "retptr")
+            };
+            self.builder.build_store(ret_field, field);
+        }
+        self.builder
+            .build_return(Some(&i32_type.const_int(1, false)));
+
+        self.builder.position_at_end(&call_block);
+
+        let mut args: Vec<BasicValueEnum> = Vec::new();
+        for (i, t) in sig.param_types.iter().enumerate() {
+            let index = i64_type.const_int(i as u64, false);
+            let arg_ptr = unsafe { self.builder.build_gep(args_ptr, &[index], "argptr") };
+            let arg = self.builder.build_load(arg_ptr, "arg").into_int_value();
+            let arg_type = match t {
+                Type::Bool => self.context.bool_type(),
+                _ => self.context.i32_type(),
+            };
+            args.push(self.builder.build_int_truncate(arg, arg_type, "arg").into());
+        }
+
+        let function = self.module.get_function(&sig.name).unwrap();
+        let call = self.builder.build_call(function, &args, "call");
+        if sig.return_type != Type::Void {
+            let ret = call.try_as_basic_value().left().unwrap().into_int_value();
+            let ret = match sig.return_type {
+                Type::Bool => self.builder.build_int_z_extend(ret, i64_type, "ret"),
+                _ => self.builder.build_int_s_extend(ret, i64_type, "ret"),
+            };
+            self.builder.build_store(ret_ptr, ret);
+        }
+        self.builder
+            .build_return(Some(&i32_type.const_int(0, false)));
+    }
+}
+
+// A compiled program whose functions can be called any number of times.
+pub struct JitSession {
+    execution_engine: ExecutionEngine,
+    signatures: Vec<Signature>,
+    // Owns the module run by the execution engine
+    _module: CompiledModule,
+}
+
+impl JitSession {
+    pub fn new(fn_list: Functions) -> CompileRes<Self> {
+        Self::with_options(fn_list, &CompileOptions::default())
+    }
+
+    pub fn with_options(fn_list: Functions, options: &CompileOptions) -> CompileRes<Self> {
+        let module = compile_module(fn_list, options, true)?;
+        module.check_host_target("running")?;
+
+        let execution_engine = module
+            .module
+            .create_jit_execution_engine(module.opt_level.codegen_level())
+            .map_err(|e| CompileError::Jit(e.to_string()))?;
+        Ok(JitSession {
+            execution_engine,
+            signatures: module.signatures.clone(),
+            _module: module,
+        })
+    }
+
+    // Calls the function with the arguments, which must match the types of
+    // its parameters. Functions without a return value return ´()´. A panic
+    // of the function is returned as an error, and the vectors it had
+    // allocated are leaked.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> CompileRes<Value> {
+        let sig = match self.signatures.iter().find(|sig| sig.name == name) {
+            Some(sig) => sig,
+            None => return Err(CompileError::UnknownFunction(name.to_string())),
+        };
+        if !sig.is_callable() {
+            return err_unsupported(&format!(
+                "a call to ´{}´ whose signature has other types than ´i32´ and ´bool´",
+                name
+            ));
+        }
+        if sig.param_types.len() != args.len() {
+            return Err(CompileError::InvalidArgs(format!(
+                "function ´{}´ takes {} argument(s) but {} were supplied",
+                name,
+                sig.param_types.len(),
+                args.len()
+            )));
+        }
+
+        let mut raw_args: Vec<i64> = Vec::new();
+        for (param_type, arg) in sig.param_types.iter().zip(args) {
+            match (param_type, arg) {
+                (Type::Int32, Value::Num(i)) => raw_args.push(i as i64),
+                (Type::Bool, Value::Bool(b)) => raw_args.push(b as i64),
+                (param_type, arg) => {
+                    return Err(CompileError::InvalidArgs(format!(
+                        "function ´{}´ expected ´{}´, found ´{:?}´",
+                        name,
+                        String::from(param_type.clone()),
+                        arg
+                    )))
+                }
+            }
+        }
+
+        let entry: JitFunction<EntryFunc> =
+            unsafe { self.execution_engine.get_function(&entry_name(name)) }
+                .map_err(|e| CompileError::Jit(format!("{:?}", e)))?;
+        let mut ret = [0i64; 4];
+        if unsafe { entry.call(raw_args.as_ptr(), ret.as_mut_ptr()) } != 0 {
+            // The message and the file are global strings of the module.
+            let (msg, file) = unsafe {
+                (
+                    CStr::from_ptr(ret[0] as *const c_char),
+                    CStr::from_ptr(ret[1] as *const c_char),
+                )
+            };
+            return Err(CompileError::Panic(format!(
+                "panicked at '{}', {}:{}:{}",
+                msg.to_string_lossy(),
+                file.to_string_lossy(),
+                ret[2],
+                ret[3]
+            )));
+        }
+
+        Ok(match sig.return_type {
+            Type::Int32 => Value::Num(ret[0] as i32),
+            Type::Bool => Value::Bool(ret[0] != 0),
+            _ => Value::Tuple(vec![]),
+        })
+    }
+}
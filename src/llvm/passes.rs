@@ -0,0 +1,71 @@
+// Optimisation pipeline run on compiled modules. The levels follow -O0 to -O3
+// of rustc and clang, but only a small set of passes is used.
+use inkwell::{module::Module, passes::PassManager, OptimizationLevel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+}
+
+impl OptLevel {
+    // The level used by the JIT and target machines for code generation.
+    pub(super) fn codegen_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+impl Default for OptLevel {
+    fn default() -> Self {
+        OptLevel::O0
+    }
+}
+
+// Runs the passes of the level on every function and then on the module.
+// Variables are compiled to allocas, so mem2reg is what turns them into
+// registers. Inlining is only done from -O2 since it grows the code.
+pub(super) fn run_passes(module: &Module, level: OptLevel) {
+    if level == OptLevel::O0 {
+        return;
+    }
+
+    let fpm = PassManager::create(module);
+    fpm.add_promote_memory_to_register_pass();
+    fpm.add_instruction_combining_pass();
+    fpm.add_cfg_simplification_pass();
+    if level >= OptLevel::O2 {
+        fpm.add_reassociate_pass();
+        fpm.add_gvn_pass();
+        fpm.add_dead_store_elimination_pass();
+        fpm.add_cfg_simplification_pass();
+    }
+    if level >= OptLevel::O3 {
+        fpm.add_aggressive_dce_pass();
+        fpm.add_instruction_combining_pass();
+    }
+    fpm.initialize();
+
+    let mut function = module.get_first_function();
+    while let Some(f) = function {
+        fpm.run_on(&f);
+        function = f.get_next_function();
+    }
+    fpm.finalize();
+
+    if level >= OptLevel::O2 {
+        let mpm = PassManager::create(());
+        mpm.add_function_inlining_pass();
+        mpm.add_promote_memory_to_register_pass();
+        mpm.add_instruction_combining_pass();
+        mpm.add_gvn_pass();
+        mpm.add_cfg_simplification_pass();
+        mpm.run_on(module);
+    }
+}
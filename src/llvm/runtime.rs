@@ -0,0 +1,464 @@
+// Small runtime used by the generated code. Functions from libc are declared
+// and resolved by the JIT or the linker, helpers are defined directly in IR.
+use super::Compiler;
+
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    builder::Builder,
+    module::Linkage,
+    types::{IntType, StructType},
+    values::{BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
+};
+
+impl<'a> Compiler<'a> {
+    // int printf(const char *fmt, ...)
+    pub(super) fn rt_printf(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("printf") {
+            return function;
+        }
+
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.context.i32_type().fn_type(&[str_type.into()], true);
+        self.module.add_function("printf", fn_type, None)
+    }
+
+    // void *realloc(void *ptr, size_t size)
+    pub(super) fn rt_realloc(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("realloc") {
+            return function;
+        }
+
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = ptr_type.fn_type(&[ptr_type.into(), self.context.i64_type().into()], false);
+        self.module.add_function("realloc", fn_type, None)
+    }
+
+    // void free(void *ptr)
+    pub(super) fn rt_free(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("free") {
+            return function;
+        }
+
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.context.void_type().fn_type(&[ptr_type.into()], false);
+        self.module.add_function("free", fn_type, None)
+    }
+
+    // Vectors are represented as { i8 *data, i64 len, i64 cap }. The data is
+    // allocated with realloc and cast to the element type when accessed.
+    pub(super) fn rt_vec_type(&self) -> StructType {
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let i64_type = self.context.i64_type();
+        self.context
+            .struct_type(&[ptr_type.into(), i64_type.into(), i64_type.into()], false)
+    }
+
+    // void __rt_vec_reserve(vec *v, i64 elem_size)
+    // Makes room for at least one more element by doubling the capacity of
+    // a full vector. Empty vectors start with room for four elements.
+    pub(super) fn rt_vec_reserve(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("__rt_vec_reserve") {
+            return function;
+        }
+
+        let i64_type = self.context.i64_type();
+        let vec_ptr_type = self.rt_vec_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[vec_ptr_type.into(), i64_type.into()], false);
+        let function =
+            self.module
+                .add_function("__rt_vec_reserve", fn_type, Some(Linkage::Internal));
+        let vec_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
+        let elem_size = function.get_nth_param(1).unwrap().into_int_value();
+
+        let builder = self.context.create_builder();
+        let entry = self.context.append_basic_block(&function, "entry");
+        let grow = self.context.append_basic_block(&function, "grow");
+        let done = self.context.append_basic_block(&function, "done");
+
+        builder.position_at_end(&entry);
+        let vec = builder.build_load(vec_ptr, "vec").into_struct_value();
+        let len = builder
+            .build_extract_value(vec, 1, "len")
+            .unwrap()
+            .into_int_value();
+        let cap = builder
+            .build_extract_value(vec, 2, "cap")
+            .unwrap()
+            .into_int_value();
+        let full = builder.build_int_compare(IntPredicate::EQ, len, cap, "full");
+        builder.build_conditional_branch(full, &grow, &done);
+
+        builder.position_at_end(&grow);
+        let zero = i64_type.const_int(0, false);
+        let is_empty = builder.build_int_compare(IntPredicate::EQ, cap, zero, "isempty");
+        let doubled = builder.build_int_mul(cap, i64_type.const_int(2, false), "doubled");
+        let new_cap = builder
+            .build_select(is_empty, i64_type.const_int(4, false), doubled, "newcap")
+            .into_int_value();
+        let data = builder.build_extract_value(vec, 0, "data").unwrap();
+        let size = builder.build_int_mul(new_cap, elem_size, "size");
+        let new_data = builder
+            .build_call(self.rt_realloc(), &[data, size.into()], "newdata")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        let vec = builder.build_insert_value(vec, new_data, 0, "vec").unwrap();
+        let vec = builder.build_insert_value(vec, new_cap, 2, "vec").unwrap();
+        builder.build_store(vec_ptr, vec);
+        builder.build_unconditional_branch(&done);
+
+        builder.position_at_end(&done);
+        builder.build_return(None);
+
+        function
+    }
+
+    // vec __rt_vec_clone(vec *v, i64 elem_size)
+    // Returns a copy of the vector with its own data, with room for exactly
+    // its elements.
+    pub(super) fn rt_vec_clone(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("__rt_vec_clone") {
+            return function;
+        }
+
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let vec_type = self.rt_vec_type();
+        let fn_type = vec_type.fn_type(
+            &[
+                vec_type.ptr_type(AddressSpace::Generic).into(),
+                i64_type.into(),
+            ],
+            false,
+        );
+        let function = self
+            .module
+            .add_function("__rt_vec_clone", fn_type, Some(Linkage::Internal));
+        let vec_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
+        let elem_size = function.get_nth_param(1).unwrap().into_int_value();
+
+        // void @llvm.memcpy.p0i8.p0i8.i64(i8 *dest, i8 *src, i64 len, i1 volatile)
+        let memcpy = match self.module.get_function("llvm.memcpy.p0i8.p0i8.i64") {
+            Some(memcpy) => memcpy,
+            None => self.module.add_function(
+                "llvm.memcpy.p0i8.p0i8.i64",
+                self.context.void_type().fn_type(
+                    &[
+                        ptr_type.into(),
+                        ptr_type.into(),
+                        i64_type.into(),
+                        self.context.bool_type().into(),
+                    ],
+                    false,
+                ),
+                None,
+            ),
+        };
+
+        let builder = self.context.create_builder();
+        let entry = self.context.append_basic_block(&function, "entry");
+        builder.position_at_end(&entry);
+        let vec = builder.build_load(vec_ptr, "vec").into_struct_value();
+        let data = builder.build_extract_value(vec, 0, "data").unwrap();
+        let len = builder.build_extract_value(vec, 1, "len").unwrap();
+        let size = builder.build_int_mul(len.into_int_value(), elem_size, "size");
+        let new_data = builder
+            .build_call(
+                self.rt_realloc(),
+                &[ptr_type.const_null().into(), size.into()],
+                "newdata",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        builder.build_call(
+            memcpy,
+            &[
+                new_data,
+                data,
+                size.into(),
+                self.context.bool_type().const_int(0, false).into(),
+            ],
+            "memcpy",
+        );
+        let copy = builder
+            .build_insert_value(vec, new_data, 0, "copy")
+            .unwrap();
+        let copy = builder.build_insert_value(copy, len, 2, "copy").unwrap();
+        builder.build_return(Some(&copy));
+
+        function
+    }
+
+    // void __rt_panic(const char *msg, const char *file, i32 line, i32 column)
+    // Writes the message and the source location to stderr and exits with the
+    // same code as a panicking Rust program. In a JIT session, the arguments
+    // are stored in __rt_panic_info instead and the panic jumps back to the
+    // entry function, which returns them to Rust.
+    pub(super) fn rt_panic(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("__rt_panic") {
+            return function;
+        }
+
+        let i32_type = self.context.i32_type();
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let void_type = self.context.void_type();
+
+        let function = self.module.add_function(
+            "__rt_panic",
+            void_type.fn_type(
+                &[
+                    str_type.into(),
+                    str_type.into(),
+                    i32_type.into(),
+                    i32_type.into(),
+                ],
+                false,
+            ),
+            Some(Linkage::Internal),
+        );
+
+        let builder = self.context.create_builder();
+        let entry = self.context.append_basic_block(&function, "entry");
+        builder.position_at_end(&entry);
+
+        if self.recover_panics {
+            let info = self.rt_panic_info();
+            let zero = i32_type.const_int(0, false);
+            for (i, param) in function.get_params().into_iter().enumerate() {
+                let index = i32_type.const_int(i as u64, false);
+                let field = unsafe { builder.build_gep(info, &[zero, index], "field") };
+                builder.build_store(field, param);
+            }
+            let jmp_buf = builder.build_pointer_cast(self.rt_panic_jmp_buf(), str_type, "jmpbuf");
+            builder.build_call(
+                self.rt_longjmp(),
+                &[jmp_buf.into(), i32_type.const_int(1, false).into()],
+                "longjmp",
+            );
+            builder.build_unreachable();
+            return function;
+        }
+
+        let dprintf = self.module.add_function(
+            "dprintf",
+            i32_type.fn_type(&[i32_type.into(), str_type.into()], true),
+            None,
+        );
+        let exit =
+            self.module
+                .add_function("exit", void_type.fn_type(&[i32_type.into()], false), None);
+
+        let stderr = i32_type.const_int(2, false);
+        let fmt = builder.build_global_string_ptr("panicked at '%s', %s:%u:%u\n", "panicfmt");
+        let mut args: Vec<BasicValueEnum> = vec![stderr.into(), fmt.as_pointer_value().into()];
+        args.extend(function.get_params());
+        builder.build_call(dprintf, &args, "dprintf");
+        builder.build_call(exit, &[i32_type.const_int(101, false).into()], "exit");
+        builder.build_unreachable();
+
+        function
+    }
+
+    // { const char *msg, const char *file, i32 line, i32 column } __rt_panic_info
+    // The arguments of the last panic in a JIT session.
+    pub(super) fn rt_panic_info(&self) -> PointerValue {
+        if let Some(global) = self.module.get_global("__rt_panic_info") {
+            return global.as_pointer_value();
+        }
+
+        let i32_type = self.context.i32_type();
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let info_type = self.context.struct_type(
+            &[
+                str_type.into(),
+                str_type.into(),
+                i32_type.into(),
+                i32_type.into(),
+            ],
+            false,
+        );
+        let global = self.module.add_global(info_type, None, "__rt_panic_info");
+        global.set_linkage(Linkage::Internal);
+        global.set_initializer(&info_type.const_zero());
+        global.as_pointer_value()
+    }
+
+    // jmp_buf __rt_panic_jmp_buf
+    // Saved by the entry functions of a JIT session and restored by panics.
+    // Has room for the jmp_buf of glibc on x86-64 and AArch64.
+    pub(super) fn rt_panic_jmp_buf(&self) -> PointerValue {
+        if let Some(global) = self.module.get_global("__rt_panic_jmp_buf") {
+            return global.as_pointer_value();
+        }
+
+        let buf_type = self.context.i64_type().array_type(64);
+        let global = self.module.add_global(buf_type, None, "__rt_panic_jmp_buf");
+        global.set_linkage(Linkage::Internal);
+        global.set_alignment(16);
+        global.set_initializer(&buf_type.const_zero());
+        global.as_pointer_value()
+    }
+
+    // int _setjmp(jmp_buf env)
+    // Returns 0 when called and the value passed to _longjmp when it is
+    // jumped to. The signal mask is not saved.
+    pub(super) fn rt_setjmp(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("_setjmp") {
+            return function;
+        }
+
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.context.i32_type().fn_type(&[ptr_type.into()], false);
+        let function = self.module.add_function("_setjmp", fn_type, None);
+        let returns_twice = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("returns_twice"), 0);
+        function.add_attribute(AttributeLoc::Function, returns_twice);
+        function
+    }
+
+    // void _longjmp(jmp_buf env, int val)
+    pub(super) fn rt_longjmp(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("_longjmp") {
+            return function;
+        }
+
+        let i32_type = self.context.i32_type();
+        let ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[ptr_type.into(), i32_type.into()], false);
+        let function = self.module.add_function("_longjmp", fn_type, None);
+        let noreturn = self
+            .context
+            .create_enum_attribute(Attribute::get_named_enum_kind_id("noreturn"), 0);
+        function.add_attribute(AttributeLoc::Function, noreturn);
+        function
+    }
+
+    // {iN, i1} @llvm.<op>.with.overflow.iN(iN, iN)
+    // Intrinsic returning the wrapped result of the operation and whether it
+    // overflowed. The op is one of sadd, uadd, ssub, usub, smul and umul.
+    pub(super) fn rt_overflow_intrinsic(&self, op: &str, int_type: IntType) -> FunctionValue {
+        let name = format!("llvm.{}.with.overflow.i{}", op, int_type.get_bit_width());
+        if let Some(function) = self.module.get_function(&name) {
+            return function;
+        }
+
+        let ret_type = self
+            .context
+            .struct_type(&[int_type.into(), self.context.bool_type().into()], false);
+        self.module.add_function(
+            &name,
+            ret_type.fn_type(&[int_type.into(), int_type.into()], false),
+            None,
+        )
+    }
+
+    // void __rt_utf8_encode(i32 c, i8 *buf)
+    // Writes the UTF-8 encoding of the code point c followed by a NUL byte to
+    // buf, which must have room for five bytes.
+    pub(super) fn rt_utf8_encode(&self) -> FunctionValue {
+        if let Some(function) = self.module.get_function("__rt_utf8_encode") {
+            return function;
+        }
+
+        let i8_type = self.context.i8_type();
+        let i32_type = self.context.i32_type();
+        let fn_type = self.context.void_type().fn_type(
+            &[
+                i32_type.into(),
+                i8_type.ptr_type(AddressSpace::Generic).into(),
+            ],
+            false,
+        );
+        let function =
+            self.module
+                .add_function("__rt_utf8_encode", fn_type, Some(Linkage::Internal));
+        let code = function.get_nth_param(0).unwrap().into_int_value();
+        let buf = function.get_nth_param(1).unwrap().into_pointer_value();
+
+        // The builder of the compiler is in the middle of another function.
+        let builder = self.context.create_builder();
+        let entry = self.context.append_basic_block(&function, "entry");
+        let one = self.context.append_basic_block(&function, "one");
+        let not_one = self.context.append_basic_block(&function, "not_one");
+        let two = self.context.append_basic_block(&function, "two");
+        let not_two = self.context.append_basic_block(&function, "not_two");
+        let three = self.context.append_basic_block(&function, "three");
+        let four = self.context.append_basic_block(&function, "four");
+
+        // Stores the bytes of the encoding, the first byte is prefixed with the
+        // marker and each following byte holds six bits of the code point.
+        let store_bytes = |len: u64, marker: u64| {
+            for i in 0..len {
+                let shift = i32_type.const_int(6 * (len - 1 - i), false);
+                let bits = builder.build_right_shift(code, shift, false, "bits");
+                let (mask, prefix) = if i == 0 {
+                    (0x7f >> len, marker)
+                } else {
+                    (0x3f, 0x80)
+                };
+                let byte = builder.build_or(
+                    builder.build_and(bits, i32_type.const_int(mask, false), "mask"),
+                    i32_type.const_int(prefix, false),
+                    "byte",
+                );
+                self.rt_store_byte(
+                    &builder,
+                    buf,
+                    i,
+                    builder.build_int_truncate(byte, i8_type, "trunc"),
+                );
+            }
+            self.rt_store_byte(&builder, buf, len, i8_type.const_int(0, false));
+            builder.build_return(None);
+        };
+
+        let below = |limit: u64| {
+            builder.build_int_compare(
+                IntPredicate::ULT,
+                code,
+                i32_type.const_int(limit, false),
+                "below",
+            )
+        };
+
+        builder.position_at_end(&entry);
+        builder.build_conditional_branch(below(0x80), &one, &not_one);
+        builder.position_at_end(&not_one);
+        builder.build_conditional_branch(below(0x800), &two, &not_two);
+        builder.position_at_end(&not_two);
+        builder.build_conditional_branch(below(0x10000), &three, &four);
+
+        builder.position_at_end(&one);
+        self.rt_store_byte(
+            &builder,
+            buf,
+            0,
+            builder.build_int_truncate(code, i8_type, "trunc"),
+        );
+        self.rt_store_byte(&builder, buf, 1, i8_type.const_int(0, false));
+        builder.build_return(None);
+        builder.position_at_end(&two);
+        store_bytes(2, 0xc0);
+        builder.position_at_end(&three);
+        store_bytes(3, 0xe0);
+        builder.position_at_end(&four);
+        store_bytes(4, 0xf0);
+
+        function
+    }
+
+    fn rt_store_byte(&self, builder: &Builder, buf: PointerValue, index: u64, byte: IntValue) {
+        let index = self.context.i32_type().const_int(index, false);
+        let ptr = unsafe { builder.build_gep(buf, &[index], "byteptr") };
+        builder.build_store(ptr, byte);
+    }
+}
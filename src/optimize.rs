@@ -0,0 +1,666 @@
+use crate::ast::*;
+use crate::fold::{fold_program, pattern_names, Diagnostic};
+use crate::type_checker::is_int_literal;
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::iter;
+
+// Optimisations on the AST that keep the output and errors of a program the
+// same with the interpreter. Each pass can be turned off in
+// ´OptimizeOptions´. They run in the order of the fields, so that constants
+// passed to inlined functions are folded and the functions that are no
+// longer called are removed.
+//
+// Calls to small functions that are not recursive are replaced by the body
+// of the function when the call is a statement, the value of a ´let´, an
+// assignment to a variable or a ´return´. The arguments are bound with a
+// ´let´ to the parameters, and the parameters and variables of the function
+// are renamed to names with an ´_´, which the parser does not accept, so
+// they cannot clash with the variables of the caller. Only functions whose
+// single ´return´ is their last statement are inlined.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizeOptions {
+    pub inline: bool,
+    // Largest number of expressions in a function that is inlined
+    pub inline_size: usize,
+    // Constant folding and propagation, see ´fold_program´
+    pub fold: bool,
+    // Removes the statements after a ´return´
+    pub dead_code: bool,
+    // Removes ´let´s of variables that are never used, if their value
+    // cannot fail or print
+    pub unused_lets: bool,
+    // Removes the functions that are not called from main, if there is one
+    pub unused_functions: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        OptimizeOptions {
+            inline: true,
+            inline_size: 40,
+            fold: true,
+            dead_code: true,
+            unused_lets: true,
+            unused_functions: true,
+        }
+    }
+}
+
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinOp(l, _, r) | Expr::Index(l, r) => vec![l, r],
+        Expr::Let(pattern, _, val) => vec![pattern, val],
+        Expr::VarOp(var, _, val) => vec![var, val],
+        Expr::TupleIndex(expr, _) | Expr::Cast(expr, _) | Expr::Return(expr) => vec![expr],
+        Expr::Tuple(exprs) => exprs.iter().collect(),
+        Expr::MethodCall(expr, _, args) => iter::once(&**expr).chain(args.iter()).collect(),
+        Expr::If(cond, block) | Expr::IfElse(cond, block) | Expr::While(cond, block) => {
+            iter::once(&**cond).chain(block.content.iter()).collect()
+        }
+        Expr::FuncCall(fn_call) => fn_call.args.content.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn children_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::BinOp(l, _, r) | Expr::Index(l, r) => vec![l, r],
+        Expr::Let(pattern, _, val) => vec![pattern, val],
+        Expr::VarOp(var, _, val) => vec![var, val],
+        Expr::TupleIndex(expr, _) | Expr::Cast(expr, _) | Expr::Return(expr) => vec![expr],
+        Expr::Tuple(exprs) => exprs.iter_mut().collect(),
+        Expr::MethodCall(expr, _, args) => iter::once(&mut **expr).chain(args.iter_mut()).collect(),
+        Expr::If(cond, block) | Expr::IfElse(cond, block) | Expr::While(cond, block) => {
+            iter::once(&mut **cond)
+                .chain(block.content.iter_mut())
+                .collect()
+        }
+        Expr::FuncCall(fn_call) => fn_call.args.content.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The statements of the blocks of ´if´ and ´while´
+fn inner_block(stmt: &mut Expr) -> Option<&mut Block> {
+    match stmt {
+        Expr::If(_, block) | Expr::IfElse(_, block) | Expr::While(_, block) => Some(block),
+        _ => None,
+    }
+}
+
+fn size(expr: &Expr) -> usize {
+    1 + children(expr).into_iter().map(size).sum::<usize>()
+}
+
+fn mentions(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Var(var_name) => var_name == name,
+        expr => children(expr).into_iter().any(|e| mentions(e, name)),
+    }
+}
+
+fn contains_return(expr: &Expr) -> bool {
+    match expr {
+        Expr::Return(_) => true,
+        expr => children(expr).into_iter().any(contains_return),
+    }
+}
+
+// Values that are computed without side effects or errors, in programs
+// that pass the type checker
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Num(_)
+        | Expr::Float(_)
+        | Expr::Bool(_)
+        | Expr::Char(_)
+        | Expr::Str(_)
+        | Expr::Var(_)
+        | Expr::VecNew => true,
+        Expr::Tuple(exprs) => exprs.iter().all(is_pure),
+        Expr::Cast(expr, _) => is_pure(expr),
+        _ => false,
+    }
+}
+
+fn collect_calls(expr: &Expr, calls: &mut HashSet<String>) {
+    if let Expr::FuncCall(fn_call) = expr {
+        if !fn_call.is_builtin() {
+            calls.insert(fn_call.name.clone());
+        }
+    }
+    for e in children(expr) {
+        collect_calls(e, calls);
+    }
+}
+
+fn collect_names(expr: &Expr, names: &mut HashSet<String>) {
+    if let Expr::Var(name) = expr {
+        names.insert(name.clone());
+    }
+    for e in children(expr) {
+        collect_names(e, names);
+    }
+}
+
+// The variables declared in the statement, including in nested blocks
+fn declared_names(expr: &Expr, names: &mut Vec<String>) {
+    if let Expr::Let(pattern, _, _) = expr {
+        pattern_names(pattern, names);
+    }
+    for e in children(expr) {
+        declared_names(e, names);
+    }
+}
+
+fn rename(expr: &mut Expr, renames: &HashMap<String, String>) {
+    if let Expr::Var(name) = expr {
+        if let Some(new_name) = renames.get(name) {
+            *name = new_name.clone();
+        }
+    }
+    for e in children_mut(expr) {
+        rename(e, renames);
+    }
+}
+
+// The functions called directly by each function
+fn call_graph(fn_list: &[Function]) -> HashMap<String, HashSet<String>> {
+    fn_list
+        .iter()
+        .map(|func| {
+            let mut calls: HashSet<String> = HashSet::new();
+            for stmt in func.block.content.iter() {
+                collect_calls(stmt, &mut calls);
+            }
+            (func.name.clone(), calls)
+        })
+        .collect()
+}
+
+// The functions called by the functions in ´from´, directly or not
+fn reachable(graph: &HashMap<String, HashSet<String>>, from: &HashSet<String>) -> HashSet<String> {
+    let mut found: HashSet<String> = HashSet::new();
+    let mut stack: Vec<&String> = from.iter().collect();
+    while let Some(name) = stack.pop() {
+        if let Some(calls) = graph.get(name) {
+            for callee in calls {
+                if found.insert(callee.clone()) {
+                    stack.push(callee);
+                }
+            }
+        }
+    }
+    found
+}
+
+fn ends_with_return(func: &Function) -> bool {
+    matches!(func.block.content.last(), Some(Expr::Return(_)))
+}
+
+fn is_inlinable(
+    func: &Function,
+    graph: &HashMap<String, HashSet<String>>,
+    options: &OptimizeOptions,
+) -> bool {
+    let content = &func.block.content;
+    let returns = content.iter().filter(|stmt| contains_return(stmt)).count();
+    !func.is_extern
+        && func.name != "main"
+        && !content.is_empty()
+        // Tuples and vectors returned by a function are coerced to its type.
+        && !matches!(func.return_type, Type::Tuple(_) | Type::Vec(_))
+        && ((returns == 0 && func.return_type == Type::Void)
+            || (returns == 1 && ends_with_return(func)))
+        && content.iter().map(size).sum::<usize>() <= options.inline_size
+        && !reachable(graph, &graph[&func.name]).contains(&func.name)
+}
+
+struct Inliner<'a> {
+    callees: &'a HashMap<String, Function>,
+    // Every variable of the function that is optimised, so that the new
+    // variables get other names
+    names: HashSet<String>,
+}
+
+impl<'a> Inliner<'a> {
+    fn callee(&self, fn_call: &FunctionCall) -> Option<&'a Function> {
+        let callees: &'a HashMap<String, Function> = self.callees;
+        match callees.get(&fn_call.name) {
+            Some(callee)
+                if !fn_call.is_builtin() && callee.params.len() == fn_call.args.content.len() =>
+            {
+                Some(callee)
+            }
+            _ => None,
+        }
+    }
+
+    fn fresh_name(&mut self, name: &str, callee: &str) -> String {
+        let mut n = 1;
+        loop {
+            let new_name = format!("{}_{}_{}", name, callee, n);
+            if self.names.insert(new_name.clone()) {
+                return new_name;
+            }
+            n += 1;
+        }
+    }
+
+    // The statements of the body of the callee, after the arguments are
+    // bound to the parameters, and the expression of its final ´return´.
+    fn expand(&mut self, callee: &Function, args: Vec<Expr>) -> (Vec<Expr>, Option<Expr>) {
+        let mut renames: HashMap<String, String> = HashMap::new();
+        let mut stmts: Vec<Expr> = Vec::new();
+        for (param, arg) in callee.params.iter().zip(args) {
+            let new_name = self.fresh_name(&param.name, &callee.name);
+            renames.insert(param.name.clone(), new_name.clone());
+            stmts.push(Expr::Let(
+                Box::new(Expr::Var(new_name)),
+                param.param_type.clone(),
+                Box::new(arg),
+            ));
+        }
+        let mut names: Vec<String> = Vec::new();
+        for stmt in callee.block.content.iter() {
+            declared_names(stmt, &mut names);
+        }
+        for name in names {
+            if let Entry::Vacant(entry) = renames.entry(name) {
+                let new_name = self.fresh_name(entry.key(), &callee.name);
+                entry.insert(new_name);
+            }
+        }
+
+        let mut body = callee.block.content.clone();
+        for stmt in body.iter_mut() {
+            rename(stmt, &renames);
+        }
+        let res = match body.pop() {
+            // Returned literals are bound to a variable of the return type, so
+            // that they are computed in the type wherever the call was.
+            Some(Expr::Return(val))
+                if callee.return_type.is_int()
+                    && callee.return_type != Type::Int32
+                    && is_int_literal(&val) =>
+            {
+                let new_name = self.fresh_name("return", &callee.name);
+                body.push(Expr::Let(
+                    Box::new(Expr::Var(new_name.clone())),
+                    callee.return_type.clone(),
+                    val,
+                ));
+                Some(Expr::Var(new_name))
+            }
+            Some(Expr::Return(val)) => Some(*val),
+            Some(stmt) => {
+                body.push(stmt);
+                None
+            }
+            None => None,
+        };
+        stmts.extend(body);
+        (stmts, res)
+    }
+
+    // Inlines the call if its value is the expression, and adds the
+    // statements of the callee before the statement.
+    fn inline_value(&mut self, val: Expr, stmts: &mut Vec<Expr>) -> Expr {
+        let fn_call = match val {
+            Expr::FuncCall(fn_call) => fn_call,
+            val => return val,
+        };
+        let callee = match self.callee(&fn_call) {
+            Some(callee) => callee,
+            None => return Expr::FuncCall(fn_call),
+        };
+        match self.expand(callee, fn_call.args.content.clone()) {
+            (body, Some(res)) => {
+                stmts.extend(body);
+                res
+            }
+            // Without a final ´return´ there is no value to replace the call.
+            (_, None) => Expr::FuncCall(fn_call),
+        }
+    }
+
+    fn inline_stmt(&mut self, stmt: Expr) -> Vec<Expr> {
+        let mut stmts: Vec<Expr> = Vec::new();
+        let stmt = match stmt {
+            Expr::FuncCall(fn_call) => match self.callee(&fn_call) {
+                Some(callee) => {
+                    let (body, res) = self.expand(callee, fn_call.args.content);
+                    stmts.extend(body);
+                    match res {
+                        Some(val) => val,
+                        None => return stmts,
+                    }
+                }
+                None => Expr::FuncCall(fn_call),
+            },
+            Expr::Let(pattern, t, val) => {
+                let val = self.inline_value(*val, &mut stmts);
+                Expr::Let(pattern, t, Box::new(val))
+            }
+            // An index could be evaluated before the arguments of the call.
+            Expr::VarOp(var, op, val) if matches!(*var, Expr::Var(_)) => {
+                let val = self.inline_value(*val, &mut stmts);
+                Expr::VarOp(var, op, Box::new(val))
+            }
+            Expr::Return(val) => Expr::Return(Box::new(self.inline_value(*val, &mut stmts))),
+            mut stmt => {
+                if let Some(block) = inner_block(&mut stmt) {
+                    self.inline_block(block);
+                }
+                stmt
+            }
+        };
+        stmts.push(stmt);
+        stmts
+    }
+
+    // Statements added for a call get the position of the call.
+    fn inline_block(&mut self, block: &mut Block) {
+        let has_spans = block.stmt_spans.len() == block.content.len();
+        let content = std::mem::take(&mut block.content);
+        let spans = std::mem::take(&mut block.stmt_spans);
+        for (i, stmt) in content.into_iter().enumerate() {
+            let stmts = self.inline_stmt(stmt);
+            if has_spans {
+                block.stmt_spans.extend(vec![spans[i]; stmts.len()]);
+            }
+            block.content.extend(stmts);
+        }
+    }
+}
+
+fn inline_calls(fn_list: &mut Functions, options: &OptimizeOptions) {
+    let graph = call_graph(fn_list);
+    // Functions are inlined as they were before the pass.
+    let callees: HashMap<String, Function> = fn_list
+        .iter()
+        .filter(|func| is_inlinable(func, &graph, options))
+        .map(|func| (func.name.clone(), func.clone()))
+        .collect();
+
+    for func in fn_list.iter_mut() {
+        let mut names: HashSet<String> = func.params.iter().map(|p| p.name.clone()).collect();
+        for stmt in func.block.content.iter() {
+            collect_names(stmt, &mut names);
+        }
+        let mut inliner = Inliner {
+            callees: &callees,
+            names,
+        };
+        inliner.inline_block(&mut func.block);
+    }
+}
+
+fn remove_dead_code(block: &mut Block) {
+    if let Some(pos) = block
+        .content
+        .iter()
+        .position(|stmt| matches!(stmt, Expr::Return(_)))
+    {
+        block.content.truncate(pos + 1);
+        block.stmt_spans.truncate(pos + 1);
+    }
+    for stmt in block.content.iter_mut() {
+        if let Some(inner) = inner_block(stmt) {
+            remove_dead_code(inner);
+        }
+    }
+}
+
+// The last statement is kept since it is the value of the block.
+fn remove_unused_lets(block: &mut Block) {
+    for stmt in block.content.iter_mut() {
+        if let Some(inner) = inner_block(stmt) {
+            remove_unused_lets(inner);
+        }
+    }
+
+    let content = &block.content;
+    let mut keep = vec![true; content.len()];
+    for i in (0..content.len().saturating_sub(1)).rev() {
+        if let Expr::Let(pattern, _, val) = &content[i] {
+            let mut names: Vec<String> = Vec::new();
+            pattern_names(pattern, &mut names);
+            let used = names.iter().any(|name| {
+                content[i + 1..]
+                    .iter()
+                    .zip(keep[i + 1..].iter())
+                    .any(|(stmt, kept)| *kept && mentions(stmt, name))
+            });
+            keep[i] = used || !is_pure(val);
+        }
+    }
+
+    let has_spans = block.stmt_spans.len() == block.content.len();
+    let content = std::mem::take(&mut block.content);
+    let spans = std::mem::take(&mut block.stmt_spans);
+    for (i, stmt) in content.into_iter().enumerate() {
+        if keep[i] {
+            block.content.push(stmt);
+            if has_spans {
+                block.stmt_spans.push(spans[i]);
+            }
+        }
+    }
+}
+
+fn remove_unused_functions(fn_list: &mut Functions) {
+    if fn_list.iter().all(|func| func.name != "main") {
+        return;
+    }
+    let main: HashSet<String> = iter::once("main".to_string()).collect();
+    let mut used = reachable(&call_graph(fn_list), &main);
+    used.extend(main);
+    fn_list.retain(|func| used.contains(&func.name));
+}
+
+// Optimises the program in place with the passes selected in the options.
+// Returns the diagnostics of constant folding.
+pub fn optimize_program(fn_list: &mut Functions, options: &OptimizeOptions) -> Vec<Diagnostic> {
+    if options.inline {
+        inline_calls(fn_list, options);
+    }
+    let diagnostics = if options.fold {
+        fold_program(fn_list)
+    } else {
+        Vec::new()
+    };
+    for func in fn_list.iter_mut() {
+        if options.dead_code {
+            remove_dead_code(&mut func.block);
+        }
+        if options.unused_lets {
+            remove_unused_lets(&mut func.block);
+        }
+    }
+    if options.unused_functions {
+        remove_unused_functions(fn_list);
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod optimize_tests {
+    use super::*;
+    use crate::interpreter::*;
+    use crate::parser::*;
+
+    fn optimize(program: &str, options: &OptimizeOptions) -> Functions {
+        let mut fn_list = parse_program(program).unwrap().1;
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut fn_list.clone(), &mut expected_out);
+
+        optimize_program(&mut fn_list, options);
+        let mut out: Vec<u8> = Vec::new();
+        let res = eval_program_with_output(&mut fn_list.clone(), &mut out);
+        assert_eq!(res, expected);
+        assert_eq!(out, expected_out);
+        fn_list
+    }
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(Expr::Var(name.to_string()))
+    }
+
+    #[test]
+    fn optimize_inline() {
+        let program = "
+        fn fuck(off: i32) -> i32 {
+            let a: i32 = (off * 2);
+            return (a + 1);
+        }
+
+        fn main() -> i32 {
+            let off: i32 = 3;
+            let a: i32 = fuck((off + 1));
+            return fuck(a);
+        }
+        ";
+        let options = OptimizeOptions {
+            fold: false,
+            unused_lets: false,
+            ..OptimizeOptions::default()
+        };
+        let fn_list = optimize(program, &options);
+        // ´fuck´ is no longer called and is removed.
+        assert_eq!(fn_list.len(), 1);
+        let content = &fn_list[0].block.content;
+        assert_eq!(content.len(), 7);
+        let plus = Op::MathOp(MathToken::Plus);
+        assert_eq!(
+            content[1],
+            Expr::Let(
+                var("off_fuck_1"),
+                Type::Int32,
+                Box::new(Expr::BinOp(var("off"), plus, Box::new(Expr::Num(1))))
+            )
+        );
+        // Locals are renamed so that they do not hide the variables of main.
+        assert_eq!(
+            content[3],
+            Expr::Let(
+                var("a"),
+                Type::Int32,
+                Box::new(Expr::BinOp(var("a_fuck_1"), plus, Box::new(Expr::Num(1))))
+            )
+        );
+        assert_eq!(
+            content[6],
+            Expr::Return(Box::new(Expr::BinOp(
+                var("a_fuck_2"),
+                plus,
+                Box::new(Expr::Num(1))
+            )))
+        );
+        assert_eq!(fn_list[0].block.stmt_spans.len(), content.len());
+    }
+
+    #[test]
+    fn optimize_not_inlined() {
+        let program = "
+        fn fibo(i: i32) -> i32 {
+            if (i < 3) {
+                return 1;
+            };
+            return (fibo((i - 1)) + fibo((i - 2)));
+        }
+
+        fn twice(a: i32) -> i32 {
+            return (a * 2);
+        }
+
+        fn main() -> i32 {
+            return (twice(fibo(5)) + 1);
+        }
+        ";
+        // Recursive functions and calls inside expressions are kept.
+        let fn_list = optimize(program, &OptimizeOptions::default());
+        assert_eq!(fn_list.len(), 3);
+
+        let options = OptimizeOptions {
+            inline_size: 5,
+            ..OptimizeOptions::default()
+        };
+        let program = "
+        fn twice(a: i32) -> i32 {
+            return ((a + a) + (a + a));
+        }
+
+        fn main() -> i32 {
+            return twice(3);
+        }
+        ";
+        let fn_list = optimize(program, &options);
+        assert_eq!(fn_list.len(), 2);
+    }
+
+    #[test]
+    fn optimize_dead_code() {
+        let program = "
+        fn main() -> i32 {
+            let a: i32 = 1;
+            let b: i32 = 2;
+            let c: bool = (a < b);
+            let d: i32 = (a / 0);
+            let e: i32 = 7;
+            while c {
+                return a;
+                println(\"never\");
+            };
+            return 0;
+            println(\"never\");
+        }
+        ";
+        let options = OptimizeOptions {
+            fold: false,
+            ..OptimizeOptions::default()
+        };
+        let fn_list = optimize(program, &options);
+        let content = &fn_list[0].block.content;
+        // ´b´ is used by ´c´ and ´d´ can fail, so they are kept.
+        assert_eq!(content.len(), 6);
+        assert!(!content.iter().any(|stmt| mentions(stmt, "e")));
+        match &content[4] {
+            Expr::While(_, block) => assert_eq!(block.content.len(), 1),
+            stmt => panic!("{:?}", stmt),
+        }
+        assert_eq!(content[5], Expr::Return(Box::new(Expr::Num(0))));
+    }
+
+    #[test]
+    fn optimize_unused_functions() {
+        let program = "
+        fn unused() -> () {
+            println(\"unused\");
+        }
+
+        fn used() -> () {
+            println(\"used\");
+        }
+
+        fn main() -> () {
+            used();
+        }
+        ";
+        let options = OptimizeOptions {
+            inline: false,
+            ..OptimizeOptions::default()
+        };
+        let fn_list = optimize(program, &options);
+        let names: Vec<&str> = fn_list.iter().map(|func| func.name.as_str()).collect();
+        assert_eq!(names, vec!["used", "main"]);
+
+        // Libraries have no main and every function is kept.
+        let mut fn_list = parse_program("fn f() -> () { println(\"f\"); }").unwrap().1;
+        optimize_program(&mut fn_list, &OptimizeOptions::default());
+        assert_eq!(fn_list.len(), 1);
+    }
+}
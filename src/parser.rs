@@ -5,9 +5,9 @@ use crate::ast::*;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{alphanumeric1, digit1, multispace0},
-    combinator::map,
-    multi::{fold_many0, many0},
+    character::complete::{alphanumeric1, digit1, multispace0, none_of, one_of},
+    combinator::{map, map_res, opt, recognize, value, verify},
+    multi::{fold_many0, many0, many1},
     sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
@@ -21,24 +21,95 @@ fn parse_var(input: &str) -> IResult<&str, Expr> {
     )(input)
 }
 
+// Parses tuple types with at least two elements. E.g. '(i32, bool)'.
+fn parse_tuple_type(input: &str) -> IResult<&str, Type> {
+    let (substring, (first, rest)) = delimited(
+        tag("("),
+        tuple((parse_type, many1(preceded(tag(","), parse_type)))),
+        tag(")"),
+    )(input)?;
+
+    let mut types = vec![first];
+    types.extend(rest);
+    Ok((substring, Type::Tuple(types)))
+}
+
+// Parses vector types. Only scalar element types are allowed. E.g. 'Vec<u8>'.
+fn parse_vec_type(input: &str) -> IResult<&str, Type> {
+    let (substring, elem_type) = delimited(
+        terminated(tag("Vec"), multispace0),
+        delimited(tag("<"), parse_scalar_type, tag(">")),
+        multispace0,
+    )(input)?;
+
+    Ok((substring, Type::Vec(Box::new(elem_type))))
+}
+
 fn parse_type(input: &str) -> IResult<&str, Type> {
     delimited(
         multispace0,
         alt((
+            parse_tuple_type,
+            parse_vec_type,
+            parse_scalar_type,
+            map(tag("&str"), |_| Type::Str),
+            map(tag("()"), |_| Type::Void),
+        )),
+        multispace0,
+    )(input)
+}
+
+fn parse_scalar_type(input: &str) -> IResult<&str, Type> {
+    delimited(
+        multispace0,
+        alt((
+            map(tag("i8"), |_| Type::Int8),
+            map(tag("i16"), |_| Type::Int16),
             map(tag("i32"), |_| Type::Int32),
+            map(tag("i64"), |_| Type::Int64),
+            map(tag("u8"), |_| Type::UInt8),
+            map(tag("u16"), |_| Type::UInt16),
+            map(tag("u32"), |_| Type::UInt32),
+            map(tag("u64"), |_| Type::UInt64),
+            map(tag("usize"), |_| Type::USize),
+            map(tag("f64"), |_| Type::Float64),
+            map(tag("char"), |_| Type::Char),
             map(tag("bool"), |_| Type::Bool),
-            map(tag("()"), |_| Type::Void),
         )),
         multispace0,
     )(input)
 }
 
+// Parses the left-hand side of a declaration. Either a single variable
+// or a destructuring tuple pattern such as '(a, (b, c))'.
+fn parse_pattern(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(
+            delimited(
+                multispace0,
+                delimited(
+                    tag("("),
+                    tuple((parse_pattern, many1(preceded(tag(","), parse_pattern)))),
+                    tag(")"),
+                ),
+                multispace0,
+            ),
+            |(first, rest)| {
+                let mut patterns = vec![first];
+                patterns.extend(rest);
+                Expr::Tuple(patterns)
+            },
+        ),
+        parse_var,
+    ))(input)
+}
+
 // Parses declaration of a variable
 fn parse_declaration(input: &str) -> IResult<&str, Expr> {
     let (substring, (id, type_lit, expr)): (&str, (Expr, Type, Expr)) = tuple((
         preceded(
             multispace0,
-            preceded(tag("let"), preceded(multispace0, parse_var)),
+            preceded(tag("let"), preceded(multispace0, parse_pattern)),
         ),
         preceded(
             multispace0,
@@ -56,20 +127,101 @@ fn parse_declaration(input: &str) -> IResult<&str, Expr> {
     Ok((substring, Expr::Let(Box::new(id), type_lit, Box::new(expr))))
 }
 
-// Parses any i32. Handles multiple negative signs.
-fn parse_i32(input: &str) -> IResult<&str, Expr> {
+// Parses any integer literal. Handles multiple negative signs. The literal is
+// range-checked against its type by the type checker, so only literals that
+// do not fit in an i128 are rejected here.
+fn parse_int(input: &str) -> IResult<&str, Expr> {
     let (substring, sign) = fold_many0(
         delimited(multispace0, tag("-"), multispace0),
         1,
-        |mut sign: i32, _| {
+        |mut sign: i128, _| {
             sign *= -1;
             sign
         },
     )(input)?;
 
-    let (substring, digit) = delimited(multispace0, digit1, multispace0)(substring)?;
+    let (substring, num) = map_res(
+        delimited(multispace0, digit1, multispace0),
+        |digit: &str| digit.parse::<i128>(),
+    )(substring)?;
+
+    Ok((substring, Expr::Num(num * sign)))
+}
+
+// Parses any f64 with either a fractional part, an exponent or both.
+// E.g. '1.5', '2e10' and '-0.5e-3'. Handles multiple negative signs.
+fn parse_f64(input: &str) -> IResult<&str, Expr> {
+    let (substring, sign) = fold_many0(
+        delimited(multispace0, tag("-"), multispace0),
+        1.0,
+        |mut sign: f64, _| {
+            sign *= -1.0;
+            sign
+        },
+    )(input)?;
+
+    let exponent = |input| recognize(tuple((one_of("eE"), opt(one_of("+-")), digit1)))(input);
+    let (substring, float) = delimited(
+        multispace0,
+        recognize(tuple((
+            digit1,
+            alt((
+                recognize(tuple((tag("."), digit1, opt(exponent)))),
+                exponent,
+            )),
+        ))),
+        multispace0,
+    )(substring)?;
+
+    Ok((substring, Expr::Float(float.parse::<f64>().unwrap() * sign)))
+}
 
-    Ok((substring, Expr::Num(digit.parse::<i32>().unwrap() * sign)))
+// Parses the escape sequences '\n', '\t', '\\', '\0' and escaped quotes.
+fn parse_escape(input: &str) -> IResult<&str, char> {
+    preceded(
+        tag("\\"),
+        alt((
+            value('\n', tag("n")),
+            value('\t', tag("t")),
+            value('\\', tag("\\")),
+            value('\0', tag("0")),
+            value('"', tag("\"")),
+            value('\'', tag("'")),
+        )),
+    )(input)
+}
+
+// Parses string literals. E.g. '"hello {}\n"'.
+fn parse_str(input: &str) -> IResult<&str, Expr> {
+    let (substring, content) = delimited(
+        multispace0,
+        delimited(
+            tag("\""),
+            fold_many0(
+                alt((parse_escape, none_of("\\\""))),
+                String::new(),
+                |mut content: String, c| {
+                    content.push(c);
+                    content
+                },
+            ),
+            tag("\""),
+        ),
+        multispace0,
+    )(input)?;
+
+    Ok((substring, Expr::Str(content)))
+}
+
+// Parses char literals. E.g. 'a' or '\n'.
+fn parse_char(input: &str) -> IResult<&str, Expr> {
+    let (substring, c) = delimited(
+        multispace0,
+        delimited(tag("'"), alt((parse_escape, none_of("\\'"))), tag("'")),
+        multispace0,
+    )(input)?;
+
+    Ok((substring, Expr::Char(c)))
 }
 
 // Helper function to parse parentheses
@@ -81,6 +233,26 @@ fn parse_parens_expr(input: &str) -> IResult<&str, Expr> {
     )(input)
 }
 
+// Parses tuple expressions with at least two elements. E.g. '(1, a == b)'.
+fn parse_tuple(input: &str) -> IResult<&str, Expr> {
+    let (substring, (first, rest)) = delimited(
+        multispace0,
+        delimited(
+            tag("("),
+            tuple((
+                parse_right_expr,
+                many1(preceded(tag(","), parse_right_expr)),
+            )),
+            tag(")"),
+        ),
+        multispace0,
+    )(input)?;
+
+    let mut exprs = vec![first];
+    exprs.extend(rest);
+    Ok((substring, Expr::Tuple(exprs)))
+}
+
 fn parse_bool(input: &str) -> IResult<&str, Expr> {
     delimited(
         multispace0,
@@ -146,28 +318,85 @@ fn parse_any_op(input: &str) -> IResult<&str, Op> {
     alt((parse_bool_op, parse_math_op, parse_rel_op))(input)
 }
 
-// Parses arithmetic and logical binomial expressions.
-fn parse_bin_expr(input: &str) -> IResult<&str, Expr> {
-    alt((
-        map(
+// Parses 'Vec::new()'.
+fn parse_vec_new(input: &str) -> IResult<&str, Expr> {
+    value(
+        Expr::VecNew,
+        delimited(
+            multispace0,
             tuple((
-                alt((
-                    parse_bool,
-                    parse_i32,
-                    parse_parens_expr,
-                    parse_func_call,
-                    parse_var,
-                )),
-                parse_any_op,
-                parse_bin_expr,
+                tag("Vec"),
+                delimited(multispace0, tag("::"), multispace0),
+                tag("new"),
+                multispace0,
+                tag("("),
+                multispace0,
+                tag(")"),
             )),
-            |(left, op, right)| Expr::BinOp(Box::new(left), op, Box::new(right)),
+            multispace0,
         ),
+    )(input)
+}
+
+enum Postfix {
+    Index(usize),
+    Elem(Expr),
+    Method(String, Vec<Expr>),
+    Cast(Type),
+}
+
+// Parses a single operand of a binomial expression followed by any number
+// of tuple accesses, indexing, method calls and casts. E.g. 'a.0.1 as u8'
+// or 'v[i].len()'.
+fn parse_operand(input: &str) -> IResult<&str, Expr> {
+    let (substring, operand) = alt((
         parse_bool,
-        parse_i32,
+        parse_str,
+        parse_char,
+        parse_f64,
+        parse_int,
+        parse_tuple,
         parse_parens_expr,
+        parse_vec_new,
         parse_func_call,
         parse_var,
+    ))(input)?;
+
+    fold_many0(
+        alt((
+            map(
+                preceded(tag("."), tuple((alphanumeric1, parse_fn_args))),
+                |(name, args): (&str, Vec<Expr>)| Postfix::Method(name.to_string(), args),
+            ),
+            map(
+                terminated(delimited(tag("["), parse_right_expr, tag("]")), multispace0),
+                Postfix::Elem,
+            ),
+            // Indices too large for a usize are not parsed.
+            map_res(
+                terminated(preceded(tag("."), digit1), multispace0),
+                |i: &str| i.parse::<usize>().map(Postfix::Index),
+            ),
+            map(preceded(tag("as"), parse_type), Postfix::Cast),
+        )),
+        operand,
+        |expr, postfix| match postfix {
+            Postfix::Index(i) => Expr::TupleIndex(Box::new(expr), i),
+            Postfix::Elem(index) => Expr::Index(Box::new(expr), Box::new(index)),
+            Postfix::Method(name, args) => Expr::MethodCall(Box::new(expr), name, args),
+            Postfix::Cast(t) => Expr::Cast(Box::new(expr), t),
+        },
+    )(substring)
+}
+
+// Parses arithmetic and logical binomial expressions.
+fn parse_bin_expr(input: &str) -> IResult<&str, Expr> {
+    alt((
+        map(
+            tuple((parse_operand, parse_any_op, parse_bin_expr)),
+            |(left, op, right)| Expr::BinOp(Box::new(left), op, Box::new(right)),
+        ),
+        parse_operand,
     ))(input)
 }
 
@@ -194,16 +423,39 @@ fn parse_fn_params(input: &str) -> IResult<&str, Vec<Param>> {
     )(input)
 }
 
+// The parser only sees the rest of the input, so positions are recorded as
+// the length of the remaining input and resolved by parse_program.
+fn unresolved_span(input: &str) -> Span {
+    Span {
+        line: 0,
+        column: input.len() as u32,
+    }
+}
+
+// Parses a statement and records where it starts.
+fn parse_statement(input: &str) -> IResult<&str, (Span, Expr)> {
+    let (input, _) = multispace0(input)?;
+    let span = unresolved_span(input);
+    let (substring, stmt) = alt((
+        terminated(parse_keyword, terminated(tag(";"), multispace0)),
+        parse_return,
+    ))(input)?;
+
+    Ok((substring, (span, stmt)))
+}
+
 // Parses blocks of keyword statements.
-fn parse_block(input: &str) -> IResult<&str, Vec<Expr>> {
-    delimited(
-        tag("{"),
-        many0(alt((
-            terminated(parse_keyword, terminated(tag(";"), multispace0)),
-            parse_return,
-        ))),
-        tag("}"),
-    )(input)
+fn parse_block(input: &str) -> IResult<&str, Block> {
+    let span = unresolved_span(input);
+    let (substring, stmts) = delimited(tag("{"), many0(parse_statement), tag("}"))(input)?;
+
+    let (stmt_spans, content) = stmts.into_iter().unzip();
+    let block = Block {
+        content,
+        span: Some(span),
+        stmt_spans,
+    };
+    Ok((substring, block))
 }
 
 // Parses return-statements
@@ -221,10 +473,37 @@ fn parse_function(input: &str) -> IResult<&str, Function> {
         parse_block,
     ))(input)?;
 
-    let func = Function::new(id.into(), params, Block::new(block), return_type);
+    let func = Function::new(id.into(), params, block, return_type);
     Ok((substring, func))
 }
 
+// Parses the declaration of a C function without a body. The return type may
+// be left out for functions returning nothing. E.g. 'fn putchar(c: i32) -> i32;'.
+fn parse_extern_fn(input: &str) -> IResult<&str, Function> {
+    let (substring, (id, params, return_type)) = tuple((
+        delimited(multispace0, preceded(tag("fn"), parse_var), multispace0),
+        parse_fn_params,
+        terminated(
+            opt(preceded(tag("->"), parse_type)),
+            delimited(multispace0, tag(";"), multispace0),
+        ),
+    ))(input)?;
+
+    let func = Function::new_extern(id.into(), params, return_type.unwrap_or(Type::Void));
+    Ok((substring, func))
+}
+
+// Parses a block of C function declarations. E.g. 'extern "C" { fn abs(x: i32) -> i32; }'.
+fn parse_extern_block(input: &str) -> IResult<&str, Vec<Function>> {
+    preceded(
+        tuple((
+            terminated(tag("extern"), multispace0),
+            terminated(tag("\"C\""), multispace0),
+        )),
+        delimited(tag("{"), many0(parse_extern_fn), tag("}")),
+    )(input)
+}
+
 // Parses lonely if statements
 fn parse_if(input: &str) -> IResult<&str, Expr> {
     let (substring, (_, exp, block)) = tuple((
@@ -233,7 +512,7 @@ fn parse_if(input: &str) -> IResult<&str, Expr> {
         delimited(multispace0, parse_block, multispace0),
     ))(input)?;
 
-    Ok((substring, Expr::If(Box::new(exp), Block::new(block))))
+    Ok((substring, Expr::If(Box::new(exp), block)))
 }
 
 //fn parse_else(input: &str) -> IResult<&str,Expr> {}
@@ -245,13 +524,21 @@ fn parse_while(input: &str) -> IResult<&str, Expr> {
         parse_block,
     ))(input)?;
 
-    Ok((substring, Expr::While(Box::new(expr), Block::new(block))))
+    Ok((substring, Expr::While(Box::new(expr), block)))
 }
 
 // Parses variable assignments where the variable has already
 // been declared. E.g. 'a = 3;'.
+// Parses assignments to variables and vector elements. E.g. 'v[0] += 1'.
 fn parse_var_expr(input: &str) -> IResult<&str, Expr> {
-    let (substring, (var, op, expr)) = tuple((parse_var, parse_var_op, parse_right_expr))(input)?;
+    let (substring, (var, op, expr)) = tuple((
+        verify(parse_operand, |e: &Expr| match e {
+            Expr::Var(_) | Expr::Index(_, _) => true,
+            _ => false,
+        }),
+        parse_var_op,
+        parse_right_expr,
+    ))(input)?;
 
     Ok((substring, Expr::VarOp(Box::new(var), op, Box::new(expr))))
 }
@@ -266,12 +553,21 @@ fn parse_keyword(input: &str) -> IResult<&str, Expr> {
             parse_if,
             parse_while,
             parse_var_expr,
+            parse_method_call,
             parse_func_call,
         )),
         multispace0,
     )(input)
 }
 
+// Parses method calls used as statements. E.g. 'v.push(1)'.
+fn parse_method_call(input: &str) -> IResult<&str, Expr> {
+    verify(parse_operand, |e: &Expr| match e {
+        Expr::MethodCall(_, _, _) => true,
+        _ => false,
+    })(input)
+}
+
 // Parses right-hand expressions
 fn parse_right_expr(input: &str) -> IResult<&str, Expr> {
     delimited(
@@ -282,8 +578,10 @@ fn parse_right_expr(input: &str) -> IResult<&str, Expr> {
     )(input)
 }
 
+// Parses function calls. The built-in functions may also be called with
+// macro syntax, e.g. 'println!("{}", a)'.
 fn parse_func_call(input: &str) -> IResult<&str, Expr> {
-    let (substring, (fn_name, args)) = tuple((parse_var, parse_fn_args))(input)?;
+    let (substring, (fn_name, _, args)) = tuple((parse_var, opt(tag("!")), parse_fn_args))(input)?;
 
     Ok((
         substring,
@@ -316,7 +614,40 @@ fn parse_fn_args(input: &str) -> IResult<&str, Vec<Expr>> {
 // Main entry to parse a complete program
 
 pub fn parse_program(input: &str) -> IResult<&str, Functions> {
-    many0(delimited(multispace0, parse_function, multispace0))(input)
+    let (substring, items) = many0(delimited(
+        multispace0,
+        alt((map(parse_function, |func| vec![func]), parse_extern_block)),
+        multispace0,
+    ))(input)?;
+
+    let mut fn_list: Functions = items.into_iter().flatten().collect();
+    for func in fn_list.iter_mut() {
+        resolve_spans(&mut func.block, input);
+    }
+    Ok((substring, fn_list))
+}
+
+// Converts the positions recorded while parsing to lines and columns in
+// the program.
+fn resolve_spans(block: &mut Block, program: &str) {
+    let resolve = |span: Span| {
+        let before = &program[..program.len() - span.column as usize];
+        Span {
+            line: before.matches('\n').count() as u32 + 1,
+            column: before.chars().rev().take_while(|c| *c != '\n').count() as u32 + 1,
+        }
+    };
+
+    block.span = block.span.map(resolve);
+    for span in block.stmt_spans.iter_mut() {
+        *span = resolve(*span);
+    }
+    for expr in block.content.iter_mut() {
+        match expr {
+            Expr::If(_, block) | Expr::While(_, block) => resolve_spans(block, program),
+            _ => (),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -324,13 +655,60 @@ mod parse_tests {
     use super::*;
 
     #[test]
-    fn test_parse_i32() {
-        assert_eq!(parse_i32("3"), Ok(("", Expr::Num(3 as i32))));
-        assert_eq!(parse_i32("-3"), Ok(("", Expr::Num(-3 as i32))));
-        assert_eq!(parse_i32("500 + 50"), Ok(("+ 50", Expr::Num(500 as i32))));
+    fn test_parse_int() {
+        assert_eq!(parse_int("3"), Ok(("", Expr::Num(3))));
+        assert_eq!(parse_int("-3"), Ok(("", Expr::Num(-3))));
+        assert_eq!(parse_int("500 + 50"), Ok(("+ 50", Expr::Num(500))));
+        assert_eq!(
+            parse_int("- - 1000 --100 "),
+            Ok(("--100 ", Expr::Num(1000)))
+        );
+        assert_eq!(parse_int("3000000000"), Ok(("", Expr::Num(3000000000))));
+        assert_eq!(parse_int("-2147483648"), Ok(("", Expr::Num(-2147483648))));
+        assert!(parse_int("1000000000000000000000000000000000000000").is_err());
+    }
+
+    #[test]
+    fn test_parse_f64() {
+        assert_eq!(parse_f64("1.5"), Ok(("", Expr::Float(1.5))));
+        assert_eq!(parse_f64("2e10"), Ok(("", Expr::Float(2e10))));
+        assert_eq!(parse_f64("- 0.5e-3 * a"), Ok(("* a", Expr::Float(-0.5e-3))));
+        assert_eq!(parse_f64("3").is_err(), true);
+        assert_eq!(
+            parse_right_expr("1 + 2.0"),
+            Ok((
+                "",
+                Expr::BinOp(
+                    Box::new(Expr::Num(1)),
+                    Op::MathOp(MathToken::Plus),
+                    Box::new(Expr::Float(2.0))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_str() {
+        assert_eq!(
+            parse_str(r#""a = {}\n" , a"#),
+            Ok((", a", Expr::Str("a = {}\n".to_string())))
+        );
+        assert_eq!(parse_str("\"\""), Ok(("", Expr::Str(String::new()))));
+        assert_eq!(parse_char(r"'\''"), Ok(("", Expr::Char('\''))));
+        assert_eq!(parse_char("'ö'"), Ok(("", Expr::Char('ö'))));
         assert_eq!(
-            parse_i32("- - 1000 --100 "),
-            Ok(("--100 ", Expr::Num(1000 as i32)))
+            parse_keyword(r#"println!("{} {}", 'a', "b");"#),
+            Ok((
+                ";",
+                Expr::FuncCall(FunctionCall::new(
+                    "println".to_string(),
+                    Args::new(vec![
+                        Expr::Str("{} {}".to_string()),
+                        Expr::Char('a'),
+                        Expr::Str("b".to_string())
+                    ])
+                ))
+            ))
         );
     }
 
@@ -346,7 +724,111 @@ mod parse_tests {
     #[test]
     fn test_parse_type() {
         assert_eq!(parse_type("i32"), Ok(("", Type::Int32)));
-        assert_eq!(parse_type(" bool : true;"), Ok((": true;", Type::Bool)))
+        assert_eq!(parse_type(" bool : true;"), Ok((": true;", Type::Bool)));
+        assert_eq!(
+            parse_type("(i32, (bool, i32))"),
+            Ok((
+                "",
+                Type::Tuple(vec![
+                    Type::Int32,
+                    Type::Tuple(vec![Type::Bool, Type::Int32])
+                ])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_cast() {
+        assert_eq!(parse_type("u8"), Ok(("", Type::UInt8)));
+        assert_eq!(parse_type("usize"), Ok(("", Type::USize)));
+        assert_eq!(
+            parse_right_expr("a as u16 + 1"),
+            Ok((
+                "",
+                Expr::BinOp(
+                    Box::new(Expr::Cast(
+                        Box::new(Expr::Var("a".to_string())),
+                        Type::UInt16
+                    )),
+                    Op::MathOp(MathToken::Plus),
+                    Box::new(Expr::Num(1))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_tuple() {
+        assert_eq!(
+            parse_right_expr("(1, true).1"),
+            Ok((
+                "",
+                Expr::TupleIndex(
+                    Box::new(Expr::Tuple(vec![Expr::Num(1), Expr::Bool(true)])),
+                    1
+                )
+            ))
+        );
+        assert_eq!(
+            parse_right_expr("t.99999999999999999999"),
+            Ok((".99999999999999999999", Expr::Var("t".to_string())))
+        );
+        assert_eq!(
+            parse_declaration("let (a, b): (i32, bool) = f();"),
+            Ok((
+                ";",
+                Expr::Let(
+                    Box::new(Expr::Tuple(vec![
+                        Expr::Var("a".to_string()),
+                        Expr::Var("b".to_string())
+                    ])),
+                    Type::Tuple(vec![Type::Int32, Type::Bool]),
+                    Box::new(Expr::FuncCall(FunctionCall::new(
+                        "f".to_string(),
+                        Args::new(vec![])
+                    )))
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_vec() {
+        let var = |s: &str| Box::new(Expr::Var(s.to_string()));
+        assert_eq!(
+            parse_declaration("let v: Vec<u8> = Vec::new();"),
+            Ok((
+                ";",
+                Expr::Let(
+                    var("v"),
+                    Type::Vec(Box::new(Type::UInt8)),
+                    Box::new(Expr::VecNew)
+                )
+            ))
+        );
+        assert_eq!(
+            parse_keyword("v.push(a[0])"),
+            Ok((
+                "",
+                Expr::MethodCall(
+                    var("v"),
+                    "push".to_string(),
+                    vec![Expr::Index(var("a"), Box::new(Expr::Num(0)))]
+                )
+            ))
+        );
+        assert_eq!(
+            parse_keyword("v[i] += v.len()"),
+            Ok((
+                "",
+                Expr::VarOp(
+                    Box::new(Expr::Index(var("v"), var("i"))),
+                    Op::VarOp(VarToken::PlusEq),
+                    Box::new(Expr::MethodCall(var("v"), "len".to_string(), vec![]))
+                )
+            ))
+        );
+        assert_eq!(parse_type("Vec<(i32, i32)>").is_err(), true);
     }
 
     #[test]
@@ -430,4 +912,49 @@ mod parse_tests {
         assert_eq!(parse_program(program_2).is_ok(), true);
         assert_eq!(parse_program(program_3).is_ok(), true);
     }
+
+    #[test]
+    fn test_parse_spans() {
+        let program = "fn main() -> i32 {
+    let a: i32 = 1;
+    if a == 1 {
+        a = 2;
+    };
+    return a;
+}";
+        let fn_list = parse_program(program).unwrap().1;
+        let block = &fn_list[0].block;
+        let span = |line, column| Span { line, column };
+        assert_eq!(block.span, Some(span(1, 18)));
+        assert_eq!(block.stmt_spans, vec![span(2, 5), span(3, 5), span(6, 5)]);
+        match &block.content[1] {
+            Expr::If(_, if_block) => assert_eq!(if_block.stmt_spans, vec![span(4, 9)]),
+            _ => panic!("Not an if statement"),
+        }
+    }
+
+    #[test]
+    fn test_parse_extern_block() {
+        let program = "extern \"C\" {
+    fn putchar(c: i32) -> i32;
+    fn srand(seed: u32);
+}
+
+fn main() -> () {
+    putchar(72);
+}";
+        let fn_list = parse_program(program).unwrap().1;
+        assert_eq!(fn_list.len(), 3);
+        assert_eq!(
+            fn_list[0],
+            Function::new_extern(
+                "putchar".to_string(),
+                vec![Param::new("c".to_string(), Type::Int32)],
+                Type::Int32
+            )
+        );
+        assert_eq!(fn_list[1].return_type, Type::Void);
+        assert!(fn_list[1].is_extern);
+        assert!(!fn_list[2].is_extern);
+    }
 }
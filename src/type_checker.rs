@@ -7,26 +7,227 @@ pub type TypeRes<T> = Result<T, TypeErr>;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TypeErr(pub String);
 
-/// Type checks any expression.
+/// Type checks any expression. Returns the expression annotated with its type.
 fn tc_expr(
     expr: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
+) -> TypeRes<TypedExpr> {
     match expr.clone() {
-        Expr::Num(_) => Ok(Type::Int32),
-        Expr::Bool(_) => Ok(Type::Bool),
-        Expr::Var(s) => fn_context.get_last_context()?.get_val(&s),
+        // A literal with no expected type is an i32.
+        Expr::Num(_) => tc_expect(expr, &Type::Int32, fn_list, fn_context),
+        Expr::Float(f) => Ok(TypedExpr::new(TypedExprKind::Float(f), Type::Float64)),
+        Expr::Bool(b) => Ok(TypedExpr::new(TypedExprKind::Bool(b), Type::Bool)),
+        Expr::Char(c) => Ok(TypedExpr::new(TypedExprKind::Char(c), Type::Char)),
+        Expr::Str(s) => Ok(TypedExpr::new(TypedExprKind::Str(s), Type::Str)),
+        Expr::Var(s) => {
+            let var_type = fn_context.get_last_context()?.get_val(&s)?;
+            Ok(TypedExpr::new(TypedExprKind::Var(s), var_type))
+        }
+        Expr::Tuple(exprs) => tc_tuple(exprs, fn_list, fn_context),
+        Expr::TupleIndex(expr, index) => tc_tuple_index(*expr, index, fn_list, fn_context),
+        Expr::Cast(expr, target) => tc_cast(*expr, target, fn_list, fn_context),
+        Expr::VecNew => Err(TypeErr(
+            "type annotations needed for ´Vec::new()´".to_string(),
+        )),
+        Expr::Index(expr, index) => tc_index(*expr, *index, fn_list, fn_context),
+        Expr::MethodCall(expr, name, args) => {
+            tc_method_call(*expr, name, args, fn_list, fn_context)
+        }
         Expr::BinOp(l, op, r) => tc_bin_expr(*l, op, *r, fn_list, fn_context),
         Expr::VarOp(var, op, val) => tc_var_op(*var, op, *val, fn_list, fn_context),
         Expr::Let(var, var_type, expr) => tc_let_expr(*var, var_type, *expr, fn_list, fn_context),
         Expr::If(_, _) | Expr::While(_, _) => tc_cond_branch(expr, fn_list, fn_context),
         Expr::Return(expr) => tc_return(*expr, fn_list, fn_context),
+        Expr::FuncCall(fn_call) if fn_call.is_builtin() => tc_print(fn_call, fn_list, fn_context),
         Expr::FuncCall(fn_call) => tc_fn_call(fn_call, fn_list, fn_context),
         _ => Err(TypeErr("not yet implemented".to_string())),
     }
 }
 
+/// Type checks an expression against an expected type. Integer literals have no
+/// fixed type of their own and take on the expected integer type if the value fits.
+fn tc_expect(
+    expr: Expr,
+    expected: &Type,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    match (expr, expected) {
+        (Expr::Num(num), _) if expected.is_int() => {
+            let (min, max) = expected.int_range().unwrap();
+            if num < min || num > max {
+                Err(TypeErr(format!(
+                    "literal ´{}´ out of range for type {{{}}}",
+                    num,
+                    String::from(expected.clone())
+                )))
+            } else {
+                Ok(TypedExpr::new(TypedExprKind::Num(num), expected.clone()))
+            }
+        }
+        (Expr::BinOp(l, op @ Op::MathOp(_), r), _)
+            if expected.is_int() && is_int_literal(&l) && is_int_literal(&r) =>
+        {
+            let l_typed = tc_expect(*l, expected, fn_list, fn_context)?;
+            let r_typed = tc_expect(*r, expected, fn_list, fn_context)?;
+            Ok(TypedExpr::new(
+                TypedExprKind::BinOp(Box::new(l_typed), op, Box::new(r_typed)),
+                expected.clone(),
+            ))
+        }
+        (Expr::VecNew, Type::Vec(_)) => Ok(TypedExpr::new(TypedExprKind::VecNew, expected.clone())),
+        (Expr::Tuple(exprs), Type::Tuple(types)) if exprs.len() == types.len() => {
+            let mut elems: Vec<TypedExpr> = Vec::new();
+            for (expr, elem_type) in exprs.into_iter().zip(types.iter()) {
+                elems.push(tc_expect(expr, elem_type, fn_list, fn_context)?);
+            }
+            let elem_types = elems.iter().map(|e| e.expr_type.clone()).collect();
+            Ok(TypedExpr::new(
+                TypedExprKind::Tuple(elems),
+                Type::Tuple(elem_types),
+            ))
+        }
+        (expr, _) => tc_expr(expr, fn_list, fn_context),
+    }
+}
+
+/// Returns true for integer literals and arithmetic on only integer literals.
+pub(crate) fn is_int_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Num(_) => true,
+        Expr::BinOp(l, Op::MathOp(_), r) => is_int_literal(l) && is_int_literal(r),
+        _ => false,
+    }
+}
+
+/// Type checks casts such as ´a as u8´. Integers and floats can be cast to
+/// each other, booleans and chars can be cast to any integer type and only
+/// u8 can be cast to char.
+fn tc_cast(
+    expr: Expr,
+    target: Type,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let typed = tc_expr(expr.clone(), fn_list, fn_context)?;
+    let expr_type = typed.expr_type.clone();
+
+    let is_numeric = |t: &Type| t.is_int() || *t == Type::Float64;
+    if (is_numeric(&target) && is_numeric(&expr_type))
+        || (target.is_int() && (expr_type == Type::Bool || expr_type == Type::Char))
+        || (target == Type::Char && (expr_type == Type::UInt8 || expr_type == Type::Char))
+    {
+        Ok(TypedExpr::new(TypedExprKind::Cast(Box::new(typed)), target))
+    } else {
+        Err(TypeErr(format!(
+            "non-primitive cast ´{}´ -> {{{}}} as {{{}}}",
+            String::from(expr),
+            String::from(expr_type),
+            String::from(target)
+        )))
+    }
+}
+
+/// Type checks indexing such as ´v[i]´. Vectors can only be indexed by usize.
+fn tc_index(
+    expr: Expr,
+    index: Expr,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let typed = tc_expr(expr.clone(), fn_list, fn_context)?;
+    let elem_type = match &typed.expr_type {
+        Type::Vec(elem_type) => *elem_type.clone(),
+        expr_type => {
+            return Err(TypeErr(format!(
+                "cannot index into a value of type {{{}}}",
+                String::from(expr_type.clone())
+            )))
+        }
+    };
+
+    let typed_index = tc_expect(index, &Type::USize, fn_list, fn_context)?;
+    if typed_index.expr_type == Type::USize {
+        Ok(TypedExpr::new(
+            TypedExprKind::Index(Box::new(typed), Box::new(typed_index)),
+            elem_type,
+        ))
+    } else {
+        Err(TypeErr(format!(
+            "´{}´ cannot be indexed by {{{}}}",
+            String::from(expr),
+            String::from(typed_index.expr_type)
+        )))
+    }
+}
+
+/// Type checks the vector methods ´push´, ´pop´ and ´len´. Vectors can only
+/// be modified through variables.
+fn tc_method_call(
+    expr: Expr,
+    name: String,
+    args: Vec<Expr>,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let typed = tc_expr(expr.clone(), fn_list, fn_context)?;
+    let expr_type = typed.expr_type.clone();
+    let elem_type = match &expr_type {
+        Type::Vec(elem_type) => *elem_type.clone(),
+        _ => {
+            return Err(TypeErr(format!(
+                "no method named ´{}´ found for type {{{}}}",
+                name,
+                String::from(expr_type)
+            )))
+        }
+    };
+
+    let is_var = match expr {
+        Expr::Var(_) => true,
+        _ => false,
+    };
+    let method_call = |args: Vec<TypedExpr>, method_type: Type| {
+        Ok(TypedExpr::new(
+            TypedExprKind::MethodCall(Box::new(typed.clone()), name.clone(), args),
+            method_type,
+        ))
+    };
+
+    let mut args = args.into_iter();
+    match (name.as_str(), args.next(), args.next()) {
+        ("len", None, None) => method_call(vec![], Type::USize),
+        ("pop", None, None) if is_var => method_call(vec![], elem_type),
+        ("push", Some(arg), None) if is_var => {
+            let typed_arg = tc_expect(arg.clone(), &elem_type, fn_list, fn_context)?;
+            if typed_arg.expr_type == elem_type {
+                method_call(vec![typed_arg], Type::Void)
+            } else {
+                Err(TypeErr(format!(
+                    "cannot push ´{}´ of type {{{}}} to a vector of {{{}}}",
+                    String::from(arg),
+                    String::from(typed_arg.expr_type),
+                    String::from(elem_type)
+                )))
+            }
+        }
+        ("pop", _, _) | ("push", _, _) if !is_var => Err(TypeErr(format!(
+            "cannot borrow ´{}´ as mutable, it is not a variable",
+            String::from(expr)
+        ))),
+        ("len", _, _) | ("pop", _, _) | ("push", _, _) => Err(TypeErr(format!(
+            "wrong number of arguments to method ´{}´",
+            name
+        ))),
+        _ => Err(TypeErr(format!(
+            "no method named ´{}´ found for type {{{}}}",
+            name,
+            String::from(expr_type)
+        ))),
+    }
+}
+
 /// Returns a formatted TypeErr for when there is no implementation for operation between types.
 fn err_no_impl(first: Type, op: Op, second: Type) -> TypeRes<Type> {
     Err(TypeErr(format!(
@@ -35,22 +236,97 @@ fn err_no_impl(first: Type, op: Op, second: Type) -> TypeRes<Type> {
     )))
 }
 
-/// Type check expressions between two i32 values. Checks if the operation is valid,
+/// Type checks tuple expressions. The type is the tuple of the element types.
+fn tc_tuple(
+    exprs: Vec<Expr>,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let mut elems: Vec<TypedExpr> = Vec::new();
+    for expr in exprs {
+        elems.push(tc_expr(expr, fn_list, fn_context)?);
+    }
+
+    let types = elems.iter().map(|e| e.expr_type.clone()).collect();
+    Ok(TypedExpr::new(
+        TypedExprKind::Tuple(elems),
+        Type::Tuple(types),
+    ))
+}
+
+/// Type checks tuple accesses such as ´a.0´.
+fn tc_tuple_index(
+    expr: Expr,
+    index: usize,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let typed = tc_expr(expr.clone(), fn_list, fn_context)?;
+    match typed.expr_type.clone() {
+        Type::Tuple(types) => match types.get(index) {
+            Some(elem_type) => Ok(TypedExpr::new(
+                TypedExprKind::TupleIndex(Box::new(typed), index),
+                elem_type.clone(),
+            )),
+            None => Err(TypeErr(format!(
+                "no field ´{}´ on type {{{}}}",
+                index,
+                String::from(Type::Tuple(types))
+            ))),
+        },
+        other => Err(TypeErr(format!(
+            "no field ´{}´ on ´{}´ of type {{{}}}",
+            index,
+            String::from(expr),
+            String::from(other)
+        ))),
+    }
+}
+/// Type check expressions between two integer values. Both values must be of the
+/// same integer type, there is no implicit conversion between widths. Checks if the
+/// operation is valid, returns the type of the operation if successful, otherwise
+/// returns an error with a message.
+fn tc_int_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
+    if first != second || !first.is_int() {
+        err_no_impl(first, op, second)
+    } else {
+        match op {
+            Op::MathOp(MathToken::Division) => Ok(first),
+            Op::MathOp(MathToken::Multiply) => Ok(first),
+            Op::MathOp(MathToken::Plus) => Ok(first),
+            Op::MathOp(MathToken::Minus) => Ok(first),
+            Op::RelOp(RelToken::Equal) => Ok(Type::Bool),
+            Op::RelOp(RelToken::Ge) => Ok(Type::Bool),
+            Op::RelOp(RelToken::Le) => Ok(Type::Bool),
+            Op::RelOp(RelToken::Neq) => Ok(Type::Bool),
+            _ => err_no_impl(first, op, second),
+        }
+    }
+}
+
+/// Type check expressions between two f64 values. Checks if the operation is valid,
 /// returns the type of the operation if successful, otherwise returns an error with
 /// a message.
-fn tc_i32_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
-    if (first, second) != (Type::Int32, Type::Int32) {
+fn tc_f64_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
+    if (&first, &second) != (&Type::Float64, &Type::Float64) {
+        err_no_impl(first, op, second)
+    } else {
+        match op {
+            Op::MathOp(_) => Ok(Type::Float64),
+            Op::RelOp(_) => Ok(Type::Bool),
+            _ => err_no_impl(first, op, second),
+        }
+    }
+}
+
+/// Type check comparisons between two char values. Returns the type of the
+/// operation if successful, otherwise returns an error with a message.
+fn tc_char_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
+    if (&first, &second) != (&Type::Char, &Type::Char) {
         err_no_impl(first, op, second)
     } else {
         match op {
-            Op::MathOp(MathToken::Division) => Ok(Type::Int32),
-            Op::MathOp(MathToken::Multiply) => Ok(Type::Int32),
-            Op::MathOp(MathToken::Plus) => Ok(Type::Int32),
-            Op::MathOp(MathToken::Minus) => Ok(Type::Int32),
-            Op::RelOp(RelToken::Equal) => Ok(Type::Int32),
-            Op::RelOp(RelToken::Ge) => Ok(Type::Int32),
-            Op::RelOp(RelToken::Le) => Ok(Type::Int32),
-            Op::RelOp(RelToken::Neq) => Ok(Type::Int32),
+            Op::RelOp(_) => Ok(Type::Bool),
             _ => err_no_impl(first, op, second),
         }
     }
@@ -60,7 +336,7 @@ fn tc_i32_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
 /// returns the type of the operation if successful, otherwise returns an error with
 /// a message.
 fn tc_bool_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
-    if (first, second) != (Type::Bool, Type::Bool) {
+    if (&first, &second) != (&Type::Bool, &Type::Bool) {
         err_no_impl(first, op, second)
     } else {
         match op {
@@ -76,7 +352,7 @@ fn tc_bool_expr(first: Type, op: Op, second: Type) -> TypeRes<Type> {
 }
 
 /// Type check expressions between two values. Checks if the operation is valid,
-/// returns the type of the operation if successful, otherwise returns an error with
+/// returns the typed operation if successful, otherwise returns an error with
 /// a message.
 fn tc_bin_expr(
     l: Expr,
@@ -84,24 +360,42 @@ fn tc_bin_expr(
     r: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
-    let l_type = tc_expr(l.clone(), fn_list, fn_context)?;
-    let r_type = tc_expr(r.clone(), fn_list, fn_context)?;
+) -> TypeRes<TypedExpr> {
+    // A literal on one side takes on the type of the other side.
+    let (l_typed, r_typed) = match (&l, &r) {
+        (Expr::Num(_), Expr::Num(_)) => (
+            tc_expr(l, fn_list, fn_context)?,
+            tc_expr(r, fn_list, fn_context)?,
+        ),
+        (Expr::Num(_), _) => {
+            let r_typed = tc_expr(r, fn_list, fn_context)?;
+            (
+                tc_expect(l, &r_typed.expr_type, fn_list, fn_context)?,
+                r_typed,
+            )
+        }
+        _ => {
+            let l_typed = tc_expr(l, fn_list, fn_context)?;
+            let r_typed = tc_expect(r, &l_typed.expr_type, fn_list, fn_context)?;
+            (l_typed, r_typed)
+        }
+    };
 
-    let res = match (l_type, r_type) {
+    let (l_type, r_type) = (l_typed.expr_type.clone(), r_typed.expr_type.clone());
+    let op_type = match (&l_type, &r_type) {
         (Type::Bool, Type::Bool) => tc_bool_expr(l_type, op, r_type),
-        (Type::Int32, Type::Int32) => tc_i32_expr(l_type, op, r_type),
+        (Type::Float64, _) => tc_f64_expr(l_type, op, r_type),
+        (Type::Char, _) => tc_char_expr(l_type, op, r_type),
+        _ if l_type.is_int() => tc_int_expr(l_type, op, r_type),
         _ => err_no_impl(l_type, op, r_type),
-    };
+    }?;
 
-    match res {
-        Ok(op_type) => Ok(op_type),
-        Err(e) => Err(TypeErr(format!(
-            "{}",
-            e.0
-        ))),
-    }
+    Ok(TypedExpr::new(
+        TypedExprKind::BinOp(Box::new(l_typed), op, Box::new(r_typed)),
+        op_type,
+    ))
 }
+
 /// Type checks variable operations such as ´<var> = <value>´.
 fn tc_var_op(
     var: Expr,
@@ -109,16 +403,16 @@ fn tc_var_op(
     val: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
-    let var_type = tc_expr(var.clone(), fn_list, fn_context)?;
-    let val_type = tc_expr(val.clone(), fn_list, fn_context)?;
+) -> TypeRes<TypedExpr> {
+    let typed_var = tc_expr(var.clone(), fn_list, fn_context)?;
+    let typed_val = tc_expect(val.clone(), &typed_var.expr_type, fn_list, fn_context)?;
+    let (var_type, val_type) = (typed_var.expr_type.clone(), typed_val.expr_type.clone());
 
     if var_type == val_type {
-        match op {
-            Op::RelOp(_) => return Ok(Type::Bool),
-            _ => (),
-        };
-        Ok(var_type)
+        Ok(TypedExpr::new(
+            TypedExprKind::VarOp(Box::new(typed_var), op, Box::new(typed_val)),
+            Type::Void,
+        ))
     } else {
         Err(TypeErr(format!(
             "when changing the variable to ´{} {} {}´ -> expected type {{{}}}, found type {{{}}}",
@@ -138,11 +432,9 @@ fn tc_let_expr(
     expr: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
-    let expr_type: Type; // = tc_expr(expr.clone(), fn_list, fn_context)?;
-
-    match tc_expr(expr.clone(), fn_list, fn_context) {
-        Ok(expr_type_return) => expr_type = expr_type_return,
+) -> TypeRes<TypedExpr> {
+    let typed_expr = match tc_expect(expr.clone(), &var_type, fn_list, fn_context) {
+        Ok(typed_expr) => typed_expr,
         Err(e) => {
             return Err(TypeErr(format!(
                 "when assigning variable ´{}´ -> {}",
@@ -150,35 +442,66 @@ fn tc_let_expr(
                 e.0
             )))
         }
-    }
+    };
 
-    if var_type == expr_type {
-        fn_context
-            .get_last_context()?
-            .insert_to_current_scope(&String::from(var), &var_type);
-        Ok(var_type)
+    if var_type == typed_expr.expr_type {
+        let typed_var = tc_bind_pattern(var, &var_type, fn_context.get_last_context()?)?;
+        Ok(TypedExpr::new(
+            TypedExprKind::Let(Box::new(typed_var), Box::new(typed_expr)),
+            Type::Void,
+        ))
     } else {
         Err(TypeErr(format!(
             "when assigning variable ´{}´ -> expected type {{{}}}, found type {{{}}}",
             String::from(var),
             String::from(var_type),
-            String::from(expr_type)
+            String::from(typed_expr.expr_type)
         )))
     }
 }
 
+/// Binds every variable in a ´let´ pattern to its type in the current scope.
+/// Tuple patterns are destructured element-wise.
+fn tc_bind_pattern(
+    pattern: Expr,
+    var_type: &Type,
+    context: &mut Context<Type>,
+) -> TypeRes<TypedExpr> {
+    match (pattern.clone(), var_type) {
+        (Expr::Var(name), _) => {
+            context.insert_to_current_scope(&name, var_type);
+            Ok(TypedExpr::new(TypedExprKind::Var(name), var_type.clone()))
+        }
+        (Expr::Tuple(patterns), Type::Tuple(types)) if patterns.len() == types.len() => {
+            let mut typed_patterns: Vec<TypedExpr> = Vec::new();
+            for (pattern, elem_type) in patterns.into_iter().zip(types.iter()) {
+                typed_patterns.push(tc_bind_pattern(pattern, elem_type, context)?);
+            }
+            Ok(TypedExpr::new(
+                TypedExprKind::Tuple(typed_patterns),
+                var_type.clone(),
+            ))
+        }
+        _ => Err(TypeErr(format!(
+            "pattern ´{}´ does not match type {{{}}}",
+            String::from(pattern),
+            String::from(var_type.clone())
+        ))),
+    }
+}
+
 /// Type checks conditional branches e.g. ´if´ and ´while´ statements.
 fn tc_cond_branch(
     expr: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
-    let cond_type: Type;
+) -> TypeRes<TypedExpr> {
+    let cond: TypedExpr;
     let block: Block;
 
     match expr.clone() {
-        Expr::If(cond, cond_block) | Expr::While(cond, cond_block) => {
-            cond_type = tc_expr(*cond, fn_list, fn_context)?;
+        Expr::If(cond_expr, cond_block) | Expr::While(cond_expr, cond_block) => {
+            cond = tc_expr(*cond_expr, fn_list, fn_context)?;
             block = cond_block;
         }
         _ => {
@@ -189,17 +512,22 @@ fn tc_cond_branch(
         }
     }
 
-    if cond_type == Type::Bool {
-        tc_block(block, fn_list, fn_context)
+    if cond.expr_type == Type::Bool {
+        let typed_block = tc_block(block, fn_list, fn_context)?;
+        let kind = match expr {
+            Expr::If(_, _) => TypedExprKind::If(Box::new(cond), typed_block),
+            _ => TypedExprKind::While(Box::new(cond), typed_block),
+        };
+        Ok(TypedExpr::new(kind, Type::Void))
     } else {
         match expr {
             Expr::If(_, _) => Err(TypeErr(format!(
                 "in ´if´ statement -> expected {{Bool}} found type {{{}}}",
-                String::from(cond_type)
+                String::from(cond.expr_type)
             ))),
             Expr::While(_, _) => Err(TypeErr(format!(
                 "in ´while´ statement -> expected {{Bool}} found type {{{}}}",
-                String::from(cond_type)
+                String::from(cond.expr_type)
             ))),
             _ => unreachable!(),
         }
@@ -210,30 +538,69 @@ fn tc_block(
     block: Block,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
+) -> TypeRes<TypedBlock> {
     fn_context.get_last_context()?.new_scope();
 
-    let mut res: TypeRes<Type> = Err(TypeErr("Error: No expressions found!".to_string()));
-    for expr in block.content.iter() {
-        res = tc_expr(expr.clone(), fn_list, fn_context);
-        match res {
-            Ok(_) => continue,
-            Err(e) => {
-                return Err(e);
-            }
-        }
+    let mut typed_block: TypedBlock = Vec::new();
+    for (i, expr) in block.content.iter().enumerate() {
+        let mut typed_expr = tc_expr(expr.clone(), fn_list, fn_context)?;
+        typed_expr.span = block.stmt_spans.get(i).cloned();
+        typed_block.push(typed_expr);
     }
 
     fn_context.get_last_context()?.drop_current_scope();
 
-    res
+    Ok(typed_block)
+}
+
+/// Returns true for the types that can be passed to and returned from C
+/// functions. Strings are passed as a pointer to their NUL-terminated bytes.
+fn is_c_type(t: &Type) -> bool {
+    match t {
+        Type::Float64 | Type::Bool | Type::Char | Type::Str => true,
+        t => t.is_int(),
+    }
+}
+
+/// Checks the signature of a function declared in an ´extern "C"´ block.
+fn tc_extern_function(func: &Function) -> TypeRes<TypedFunction> {
+    for param in func.params.iter() {
+        if !is_c_type(&param.param_type) {
+            return Err(TypeErr(format!(
+                "in extern function ´{}´-> parameter ´{}´ of type ´{}´ cannot be passed to C",
+                func.name,
+                param.name,
+                String::from(param.param_type.clone())
+            )));
+        }
+    }
+    if func.return_type != Type::Void && !is_c_type(&func.return_type) {
+        return Err(TypeErr(format!(
+            "in extern function ´{}´-> ´{}´ cannot be returned from C",
+            func.name,
+            String::from(func.return_type.clone())
+        )));
+    }
+
+    Ok(TypedFunction {
+        name: func.name.clone(),
+        params: func.params.clone(),
+        block: Vec::new(),
+        return_type: func.return_type.clone(),
+        span: None,
+        is_extern: true,
+    })
 }
 
 fn tc_function(
     func: &Function,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
+) -> TypeRes<TypedFunction> {
+    if func.is_extern {
+        return tc_extern_function(func);
+    }
+
     let context = fn_context.new_context()?;
     context.new_scope();
 
@@ -251,7 +618,14 @@ fn tc_function(
     fn_context.drop_current_context();
 
     match res {
-        Ok(res_type) => Ok(res_type),
+        Ok(block) => Ok(TypedFunction {
+            name: func.name.clone(),
+            params: func.params.clone(),
+            block,
+            return_type: func.return_type.clone(),
+            span: func.block.span,
+            is_extern: false,
+        }),
         Err(e) => Err(TypeErr(format!("in function ´{}´-> {}", func.name, e.0))),
     }
 }
@@ -260,17 +634,20 @@ fn tc_return(
     expr: Expr,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
+) -> TypeRes<TypedExpr> {
     let ret_type = fn_context.get_last_context()?.get_val(&"return_type")?;
-    let expr_type = tc_expr(expr, fn_list, fn_context)?;
+    let typed = tc_expect(expr, &ret_type, fn_list, fn_context)?;
 
-    if expr_type == ret_type {
-        Ok(expr_type)
+    if typed.expr_type == ret_type {
+        Ok(TypedExpr::new(
+            TypedExprKind::Return(Box::new(typed)),
+            Type::Void,
+        ))
     } else {
         Err(TypeErr(format!(
             "expected return type {{{}}}, found type {{{}}}",
             String::from(ret_type),
-            String::from(expr_type)
+            String::from(typed.expr_type)
         )))
     }
 }
@@ -279,53 +656,113 @@ fn tc_fn_call(
     fn_call: FunctionCall,
     fn_list: &mut Functions,
     fn_context: &mut ContextStack<Type>,
-) -> TypeRes<Type> {
+) -> TypeRes<TypedExpr> {
     let fn_res: TypeRes<Function> = fn_list.get_fn(fn_call.name);
     let fn_content = fn_res?;
 
     let params = fn_content.params;
-    let fn_type = fn_content.return_type;
-    let args = fn_call.args;
-    let mut res: TypeRes<Type> = Err(TypeErr("Error: no parameters in function".to_string()));
+    let args = fn_call.args.content;
+    if args.len() != params.len() {
+        return Err(TypeErr(format!(
+            "function ´{}´ takes {} argument(s) but {} were supplied",
+            fn_content.name,
+            params.len(),
+            args.len()
+        )));
+    }
 
     // Check wheter params and args are same type
-    for i in 0..params.len() {
-        let arg_type = tc_expr(args.content[i].clone(), fn_list, fn_context)?;
-        let param_type = params[i].param_type;
-        if arg_type != param_type {
+    let mut typed_args: Vec<TypedExpr> = Vec::new();
+    for (param, arg) in params.iter().zip(args.into_iter()) {
+        let param_type = param.param_type.clone();
+        let typed_arg = tc_expect(arg, &param_type, fn_list, fn_context)?;
+        if typed_arg.expr_type != param_type {
             return Err(TypeErr(format!(
                 "in function call to ´{}´-> expected argument type {{{}}}, found type {{{}}}",
-                String::from(fn_content.name),
+                fn_content.name,
                 String::from(param_type),
-                String::from(arg_type),
+                String::from(typed_arg.expr_type),
             )));
-        } else {
-            res = Ok(param_type);
         }
+        typed_args.push(typed_arg);
     }
 
     // If ok - returns the function's return type.
-    match res {
-        Ok(_) => Ok(fn_type),
-        Err(e) => Err(e),
+    Ok(TypedExpr::new(
+        TypedExprKind::FuncCall(fn_content.name, typed_args),
+        fn_content.return_type,
+    ))
+}
+
+/// Type checks calls to the built-in ´print´ and ´println´. The first argument
+/// must be a string literal with one ´{}´ for each of the remaining arguments.
+/// Only strings, chars, booleans and integers can be printed.
+fn tc_print(
+    fn_call: FunctionCall,
+    fn_list: &mut Functions,
+    fn_context: &mut ContextStack<Type>,
+) -> TypeRes<TypedExpr> {
+    let mut args = fn_call.args.content.into_iter();
+    let mut typed_args: Vec<TypedExpr> = Vec::new();
+    let placeholders = match args.next() {
+        Some(Expr::Str(fmt)) => {
+            let placeholders = fmt.matches("{}").count();
+            typed_args.push(TypedExpr::new(TypedExprKind::Str(fmt), Type::Str));
+            placeholders
+        }
+        None if fn_call.name == "println" => 0,
+        _ => {
+            return Err(TypeErr(format!(
+                "format argument to ´{}´ must be a string literal",
+                fn_call.name
+            )))
+        }
+    };
+
+    let args: Vec<Expr> = args.collect();
+    if args.len() != placeholders {
+        return Err(TypeErr(format!(
+            "in call to ´{}´ -> {} placeholder(s) but {} argument(s) were supplied",
+            fn_call.name,
+            placeholders,
+            args.len()
+        )));
+    }
+
+    for arg in args {
+        let typed_arg = tc_expr(arg.clone(), fn_list, fn_context)?;
+        match typed_arg.expr_type {
+            Type::Str | Type::Char | Type::Bool => typed_args.push(typed_arg),
+            ref arg_type if arg_type.is_int() => typed_args.push(typed_arg),
+            arg_type => {
+                return Err(TypeErr(format!(
+                    "´{}´ of type {{{}}} cannot be formatted with ´{{}}´",
+                    String::from(arg),
+                    String::from(arg_type)
+                )))
+            }
+        }
     }
+
+    Ok(TypedExpr::new(
+        TypedExprKind::FuncCall(fn_call.name, typed_args),
+        Type::Void,
+    ))
 }
 
-/// Type checks a complete program.
-pub fn tc_program(fn_list: &mut Functions) -> TypeRes<Type> {
+/// Type checks a complete program. Returns the typed AST of every function.
+pub fn tc_program(fn_list: &mut Functions) -> TypeRes<TypedFunctions> {
     let mut fn_context: ContextStack<Type> = ContextStack::new();
-    let mut res: TypeRes<Type> = Err(TypeErr("Error: No functions found.".to_string()));
+    let mut typed_fns: TypedFunctions = Vec::new();
+    if fn_list.is_empty() {
+        return Err(TypeErr("Error: No functions found.".to_string()));
+    }
 
     for func in fn_list.clone() {
-        res = tc_function(&func, fn_list, &mut fn_context);
-
-        match res {
-            Ok(_) => continue,
-            Err(e) => return Err(e),
-        }
+        typed_fns.push(tc_function(&func, fn_list, &mut fn_context)?);
     }
 
-    res
+    Ok(typed_fns)
 }
 
 #[cfg(test)]
@@ -351,4 +788,258 @@ mod tc_tests {
 
         assert_eq!(tc.is_ok(), true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tuple() {
+        let program = "
+        fn pair(a: i32, b: bool) -> (i32, bool) {
+            return (a, b);
+        }
+
+        fn main() -> () {
+            let (a, b): (i32, bool) = pair(1, true);
+            let c: bool = pair(a, b).1;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let program = "
+        fn main() -> () {
+            let (a, b): (i32, bool) = (1, 2);
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_int_widths() {
+        let program = "
+        fn main() -> () {
+            let a: u8 = 200;
+            let b: u8 = a + 55;
+            let c: i64 = b as i64 * 1000;
+            let d: bool = c > 0;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let mixed = "
+        fn main() -> () {
+            let a: u8 = 1;
+            let b: i32 = 2;
+            let c: i32 = a + b;
+        }
+        ";
+        let mut tree = parse_program(mixed).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+
+        let out_of_range = "
+        fn main() -> () {
+            let a: u8 = 256;
+        }
+        ";
+        let mut tree = parse_program(out_of_range).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_wide_literals() {
+        let program = "
+        fn main() -> () {
+            let a: i64 = 3000000000;
+            let b: i32 = -2147483648;
+            let c: u64 = 18446744073709551615;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let too_wide = "
+        fn main() -> () {
+            let a: i32 = 3000000000;
+        }
+        ";
+        let mut tree = parse_program(too_wide).unwrap().1;
+        assert_eq!(
+            tc_program(&mut tree),
+            Err(TypeErr(
+                "in function ´main´-> when assigning variable ´a´ -> literal ´3000000000´ out of range for type {Int32}"
+                    .to_string()
+            ))
+        );
+
+        let untyped = "
+        fn main() -> () {
+            println(\"{}\", 2147483648);
+        }
+        ";
+        let mut tree = parse_program(untyped).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_f64() {
+        let program = "
+        fn half(a: f64) -> f64 {
+            return a / 2.0;
+        }
+
+        fn main() -> () {
+            let a: f64 = half(3 as f64);
+            let b: i32 = a as i32;
+            let c: bool = a > 1e-3;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let mixed = "
+        fn main() -> () {
+            let a: f64 = 1.5 + 1;
+        }
+        ";
+        let mut tree = parse_program(mixed).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_print() {
+        let program = r#"
+        fn main() -> () {
+            let s: &str = "x";
+            let c: char = 97 as u8 as char;
+            println!("{} {} {} {}", s, c, c == 'a', 1 + 2);
+            println();
+        }
+        "#;
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let missing = r#"
+        fn main() -> () {
+            print("{} {}", 1);
+        }
+        "#;
+        let mut tree = parse_program(missing).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+
+        let float = r#"
+        fn main() -> () {
+            print("{}", 1.5);
+        }
+        "#;
+        let mut tree = parse_program(float).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_vec() {
+        let program = "
+        fn main() -> () {
+            let v: Vec<u8> = Vec::new();
+            v.push(1);
+            v[0] += 2;
+            let i: usize = v.len() - 1;
+            let last: u8 = v.pop();
+            let first: u8 = v[i];
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_ok(), true);
+
+        let wrong_index = "
+        fn main() -> () {
+            let v: Vec<u8> = Vec::new();
+            let i: i32 = 0;
+            let first: u8 = v[i];
+        }
+        ";
+        let mut tree = parse_program(wrong_index).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+
+        let wrong_elem = "
+        fn main() -> () {
+            let v: Vec<u8> = Vec::new();
+            v.push(true);
+        }
+        ";
+        let mut tree = parse_program(wrong_elem).unwrap().1;
+        assert_eq!(tc_program(&mut tree).is_err(), true);
+    }
+
+    #[test]
+    fn test_typed_ast() {
+        let program = "
+        fn main() -> u8 {
+            let a: u8 = 1 + 2;
+            return a;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        let typed = tc_program(&mut tree).unwrap();
+        let num = |n: i128| Box::new(TypedExpr::new(TypedExprKind::Num(n), Type::UInt8));
+        let var = || {
+            Box::new(TypedExpr::new(
+                TypedExprKind::Var("a".to_string()),
+                Type::UInt8,
+            ))
+        };
+
+        assert_eq!(
+            typed[0].block,
+            vec![
+                TypedExpr::new(
+                    TypedExprKind::Let(
+                        var(),
+                        Box::new(TypedExpr::new(
+                            TypedExprKind::BinOp(num(1), Op::MathOp(MathToken::Plus), num(2)),
+                            Type::UInt8
+                        ))
+                    ),
+                    Type::Void
+                ),
+                TypedExpr::new(TypedExprKind::Return(var()), Type::Void)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extern_fn() {
+        let program = "
+        extern \"C\" {
+            fn abs(x: i32) -> i32;
+        }
+
+        fn main() -> i32 {
+            return abs(-3);
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        let typed = tc_program(&mut tree).unwrap();
+        assert!(typed[0].is_extern);
+        assert!(typed[0].block.is_empty());
+
+        let program = "
+        extern \"C\" {
+            fn abs(x: i32) -> i32;
+        }
+
+        fn main() -> bool {
+            return abs(true);
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert!(tc_program(&mut tree).is_err());
+
+        let program = "
+        extern \"C\" {
+            fn sum(v: Vec<i32>) -> i32;
+        }
+        ";
+        let mut tree = parse_program(program).unwrap().1;
+        assert!(tc_program(&mut tree).is_err());
+    }
+}
@@ -0,0 +1,489 @@
+use crate::ast::*;
+use crate::bytecode::*;
+use crate::interpreter::*;
+
+use std::io::{self, Write};
+
+// Stack machine running the bytecode from bytecode.rs. Values are the same as
+// those of the interpreter and the operations on them are done by the same
+// functions, so that programs give the same results. Every call gets a frame
+// with the slots of its variables, which are stored in a single vector.
+
+struct Frame {
+    func: usize,
+    // Instruction to continue at in the caller
+    ret_pc: usize,
+    // Index of the first slot in the locals
+    base: usize,
+    // Height of the stack when the function was called
+    stack_base: usize,
+}
+
+fn err_condition() -> EvalErr {
+    EvalErr::WrongType("Cannot evaluate condition. Not a boolean expression.".to_string())
+}
+
+// Returns the vector stored in the slot for modifying it in place.
+fn vec_mut(locals: &mut [Value], slot: usize) -> EvalRes<(&mut Vec<Value>, &Type)> {
+    match &mut locals[slot] {
+        Value::Vec(values, elem_type) => Ok((values, elem_type)),
+        _ => Err(EvalErr::WrongType("Not a vector.".to_string())),
+    }
+}
+
+fn get_elem(values: &[Value], index: &Value) -> EvalRes<Value> {
+    let index = to_index(index.clone())?;
+    match values.get(index) {
+        Some(val) => Ok(val.clone()),
+        None => Err(err_out_of_bounds(index, values.len())),
+    }
+}
+
+struct Vm<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Vm<'a> {
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("bytecode pops an empty stack")
+    }
+
+    fn top(&mut self) -> &mut Value {
+        self.stack
+            .last_mut()
+            .expect("bytecode reads an empty stack")
+    }
+
+    // Pushes a frame for the function and returns its first instruction. The
+    // arguments are given the types of the parameters. Extra arguments are
+    // dropped.
+    fn call(&mut self, func: usize, num_args: usize, ret_pc: usize) -> EvalRes<usize> {
+        let info = &self.program.functions[func];
+        let args = self.stack.split_off(self.stack.len() - num_args);
+        let base = self.locals.len();
+        for (arg, t) in args.into_iter().zip(info.param_types.iter()) {
+            self.locals.push(coerce_to(arg, t)?);
+        }
+        self.locals
+            .resize(base + info.num_locals, Value::Tuple(vec![]));
+        self.frames.push(Frame {
+            func,
+            ret_pc,
+            base,
+            stack_base: self.stack.len(),
+        });
+        Ok(info.entry)
+    }
+
+    // Pops the frame, pushes the value for the caller and returns the
+    // instruction to continue at.
+    fn ret(&mut self, val: Value) -> usize {
+        let frame = self.frames.pop().unwrap();
+        self.locals.truncate(frame.base);
+        self.stack.truncate(frame.stack_base);
+        self.stack.push(val);
+        frame.ret_pc
+    }
+
+    fn run(&mut self, out: &mut dyn Write) -> EvalRes<Value> {
+        let program = self.program;
+        let mut pc = self.call(program.main, 0, 0)?;
+
+        loop {
+            let base = self.frames.last().unwrap().base;
+            let instr = &program.code[pc];
+            pc += 1;
+
+            match instr {
+                Instr::Const(index) => self.stack.push(program.constants[*index].clone()),
+                Instr::Load(slot) => {
+                    let val = self.locals[base + slot].clone();
+                    self.stack.push(val);
+                }
+                Instr::Store(slot) => self.locals[base + slot] = self.pop(),
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Dup => {
+                    let val = self.top().clone();
+                    self.stack.push(val);
+                }
+                Instr::BinOp(op) => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    self.stack.push(eval_values(l, *op, r)?);
+                }
+                Instr::LiteralLeft(expr, op) => {
+                    let r = self.pop();
+                    let l = eval_int_literal(expr, &literal_type(&r))?;
+                    self.stack.push(eval_values(l, *op, r)?);
+                }
+                Instr::LiteralRight(op, expr) => {
+                    let l = self.pop();
+                    let r = eval_int_literal(expr, &literal_type(&l))?;
+                    self.stack.push(eval_values(l, *op, r)?);
+                }
+                Instr::Cast(target) => {
+                    let val = self.pop();
+                    self.stack.push(eval_cast(val, target)?);
+                }
+                Instr::IntLiteral(slot, expr) => {
+                    let t = literal_type(&self.locals[base + slot]);
+                    self.stack.push(eval_int_literal(expr, &t)?);
+                }
+                Instr::Coerce(t) => {
+                    let val = self.pop();
+                    self.stack.push(coerce_to(val, t)?);
+                }
+                Instr::MakeTuple(len) => {
+                    let values = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Value::Tuple(values));
+                }
+                Instr::TupleIndex(index) => match self.pop() {
+                    Value::Tuple(mut values) if *index < values.len() => {
+                        self.stack.push(values.swap_remove(*index))
+                    }
+                    _ => {
+                        return Err(EvalErr::WrongType(format!(
+                            "Cannot access field {}. Not a tuple with enough elements.",
+                            index
+                        )))
+                    }
+                },
+                Instr::Destructure(len) => match self.pop() {
+                    Value::Tuple(values) if values.len() == *len => {
+                        self.stack.extend(values.into_iter().rev())
+                    }
+                    _ => {
+                        return Err(EvalErr::TypeMismatch(
+                            "Cannot destructure value. Pattern does not match.".to_string(),
+                        ))
+                    }
+                },
+                Instr::ToIndex => {
+                    let index = to_index(self.pop())?;
+                    self.stack.push(Value::Int(index as i128, Type::USize));
+                }
+                Instr::Index => {
+                    let index = self.pop();
+                    match self.pop() {
+                        Value::Vec(values, _) => self.stack.push(get_elem(&values, &index)?),
+                        _ => {
+                            return Err(EvalErr::WrongType(
+                                "Cannot index a non-vector value.".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Instr::IndexVar(slot) => {
+                    let index = self.pop();
+                    let elem = match &self.locals[base + slot] {
+                        Value::Vec(values, _) => get_elem(values, &index)?,
+                        _ => {
+                            return Err(EvalErr::WrongType(
+                                "Cannot index a non-vector value.".to_string(),
+                            ))
+                        }
+                    };
+                    self.stack.push(elem);
+                }
+                Instr::Len => match self.pop() {
+                    Value::Vec(values, _) => self
+                        .stack
+                        .push(Value::Int(values.len() as i128, Type::USize)),
+                    _ => return Err(EvalErr::WrongType("Not a vector.".to_string())),
+                },
+                Instr::LenVar(slot) => {
+                    let (values, _) = vec_mut(&mut self.locals, base + slot)?;
+                    let len = values.len();
+                    self.stack.push(Value::Int(len as i128, Type::USize));
+                }
+                Instr::Assign(slot) => {
+                    let val = self.pop();
+                    let val = match &self.locals[base + slot] {
+                        Value::Int(_, t) => coerce_to(val, t)?,
+                        Value::Vec(_, t) => coerce_to(val, &Type::Vec(Box::new(t.clone())))?,
+                        _ => val,
+                    };
+                    self.locals[base + slot] = val.clone();
+                    self.stack.push(val);
+                }
+                Instr::AssignOp(slot, op) => {
+                    let new_val = self.pop();
+                    let old_val = self.locals[base + slot].clone();
+                    let val = eval_values(old_val, var_op_to_math_op(*op)?, new_val)?;
+                    self.locals[base + slot] = val.clone();
+                    self.stack.push(val);
+                }
+                Instr::ElemOp(slot, op, used) => {
+                    let new_val = self.pop();
+                    let index = to_index(self.pop())?;
+                    let (values, elem_type) = match &mut self.locals[base + slot] {
+                        Value::Vec(values, elem_type) => (values, elem_type),
+                        _ => {
+                            return Err(EvalErr::WrongType(
+                                "Cannot index a non-vector value.".to_string(),
+                            ))
+                        }
+                    };
+                    if index >= values.len() {
+                        return Err(err_out_of_bounds(index, values.len()));
+                    }
+                    values[index] = match op {
+                        Op::VarOp(VarToken::Assign) => coerce_to(new_val, elem_type)?,
+                        _ => eval_values(values[index].clone(), var_op_to_math_op(*op)?, new_val)?,
+                    };
+                    if *used {
+                        let val = self.locals[base + slot].clone();
+                        self.stack.push(val);
+                    }
+                }
+                Instr::VecPush(slot) => {
+                    let val = self.pop();
+                    let (values, elem_type) = vec_mut(&mut self.locals, base + slot)?;
+                    values.push(coerce_to(val, elem_type)?);
+                    self.stack.push(Value::Tuple(vec![]));
+                }
+                Instr::VecPop(slot) => {
+                    let (values, _) = vec_mut(&mut self.locals, base + slot)?;
+                    match values.pop() {
+                        Some(val) => self.stack.push(val),
+                        None => {
+                            return Err(EvalErr::IndexOutOfBounds(
+                                "called ´pop´ on an empty vector".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Instr::UnknownMethod(slot, name) => {
+                    vec_mut(&mut self.locals, base + slot)?;
+                    return Err(EvalErr::NotFound(format!("No method named ´{}´.", name)));
+                }
+                Instr::Format => {
+                    let text = format_value(self.top())?;
+                    *self.top() = Value::Str(text);
+                }
+                Instr::Print(pieces, newline) => {
+                    let args = self.stack.split_off(self.stack.len() - (pieces.len() - 1));
+                    let mut text = pieces[0].clone();
+                    for (arg, piece) in args.into_iter().zip(pieces[1..].iter()) {
+                        if let Value::Str(s) = arg {
+                            text.push_str(&s);
+                        }
+                        text.push_str(piece);
+                    }
+                    if *newline {
+                        text.push('\n');
+                    }
+                    out.write_all(text.as_bytes())
+                        .map_err(|e| EvalErr::IoError(e.to_string()))?;
+                    self.stack.push(Value::Tuple(vec![]));
+                }
+                Instr::Jump(target) => pc = *target,
+                Instr::JumpIfFalse(target) => match self.pop() {
+                    Value::Bool(true) => (),
+                    Value::Bool(false) => pc = *target,
+                    _ => return Err(err_condition()),
+                },
+                Instr::Call(func, num_args) => pc = self.call(*func, *num_args, pc)?,
+                // A return from main is kept as a return value, like in
+                // eval_program. Other functions give the value the return type.
+                Instr::Return => {
+                    let val = self.pop();
+                    if self.frames.len() == 1 {
+                        return Ok(Value::Return(Box::new(val)));
+                    }
+                    let func = &program.functions[self.frames.last().unwrap().func];
+                    let val = coerce_to(val, &func.return_type)?;
+                    pc = self.ret(val);
+                }
+                Instr::End => {
+                    let val = self.pop();
+                    if self.frames.len() == 1 {
+                        return Ok(val);
+                    }
+                    pc = self.ret(val);
+                }
+                Instr::Fail(e) => return Err(e.clone()),
+            }
+        }
+    }
+}
+
+// Runs the main function of the program. Returns the same value as
+// eval_program for the program.
+pub fn run_bytecode(program: &Program) -> EvalRes<Value> {
+    run_bytecode_with_output(program, &mut io::stdout())
+}
+
+// Same as run_bytecode but everything the program prints is written to ´out´.
+pub fn run_bytecode_with_output(program: &Program, out: &mut dyn Write) -> EvalRes<Value> {
+    let mut vm = Vm {
+        program,
+        stack: Vec::new(),
+        locals: Vec::new(),
+        frames: Vec::new(),
+    };
+    vm.run(out)
+}
+
+// Compiles the program to bytecode and runs it.
+pub fn vm_program(fn_tree: &Functions) -> EvalRes<Value> {
+    run_bytecode(&compile_bytecode(fn_tree)?)
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::*;
+    use crate::parser::*;
+
+    // Runs the program with the interpreter and the VM and checks that they
+    // give the same value and output.
+    fn assert_same(program: &str) -> EvalRes<Value> {
+        let mut tree = parse_program(program).unwrap().1;
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut tree, &mut expected_out);
+
+        let mut out: Vec<u8> = Vec::new();
+        let res =
+            compile_bytecode(&tree).and_then(|code| run_bytecode_with_output(&code, &mut out));
+        assert_eq!(res, expected);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(expected_out).unwrap()
+        );
+        res
+    }
+
+    #[test]
+    fn vm_fibo_program() {
+        let program = "
+            fn fibo(i: i32) -> i32 {
+                if i == 1 {
+                    return 0;
+                };
+                if i == 2 {
+                    return 1;
+                };
+                return fibo(i - 1) + fibo(i - 2);
+            }
+
+            fn main() -> i32 {
+                let a: i32 = fibo(20);
+                return a;
+            }
+        ";
+        assert_eq!(
+            assert_same(program),
+            Ok(Value::Return(Box::new(Value::Num(4181))))
+        );
+    }
+
+    #[test]
+    fn vm_scopes_program() {
+        let program = "
+            fn count(n: u8) -> u8 {
+                let i: u8 = 0;
+                while i < n {
+                    let a: u8 = i;
+                    i = a + 1;
+                };
+                return i;
+            }
+
+            fn main() -> () {
+                let a: i32 = 1;
+                if true {
+                    let a: bool = false;
+                    println(\"inner {}\", a);
+                };
+                let (b, (c, d)): (i32, (char, f64)) = (a + 1, ('x', 1.5));
+                println(\"{} {} {} {}\", a, b, c, count(3));
+                let v: Vec<u16> = Vec::new();
+                v.push(1);
+                v.push(2);
+                v[0] += 10;
+                v[1] = v[0] * 2;
+                println(\"{} {} {}\", v.len(), v[1], v.pop());
+                v = Vec::new();
+                v.push(3);
+                v[0] = 7;
+            }
+        ";
+        assert_eq!(
+            assert_same(program),
+            Ok(Value::Vec(vec![Value::Int(7, Type::UInt16)], Type::UInt16))
+        );
+    }
+
+    #[test]
+    fn vm_literals_program() {
+        let program = "
+            fn add(a: i64, b: u64) -> u64 {
+                return ((a + 2147483648) as u64) + (b + 3000000000);
+            }
+
+            fn big() -> i64 {
+                return 3000000000 * 2;
+            }
+
+            fn main() -> () {
+                let a: i64 = 100000 * 100000;
+                a = 3000000000 * 3;
+                let v: Vec<u64> = Vec::new();
+                v.push(5000000000);
+                v[0] += 5000000000;
+                let b: i64 = 3000000000 + a;
+                let c: i64 = a - 3000000000;
+                let t: (u8, i64) = (255, -3000000000);
+                return (add(-2147483648, 4000000000), big(), b, c, v[0], t);
+            }
+        ";
+        assert!(assert_same(program).is_ok());
+
+        let overflow = "
+            fn main() -> u8 {
+                let a: u8 = 200 + 100;
+                return a;
+            }
+        ";
+        assert!(assert_same(overflow).is_err());
+    }
+
+    #[test]
+    fn vm_errors_program() {
+        let overflow = "
+            fn main() -> i32 {
+                let a: u8 = 200;
+                print(\"{} \", a);
+                a += 100;
+                return 1;
+            }
+        ";
+        assert!(assert_same(overflow).is_err());
+
+        let unknown_var = "
+            fn main() -> i32 {
+                if true {
+                    let a: i32 = 1;
+                };
+                return a;
+            }
+        ";
+        assert!(assert_same(unknown_var).is_err());
+
+        let out_of_bounds = "
+            fn main() -> i32 {
+                let v: Vec<i32> = Vec::new();
+                v.push(1);
+                v[1] = 2;
+                return 0;
+            }
+        ";
+        assert!(assert_same(out_of_bounds).is_err());
+        assert!(assert_same("fn other() -> () { 1; }").is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use crate::ast::*;
+use crate::backend::*;
+
+mod validate;
+
+pub use validate::validate_wat;
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+// Translates programs to a WebAssembly module in the text format. Only
+// instructions of the first version of WebAssembly are used, written in the
+// folded form, e.g. ´(i32.add (local.get $a) (i32.const 1))´, so that the
+// operands are evaluated in the same order as in the interpreter.
+//
+// Integers of at most 32 bits, bool and char are i32 values, 64 bit integers
+// are i64 values. Integers narrower than 32 bits are always kept in range,
+// sign or zero extended to 32 bits. Checked arithmetic and float to integer
+// casts call helper functions that are added to the module when they are
+// used. A failed check traps with ´unreachable´.
+//
+// Every function except the extern ones is exported with its name. Functions
+// declared in extern blocks are imported from the module ´env´.
+
+// The WebAssembly type of values of the type.
+fn wasm_type(t: &Type) -> BackendRes<&'static str> {
+    Ok(match t {
+        Type::Int64 | Type::UInt64 | Type::USize => "i64",
+        Type::Float64 => "f64",
+        Type::Bool | Type::Char => "i32",
+        t if t.is_int() => "i32",
+        Type::Str => return err_unsupported("strings"),
+        Type::Tuple(_) => return err_unsupported("tuples"),
+        Type::Vec(_) => return err_unsupported("vectors"),
+        _ => return err_unsupported(&format!("values of type ´{}´", String::from(t.clone()))),
+    })
+}
+
+// The width in bits and signedness of the integer values of the type. Bools
+// and chars are unsigned integers.
+fn int_info(t: &Type) -> BackendRes<(u32, bool)> {
+    match t {
+        Type::Bool => Ok((8, false)),
+        Type::Char => Ok((32, false)),
+        t => match t.bit_width() {
+            Some(width) => Ok((width, t.is_signed())),
+            None => err_unsupported(&format!(
+                "integer operations on values of type ´{}´",
+                String::from(t.clone())
+            )),
+        },
+    }
+}
+
+// The suffix of the helper functions for the integer type, e.g. ´$rt_add_u8´.
+fn int_suffix(t: &Type) -> BackendRes<String> {
+    let (width, signed) = int_info(t)?;
+    Ok(format!("{}{}", if signed { "i" } else { "u" }, width))
+}
+
+// Integer suffixes are parsed back into the width and signedness when the
+// helper functions are generated.
+fn parse_suffix(suffix: &str) -> (u32, bool) {
+    (suffix[1..].parse().unwrap(), suffix.starts_with('i'))
+}
+
+fn int_range(width: u32, signed: bool) -> (i128, i128) {
+    if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    }
+}
+
+// Narrows an i32 value to the range of the integer type by sign or zero
+// extending the low bits.
+fn normalize(val: String, width: u32, signed: bool) -> String {
+    match (width, signed) {
+        (32, _) => val,
+        (w, true) => format!(
+            "(i32.shr_s (i32.shl {} (i32.const {})) (i32.const {}))",
+            val,
+            32 - w,
+            32 - w
+        ),
+        (w, false) => format!("(i32.and {} (i32.const {}))", val, (1u32 << w) - 1),
+    }
+}
+
+// Checked arithmetic on integers of at most 32 bits is done on i64 values,
+// where it cannot overflow, and the result is checked against the range of
+// the type. Division by zero traps in ´div´ itself.
+fn narrow_op_func(op: &str, suffix: &str) -> String {
+    let (width, signed) = parse_suffix(suffix);
+    let (min, max) = int_range(width, signed);
+    let (extend, div) = if signed {
+        ("i64.extend_i32_s", "div_s")
+    } else {
+        ("i64.extend_i32_u", "div_u")
+    };
+    let instr = if op == "div" { div } else { op };
+    let check = if signed {
+        format!(
+            "(i32.or (i64.lt_s (local.get $r) (i64.const {})) (i64.gt_s (local.get $r) (i64.const {})))",
+            min, max
+        )
+    } else {
+        format!("(i64.gt_u (local.get $r) (i64.const {}))", max)
+    };
+    format!(
+        "  (func $rt_{op}_{suffix} (param $a i32) (param $b i32) (result i32)
+    (local $r i64)
+    (local.set $r (i64.{instr} ({extend} (local.get $a)) ({extend} (local.get $b))))
+    (if {check} (then (unreachable)))
+    (i32.wrap_i64 (local.get $r)))
+",
+        op = op,
+        suffix = suffix,
+        instr = instr,
+        extend = extend,
+        check = check
+    )
+}
+
+// 64 bit addition, subtraction and multiplication check the result for
+// overflow. Division is not done by a helper function.
+fn wide_op_func(op: &str, suffix: &str) -> String {
+    let (_, signed) = parse_suffix(suffix);
+    let check = match (op, signed) {
+        ("add", true) => "(i64.lt_s (i64.and (i64.xor (local.get $a) (local.get $r)) (i64.xor (local.get $b) (local.get $r))) (i64.const 0))",
+        ("add", false) => "(i64.lt_u (local.get $r) (local.get $a))",
+        ("sub", true) => "(i64.lt_s (i64.and (i64.xor (local.get $a) (local.get $b)) (i64.xor (local.get $a) (local.get $r))) (i64.const 0))",
+        ("sub", false) => "(i64.lt_u (local.get $a) (local.get $b))",
+        // -1 * MIN overflows, and MIN / -1 would trap in the check below
+        ("mul", true) => "(if (result i32) (i64.eq (local.get $a) (i64.const -1)) (then (i64.eq (local.get $b) (i64.const -9223372036854775808))) (else (if (result i32) (i64.eqz (local.get $a)) (then (i32.const 0)) (else (i64.ne (i64.div_s (local.get $r) (local.get $a)) (local.get $b))))))",
+        _ => "(if (result i32) (i64.eqz (local.get $a)) (then (i32.const 0)) (else (i64.ne (i64.div_u (local.get $r) (local.get $a)) (local.get $b))))",
+    };
+    format!(
+        "  (func $rt_{op}_{suffix} (param $a i64) (param $b i64) (result i64)
+    (local $r i64)
+    (local.set $r (i64.{op} (local.get $a) (local.get $b)))
+    (if {check} (then (unreachable)))
+    (local.get $r))
+",
+        op = op,
+        suffix = suffix,
+        check = check
+    )
+}
+
+// Casts from f64 saturate to the range of the integer type and map NaN to 0,
+// like ´as´ in Rust. The truncation itself cannot trap after the checks.
+fn f64_to_int_func(suffix: &str) -> String {
+    let (width, signed) = parse_suffix(suffix);
+    let (min, max) = int_range(width, signed);
+    let repr = if width == 64 { "i64" } else { "i32" };
+    let trunc = format!(
+        "{}.trunc_f64_{}",
+        repr,
+        if signed || width < 32 { "s" } else { "u" }
+    );
+    format!(
+        "  (func $rt_f64_to_{suffix} (param $f f64) (result {repr})
+    (if (f64.ne (local.get $f) (local.get $f)) (then (return ({repr}.const 0))))
+    (if (f64.le (local.get $f) (f64.const {min})) (then (return ({repr}.const {min}))))
+    (if (f64.ge (local.get $f) (f64.const {max})) (then (return ({repr}.const {max}))))
+    ({trunc} (local.get $f)))
+",
+        suffix = suffix,
+        repr = repr,
+        min = min,
+        max = max,
+        trunc = trunc
+    )
+}
+
+struct WasmGen<'a> {
+    // Lines of the function being translated with their indentation
+    lines: Vec<(usize, String)>,
+    indent: usize,
+    // Locals declared by ´let´, with their WebAssembly types
+    locals: Vec<(String, &'static str)>,
+    // Names of the variables in the blocks around the statement
+    scopes: Vec<HashMap<String, String>>,
+    // Number of variables declared with each name in the function
+    declared: HashMap<String, usize>,
+    loops: usize,
+    return_type: Type,
+    fn_types: &'a HashMap<String, Type>,
+    // Helper functions used by the module, e.g. ("add", "i8")
+    helpers: &'a mut BTreeSet<(String, String)>,
+}
+
+impl<'a> WasmGen<'a> {
+    fn emit(&mut self, line: String) {
+        self.lines.push((self.indent, line));
+    }
+
+    // Variables that shadow another variable of the function get a number.
+    fn declare(&mut self, name: &str) -> String {
+        let count = self.declared.entry(name.to_string()).or_insert(0);
+        let wasm_name = match *count {
+            0 => format!("${}", name),
+            n => format!("${}_{}", name, n),
+        };
+        *count += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), wasm_name.clone());
+        wasm_name
+    }
+
+    fn lookup(&self, name: &str) -> BackendRes<String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| BackendError::UnknownVariable(name.to_string()))
+    }
+
+    fn helper(&mut self, op: &str, t: &Type) -> BackendRes<String> {
+        let suffix = int_suffix(t)?;
+        let name = format!("$rt_{}_{}", op, suffix);
+        self.helpers.insert((op.to_string(), suffix));
+        Ok(name)
+    }
+
+    fn gen_expr(&mut self, expr: &TypedExpr) -> BackendRes<String> {
+        check_value(expr)?;
+        match &expr.kind {
+            TypedExprKind::Num(num) => {
+                Ok(format!("({}.const {})", wasm_type(&expr.expr_type)?, num))
+            }
+            TypedExprKind::Float(f) => Ok(format!("(f64.const {:?})", f)),
+            TypedExprKind::Bool(b) => Ok(format!("(i32.const {})", *b as i32)),
+            TypedExprKind::Char(c) => Ok(format!("(i32.const {})", *c as u32)),
+            TypedExprKind::Var(name) => Ok(format!("(local.get {})", self.lookup(name)?)),
+            TypedExprKind::BinOp(l, op, r) => {
+                let operand_type = l.expr_type.clone();
+                let l = self.gen_expr(l)?;
+                let r = self.gen_expr(r)?;
+                self.gen_binop(l, op, r, &operand_type)
+            }
+            TypedExprKind::Cast(inner) => {
+                let src_type = inner.expr_type.clone();
+                let val = self.gen_expr(inner)?;
+                self.gen_cast(val, &src_type, &expr.expr_type)
+            }
+            TypedExprKind::FuncCall(name, _) if name == "print" || name == "println" => {
+                err_unsupported("printing")
+            }
+            TypedExprKind::FuncCall(name, args) => self.gen_call(name, args),
+            TypedExprKind::Str(_) => err_unsupported("strings"),
+            _ => err_statement_value(),
+        }
+    }
+
+    fn gen_binop(&mut self, l: String, op: &Op, r: String, t: &Type) -> BackendRes<String> {
+        let repr = wasm_type(t)?;
+        match op {
+            Op::MathOp(token) if *t == Type::Float64 => {
+                let instr = match token {
+                    MathToken::Plus => "add",
+                    MathToken::Minus => "sub",
+                    MathToken::Multiply => "mul",
+                    MathToken::Division => "div",
+                };
+                Ok(format!("(f64.{} {} {})", instr, l, r))
+            }
+            // ´div_s´ and ´div_u´ trap on division by zero and on ´MIN / -1´,
+            // which is all that 64 bit division has to check.
+            Op::MathOp(MathToken::Division) if repr == "i64" => {
+                let sign = if int_info(t)?.1 { "s" } else { "u" };
+                Ok(format!("(i64.div_{} {} {})", sign, l, r))
+            }
+            Op::MathOp(token) => {
+                let op = match token {
+                    MathToken::Plus => "add",
+                    MathToken::Minus => "sub",
+                    MathToken::Multiply => "mul",
+                    MathToken::Division => "div",
+                };
+                let func = self.helper(op, t)?;
+                Ok(format!("(call {} {} {})", func, l, r))
+            }
+            Op::RelOp(token) => {
+                let sign = if *t == Type::Float64 {
+                    ""
+                } else if int_info(t)?.1 {
+                    "_s"
+                } else {
+                    "_u"
+                };
+                let instr = match token {
+                    RelToken::Equal => "eq".to_string(),
+                    RelToken::Neq => "ne".to_string(),
+                    RelToken::Ge => format!("gt{}", sign),
+                    RelToken::Le => format!("lt{}", sign),
+                };
+                Ok(format!("({}.{} {} {})", repr, instr, l, r))
+            }
+            // Booleans are 0 or 1, so the bitwise instructions are enough.
+            Op::BoolOp(BoolToken::And) => Ok(format!("(i32.and {} {})", l, r)),
+            Op::BoolOp(BoolToken::Or) => Ok(format!("(i32.or {} {})", l, r)),
+            op => err_unsupported(&format!("´{}´ as a binary operator", op)),
+        }
+    }
+
+    fn gen_cast(&mut self, val: String, src_type: &Type, target: &Type) -> BackendRes<String> {
+        if *target == Type::Float64 {
+            if *src_type == Type::Float64 {
+                return Ok(val);
+            }
+            let (width, signed) = int_info(src_type)?;
+            return Ok(format!(
+                "(f64.convert_{}_{} {})",
+                if width == 64 { "i64" } else { "i32" },
+                if signed { "s" } else { "u" },
+                val
+            ));
+        }
+        if *src_type == Type::Float64 {
+            let func = self.helper("f64_to", target)?;
+            return Ok(format!("(call {} {})", func, val));
+        }
+
+        let (src_width, src_signed) = int_info(src_type)?;
+        let (width, signed) = int_info(target)?;
+        Ok(match (src_width == 64, width == 64) {
+            (true, true) => val,
+            (false, true) => format!(
+                "(i64.extend_i32_{} {})",
+                if src_signed { "s" } else { "u" },
+                val
+            ),
+            (true, false) => normalize(format!("(i32.wrap_i64 {})", val), width, signed),
+            (false, false) => normalize(val, width, signed),
+        })
+    }
+
+    fn gen_call(&mut self, name: &str, args: &[TypedExpr]) -> BackendRes<String> {
+        if !self.fn_types.contains_key(name) {
+            return Err(BackendError::UnknownFunction(name.to_string()));
+        }
+        let mut call = format!("(call ${}", name);
+        for arg in args {
+            call.push(' ');
+            call.push_str(&self.gen_expr(arg)?);
+        }
+        call.push(')');
+        Ok(call)
+    }
+
+    fn gen_block(&mut self, block: &[TypedExpr]) -> BackendRes<()> {
+        self.scopes.push(HashMap::new());
+        self.indent += 1;
+        for stmt in block {
+            self.gen_stmt(stmt)?;
+        }
+        self.indent -= 1;
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn gen_stmt(&mut self, stmt: &TypedExpr) -> BackendRes<()> {
+        match &stmt.kind {
+            TypedExprKind::Let(pattern, val) => {
+                let name = match &pattern.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("tuple patterns"),
+                };
+                let val = self.gen_expr(val)?;
+                let local_type = wasm_type(&pattern.expr_type)?;
+                let local = self.declare(name);
+                self.locals.push((local.clone(), local_type));
+                self.emit(format!("(local.set {} {})", local, val));
+            }
+            TypedExprKind::VarOp(var, op, val) => {
+                let name = match &var.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("assignments to vector elements"),
+                };
+                let val = self.gen_expr(val)?;
+                let local = self.lookup(name)?;
+                let current = format!("(local.get {})", local);
+                let new_val = match op {
+                    Op::VarOp(VarToken::Assign) => val,
+                    Op::VarOp(token) => {
+                        let token = match token {
+                            VarToken::PlusEq => MathToken::Plus,
+                            VarToken::MinEq => MathToken::Minus,
+                            _ => MathToken::Multiply,
+                        };
+                        self.gen_binop(current, &Op::MathOp(token), val, &var.expr_type)?
+                    }
+                    op => return err_unsupported(&format!("´{}´ as an assignment", op)),
+                };
+                self.emit(format!("(local.set {} {})", local, new_val));
+            }
+            TypedExprKind::If(cond, block) => {
+                let cond = self.gen_expr(cond)?;
+                self.emit(format!("(if {}", cond));
+                self.indent += 1;
+                self.emit("(then".to_string());
+                self.gen_block(block)?;
+                self.emit(")".to_string());
+                self.indent -= 1;
+                self.emit(")".to_string());
+            }
+            // The loop is left by branching out of the block around it.
+            TypedExprKind::While(cond, block) => {
+                let label = self.loops;
+                self.loops += 1;
+                let cond = self.gen_expr(cond)?;
+                self.emit(format!("(block $exit{}", label));
+                self.indent += 1;
+                self.emit(format!("(loop $loop{}", label));
+                self.indent += 1;
+                self.emit(format!("(br_if $exit{} (i32.eqz {}))", label, cond));
+                self.indent -= 1;
+                self.gen_block(block)?;
+                self.indent += 1;
+                self.emit(format!("(br $loop{})", label));
+                self.indent -= 1;
+                self.emit(")".to_string());
+                self.indent -= 1;
+                self.emit(")".to_string());
+            }
+            TypedExprKind::FuncCall(name, _) if name == "print" || name == "println" => {
+                return err_unsupported("printing")
+            }
+            TypedExprKind::FuncCall(name, args) => {
+                let call = self.gen_call(name, args)?;
+                if stmt.expr_type == Type::Void {
+                    self.emit(call);
+                } else {
+                    self.emit(format!("(drop {})", call));
+                }
+            }
+            TypedExprKind::Return(val) if self.return_type == Type::Void => {
+                if let TypedExprKind::FuncCall(..) = val.kind {
+                    self.gen_stmt(val)?;
+                }
+                self.emit("(return)".to_string());
+            }
+            TypedExprKind::Return(val) => {
+                let val = self.gen_expr(val)?;
+                self.emit(format!("(return {})", val));
+            }
+            _ => {
+                let val = self.gen_expr(stmt)?;
+                self.emit(format!("(drop {})", val));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The parameters and result of the function, e.g. ´(param $a i32) (result i32)´.
+fn signature(func: &TypedFunction) -> BackendRes<String> {
+    let mut parts: Vec<String> = Vec::new();
+    for param in func.params.iter() {
+        parts.push(format!(
+            "(param ${} {})",
+            param.name,
+            wasm_type(&param.param_type)?
+        ));
+    }
+    if func.return_type != Type::Void {
+        parts.push(format!("(result {})", wasm_type(&func.return_type)?));
+    }
+    Ok(parts.join(" "))
+}
+
+// Translates the program to a WebAssembly module in the text format. The
+// module is checked by ´validate_wat´ before it is returned.
+pub fn wat_program(fn_list: Functions) -> BackendRes<String> {
+    let typed_fns = check_program(fn_list)?;
+    let fn_types: HashMap<String, Type> = typed_fns
+        .iter()
+        .map(|func| (func.name.clone(), func.return_type.clone()))
+        .collect();
+
+    let mut module = String::from("(module\n");
+    // Imports have to come before the functions of the module.
+    for func in typed_fns.iter().filter(|func| func.is_extern) {
+        module.push_str(&format!(
+            "  (import \"env\" \"{}\" (func ${} {}))\n",
+            func.name,
+            func.name,
+            signature(func)?
+        ));
+    }
+
+    let mut helpers: BTreeSet<(String, String)> = BTreeSet::new();
+    for func in typed_fns.iter().filter(|func| !func.is_extern) {
+        let mut gen = WasmGen {
+            lines: Vec::new(),
+            indent: 1,
+            locals: Vec::new(),
+            scopes: vec![HashMap::new()],
+            declared: HashMap::new(),
+            loops: 0,
+            return_type: func.return_type.clone(),
+            fn_types: &fn_types,
+            helpers: &mut helpers,
+        };
+        for param in func.params.iter() {
+            gen.declare(&param.name);
+        }
+        gen.gen_block(&func.block)?;
+        // Traps when a function with a return value does not return
+        if func.return_type != Type::Void {
+            gen.indent = 2;
+            gen.emit("(unreachable)".to_string());
+        }
+
+        module.push_str(&format!("  (func ${} {}\n", func.name, signature(func)?));
+        for (local, local_type) in gen.locals.iter() {
+            module.push_str(&format!("    (local {} {})\n", local, local_type));
+        }
+        for (indent, line) in gen.lines {
+            module.push_str(&"  ".repeat(indent));
+            module.push_str(&line);
+            module.push('\n');
+        }
+        module.push_str("  )\n");
+    }
+
+    for (op, suffix) in helpers.iter() {
+        module.push_str(&match op.as_str() {
+            "f64_to" => f64_to_int_func(suffix),
+            op if suffix.ends_with("64") => wide_op_func(op, suffix),
+            op => narrow_op_func(op, suffix),
+        });
+    }
+    for func in typed_fns.iter().filter(|func| !func.is_extern) {
+        module.push_str(&format!(
+            "  (export \"{}\" (func ${}))\n",
+            func.name, func.name
+        ));
+    }
+    module.push_str(")\n");
+
+    validate_wat(&module)?;
+    Ok(module)
+}
+
+// Translates the program and writes the module to the file.
+pub fn build_wat_program(fn_list: Functions, path: &Path) -> BackendRes<()> {
+    let module = wat_program(fn_list)?;
+    std::fs::write(path, module).map_err(|e| BackendError::Io(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod wasm_tests {
+    use super::*;
+    use crate::backend::backend_tests::*;
+    use crate::parser::*;
+
+    #[test]
+    fn wasm_fibo_program() {
+        let module = wat_program(parse_program(FIBO).unwrap().1).unwrap();
+        assert!(module.contains("  (func $fibo (param $i i32) (result i32)\n"));
+        assert!(module.contains(
+            "    (return (call $rt_add_i32 (call $fibo (call $rt_sub_i32 (local.get $i) (i32.const 1))) (call $fibo (call $rt_sub_i32 (local.get $i) (i32.const 2)))))\n"
+        ));
+        assert!(module.contains("  (export \"fibo\" (func $fibo))\n"));
+        assert!(module.contains("  (export \"main\" (func $main))\n"));
+    }
+
+    #[test]
+    fn wasm_while_program() {
+        let program = "
+            fn main() -> i64 {
+                let total: i64 = 0;
+                let i: u8 = 0;
+                while i < 10 {
+                    i += 1;
+                    let i: u8 = i * 2;
+                    total += i as i64;
+                };
+                return total;
+            }
+        ";
+        let module = wat_program(parse_program(program).unwrap().1).unwrap();
+        let expected = "  (func $main (result i64)
+    (local $total i64)
+    (local $i i32)
+    (local $i_1 i32)
+    (local.set $total (i64.const 0))
+    (local.set $i (i32.const 0))
+    (block $exit0
+      (loop $loop0
+        (br_if $exit0 (i32.eqz (i32.lt_u (local.get $i) (i32.const 10))))
+        (local.set $i (call $rt_add_u8 (local.get $i) (i32.const 1)))
+        (local.set $i_1 (call $rt_mul_u8 (local.get $i) (i32.const 2)))
+        (local.set $total (call $rt_add_i64 (local.get $total) (i64.extend_i32_u (local.get $i_1))))
+        (br $loop0)
+      )
+    )
+    (return (local.get $total))
+    (unreachable)
+  )
+";
+        assert!(module.contains(expected));
+    }
+
+    #[test]
+    fn wasm_extern_program() {
+        let program = "
+            extern \"C\" {
+                fn putchar(c: i32) -> i32;
+            }
+
+            fn main() -> () {
+                putchar(65);
+            }
+        ";
+        let module = wat_program(parse_program(program).unwrap().1).unwrap();
+        assert!(module.starts_with(
+            "(module\n  (import \"env\" \"putchar\" (func $putchar (param $c i32) (result i32)))\n"
+        ));
+        assert!(module.contains("    (drop (call $putchar (i32.const 65)))\n"));
+        assert!(!module.contains("(export \"putchar\""));
+    }
+
+    #[test]
+    fn wasm_unsupported_program() {
+        let program = "
+            fn main() -> i32 {
+                println(\"{}\", 1);
+                return 0;
+            }
+        ";
+        let tree = parse_program(program).unwrap().1;
+        assert_eq!(
+            wat_program(tree),
+            Err(BackendError::Unsupported("printing".to_string()))
+        );
+    }
+
+    #[test]
+    fn validate_wat_errors() {
+        let invalid = |module: &str| match validate_wat(module) {
+            Err(BackendError::Invalid(msg)) => msg,
+            res => panic!("{:?}", res),
+        };
+
+        assert!(
+            validate_wat("(module (func $f (param $a i32) (result i32) (local.get $a)))").is_ok()
+        );
+        assert_eq!(
+            invalid("(module (func $f (result i32) (local.get $a)))"),
+            "in function ´$f´: unknown local ´$a´"
+        );
+        assert_eq!(
+            invalid("(module (func $f (i32.const 1)))"),
+            "in function ´$f´: a value of type I32 is left on the stack"
+        );
+        assert_eq!(
+            invalid("(module (func $f (result i32) (i32.add (i32.const 1) (i64.const 2))))"),
+            "in function ´$f´: expected a value of type I32, found Val(I64)"
+        );
+        assert_eq!(
+            invalid("(module (func $f (block $a (br $b))))"),
+            "in function ´$f´: unknown label ´$b´"
+        );
+        assert_eq!(
+            invalid("(module (func $f) (import \"env\" \"g\" (func $g)))"),
+            "imports have to come before the functions"
+        );
+        assert_eq!(
+            invalid("(module (func $f) (export \"f\" (func $f)) (export \"f\" (func $f)))"),
+            "´f´ is exported twice"
+        );
+        assert_eq!(invalid("(module (func $f)"), "missing ´)´");
+    }
+}
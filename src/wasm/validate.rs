@@ -0,0 +1,506 @@
+// Structural validator for the modules generated by the WebAssembly
+// backend. It checks the subset of the text format that the backend uses:
+// a module of imports, functions and exports, where every instruction is
+// written in the folded form. Instructions are type checked like a
+// WebAssembly validator does, including the operands of calls, the labels of
+// branches and the values left at the end of blocks.
+use crate::backend::*;
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+enum SExpr {
+    Atom(String),
+    Str(String),
+    List(Vec<SExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+// What an instruction leaves on the stack. Instructions that never
+// continue, like ´return´ and ´br´, can be used where any value is expected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Res {
+    Empty,
+    Val(ValType),
+    Never,
+}
+
+// Parameters of a function with their names, if they have one
+type NamedParams = Vec<(Option<String>, ValType)>;
+
+struct FnSig {
+    params: Vec<ValType>,
+    result: Option<ValType>,
+}
+
+fn err_invalid<T>(msg: String) -> BackendRes<T> {
+    Err(BackendError::Invalid(msg))
+}
+
+fn parse_sexprs(source: &str) -> BackendRes<Vec<SExpr>> {
+    let mut stack: Vec<Vec<SExpr>> = vec![Vec::new()];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.push(SExpr::List(list)),
+                    None => return err_invalid("unexpected ´)´".to_string()),
+                }
+            }
+            ';' if chars.peek() == Some(&';') => {
+                for c in &mut chars {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => s.push(c),
+                            None => return err_invalid("unterminated string".to_string()),
+                        },
+                        Some(c) => s.push(c),
+                        None => return err_invalid("unterminated string".to_string()),
+                    }
+                }
+                stack.last_mut().unwrap().push(SExpr::Str(s));
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                }
+                stack.last_mut().unwrap().push(SExpr::Atom(atom));
+            }
+        }
+    }
+    if stack.len() != 1 {
+        return err_invalid("missing ´)´".to_string());
+    }
+    Ok(stack.pop().unwrap())
+}
+
+fn val_type(e: &SExpr) -> BackendRes<ValType> {
+    match e {
+        SExpr::Atom(t) if t == "i32" => Ok(ValType::I32),
+        SExpr::Atom(t) if t == "i64" => Ok(ValType::I64),
+        SExpr::Atom(t) if t == "f64" => Ok(ValType::F64),
+        e => err_invalid(format!("unknown value type {:?}", e)),
+    }
+}
+
+// Identifiers start with ´$´, e.g. ´$main´.
+fn id(e: Option<&SExpr>) -> BackendRes<String> {
+    match e {
+        Some(SExpr::Atom(id)) if id.starts_with('$') && id.len() > 1 => Ok(id.clone()),
+        e => err_invalid(format!("expected an identifier, found {:?}", e)),
+    }
+}
+
+// Splits a list into its keyword and the rest, e.g. ´(param $a i32)´.
+fn keyword(e: &SExpr) -> Option<(&str, &[SExpr])> {
+    match e {
+        SExpr::List(items) => match items.split_first() {
+            Some((SExpr::Atom(kw), rest)) => Some((kw.as_str(), rest)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Reads the ´(param ...)´ and ´(result ...)´ of a function type and returns
+// the named parameters and the remaining fields.
+fn fn_type(fields: &[SExpr]) -> BackendRes<(NamedParams, Option<ValType>, &[SExpr])> {
+    let mut params: NamedParams = Vec::new();
+    let mut result: Option<ValType> = None;
+    let mut rest = fields;
+    while let Some((first, tail)) = rest.split_first() {
+        match keyword(first) {
+            Some(("param", [name, t])) if result.is_none() => {
+                params.push((Some(id(Some(name))?), val_type(t)?))
+            }
+            Some(("param", types)) if result.is_none() => {
+                for t in types {
+                    params.push((None, val_type(t)?));
+                }
+            }
+            Some(("result", [t])) if result.is_none() => result = Some(val_type(t)?),
+            Some(("result", _)) => return err_invalid("expected a single result".to_string()),
+            _ => break,
+        }
+        rest = tail;
+    }
+    Ok((params, result, rest))
+}
+
+// The operand and result types of the numeric instructions.
+fn numeric_sig(op: &str) -> Option<(Vec<ValType>, ValType)> {
+    use ValType::*;
+    let conversion = match op {
+        "i32.wrap_i64" => Some((I64, I32)),
+        "i64.extend_i32_s" | "i64.extend_i32_u" => Some((I32, I64)),
+        "f64.convert_i32_s" | "f64.convert_i32_u" => Some((I32, F64)),
+        "f64.convert_i64_s" | "f64.convert_i64_u" => Some((I64, F64)),
+        "i32.trunc_f64_s" | "i32.trunc_f64_u" => Some((F64, I32)),
+        "i64.trunc_f64_s" | "i64.trunc_f64_u" => Some((F64, I64)),
+        _ => None,
+    };
+    if let Some((from, to)) = conversion {
+        return Some((vec![from], to));
+    }
+
+    let dot = op.find('.')?;
+    let (prefix, name) = (&op[..dot], &op[dot + 1..]);
+    let t = match prefix {
+        "i32" => I32,
+        "i64" => I64,
+        "f64" => F64,
+        _ => return None,
+    };
+    let int_binary = [
+        "add", "sub", "mul", "div_s", "div_u", "rem_s", "rem_u", "and", "or", "xor", "shl",
+        "shr_s", "shr_u",
+    ];
+    let int_compare = [
+        "eq", "ne", "lt_s", "lt_u", "gt_s", "gt_u", "le_s", "le_u", "ge_s", "ge_u",
+    ];
+    let float_binary = ["add", "sub", "mul", "div", "min", "max"];
+    let float_compare = ["eq", "ne", "lt", "gt", "le", "ge"];
+    match t {
+        F64 if float_binary.contains(&name) => Some((vec![t, t], t)),
+        F64 if float_compare.contains(&name) => Some((vec![t, t], I32)),
+        F64 => None,
+        _ if int_binary.contains(&name) => Some((vec![t, t], t)),
+        _ if int_compare.contains(&name) => Some((vec![t, t], I32)),
+        _ if name == "eqz" => Some((vec![t], I32)),
+        _ => None,
+    }
+}
+
+fn check_const(t: ValType, imm: &[SExpr]) -> BackendRes<()> {
+    let literal = match imm {
+        [SExpr::Atom(literal)] => literal.replace('_', ""),
+        _ => return err_invalid("expected a single constant".to_string()),
+    };
+    let in_range = match t {
+        ValType::I32 => match literal.parse::<i64>() {
+            Ok(n) => (-(1 << 31)..(1 << 32)).contains(&n),
+            Err(_) => false,
+        },
+        ValType::I64 => match literal.parse::<i128>() {
+            Ok(n) => (-(1 << 63)..(1 << 64)).contains(&n),
+            Err(_) => false,
+        },
+        ValType::F64 => {
+            let unsigned = literal.trim_start_matches(&['-', '+'][..]);
+            unsigned == "inf" || unsigned == "nan" || literal.parse::<f64>().is_ok()
+        }
+    };
+    if in_range {
+        Ok(())
+    } else {
+        err_invalid(format!("invalid constant ´{}´", literal))
+    }
+}
+
+struct FnChecker<'a> {
+    funcs: &'a HashMap<String, FnSig>,
+    locals: HashMap<String, ValType>,
+    result: Option<ValType>,
+    // Labels of the blocks around the instruction, None for ´if´
+    labels: Vec<Option<String>>,
+}
+
+impl<'a> FnChecker<'a> {
+    fn operand(&mut self, e: &SExpr, expected: ValType) -> BackendRes<()> {
+        match self.instr(e)? {
+            Res::Val(t) if t == expected => Ok(()),
+            Res::Never => Ok(()),
+            res => err_invalid(format!(
+                "expected a value of type {:?}, found {:?}",
+                expected, res
+            )),
+        }
+    }
+
+    fn operands(&mut self, args: &[SExpr], types: &[ValType]) -> BackendRes<()> {
+        if args.len() != types.len() {
+            return err_invalid(format!(
+                "expected {} operand(s), found {}",
+                types.len(),
+                args.len()
+            ));
+        }
+        for (arg, t) in args.iter().zip(types) {
+            self.operand(arg, *t)?;
+        }
+        Ok(())
+    }
+
+    fn local(&self, e: Option<&SExpr>) -> BackendRes<(String, ValType)> {
+        let name = id(e)?;
+        match self.locals.get(&name) {
+            Some(t) => Ok((name, *t)),
+            None => err_invalid(format!("unknown local ´{}´", name)),
+        }
+    }
+
+    fn label(&self, e: Option<&SExpr>) -> BackendRes<()> {
+        let name = id(e)?;
+        if self.labels.contains(&Some(name.clone())) {
+            Ok(())
+        } else {
+            err_invalid(format!("unknown label ´{}´", name))
+        }
+    }
+
+    // Checks the instructions of a block. Only the last instruction may
+    // leave a value, which has to be the result of the block.
+    fn seq(&mut self, instrs: &[SExpr], expected: Option<ValType>) -> BackendRes<()> {
+        let mut diverges = false;
+        let mut last = Res::Empty;
+        for (i, instr) in instrs.iter().enumerate() {
+            last = self.instr(instr)?;
+            match last {
+                Res::Never => diverges = true,
+                Res::Val(t) if i + 1 < instrs.len() || expected != Some(t) => {
+                    return err_invalid(format!("a value of type {:?} is left on the stack", t))
+                }
+                _ => (),
+            }
+        }
+        match (expected, last) {
+            (Some(t), Res::Empty) if !diverges => err_invalid(format!(
+                "expected a value of type {:?} at the end of the block",
+                t
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Blocks, loops and ifs may be labeled, e.g. ´(block $exit ...)´.
+    fn block_label(rest: &[SExpr]) -> (Option<String>, &[SExpr]) {
+        match rest.split_first() {
+            Some((SExpr::Atom(label), tail)) if label.starts_with('$') => {
+                (Some(label.clone()), tail)
+            }
+            _ => (None, rest),
+        }
+    }
+
+    fn instr(&mut self, e: &SExpr) -> BackendRes<Res> {
+        let (op, rest) = match keyword(e) {
+            Some(kw) => kw,
+            None => return err_invalid(format!("expected a folded instruction, found {:?}", e)),
+        };
+        match op {
+            "i32.const" => check_const(ValType::I32, rest).map(|_| Res::Val(ValType::I32)),
+            "i64.const" => check_const(ValType::I64, rest).map(|_| Res::Val(ValType::I64)),
+            "f64.const" => check_const(ValType::F64, rest).map(|_| Res::Val(ValType::F64)),
+            "local.get" if rest.len() == 1 => Ok(Res::Val(self.local(rest.first())?.1)),
+            "local.set" if rest.len() == 2 => {
+                let (_, t) = self.local(rest.first())?;
+                self.operand(&rest[1], t)?;
+                Ok(Res::Empty)
+            }
+            "call" => {
+                let name = id(rest.first())?;
+                let sig = match self.funcs.get(&name) {
+                    Some(sig) => sig,
+                    None => return err_invalid(format!("unknown function ´{}´", name)),
+                };
+                let params = sig.params.clone();
+                let result = sig.result;
+                self.operands(&rest[1..], &params)?;
+                Ok(result.map_or(Res::Empty, Res::Val))
+            }
+            "drop" if rest.len() == 1 => match self.instr(&rest[0])? {
+                Res::Empty => err_invalid("´drop´ without a value".to_string()),
+                _ => Ok(Res::Empty),
+            },
+            "return" => {
+                match (self.result, rest) {
+                    (None, []) => (),
+                    (Some(t), [val]) => self.operand(val, t)?,
+                    _ => return err_invalid("´return´ does not match the result".to_string()),
+                }
+                Ok(Res::Never)
+            }
+            "unreachable" if rest.is_empty() => Ok(Res::Never),
+            "br" if rest.len() == 1 => {
+                self.label(rest.first())?;
+                Ok(Res::Never)
+            }
+            "br_if" if rest.len() == 2 => {
+                self.label(rest.first())?;
+                self.operand(&rest[1], ValType::I32)?;
+                Ok(Res::Empty)
+            }
+            "block" | "loop" => {
+                let (label, body) = Self::block_label(rest);
+                self.labels.push(label);
+                self.seq(body, None)?;
+                self.labels.pop();
+                Ok(Res::Empty)
+            }
+            "if" => self.if_instr(rest),
+            op => match numeric_sig(op) {
+                Some((params, result)) => {
+                    self.operands(rest, &params)?;
+                    Ok(Res::Val(result))
+                }
+                None => err_invalid(format!("unknown instruction ´{}´", op)),
+            },
+        }
+    }
+
+    // ´(if (result t)? cond (then ...) (else ...)?)´. The else branch is
+    // required when the if has a result.
+    fn if_instr(&mut self, rest: &[SExpr]) -> BackendRes<Res> {
+        let (label, rest) = Self::block_label(rest);
+        let (params, result, rest) = fn_type(rest)?;
+        if !params.is_empty() {
+            return err_invalid("´if´ with parameters".to_string());
+        }
+        let (cond, branches) = match rest.split_first() {
+            Some(split) => split,
+            None => return err_invalid("´if´ without a condition".to_string()),
+        };
+        self.operand(cond, ValType::I32)?;
+
+        self.labels.push(label);
+        let res = match branches {
+            [then] => match keyword(then) {
+                Some(("then", body)) if result.is_none() => self.seq(body, None),
+                _ => err_invalid("expected ´(then ...)´ and ´(else ...)´".to_string()),
+            },
+            [then, els] => match (keyword(then), keyword(els)) {
+                (Some(("then", then_body)), Some(("else", else_body))) => self
+                    .seq(then_body, result)
+                    .and_then(|_| self.seq(else_body, result)),
+                _ => err_invalid("expected ´(then ...)´ and ´(else ...)´".to_string()),
+            },
+            _ => err_invalid("expected ´(then ...)´ and ´(else ...)´".to_string()),
+        };
+        self.labels.pop();
+        res?;
+        Ok(result.map_or(Res::Empty, Res::Val))
+    }
+}
+
+// Validates a module in the text format. Returns ´BackendError::Invalid´
+// with the reason if the module is malformed or does not type check.
+pub fn validate_wat(source: &str) -> BackendRes<()> {
+    let module = match parse_sexprs(source)?.as_slice() {
+        [module] => match keyword(module) {
+            Some(("module", fields)) => fields.to_vec(),
+            _ => return err_invalid("expected ´(module ...)´".to_string()),
+        },
+        _ => return err_invalid("expected a single module".to_string()),
+    };
+
+    // Functions can be called before they are defined, so the signatures
+    // are collected first.
+    let mut funcs: HashMap<String, FnSig> = HashMap::new();
+    let mut seen_func = false;
+    for field in module.iter() {
+        let (name, sig_fields) = match keyword(field) {
+            Some(("import", [SExpr::Str(_), SExpr::Str(_), desc])) => {
+                if seen_func {
+                    return err_invalid("imports have to come before the functions".to_string());
+                }
+                match keyword(desc) {
+                    Some(("func", rest)) => (id(rest.first())?, &rest[1..]),
+                    _ => return err_invalid("only functions can be imported".to_string()),
+                }
+            }
+            Some(("func", rest)) => {
+                seen_func = true;
+                (id(rest.first())?, &rest[1..])
+            }
+            Some(("export", _)) => continue,
+            _ => return err_invalid(format!("unknown module field {:?}", field)),
+        };
+        let (params, result, _) = fn_type(sig_fields)?;
+        let sig = FnSig {
+            params: params.into_iter().map(|(_, t)| t).collect(),
+            result,
+        };
+        if funcs.insert(name.clone(), sig).is_some() {
+            return err_invalid(format!("function ´{}´ is defined twice", name));
+        }
+    }
+
+    let mut exports: HashSet<String> = HashSet::new();
+    for field in module.iter() {
+        match keyword(field) {
+            Some(("func", rest)) => {
+                let name = id(rest.first())?;
+                if let Err(BackendError::Invalid(msg)) = check_func(&funcs, &rest[1..]) {
+                    return err_invalid(format!("in function ´{}´: {}", name, msg));
+                }
+            }
+            Some(("export", [SExpr::Str(export), desc])) => {
+                match keyword(desc) {
+                    Some(("func", [target])) if funcs.contains_key(&id(Some(target))?) => (),
+                    _ => return err_invalid(format!("export ´{}´ of an unknown function", export)),
+                }
+                if !exports.insert(export.clone()) {
+                    return err_invalid(format!("´{}´ is exported twice", export));
+                }
+            }
+            Some(("export", _)) => return err_invalid("malformed export".to_string()),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn check_func(funcs: &HashMap<String, FnSig>, fields: &[SExpr]) -> BackendRes<()> {
+    let (params, result, mut rest) = fn_type(fields)?;
+    let mut locals: HashMap<String, ValType> = HashMap::new();
+    let mut declare = |name: String, t: ValType| {
+        if locals.insert(name.clone(), t).is_some() {
+            return err_invalid(format!("local ´{}´ is declared twice", name));
+        }
+        Ok(())
+    };
+    for (name, t) in params {
+        if let Some(name) = name {
+            declare(name, t)?;
+        }
+    }
+    while let Some((first, tail)) = rest.split_first() {
+        match keyword(first) {
+            Some(("local", [name, t])) => declare(id(Some(name))?, val_type(t)?)?,
+            _ => break,
+        }
+        rest = tail;
+    }
+
+    let mut checker = FnChecker {
+        funcs,
+        locals,
+        result,
+        labels: Vec::new(),
+    };
+    checker.seq(rest, result)
+}
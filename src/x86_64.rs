@@ -0,0 +1,1266 @@
+use crate::ast::*;
+use crate::backend::*;
+
+mod regalloc;
+
+use regalloc::{allocate, Loc};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+// Translates programs to x86-64 assembly in the GNU syntax, for Linux and the
+// System V ABI. Functions are first lowered to a list of instructions on
+// virtual registers, which are given registers or stack slots by the linear
+// scan allocator in regalloc.rs. Each instruction is then translated on its
+// own: the operands are loaded into the scratch registers %rax and %rcx, the
+// result is computed there and stored in the location of the destination.
+//
+// Every value is kept in a 64 bit register. Integers narrower than 64 bits
+// are sign or zero extended, bools are 0 or 1 and f64 values are kept as
+// their bits and moved to %xmm registers when used. Arithmetic is checked
+// and calls ´rt_panic´, which prints the message to stderr and exits with
+// code 101 like a panicking Rust program. Printing calls ´printf´, so the
+// program is linked with libc.
+//
+// Functions of the program are prefixed with ´f_´ and called by a C ´main´,
+// which returns the value of an i32 ´main´ as the exit code.
+
+type VReg = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Reg(VReg),
+    Imm(i64),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(v) => write!(f, "v{}", v),
+            Operand::Imm(imm) => write!(f, "{}", imm),
+        }
+    }
+}
+
+// The width in bits and signedness of integer values. Bools and chars are
+// unsigned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IntKind {
+    width: u32,
+    signed: bool,
+}
+
+impl IntKind {
+    fn of(t: &Type) -> BackendRes<IntKind> {
+        match t {
+            Type::Bool => Ok(IntKind {
+                width: 8,
+                signed: false,
+            }),
+            Type::Char => Ok(IntKind {
+                width: 32,
+                signed: false,
+            }),
+            t => match t.bit_width() {
+                Some(width) => Ok(IntKind {
+                    width,
+                    signed: t.is_signed(),
+                }),
+                None => err_unsupported(&format!(
+                    "integer operations on values of type ´{}´",
+                    String::from(t.clone())
+                )),
+            },
+        }
+    }
+
+    fn range(self) -> (i128, i128) {
+        if self.signed {
+            (-(1 << (self.width - 1)), (1 << (self.width - 1)) - 1)
+        } else {
+            (0, (1 << self.width) - 1)
+        }
+    }
+}
+
+impl fmt::Display for IntKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.width)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conversion {
+    // Sign or zero extends the low bits to the integer type
+    Int(IntKind),
+    IntToF64(IntKind),
+    // Saturates to the range of the integer type, NaN becomes 0
+    F64ToInt(IntKind),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PrintArg {
+    Int(bool), // signed
+    Str,
+    Bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Mov(VReg, Operand),
+    // Parameter number n of the function, counted separately for integer and
+    // float parameters, and whether it is a float
+    Param(VReg, usize, bool),
+    // Address of a string in the data section
+    LoadStr(VReg, usize),
+    Arith(VReg, MathToken, Operand, Operand, IntKind),
+    FArith(VReg, MathToken, Operand, Operand),
+    Bitwise(VReg, BoolToken, Operand, Operand),
+    // Comparison of integers of the kind, or of floats for None
+    Compare(VReg, RelToken, Operand, Operand, Option<IntKind>),
+    Convert(VReg, Conversion, Operand),
+    // Result, symbol, arguments and whether they are floats, whether the
+    // result is a float, and the kind of integer results of C functions,
+    // whose upper bits are undefined
+    Call(
+        Option<VReg>,
+        String,
+        Vec<(Operand, bool)>,
+        bool,
+        Option<IntKind>,
+    ),
+    // printf with the format string and at most one argument
+    Print(usize, Option<(Operand, PrintArg)>),
+    PrintChar(Operand),
+    Label(usize),
+    Jump(usize),
+    JumpIfZero(Operand, usize),
+    // Return value and whether it is a float
+    Ret(Option<(Operand, bool)>),
+    Panic(usize),
+}
+
+impl Inst {
+    // The virtual registers read by the instruction.
+    fn uses(&self) -> Vec<VReg> {
+        let ops: Vec<Operand> = match self {
+            Inst::Mov(_, a) | Inst::Convert(_, _, a) | Inst::PrintChar(a) => vec![*a],
+            Inst::Arith(_, _, a, b, _)
+            | Inst::FArith(_, _, a, b)
+            | Inst::Bitwise(_, _, a, b)
+            | Inst::Compare(_, _, a, b, _) => vec![*a, *b],
+            Inst::Call(_, _, args, _, _) => args.iter().map(|(arg, _)| *arg).collect(),
+            Inst::Print(_, Some((a, _))) | Inst::JumpIfZero(a, _) | Inst::Ret(Some((a, _))) => {
+                vec![*a]
+            }
+            _ => vec![],
+        };
+        ops.into_iter()
+            .filter_map(|op| match op {
+                Operand::Reg(v) => Some(v),
+                Operand::Imm(_) => None,
+            })
+            .collect()
+    }
+
+    // The virtual register written by the instruction.
+    fn def(&self) -> Option<VReg> {
+        match self {
+            Inst::Mov(v, _)
+            | Inst::Param(v, _, _)
+            | Inst::LoadStr(v, _)
+            | Inst::Arith(v, ..)
+            | Inst::FArith(v, ..)
+            | Inst::Bitwise(v, ..)
+            | Inst::Compare(v, ..)
+            | Inst::Convert(v, ..) => Some(*v),
+            Inst::Call(v, ..) => *v,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inst::Mov(v, a) => write!(f, "v{} = {}", v, a),
+            Inst::Param(v, n, float) => {
+                write!(f, "v{} = {}param {}", v, if *float { "f" } else { "" }, n)
+            }
+            Inst::LoadStr(v, s) => write!(f, "v{} = str {}", v, s),
+            Inst::Arith(v, op, a, b, kind) => write!(f, "v{} = {}.{} {} {}", v, kind, op, a, b),
+            Inst::FArith(v, op, a, b) => write!(f, "v{} = f64.{} {} {}", v, op, a, b),
+            Inst::Bitwise(v, op, a, b) => write!(f, "v{} = {} {} {}", v, a, op, b),
+            Inst::Compare(v, op, a, b, _) => write!(f, "v{} = {} {} {}", v, a, op, b),
+            Inst::Convert(v, conv, a) => write!(f, "v{} = {:?} {}", v, conv, a),
+            Inst::Call(v, name, args, _, _) => {
+                if let Some(v) = v {
+                    write!(f, "v{} = ", v)?;
+                }
+                let args: Vec<String> = args.iter().map(|(arg, _)| arg.to_string()).collect();
+                write!(f, "call {}({})", name, args.join(", "))
+            }
+            Inst::Print(fmt, Some((a, _))) => write!(f, "print str {}, {}", fmt, a),
+            Inst::Print(fmt, None) => write!(f, "print str {}", fmt),
+            Inst::PrintChar(a) => write!(f, "print char {}", a),
+            Inst::Label(l) => write!(f, "label {}", l),
+            Inst::Jump(l) => write!(f, "jump {}", l),
+            Inst::JumpIfZero(a, l) => write!(f, "jump {} if {} == 0", l, a),
+            Inst::Ret(Some((a, _))) => write!(f, "return {}", a),
+            Inst::Ret(None) => write!(f, "return"),
+            Inst::Panic(msg) => write!(f, "panic str {}", msg),
+        }
+    }
+}
+
+// String literals, format strings and panic messages of the program
+#[derive(Default)]
+struct Strings {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl Strings {
+    fn get(&mut self, s: &str) -> usize {
+        if let Some(i) = self.indices.get(s) {
+            return *i;
+        }
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+}
+
+struct FnSig {
+    return_type: Type,
+    is_extern: bool,
+}
+
+struct Lowering<'a> {
+    code: Vec<Inst>,
+    vregs: usize,
+    labels: usize,
+    // Virtual registers of the variables in the blocks around the statement
+    scopes: Vec<HashMap<String, VReg>>,
+    strings: &'a mut Strings,
+    sigs: &'a HashMap<String, FnSig>,
+}
+
+impl<'a> Lowering<'a> {
+    fn vreg(&mut self) -> VReg {
+        self.vregs += 1;
+        self.vregs - 1
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn lookup(&self, name: &str) -> BackendRes<VReg> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| BackendError::UnknownVariable(name.to_string()))
+    }
+
+    fn gen_expr(&mut self, expr: &TypedExpr) -> BackendRes<Operand> {
+        check_value(expr)?;
+        match &expr.kind {
+            TypedExprKind::Num(num) if expr.expr_type == Type::Float64 => {
+                Ok(Operand::Imm((*num as f64).to_bits() as i64))
+            }
+            TypedExprKind::Num(num) => Ok(Operand::Imm(*num as i64)),
+            TypedExprKind::Float(f) => Ok(Operand::Imm(f.to_bits() as i64)),
+            TypedExprKind::Bool(b) => Ok(Operand::Imm(*b as i64)),
+            TypedExprKind::Char(c) => Ok(Operand::Imm(*c as i64)),
+            TypedExprKind::Str(s) => {
+                let v = self.vreg();
+                let s = self.strings.get(s);
+                self.code.push(Inst::LoadStr(v, s));
+                Ok(Operand::Reg(v))
+            }
+            TypedExprKind::Var(name) => Ok(Operand::Reg(self.lookup(name)?)),
+            TypedExprKind::BinOp(l, op, r) => {
+                let operand_type = l.expr_type.clone();
+                let l = self.gen_expr(l)?;
+                let r = self.gen_expr(r)?;
+                self.gen_binop(l, op, r, &operand_type)
+            }
+            TypedExprKind::Cast(inner) => {
+                let src_type = inner.expr_type.clone();
+                let val = self.gen_expr(inner)?;
+                let conv = match (&src_type, &expr.expr_type) {
+                    (Type::Float64, Type::Float64) => return Ok(val),
+                    (Type::Float64, t) => Conversion::F64ToInt(IntKind::of(t)?),
+                    (t, Type::Float64) => Conversion::IntToF64(IntKind::of(t)?),
+                    (_, t) => Conversion::Int(IntKind::of(t)?),
+                };
+                let v = self.vreg();
+                self.code.push(Inst::Convert(v, conv, val));
+                Ok(Operand::Reg(v))
+            }
+            TypedExprKind::FuncCall(name, _) if name == "print" || name == "println" => {
+                err_unsupported("´print´ as a value")
+            }
+            TypedExprKind::FuncCall(name, args) => {
+                let v = self.vreg();
+                self.gen_call(Some(v), name, args)?;
+                Ok(Operand::Reg(v))
+            }
+            _ => err_statement_value(),
+        }
+    }
+
+    fn gen_binop(&mut self, l: Operand, op: &Op, r: Operand, t: &Type) -> BackendRes<Operand> {
+        let v = self.vreg();
+        let inst = match op {
+            Op::MathOp(token) if *t == Type::Float64 => Inst::FArith(v, *token, l, r),
+            Op::MathOp(token) => Inst::Arith(v, *token, l, r, IntKind::of(t)?),
+            Op::RelOp(token) if *t == Type::Float64 => Inst::Compare(v, *token, l, r, None),
+            Op::RelOp(token) => Inst::Compare(v, *token, l, r, Some(IntKind::of(t)?)),
+            // ´andq´ and ´orq´ of the two booleans
+            Op::BoolOp(token) => Inst::Bitwise(v, *token, l, r),
+            op => return err_unsupported(&format!("´{}´ as a binary operator", op)),
+        };
+        self.code.push(inst);
+        Ok(Operand::Reg(v))
+    }
+
+    fn gen_call(&mut self, result: Option<VReg>, name: &str, args: &[TypedExpr]) -> BackendRes<()> {
+        let sig = match self.sigs.get(name) {
+            Some(sig) => sig,
+            None => return Err(BackendError::UnknownFunction(name.to_string())),
+        };
+        let is_extern = sig.is_extern;
+        let returns_float = sig.return_type == Type::Float64;
+        let normalize = match &sig.return_type {
+            t if is_extern && (t.is_int() || *t == Type::Bool || *t == Type::Char) => {
+                Some(IntKind::of(t)?)
+            }
+            _ => None,
+        };
+
+        let mut arg_vals: Vec<(Operand, bool)> = Vec::new();
+        for arg in args {
+            let is_float = arg.expr_type == Type::Float64;
+            arg_vals.push((self.gen_expr(arg)?, is_float));
+        }
+        let symbol = if is_extern {
+            name.to_string()
+        } else {
+            format!("f_{}", name)
+        };
+        self.code.push(Inst::Call(
+            result,
+            symbol,
+            arg_vals,
+            returns_float,
+            normalize,
+        ));
+        Ok(())
+    }
+
+    // Every argument is evaluated before anything is printed. The text
+    // before each argument is printed together with it.
+    fn gen_print(&mut self, name: &str, args: &[TypedExpr]) -> BackendRes<()> {
+        let mut args = args.iter();
+        let fmt = match args.next().map(|arg| &arg.kind) {
+            Some(TypedExprKind::Str(fmt)) => fmt.replace('%', "%%"),
+            None => String::new(),
+            _ => return err_unsupported("a format string that is not a literal"),
+        };
+
+        let mut vals: Vec<(Operand, Type)> = Vec::new();
+        for arg in args {
+            vals.push((self.gen_expr(arg)?, arg.expr_type.clone()));
+        }
+
+        let mut pieces = fmt.split("{}");
+        let mut text = pieces.next().unwrap_or("").to_string();
+        for ((val, t), piece) in vals.into_iter().zip(pieces) {
+            let (spec, arg) = match t {
+                Type::Str => ("%s", PrintArg::Str),
+                Type::Bool => ("%s", PrintArg::Bool),
+                Type::Char => {
+                    if !text.is_empty() {
+                        let fmt = self.strings.get(&text);
+                        self.code.push(Inst::Print(fmt, None));
+                    }
+                    self.code.push(Inst::PrintChar(val));
+                    text = piece.to_string();
+                    continue;
+                }
+                t if t.is_int() && t.is_signed() => ("%ld", PrintArg::Int(true)),
+                t if t.is_int() => ("%lu", PrintArg::Int(false)),
+                t => {
+                    return err_unsupported(&format!(
+                        "printing values of type ´{}´",
+                        String::from(t)
+                    ))
+                }
+            };
+            let fmt = self.strings.get(&format!("{}{}", text, spec));
+            self.code.push(Inst::Print(fmt, Some((val, arg))));
+            text = piece.to_string();
+        }
+        if name == "println" {
+            text.push('\n');
+        }
+        if !text.is_empty() {
+            let fmt = self.strings.get(&text);
+            self.code.push(Inst::Print(fmt, None));
+        }
+        Ok(())
+    }
+
+    fn gen_block(&mut self, block: &[TypedExpr]) -> BackendRes<()> {
+        self.scopes.push(HashMap::new());
+        for stmt in block {
+            self.gen_stmt(stmt)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn gen_stmt(&mut self, stmt: &TypedExpr) -> BackendRes<()> {
+        match &stmt.kind {
+            TypedExprKind::Let(pattern, val) => {
+                let name = match &pattern.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("tuple patterns"),
+                };
+                let val = self.gen_expr(val)?;
+                let v = self.vreg();
+                self.code.push(Inst::Mov(v, val));
+                self.scopes.last_mut().unwrap().insert(name.to_string(), v);
+            }
+            TypedExprKind::VarOp(var, op, val) => {
+                let name = match &var.kind {
+                    TypedExprKind::Var(name) => name,
+                    _ => return err_unsupported("assignments to vector elements"),
+                };
+                let val = self.gen_expr(val)?;
+                let v = self.lookup(name)?;
+                let new_val = match op {
+                    Op::VarOp(VarToken::Assign) => val,
+                    Op::VarOp(token) => {
+                        let token = match token {
+                            VarToken::PlusEq => MathToken::Plus,
+                            VarToken::MinEq => MathToken::Minus,
+                            _ => MathToken::Multiply,
+                        };
+                        self.gen_binop(Operand::Reg(v), &Op::MathOp(token), val, &var.expr_type)?
+                    }
+                    op => return err_unsupported(&format!("´{}´ as an assignment", op)),
+                };
+                self.code.push(Inst::Mov(v, new_val));
+            }
+            TypedExprKind::If(cond, block) => {
+                let end = self.label();
+                let cond = self.gen_expr(cond)?;
+                self.code.push(Inst::JumpIfZero(cond, end));
+                self.gen_block(block)?;
+                self.code.push(Inst::Label(end));
+            }
+            TypedExprKind::While(cond, block) => {
+                let start = self.label();
+                let end = self.label();
+                self.code.push(Inst::Label(start));
+                let cond = self.gen_expr(cond)?;
+                self.code.push(Inst::JumpIfZero(cond, end));
+                self.gen_block(block)?;
+                self.code.push(Inst::Jump(start));
+                self.code.push(Inst::Label(end));
+            }
+            TypedExprKind::FuncCall(name, args) if name == "print" || name == "println" => {
+                self.gen_print(name, args)?
+            }
+            TypedExprKind::FuncCall(name, args) => self.gen_call(None, name, args)?,
+            TypedExprKind::Return(val) if val.expr_type == Type::Void => {
+                self.gen_stmt(val)?;
+                self.code.push(Inst::Ret(None));
+            }
+            TypedExprKind::Return(val) => {
+                let is_float = val.expr_type == Type::Float64;
+                let val = self.gen_expr(val)?;
+                self.code.push(Inst::Ret(Some((val, is_float))));
+            }
+            _ => {
+                self.gen_expr(stmt)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Lowers the function to instructions on virtual registers.
+fn lower_function(
+    func: &TypedFunction,
+    sigs: &HashMap<String, FnSig>,
+    strings: &mut Strings,
+) -> BackendRes<Vec<Inst>> {
+    let mut lowering = Lowering {
+        code: Vec::new(),
+        vregs: 0,
+        labels: 0,
+        scopes: vec![HashMap::new()],
+        strings,
+        sigs,
+    };
+    let (mut ints, mut floats) = (0, 0);
+    for param in func.params.iter() {
+        let v = lowering.vreg();
+        let is_float = param.param_type == Type::Float64;
+        let n = if is_float { &mut floats } else { &mut ints };
+        lowering.code.push(Inst::Param(v, *n, is_float));
+        *n += 1;
+        lowering.scopes[0].insert(param.name.clone(), v);
+    }
+    lowering.gen_block(&func.block)?;
+    if func.return_type == Type::Void {
+        lowering.code.push(Inst::Ret(None));
+    } else {
+        // Reached when a function with a return value does not return
+        let msg = format!("function {} did not return a value", func.name);
+        let msg = lowering.strings.get(&msg);
+        lowering.code.push(Inst::Panic(msg));
+    }
+    Ok(lowering.code)
+}
+
+const INT_ARG_REGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+const FLOAT_ARG_REGS: usize = 8;
+
+// Loads an operand into the register.
+fn load(out: &mut Vec<String>, locs: &HashMap<VReg, Loc>, op: Operand, reg: &str) {
+    out.push(match op {
+        Operand::Imm(imm) if imm == imm as i32 as i64 => format!("movq ${}, {}", imm, reg),
+        Operand::Imm(imm) => format!("movabsq ${}, {}", imm, reg),
+        Operand::Reg(v) => format!("movq {}, {}", locs[&v], reg),
+    });
+}
+
+fn store(out: &mut Vec<String>, locs: &HashMap<VReg, Loc>, reg: &str, v: VReg) {
+    out.push(format!("movq {}, {}", reg, locs[&v]));
+}
+
+// Panics with the message unless the condition code holds, e.g. ´no´ for
+// no overflow.
+fn check(out: &mut Vec<String>, cond: &str, msg: &str) {
+    out.push(format!("j{} 1f", cond));
+    out.push(format!("leaq {}(%rip), %rdi", msg));
+    out.push("call rt_panic".to_string());
+    out.push("1:".to_string());
+}
+
+// The instruction that sign or zero extends the low bits of %rax into dst.
+fn extend_rax(kind: IntKind, dst: &str) -> String {
+    let (src, suffix) = match kind.width {
+        8 => ("%al", "b"),
+        16 => ("%ax", "w"),
+        _ => ("%eax", "l"),
+    };
+    match (kind.signed, kind.width) {
+        (true, _) => format!("movs{}q {}, {}", suffix, src, dst),
+        (false, 32) => format!("movl %eax, {}", dst.replace("%r", "%e")),
+        (false, _) => format!("movz{}l {}, {}", suffix, src, dst.replace("%r", "%e")),
+    }
+}
+
+fn str_label(s: usize) -> String {
+    format!(".Lstr{}", s)
+}
+
+struct Emitter<'a> {
+    out: Vec<String>,
+    locs: &'a HashMap<VReg, Loc>,
+    // Prefix of the labels of the function
+    prefix: String,
+}
+
+impl<'a> Emitter<'a> {
+    fn emit_call(&mut self, symbol: &str, args: &[(Operand, bool)]) {
+        let mut ints: Vec<Operand> = Vec::new();
+        let mut floats: Vec<Operand> = Vec::new();
+        let mut stack: Vec<Operand> = Vec::new();
+        for (arg, is_float) in args {
+            if *is_float && floats.len() < FLOAT_ARG_REGS {
+                floats.push(*arg);
+            } else if !*is_float && ints.len() < INT_ARG_REGS.len() {
+                ints.push(*arg);
+            } else {
+                stack.push(*arg);
+            }
+        }
+        // The stack has to stay aligned to 16 bytes at the call.
+        let padding = stack.len() % 2;
+        if padding == 1 {
+            self.out.push("subq $8, %rsp".to_string());
+        }
+        for arg in stack.iter().rev() {
+            load(&mut self.out, self.locs, *arg, "%rax");
+            self.out.push("pushq %rax".to_string());
+        }
+        for (i, arg) in floats.iter().enumerate() {
+            load(&mut self.out, self.locs, *arg, "%rax");
+            self.out.push(format!("movq %rax, %xmm{}", i));
+        }
+        for (arg, reg) in ints.iter().zip(INT_ARG_REGS.iter()) {
+            load(&mut self.out, self.locs, *arg, reg);
+        }
+        // Variadic functions read the number of float arguments from %al.
+        self.out.push(format!("movl ${}, %eax", floats.len()));
+        if symbol.starts_with("f_") || symbol.starts_with("rt_") {
+            self.out.push(format!("call {}", symbol));
+        } else {
+            self.out.push(format!("call {}@PLT", symbol));
+        }
+        if !stack.is_empty() {
+            self.out
+                .push(format!("addq ${}, %rsp", 8 * (stack.len() + padding)));
+        }
+    }
+
+    fn emit_arith(&mut self, v: VReg, op: MathToken, a: Operand, b: Operand, kind: IntKind) {
+        load(&mut self.out, self.locs, a, "%rax");
+        load(&mut self.out, self.locs, b, "%rcx");
+        let (instr, msg) = match op {
+            MathToken::Plus => ("addq", "add"),
+            MathToken::Minus => ("subq", "sub"),
+            MathToken::Multiply => ("imulq", "mul"),
+            MathToken::Division => ("idivq", "div"),
+        };
+        let msg = format!(".Lmsg_{}", msg);
+
+        if op == MathToken::Division {
+            self.out.push("testq %rcx, %rcx".to_string());
+            check(&mut self.out, "nz", ".Lmsg_zero");
+            if kind.signed && kind.width == 64 {
+                // MIN / -1 does not fit and makes idiv fault
+                self.out.push("cmpq $-1, %rcx".to_string());
+                self.out.push("jne 2f".to_string());
+                self.out
+                    .push("movabsq $-9223372036854775808, %rdx".to_string());
+                self.out.push("cmpq %rdx, %rax".to_string());
+                check(&mut self.out, "ne", &msg);
+                self.out.push("2:".to_string());
+            }
+            if kind.signed {
+                self.out.push("cqto".to_string());
+                self.out.push("idivq %rcx".to_string());
+            } else {
+                self.out.push("xorl %edx, %edx".to_string());
+                self.out.push("divq %rcx".to_string());
+            }
+        } else if kind.width == 64 && !kind.signed && op == MathToken::Multiply {
+            self.out.push("mulq %rcx".to_string());
+            check(&mut self.out, "no", &msg);
+        } else {
+            self.out.push(format!("{} %rcx, %rax", instr));
+            if kind.width == 64 {
+                let cond = if kind.signed { "no" } else { "nc" };
+                check(&mut self.out, cond, &msg);
+            }
+        }
+
+        // Narrower integers cannot overflow in 64 bits. The result is in range
+        // if extending its low bits gives the same value.
+        if kind.width < 64 {
+            self.out.push(extend_rax(kind, "%rcx"));
+            self.out.push("cmpq %rcx, %rax".to_string());
+            check(&mut self.out, "e", &msg);
+        }
+        store(&mut self.out, self.locs, "%rax", v);
+    }
+
+    fn emit_compare(
+        &mut self,
+        v: VReg,
+        op: RelToken,
+        a: Operand,
+        b: Operand,
+        kind: Option<IntKind>,
+    ) {
+        load(&mut self.out, self.locs, a, "%rax");
+        load(&mut self.out, self.locs, b, "%rcx");
+        match kind {
+            Some(kind) => {
+                self.out.push("cmpq %rcx, %rax".to_string());
+                let cond = match (op, kind.signed) {
+                    (RelToken::Equal, _) => "e",
+                    (RelToken::Neq, _) => "ne",
+                    (RelToken::Ge, true) => "g",
+                    (RelToken::Ge, false) => "a",
+                    (RelToken::Le, true) => "l",
+                    (RelToken::Le, false) => "b",
+                };
+                self.out.push(format!("set{} %al", cond));
+            }
+            // Comparisons with NaN are unordered and set the parity flag.
+            None => {
+                self.out.push("movq %rax, %xmm0".to_string());
+                self.out.push("movq %rcx, %xmm1".to_string());
+                let lines: &[&str] = match op {
+                    RelToken::Equal => &[
+                        "ucomisd %xmm1, %xmm0",
+                        "sete %al",
+                        "setnp %cl",
+                        "andb %cl, %al",
+                    ],
+                    RelToken::Neq => &[
+                        "ucomisd %xmm1, %xmm0",
+                        "setne %al",
+                        "setp %cl",
+                        "orb %cl, %al",
+                    ],
+                    RelToken::Ge => &["ucomisd %xmm1, %xmm0", "seta %al"],
+                    RelToken::Le => &["ucomisd %xmm0, %xmm1", "seta %al"],
+                };
+                self.out.extend(lines.iter().map(|line| line.to_string()));
+            }
+        }
+        self.out.push("movzbl %al, %eax".to_string());
+        store(&mut self.out, self.locs, "%rax", v);
+    }
+
+    fn emit_convert(&mut self, v: VReg, conv: Conversion, a: Operand) {
+        load(&mut self.out, self.locs, a, "%rax");
+        match conv {
+            Conversion::Int(kind) if kind.width == 64 => (),
+            Conversion::Int(kind) => self.out.push(extend_rax(kind, "%rax")),
+            Conversion::IntToF64(kind) if kind.width == 64 && !kind.signed => {
+                self.out.push("movq %rax, %rdi".to_string());
+                self.out.push("call rt_u64_to_f64".to_string());
+            }
+            Conversion::IntToF64(_) => {
+                self.out.push("cvtsi2sdq %rax, %xmm0".to_string());
+                self.out.push("movq %xmm0, %rax".to_string());
+            }
+            Conversion::F64ToInt(kind) => {
+                let (min, max) = kind.range();
+                self.out.push("movq %rax, %xmm0".to_string());
+                self.out
+                    .push(format!("movabsq ${}, %rax", (min as f64).to_bits() as i64));
+                self.out.push("movq %rax, %xmm1".to_string());
+                self.out
+                    .push(format!("movabsq ${}, %rax", (max as f64).to_bits() as i64));
+                self.out.push("movq %rax, %xmm2".to_string());
+                self.out.push(format!("movabsq ${}, %rdi", min as i64));
+                self.out
+                    .push(format!("movabsq ${}, %rsi", max as u64 as i64));
+                self.out.push("call rt_f64_to_int".to_string());
+            }
+        }
+        store(&mut self.out, self.locs, "%rax", v);
+    }
+
+    fn emit_inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Mov(v, a) => {
+                load(&mut self.out, self.locs, *a, "%rax");
+                store(&mut self.out, self.locs, "%rax", *v);
+            }
+            Inst::Param(v, n, is_float) => {
+                match (*is_float, *n) {
+                    (true, n) if n < FLOAT_ARG_REGS => {
+                        self.out.push(format!("movq %xmm{}, %rax", n));
+                    }
+                    (false, n) if n < INT_ARG_REGS.len() => {
+                        self.out.push(format!("movq {}, %rax", INT_ARG_REGS[n]));
+                    }
+                    // Stack parameters are filled in by ´emit_function´.
+                    _ => unreachable!(),
+                }
+                store(&mut self.out, self.locs, "%rax", *v);
+            }
+            Inst::LoadStr(v, s) => {
+                self.out.push(format!("leaq {}(%rip), %rax", str_label(*s)));
+                store(&mut self.out, self.locs, "%rax", *v);
+            }
+            Inst::Arith(v, op, a, b, kind) => self.emit_arith(*v, *op, *a, *b, *kind),
+            Inst::FArith(v, op, a, b) => {
+                load(&mut self.out, self.locs, *a, "%rax");
+                load(&mut self.out, self.locs, *b, "%rcx");
+                self.out.push("movq %rax, %xmm0".to_string());
+                self.out.push("movq %rcx, %xmm1".to_string());
+                let instr = match op {
+                    MathToken::Plus => "addsd",
+                    MathToken::Minus => "subsd",
+                    MathToken::Multiply => "mulsd",
+                    MathToken::Division => "divsd",
+                };
+                self.out.push(format!("{} %xmm1, %xmm0", instr));
+                self.out.push("movq %xmm0, %rax".to_string());
+                store(&mut self.out, self.locs, "%rax", *v);
+            }
+            Inst::Bitwise(v, op, a, b) => {
+                load(&mut self.out, self.locs, *a, "%rax");
+                load(&mut self.out, self.locs, *b, "%rcx");
+                let instr = match op {
+                    BoolToken::And => "andq",
+                    BoolToken::Or => "orq",
+                };
+                self.out.push(format!("{} %rcx, %rax", instr));
+                store(&mut self.out, self.locs, "%rax", *v);
+            }
+            Inst::Compare(v, op, a, b, kind) => self.emit_compare(*v, *op, *a, *b, *kind),
+            Inst::Convert(v, conv, a) => self.emit_convert(*v, *conv, *a),
+            Inst::Call(v, symbol, args, returns_float, normalize) => {
+                self.emit_call(symbol, args);
+                if let Some(v) = v {
+                    if *returns_float {
+                        self.out.push("movq %xmm0, %rax".to_string());
+                    }
+                    if let Some(kind) = normalize {
+                        if kind.width < 64 {
+                            self.out.push(extend_rax(*kind, "%rax"));
+                        }
+                    }
+                    store(&mut self.out, self.locs, "%rax", *v);
+                }
+            }
+            Inst::Print(fmt, arg) => {
+                match arg {
+                    Some((a, PrintArg::Bool)) => {
+                        load(&mut self.out, self.locs, *a, "%rax");
+                        self.out.push("leaq .Ltrue(%rip), %rsi".to_string());
+                        self.out.push("leaq .Lfalse(%rip), %rcx".to_string());
+                        self.out.push("testq %rax, %rax".to_string());
+                        self.out.push("cmoveq %rcx, %rsi".to_string());
+                    }
+                    Some((a, _)) => load(&mut self.out, self.locs, *a, "%rsi"),
+                    None => (),
+                }
+                self.out
+                    .push(format!("leaq {}(%rip), %rdi", str_label(*fmt)));
+                self.out.push("xorl %eax, %eax".to_string());
+                self.out.push("call printf@PLT".to_string());
+            }
+            Inst::PrintChar(a) => {
+                load(&mut self.out, self.locs, *a, "%rdi");
+                self.out.push("call rt_print_char".to_string());
+            }
+            Inst::Label(l) => self.out.push(format!("{}_{}:", self.prefix, l)),
+            Inst::Jump(l) => self.out.push(format!("jmp {}_{}", self.prefix, l)),
+            Inst::JumpIfZero(a, l) => {
+                load(&mut self.out, self.locs, *a, "%rax");
+                self.out.push("testq %rax, %rax".to_string());
+                self.out.push(format!("jz {}_{}", self.prefix, l));
+            }
+            Inst::Ret(val) => {
+                if let Some((a, is_float)) = val {
+                    load(&mut self.out, self.locs, *a, "%rax");
+                    if *is_float {
+                        self.out.push("movq %rax, %xmm0".to_string());
+                    }
+                }
+                self.out.push(format!("jmp {}_ret", self.prefix));
+            }
+            Inst::Panic(msg) => {
+                self.out
+                    .push(format!("leaq {}(%rip), %rdi", str_label(*msg)));
+                self.out.push("call rt_panic".to_string());
+            }
+        }
+    }
+}
+
+// Translates the instructions of the function. The frame holds the callee
+// saved registers used by the allocator and the stack slots of the spilled
+// virtual registers.
+fn emit_function(name: &str, index: usize, code: &[Inst]) -> String {
+    let alloc = allocate(code, &regalloc::REGS);
+    let saved = alloc.used_regs.len();
+    // The return address and %rbp leave the stack aligned to 16 bytes.
+    let frame = 8 * alloc.slots + 8 * ((saved + alloc.slots) % 2);
+
+    let mut asm = format!(
+        "\n    .globl f_{name}\n    .type f_{name}, @function\nf_{name}:\n",
+        name = name
+    );
+    let mut vregs: Vec<(&VReg, &Loc)> = alloc.locs.iter().collect();
+    vregs.sort();
+    for (v, loc) in vregs {
+        asm.push_str(&format!("    # v{} in {}\n", v, loc));
+    }
+    let mut prologue = vec!["pushq %rbp".to_string(), "movq %rsp, %rbp".to_string()];
+    for reg in alloc.used_regs.iter() {
+        prologue.push(format!("pushq {}", reg));
+    }
+    if frame > 0 {
+        prologue.push(format!("subq ${}, %rsp", frame));
+    }
+
+    let mut emitter = Emitter {
+        out: prologue,
+        locs: &alloc.locs,
+        prefix: format!(".Lf{}", index),
+    };
+    // Parameters that did not fit in registers were pushed by the caller.
+    let (mut ints, mut floats) = (0, 0);
+    for inst in code.iter() {
+        emitter.out.push(format!("# {}", inst));
+        match inst {
+            Inst::Param(v, _, is_float) => {
+                let n = if *is_float { &mut floats } else { &mut ints };
+                let in_reg = if *is_float {
+                    *n < FLOAT_ARG_REGS
+                } else {
+                    *n < INT_ARG_REGS.len()
+                };
+                *n += 1;
+                if in_reg {
+                    emitter.emit_inst(inst);
+                    continue;
+                }
+                let stack_index = (ints.max(INT_ARG_REGS.len()) - INT_ARG_REGS.len())
+                    + (floats.max(FLOAT_ARG_REGS) - FLOAT_ARG_REGS)
+                    - 1;
+                emitter
+                    .out
+                    .push(format!("movq {}(%rbp), %rax", 16 + 8 * stack_index));
+                store(&mut emitter.out, &alloc.locs, "%rax", *v);
+            }
+            inst => emitter.emit_inst(inst),
+        }
+    }
+    emitter.out.push(format!("{}_ret:", emitter.prefix));
+    if saved > 0 {
+        emitter.out.push(format!("leaq -{}(%rbp), %rsp", 8 * saved));
+    } else {
+        emitter.out.push("movq %rbp, %rsp".to_string());
+    }
+    for reg in alloc.used_regs.iter().rev() {
+        emitter.out.push(format!("popq {}", reg));
+    }
+    emitter.out.push("popq %rbp".to_string());
+    emitter.out.push("ret".to_string());
+
+    for line in emitter.out {
+        if line.ends_with(':') {
+            asm.push_str(&line);
+        } else {
+            asm.push_str("    ");
+            asm.push_str(&line);
+        }
+        asm.push('\n');
+    }
+    asm
+}
+
+// Escapes the string for ´.asciz´. Bytes outside of printable ASCII are
+// written as octal escapes.
+fn asm_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Functions called by the generated code. ´rt_panic´ takes the message in
+// %rdi, ´rt_print_char´ the code point in %rdi, ´rt_f64_to_int´ the float
+// in %xmm0, the range as floats in %xmm1 and %xmm2 and as integers in %rdi
+// and %rsi, and ´rt_u64_to_f64´ the integer in %rdi. The conversions return
+// the bits of the result in %rax.
+const RUNTIME: &str = r#"
+rt_panic:
+    pushq %rbx
+    movq %rdi, %rbx
+    xorl %edi, %edi
+    call fflush@PLT
+    movl $2, %edi
+    leaq .Lpanic_fmt(%rip), %rsi
+    movq %rbx, %rdx
+    xorl %eax, %eax
+    call dprintf@PLT
+    movl $101, %edi
+    call exit@PLT
+
+rt_print_char:
+    subq $24, %rsp
+    cmpq $0x80, %rdi
+    jae 1f
+    movb %dil, (%rsp)
+    movb $0, 1(%rsp)
+    jmp 4f
+1:
+    cmpq $0x800, %rdi
+    jae 2f
+    movq %rdi, %rax
+    shrq $6, %rax
+    orb $0xc0, %al
+    movb %al, (%rsp)
+    movq %rdi, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 1(%rsp)
+    movb $0, 2(%rsp)
+    jmp 4f
+2:
+    cmpq $0x10000, %rdi
+    jae 3f
+    movq %rdi, %rax
+    shrq $12, %rax
+    orb $0xe0, %al
+    movb %al, (%rsp)
+    movq %rdi, %rax
+    shrq $6, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 1(%rsp)
+    movq %rdi, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 2(%rsp)
+    movb $0, 3(%rsp)
+    jmp 4f
+3:
+    movq %rdi, %rax
+    shrq $18, %rax
+    orb $0xf0, %al
+    movb %al, (%rsp)
+    movq %rdi, %rax
+    shrq $12, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 1(%rsp)
+    movq %rdi, %rax
+    shrq $6, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 2(%rsp)
+    movq %rdi, %rax
+    andb $0x3f, %al
+    orb $0x80, %al
+    movb %al, 3(%rsp)
+    movb $0, 4(%rsp)
+4:
+    leaq .Lstr_fmt(%rip), %rdi
+    movq %rsp, %rsi
+    xorl %eax, %eax
+    call printf@PLT
+    addq $24, %rsp
+    ret
+
+rt_f64_to_int:
+    ucomisd %xmm0, %xmm0
+    jp 1f
+    ucomisd %xmm1, %xmm0
+    jbe 2f
+    ucomisd %xmm2, %xmm0
+    jae 3f
+    movabsq $0x43e0000000000000, %rax
+    movq %rax, %xmm3
+    ucomisd %xmm3, %xmm0
+    jae 4f
+    cvttsd2siq %xmm0, %rax
+    ret
+4:
+    subsd %xmm3, %xmm0
+    cvttsd2siq %xmm0, %rax
+    btcq $63, %rax
+    ret
+1:
+    xorl %eax, %eax
+    ret
+2:
+    movq %rdi, %rax
+    ret
+3:
+    movq %rsi, %rax
+    ret
+
+rt_u64_to_f64:
+    testq %rdi, %rdi
+    js 1f
+    cvtsi2sdq %rdi, %xmm0
+    movq %xmm0, %rax
+    ret
+1:
+    movq %rdi, %rax
+    shrq $1, %rax
+    andl $1, %edi
+    orq %rdi, %rax
+    cvtsi2sdq %rax, %xmm0
+    addsd %xmm0, %xmm0
+    movq %xmm0, %rax
+    ret
+"#;
+
+const RUNTIME_DATA: &str = r#"    .section .rodata
+.Lpanic_fmt:
+    .asciz "panicked at '%s'\n"
+.Lstr_fmt:
+    .asciz "%s"
+.Ltrue:
+    .asciz "true"
+.Lfalse:
+    .asciz "false"
+.Lmsg_add:
+    .asciz "attempt to add with overflow"
+.Lmsg_sub:
+    .asciz "attempt to subtract with overflow"
+.Lmsg_mul:
+    .asciz "attempt to multiply with overflow"
+.Lmsg_div:
+    .asciz "attempt to divide with overflow"
+.Lmsg_zero:
+    .asciz "attempt to divide by zero"
+"#;
+
+// Translates the program to x86-64 assembly. ´main´ is translated to
+// ´f_main´, which is called by a C ´main´ returning its value as the exit
+// code.
+pub fn asm_program(fn_list: Functions) -> BackendRes<String> {
+    let typed_fns = check_program(fn_list)?;
+    let mut sigs: HashMap<String, FnSig> = HashMap::new();
+    for func in typed_fns.iter() {
+        for t in func
+            .params
+            .iter()
+            .map(|param| &param.param_type)
+            .chain(std::iter::once(&func.return_type))
+        {
+            match t {
+                Type::Tuple(_) => return err_unsupported("tuples"),
+                Type::Vec(_) => return err_unsupported("vectors"),
+                _ => (),
+            }
+        }
+        let sig = FnSig {
+            return_type: func.return_type.clone(),
+            is_extern: func.is_extern,
+        };
+        sigs.insert(func.name.clone(), sig);
+    }
+
+    let mut strings = Strings::default();
+    let mut asm = String::from("    .text\n");
+    for (index, func) in typed_fns.iter().filter(|func| !func.is_extern).enumerate() {
+        let code = lower_function(func, &sigs, &mut strings)?;
+        asm.push_str(&emit_function(&func.name, index, &code));
+    }
+
+    asm.push_str("\n    .globl main\n    .type main, @function\nmain:\n");
+    asm.push_str("    pushq %rbp\n    movq %rsp, %rbp\n    call f_main\n");
+    match sigs["main"].return_type {
+        Type::Int32 => (),
+        Type::Void => asm.push_str("    xorl %eax, %eax\n"),
+        _ => return err_unsupported("a ´main´ function that does not return ´i32´ or ´()´"),
+    }
+    asm.push_str("    popq %rbp\n    ret\n");
+    asm.push_str(RUNTIME);
+
+    asm.push('\n');
+    asm.push_str(RUNTIME_DATA);
+    for (i, s) in strings.strings.iter().enumerate() {
+        asm.push_str(&format!(
+            "{}:\n    .asciz {}\n",
+            str_label(i),
+            asm_string(s)
+        ));
+    }
+    asm.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+    Ok(asm)
+}
+
+// Translates the program, assembles it with ´as´ and links it with ´cc´.
+// The assembly is written next to the executable with the extension .s.
+pub fn build_asm_program(fn_list: Functions, path: &Path) -> BackendRes<()> {
+    let asm = asm_program(fn_list)?;
+    let asm_path = path.with_extension("s");
+    let obj_path = path.with_extension("o");
+    std::fs::write(&asm_path, asm)
+        .map_err(|e| BackendError::Io(format!("{}: {}", asm_path.display(), e)))?;
+    run_tool(
+        "as",
+        &[asm_path.as_os_str(), "-o".as_ref(), obj_path.as_os_str()],
+    )?;
+    let res = run_tool(
+        "cc",
+        &[obj_path.as_os_str(), "-o".as_ref(), path.as_os_str()],
+    );
+    let _ = std::fs::remove_file(&obj_path);
+    res
+}
+
+#[cfg(test)]
+mod x86_64_tests {
+    use super::*;
+    use crate::backend::backend_tests::*;
+    use crate::parser::*;
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn run_asm(program: &str, name: &str) -> (Option<i32>, String) {
+        run_built(build_asm_program, program, &format!("x86_64-{}", name), "s")
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn x86_64_fibo_program() {
+        let asm = asm_program(parse_program(FIBO).unwrap().1).unwrap();
+        assert!(asm.contains("\nf_fibo:\n"));
+        assert!(asm.contains("\nmain:\n    pushq %rbp\n    movq %rsp, %rbp\n    call f_main\n"));
+        assert_eq!(run_asm(FIBO, "fibo"), (Some(8), String::new()));
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn x86_64_spilling_program() {
+        // More live values than registers, and arguments passed on the stack
+        let program = "
+            fn sum(a: i32, b: i64, c: f64, d: u8, e: i32, f: i32, g: i32, h: i32, i: f64) -> i64 {
+                let x: i64 = ((a + e) + (f + (g + h))) as i64;
+                return (x + b) + ((c * i) as i64);
+            }
+
+            fn main() -> i32 {
+                let a: i32 = 1;
+                let b: i32 = 2;
+                let c: i32 = 3;
+                let d: i32 = 4;
+                let e: i32 = 5;
+                let f: i32 = 6;
+                let g: i32 = 7;
+                let h: i32 = 8;
+                let s: i32 = ((a + b) + (c + d)) + ((e + f) + (g + h));
+                println(\"{} {}\", s, sum(a, 2 as i64, 2.5, 200 as u8, c, d, e, f, 4.0));
+                return s;
+            }
+        ";
+        let asm = asm_program(parse_program(program).unwrap().1).unwrap();
+        assert!(asm.contains("(%rbp)\n"));
+        assert_eq!(
+            run_asm(program, "spilling"),
+            (Some(36), "36 31\n".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn x86_64_overflow_program() {
+        let program = "
+            fn main() -> i32 {
+                let a: i8 = 100;
+                println(\"{} {}\", a, 'é');
+                a += 100;
+                println(\"{}\", a);
+                return 0;
+            }
+        ";
+        assert_eq!(
+            run_asm(program, "overflow"),
+            (Some(101), "100 é\n".to_string())
+        );
+    }
+}
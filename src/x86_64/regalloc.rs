@@ -0,0 +1,262 @@
+use super::{Inst, VReg};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Register allocation with linear scan, as described by Poletto and Sarkar.
+// The live interval of a virtual register goes from the first to the last
+// instruction where it is defined or live. Intervals are visited by their
+// start and given a free register. When there is none, the interval that
+// ends last is spilled to its own stack slot.
+//
+// Only the callee saved registers are allocated, so values survive calls
+// without being saved around them. The other registers are free for the
+// translation of each instruction.
+
+pub const REGS: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Loc {
+    Reg(&'static str),
+    // Offset from %rbp
+    Stack(i64),
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Loc::Reg(reg) => write!(f, "{}", reg),
+            Loc::Stack(offset) => write!(f, "{}(%rbp)", offset),
+        }
+    }
+}
+
+pub struct Allocation {
+    pub locs: HashMap<VReg, Loc>,
+    // The registers that have to be saved by the function, in the order of
+    // the registers given to ´allocate´
+    pub used_regs: Vec<&'static str>,
+    pub slots: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Interval {
+    vreg: VReg,
+    start: usize,
+    end: usize,
+}
+
+fn successors(code: &[Inst], labels: &HashMap<usize, usize>, i: usize) -> Vec<usize> {
+    let next = if i + 1 < code.len() {
+        vec![i + 1]
+    } else {
+        vec![]
+    };
+    match &code[i] {
+        Inst::Jump(l) => vec![labels[l]],
+        Inst::JumpIfZero(_, l) => {
+            let mut succs = next;
+            succs.push(labels[l]);
+            succs
+        }
+        Inst::Ret(_) | Inst::Panic(_) => vec![],
+        _ => next,
+    }
+}
+
+// The virtual registers that are live after each instruction, found by
+// iterating the dataflow equations backwards until nothing changes.
+fn live_out(code: &[Inst]) -> Vec<HashSet<VReg>> {
+    let mut labels: HashMap<usize, usize> = HashMap::new();
+    for (i, inst) in code.iter().enumerate() {
+        if let Inst::Label(l) = inst {
+            labels.insert(*l, i);
+        }
+    }
+    let succs: Vec<Vec<usize>> = (0..code.len())
+        .map(|i| successors(code, &labels, i))
+        .collect();
+
+    let mut live_in: Vec<HashSet<VReg>> = vec![HashSet::new(); code.len()];
+    let mut live_out: Vec<HashSet<VReg>> = vec![HashSet::new(); code.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..code.len()).rev() {
+            let mut out: HashSet<VReg> = HashSet::new();
+            for succ in succs[i].iter() {
+                out.extend(live_in[*succ].iter().cloned());
+            }
+            let mut live: HashSet<VReg> = out.clone();
+            if let Some(def) = code[i].def() {
+                live.remove(&def);
+            }
+            live.extend(code[i].uses());
+            if live != live_in[i] || out != live_out[i] {
+                changed = true;
+                live_in[i] = live;
+                live_out[i] = out;
+            }
+        }
+    }
+    live_out
+}
+
+fn intervals(code: &[Inst]) -> Vec<Interval> {
+    let live_out = live_out(code);
+    let mut ranges: HashMap<VReg, (usize, usize)> = HashMap::new();
+    for (i, inst) in code.iter().enumerate() {
+        let vregs = live_out[i]
+            .iter()
+            .cloned()
+            .chain(inst.uses())
+            .chain(inst.def());
+        for v in vregs {
+            let range = ranges.entry(v).or_insert((i, i));
+            range.0 = range.0.min(i);
+            range.1 = range.1.max(i);
+        }
+    }
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(vreg, (start, end))| Interval { vreg, start, end })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.vreg));
+    intervals
+}
+
+// Gives every virtual register of the code one of the registers or a stack
+// slot. Slots are placed below the saved registers in the frame.
+pub fn allocate(code: &[Inst], regs: &[&'static str]) -> Allocation {
+    let mut regs_of: HashMap<VReg, &'static str> = HashMap::new();
+    let mut spilled: Vec<VReg> = Vec::new();
+    let mut free: Vec<&'static str> = regs.iter().rev().cloned().collect();
+    // Sorted by the end of the intervals
+    let mut active: Vec<Interval> = Vec::new();
+
+    for interval in intervals(code) {
+        while !active.is_empty() && active[0].end < interval.start {
+            let expired = active.remove(0);
+            free.push(regs_of[&expired.vreg]);
+        }
+        let reg = match free.pop() {
+            Some(reg) => reg,
+            None => match active.last().cloned() {
+                Some(last) if last.end > interval.end => {
+                    active.pop();
+                    spilled.push(last.vreg);
+                    regs_of.remove(&last.vreg).unwrap()
+                }
+                _ => {
+                    spilled.push(interval.vreg);
+                    continue;
+                }
+            },
+        };
+        regs_of.insert(interval.vreg, reg);
+        let pos = active
+            .iter()
+            .position(|other| other.end > interval.end)
+            .unwrap_or(active.len());
+        active.insert(pos, interval);
+    }
+
+    let used_regs: Vec<&'static str> = regs
+        .iter()
+        .filter(|reg| regs_of.values().any(|used| used == *reg))
+        .cloned()
+        .collect();
+    let saved = used_regs.len() as i64;
+    let mut locs: HashMap<VReg, Loc> = regs_of
+        .into_iter()
+        .map(|(v, reg)| (v, Loc::Reg(reg)))
+        .collect();
+    for (i, v) in spilled.iter().enumerate() {
+        locs.insert(*v, Loc::Stack(-8 * (saved + i as i64 + 1)));
+    }
+    Allocation {
+        locs,
+        used_regs,
+        slots: spilled.len(),
+    }
+}
+
+#[cfg(test)]
+mod regalloc_tests {
+    use super::super::{IntKind, Operand};
+    use super::*;
+    use crate::ast::{MathToken, RelToken};
+
+    const I64: IntKind = IntKind {
+        width: 64,
+        signed: true,
+    };
+
+    #[test]
+    fn regalloc_loop_intervals() {
+        let code = vec![
+            Inst::Mov(0, Operand::Imm(0)),
+            Inst::Label(0),
+            Inst::Compare(
+                1,
+                RelToken::Le,
+                Operand::Reg(0),
+                Operand::Imm(10),
+                Some(I64),
+            ),
+            Inst::JumpIfZero(Operand::Reg(1), 1),
+            Inst::Arith(0, MathToken::Plus, Operand::Reg(0), Operand::Imm(1), I64),
+            Inst::Jump(0),
+            Inst::Label(1),
+            Inst::Ret(Some((Operand::Reg(0), false))),
+        ];
+        assert_eq!(
+            intervals(&code),
+            vec![
+                Interval {
+                    vreg: 0,
+                    start: 0,
+                    end: 7
+                },
+                Interval {
+                    vreg: 1,
+                    start: 2,
+                    end: 3
+                },
+            ]
+        );
+        let alloc = allocate(&code, &REGS);
+        assert_eq!(alloc.locs[&0], Loc::Reg("%rbx"));
+        assert_eq!(alloc.locs[&1], Loc::Reg("%r12"));
+        assert_eq!(alloc.slots, 0);
+    }
+
+    #[test]
+    fn regalloc_spilling() {
+        // Eight values that are all live at the same time, then summed
+        let mut code: Vec<Inst> = (0..8)
+            .map(|v| Inst::Mov(v, Operand::Imm(v as i64)))
+            .collect();
+        for v in 8..15 {
+            let a = if v == 8 { 0 } else { v - 1 };
+            let b = v - 7;
+            let inst = Inst::Arith(v, MathToken::Plus, Operand::Reg(a), Operand::Reg(b), I64);
+            code.push(inst);
+        }
+        code.push(Inst::Ret(Some((Operand::Reg(14), false))));
+
+        let alloc = allocate(&code, &REGS[..2]);
+        assert_eq!(alloc.used_regs, vec!["%rbx", "%r12"]);
+        assert!(alloc.slots >= 6);
+        assert_eq!(alloc.locs.len(), 15);
+        let intervals = intervals(&code);
+        for a in intervals.iter() {
+            for b in intervals.iter() {
+                let overlap = a.start <= b.end && b.start <= a.end;
+                if a.vreg != b.vreg && overlap {
+                    assert_ne!(alloc.locs[&a.vreg], alloc.locs[&b.vreg]);
+                }
+            }
+        }
+    }
+}
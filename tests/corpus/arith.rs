@@ -0,0 +1,23 @@
+fn gcd(a: i32, b: i32) -> i32 {
+    while b != 0 {
+        let t: i32 = b;
+        b = a - (a / b) * b;
+        a = t;
+    };
+    return a;
+}
+
+fn main() -> i32 {
+    let a: i32 = gcd(84, 36);
+    let sum: i32 = 0;
+    let i: i32 = 1;
+    while i < 11 {
+        sum += i * i;
+        i += 1;
+    };
+    if (sum > 300) && (a == 12) {
+        sum -= 300;
+    };
+    println("{} {}", a, sum);
+    return sum;
+}
@@ -0,0 +1,8 @@
+fn main() -> i32 {
+    let small: i8 = -128;
+    let d: i8 = -1;
+    let q: i8 = small / (d + 2);
+    println("{}", q);
+    let zero: u64 = 0;
+    return (10 / zero) as i32;
+}
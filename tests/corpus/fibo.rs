@@ -0,0 +1,13 @@
+fn fibo(i: i32) -> i32 {
+    if i < 3 {
+        return 1;
+    };
+    return fibo(i - 1) + fibo(i - 2);
+}
+
+fn main() -> i32 {
+    let n: i32 = fibo(20);
+    let even: bool = ((n / 2) * 2) == n;
+    println("{} {}", n, even);
+    return fibo(10);
+}
@@ -0,0 +1,13 @@
+fn main() -> i32 {
+    let big: f64 = 1e20;
+    let nan: f64 = 0.0 / 0.0;
+    let a: i32 = big as i32;
+    let b: i32 = (0.0 - big) as i32;
+    let c: u8 = nan as u8;
+    let d: u8 = -5.5 as u8;
+    let e: i64 = big as i64;
+    let f: u16 = 70000.9 as u16;
+    let g: i8 = -128.9 as i8;
+    println("{} {} {} {} {} {} {}", a, b, c, d, e, f, g);
+    return (c as i32) + 3;
+}
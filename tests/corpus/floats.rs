@@ -0,0 +1,13 @@
+fn area(r: f64) -> f64 {
+    return (r * r) * 3.5;
+}
+
+fn main() -> i32 {
+    let a: f64 = area(2.0);
+    let big: bool = a > 10.0;
+    let n: i32 = a as i32;
+    let small: u8 = 300 as u8;
+    let c: i64 = (n as i64) * 1000000000;
+    println("{} {} {} {}", n, small, c, big);
+    return n;
+}
@@ -0,0 +1,6 @@
+fn main() -> i32 {
+    let v: Vec<i32> = Vec::new();
+    v.push(1);
+    println("before");
+    return v[3];
+}
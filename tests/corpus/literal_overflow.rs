@@ -0,0 +1,6 @@
+fn main() -> i32 {
+    let a: i64 = 3000000000;
+    println("{}", a);
+    let b: u8 = 200 + 100;
+    return b as i32;
+}
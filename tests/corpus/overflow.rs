@@ -0,0 +1,12 @@
+fn square(a: i32) -> i32 {
+    return a * a;
+}
+
+fn main() -> i32 {
+    let i: i32 = 1000;
+    while i < 1000000 {
+        println("{}", square(i));
+        i *= 10;
+    };
+    return 0;
+}
@@ -0,0 +1,18 @@
+fn main() -> i32 {
+    let a: i32 = 1;
+    let c: bool = true;
+    if c {
+        let a: i32 = 2;
+        let a: bool = false;
+        println("{}", a);
+    };
+    while c {
+        let a: u8 = 250;
+        a += 5;
+        println("{}", a);
+        c = false;
+    };
+    let a: i32 = a + 10;
+    println("{}", a);
+    return a;
+}
@@ -0,0 +1,13 @@
+fn upper(c: char) -> char {
+    let code: u8 = c as u8;
+    code -= 32;
+    return code as char;
+}
+
+fn main() -> () {
+    let name: &str = "world";
+    let c: char = upper('h');
+    let code: u32 = 'é' as u32;
+    print("{}ello, {}! ", c, name);
+    println("{} {} {}", code, c == 'H', '✓');
+}
@@ -0,0 +1,13 @@
+fn divmod(a: i32, b: i32) -> (i32, i32) {
+    return (a / b, a - (a / b) * b);
+}
+
+fn main() -> i32 {
+    let (q, r): (i32, i32) = divmod(17, 5);
+    let t: ((i32, i32), bool) = (divmod(9, 2), true);
+    if t.1 {
+        q += t.0.1;
+    };
+    println("{} {}", q, r);
+    return (q * 10) + r;
+}
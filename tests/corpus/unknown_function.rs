@@ -0,0 +1,4 @@
+fn main() -> i32 {
+    let a: i32 = 1;
+    return missing(a);
+}
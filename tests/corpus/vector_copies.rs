@@ -0,0 +1,20 @@
+fn sum(v: Vec<i32>) -> i32 {
+    let total: i32 = 0;
+    while v.len() > 0 {
+        total += v.pop();
+    };
+    return total;
+}
+
+fn main() -> i32 {
+    let v: Vec<i32> = Vec::new();
+    v.push(1);
+    v.push(2);
+    v.push(3);
+    let s: i32 = sum(v);
+    let w: Vec<i32> = v;
+    w.push(4);
+    v[0] = 10;
+    println("{} {} {} {} {}", s, v.len(), w.len(), v[0], w[0]);
+    return (v.len() as i32) + s;
+}
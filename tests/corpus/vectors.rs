@@ -0,0 +1,22 @@
+fn sum(v: Vec<i64>) -> i64 {
+    let s: i64 = 0;
+    let i: usize = 0;
+    while i < v.len() {
+        s += v[i];
+        i += 1;
+    };
+    return s;
+}
+
+fn main() -> i32 {
+    let v: Vec<i64> = Vec::new();
+    v.push(10);
+    v.push(20);
+    v.push(30);
+    v[0] *= 2;
+    let last: i64 = v.pop();
+    let n: usize = v.len();
+    let total: i64 = sum(v) + last;
+    println("{} {}", total, n);
+    return total as i32;
+}
@@ -0,0 +1,22 @@
+fn add(a: i64, b: u64) -> u64 {
+    return ((a + 2147483648) as u64) + (b + 3000000000);
+}
+
+fn big() -> i64 {
+    return 3000000000 * 2;
+}
+
+fn main() -> i32 {
+    let a: i64 = 100000 * 100000;
+    a = 3000000000 * 3;
+    let b: u8 = 200 + 55;
+    let c: i64 = a - 3000000000;
+    let d: u32 = 4000000000 + 294967295;
+    let v: Vec<u64> = Vec::new();
+    v.push(5000000000);
+    v[0] += 5000000000;
+    let t: (u8, i64) = (255, -3000000000);
+    println("{} {} {} {} {} {}", add(-2147483648, 4000000000), big(), a, b, c, d);
+    println("{} {} {}", v[0], t.0, t.1);
+    return b as i32;
+}
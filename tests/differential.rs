@@ -0,0 +1,423 @@
+extern crate simple_rustc;
+
+use crate::simple_rustc::ast::*;
+use crate::simple_rustc::backend::*;
+use crate::simple_rustc::bytecode::*;
+use crate::simple_rustc::c99::*;
+use crate::simple_rustc::fold::*;
+use crate::simple_rustc::interpreter::*;
+use crate::simple_rustc::ir::*;
+use crate::simple_rustc::llvm::*;
+use crate::simple_rustc::optimize::*;
+use crate::simple_rustc::parser::*;
+use crate::simple_rustc::type_checker::*;
+use crate::simple_rustc::vm::*;
+use crate::simple_rustc::x86_64::*;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+// Runs every program in tests/corpus with the interpreter and as a compiled
+// executable and compares the results. The compiled programs are run as
+// separate processes, so that a program that panics, and exits, does not
+// stop the test.
+
+// What running a program results in. Runtime errors of the interpreter are
+// panics in compiled code. The output printed before the panic is compared
+// as well.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    // Exit code and output. The exit code is the value returned by an i32
+    // main, truncated to 8 bits like the exit code of a process.
+    Exit(i32, String),
+    Panic(String),
+    // Programs that the interpreter cannot run and the compiler does not compile
+    Rejected,
+}
+
+fn interpret(program: &str) -> Outcome {
+    let mut tree = parse_program(program).unwrap().1;
+    let mut out: Vec<u8> = Vec::new();
+    let res = eval_program_with_output(&mut tree, &mut out);
+    let out = String::from_utf8(out).unwrap();
+
+    match res {
+        Ok(Value::Return(val)) => match *val {
+            Value::Num(i) => Outcome::Exit(i & 0xff, out),
+            _ => Outcome::Exit(0, out),
+        },
+        Ok(_) => Outcome::Exit(0, out),
+        Err(EvalErr::DivisionByZero)
+        | Err(EvalErr::IndexOutOfBounds(_))
+        | Err(EvalErr::Overflow(_)) => Outcome::Panic(out),
+        Err(_) => Outcome::Rejected,
+    }
+}
+
+fn compile_and_run(program: &str, exe_path: &Path) -> Outcome {
+    let tree = parse_program(program).unwrap().1;
+    let options = CompileOptions {
+        output_kind: OutputKind::Executable,
+        output_path: exe_path.to_path_buf(),
+        ..CompileOptions::default()
+    };
+    match build_program(tree, &options) {
+        Ok(()) => (),
+        Err(CompileError::TypeError(_)) | Err(CompileError::Unsupported(_)) => {
+            return Outcome::Rejected
+        }
+        Err(e) => panic!("{}", e),
+    }
+    run_executable(exe_path)
+}
+
+fn run_executable(exe_path: &Path) -> Outcome {
+    let output = process::Command::new(exe_path).output().unwrap();
+    let _ = fs::remove_file(exe_path);
+    let out = String::from_utf8(output.stdout).unwrap();
+    match output.status.code() {
+        Some(101) if String::from_utf8_lossy(&output.stderr).starts_with("panicked at") => {
+            Outcome::Panic(out)
+        }
+        Some(code) => Outcome::Exit(code, out),
+        None => panic!("{} was killed by a signal", exe_path.display()),
+    }
+}
+
+// Runs main in a JIT session. Compiled code prints with the printf of the
+// test process, so the output is not captured and only the exit code and
+// panics are compared.
+fn run_jit(program: &str) -> Outcome {
+    let tree = parse_program(program).unwrap().1;
+    let session = match JitSession::new(tree) {
+        Ok(session) => session,
+        Err(CompileError::TypeError(_)) | Err(CompileError::Unsupported(_)) => {
+            return Outcome::Rejected
+        }
+        Err(e) => panic!("{}", e),
+    };
+    match session.call("main", vec![]) {
+        Ok(Value::Num(i)) => Outcome::Exit(i & 0xff, String::new()),
+        Ok(_) => Outcome::Exit(0, String::new()),
+        Err(CompileError::Panic(_)) => Outcome::Panic(String::new()),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+// Writes an executable of the program with a backend other than LLVM
+type BuildFn = fn(Functions, &Path) -> BackendRes<()>;
+
+// Builds the program with a backend that writes a file with the extension
+// next to the executable for the system tools, e.g. ´.c´ for ´cc´. Programs
+// that use features the backend cannot translate are skipped.
+fn build_and_run(
+    build: BuildFn,
+    extension: &str,
+    program: &str,
+    exe_path: &Path,
+) -> Option<Outcome> {
+    let tree = parse_program(program).unwrap().1;
+    match build(tree, exe_path) {
+        Ok(()) => (),
+        Err(BackendError::Unsupported(_)) => return None,
+        Err(BackendError::TypeError(_)) => return Some(Outcome::Rejected),
+        Err(e) => panic!("{}", e),
+    }
+    let _ = fs::remove_file(exe_path.with_extension(extension));
+    Some(run_executable(exe_path))
+}
+
+// Lowers the program to the IR and runs it with the IR interpreter, which
+// returns the value of main instead of the return statement.
+fn run_ir_program(program: &str) -> Option<Outcome> {
+    let tree = parse_program(program).unwrap().1;
+    let module = match lower_program(tree) {
+        Ok(module) => module,
+        Err(BackendError::Unsupported(_)) => return None,
+        Err(BackendError::TypeError(_)) => return Some(Outcome::Rejected),
+        Err(e) => panic!("{}", e),
+    };
+    let mut out: Vec<u8> = Vec::new();
+    let res = run_ir_with_output(&module, &mut out);
+    let out = String::from_utf8(out).unwrap();
+
+    Some(match res {
+        Ok(Value::Num(i)) => Outcome::Exit(i & 0xff, out),
+        Ok(_) => Outcome::Exit(0, out),
+        Err(EvalErr::DivisionByZero)
+        | Err(EvalErr::IndexOutOfBounds(_))
+        | Err(EvalErr::Overflow(_)) => Outcome::Panic(out),
+        Err(_) => Outcome::Rejected,
+    })
+}
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn interpreter_matches_compiler() {
+    let mut mismatches: Vec<String> = Vec::new();
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let exe_path = env::temp_dir().join(format!("differential-{}-{}", process::id(), name));
+
+        let interpreted = interpret(&program);
+        let compiled = compile_and_run(&program, &exe_path);
+        if interpreted != compiled {
+            mismatches.push(format!(
+                "{}:\n{}\ninterpreter: {:?}\ncompiled:    {:?}\n",
+                path.display(),
+                program,
+                interpreted,
+                compiled
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "{} program(s) differ\n\n{}",
+        mismatches.len(),
+        mismatches.join("\n")
+    );
+}
+
+// Functions called in a JIT session panic without exiting, unlike the
+// executables compared above.
+#[test]
+fn interpreter_matches_jit() {
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let expected = match interpret(&program) {
+            Outcome::Exit(code, _) => Outcome::Exit(code, String::new()),
+            Outcome::Panic(_) => Outcome::Panic(String::new()),
+            Outcome::Rejected => Outcome::Rejected,
+        };
+        assert_eq!(expected, run_jit(&program), "{}", path.display());
+    }
+}
+
+// The assembly backend only targets x86-64 Linux.
+#[test]
+fn interpreter_matches_backends() {
+    let mut backends: Vec<(&str, BuildFn, &str)> = vec![("c99", build_c_program, "c")];
+    if cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+        backends.push(("x86_64", build_asm_program, "s"));
+    }
+
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let expected = interpret(&program);
+
+        for (backend, build, extension) in backends.iter() {
+            let exe_path = env::temp_dir().join(format!(
+                "differential-{}-{}-{}",
+                backend,
+                process::id(),
+                name
+            ));
+            if let Some(compiled) = build_and_run(*build, extension, &program, &exe_path) {
+                assert_eq!(expected, compiled, "{} ({})", path.display(), backend);
+            }
+        }
+    }
+}
+
+#[test]
+fn interpreter_matches_ir() {
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        if let Some(res) = run_ir_program(&program) {
+            assert_eq!(interpret(&program), res, "{}", path.display());
+        }
+    }
+}
+
+// The VM runs the same values through the same operations as the
+// interpreter, so the results are compared exactly, including errors.
+#[test]
+fn vm_matches_interpreter() {
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let mut tree = parse_program(&program).unwrap().1;
+
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut tree, &mut expected_out);
+        let mut out: Vec<u8> = Vec::new();
+        let res =
+            compile_bytecode(&tree).and_then(|code| run_bytecode_with_output(&code, &mut out));
+
+        assert_eq!(res, expected, "{}", path.display());
+        assert_eq!(out, expected_out, "{}", path.display());
+    }
+}
+
+// Folding evaluates constant operations with the interpreter, so a folded
+// program gives the same results, including errors.
+#[test]
+fn folding_preserves_results() {
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let mut tree = parse_program(&program).unwrap().1;
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut tree.clone(), &mut expected_out);
+
+        fold_program(&mut tree);
+        let mut out: Vec<u8> = Vec::new();
+        let res = eval_program_with_output(&mut tree, &mut out);
+
+        assert_eq!(res, expected, "{}", path.display());
+        assert_eq!(out, expected_out, "{}", path.display());
+    }
+}
+
+// The optimised program also has to pass the type checker, so that it can
+// be compiled.
+#[test]
+fn optimizing_preserves_results() {
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        let mut tree = parse_program(&program).unwrap().1;
+        let mut expected_out: Vec<u8> = Vec::new();
+        let expected = eval_program_with_output(&mut tree.clone(), &mut expected_out);
+        let type_checks = tc_program(&mut tree.clone()).is_ok();
+
+        optimize_program(&mut tree, &OptimizeOptions::default());
+        let mut out: Vec<u8> = Vec::new();
+        let res = eval_program_with_output(&mut tree.clone(), &mut out);
+
+        assert_eq!(res, expected, "{}", path.display());
+        assert_eq!(out, expected_out, "{}", path.display());
+        assert_eq!(
+            tc_program(&mut tree).is_ok(),
+            type_checks,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+// Collects the names of the expression variants used in the expression.
+fn collect_variants(expr: &Expr, variants: &mut HashSet<&'static str>) {
+    let visit_all = |exprs: &[Expr], variants: &mut HashSet<&'static str>| {
+        for e in exprs {
+            collect_variants(e, variants);
+        }
+    };
+
+    let name = match expr {
+        Expr::BinOp(l, _, r) => {
+            visit_all(&[*l.clone(), *r.clone()], variants);
+            "BinOp"
+        }
+        Expr::Num(_) => "Num",
+        Expr::Float(_) => "Float",
+        Expr::Var(_) => "Var",
+        Expr::Bool(_) => "Bool",
+        Expr::Char(_) => "Char",
+        Expr::Str(_) => "Str",
+        Expr::Tuple(exprs) => {
+            visit_all(exprs, variants);
+            "Tuple"
+        }
+        Expr::TupleIndex(e, _) => {
+            collect_variants(e, variants);
+            "TupleIndex"
+        }
+        Expr::Cast(e, _) => {
+            collect_variants(e, variants);
+            "Cast"
+        }
+        Expr::VecNew => "VecNew",
+        Expr::Index(l, r) => {
+            visit_all(&[*l.clone(), *r.clone()], variants);
+            "Index"
+        }
+        Expr::MethodCall(e, _, args) => {
+            collect_variants(e, variants);
+            visit_all(args, variants);
+            "MethodCall"
+        }
+        Expr::Let(pattern, _, e) => {
+            visit_all(&[*pattern.clone(), *e.clone()], variants);
+            "Let"
+        }
+        Expr::VarOp(l, _, r) => {
+            visit_all(&[*l.clone(), *r.clone()], variants);
+            "VarOp"
+        }
+        Expr::If(cond, block) => {
+            collect_variants(cond, variants);
+            visit_all(&block.content, variants);
+            "If"
+        }
+        Expr::IfElse(cond, block) => {
+            collect_variants(cond, variants);
+            visit_all(&block.content, variants);
+            "IfElse"
+        }
+        Expr::While(cond, block) => {
+            collect_variants(cond, variants);
+            visit_all(&block.content, variants);
+            "While"
+        }
+        Expr::FuncCall(fn_call) => {
+            visit_all(&fn_call.args.content, variants);
+            "FuncCall"
+        }
+        Expr::Return(e) => {
+            collect_variants(e, variants);
+            "Return"
+        }
+    };
+    variants.insert(name);
+}
+
+// ´IfElse´ is not produced by the parser and is the only variant left out.
+#[test]
+fn corpus_covers_every_expr() {
+    let mut variants: HashSet<&'static str> = HashSet::new();
+    for path in corpus() {
+        let program = fs::read_to_string(&path).unwrap();
+        for func in parse_program(&program).unwrap().1 {
+            for expr in func.block.content.iter() {
+                collect_variants(expr, &mut variants);
+            }
+        }
+    }
+
+    let expected = vec![
+        "BinOp",
+        "Num",
+        "Float",
+        "Var",
+        "Bool",
+        "Char",
+        "Str",
+        "Tuple",
+        "TupleIndex",
+        "Cast",
+        "VecNew",
+        "Index",
+        "MethodCall",
+        "Let",
+        "VarOp",
+        "If",
+        "While",
+        "FuncCall",
+        "Return",
+    ];
+    for variant in expected {
+        assert!(variants.contains(variant), "no program uses ´{}´", variant);
+    }
+}